@@ -0,0 +1,54 @@
+//! A single shaded sphere on a checkered floor.
+//!
+//! Run with `cargo run --example basic_sphere > basic_sphere.ppm`.
+
+use rtchallenge::camera::*;
+use rtchallenge::canvas::*;
+use rtchallenge::color::*;
+use rtchallenge::geometry::*;
+use rtchallenge::light::*;
+use rtchallenge::material::*;
+use rtchallenge::object::*;
+use rtchallenge::ppm::*;
+use rtchallenge::scene::*;
+use rtchallenge::texture::*;
+use rtchallenge::transform::*;
+use rtchallenge::tuple::*;
+
+pub fn render(hsize: usize, vsize: usize) -> Canvas {
+    let mut camera = Camera::new(hsize, vsize, std::f32::consts::FRAC_PI_3);
+    camera.set_transform(Transform::look_at(
+        point3(0., 1.5, -5.),
+        point3(0., 1., 0.),
+        vector3(0., 1., 0.),
+    ));
+
+    let mut scene = Scene::new();
+    scene.add_light(Light::new(point3(-10., 10., -10.), Color::WHITE));
+    scene.add_object(
+        Object::new().geometry(Geometry::plane()).material(
+            Material::new()
+                .texture(Texture::checkerboard_2d(
+                    Color::WHITE,
+                    Color::new(0.5, 0.5, 0.5),
+                ))
+                .specular(0.),
+        ),
+    );
+    scene.add_object(
+        Object::new()
+            .transform(Transform::new().translate(0., 1., 0.))
+            .material(
+                Material::new()
+                    .color(Color::new(0.1, 1., 0.5))
+                    .diffuse(0.7)
+                    .specular(0.3),
+            ),
+    );
+
+    camera.render(scene)
+}
+
+fn main() {
+    print!("{}", canvas_to_ppm(render(400, 200)));
+}
@@ -0,0 +1,59 @@
+//! A glass sphere with an air bubble inside, in front of a striped wall.
+//!
+//! Run with `cargo run --example glass > glass.ppm`.
+
+use rtchallenge::camera::*;
+use rtchallenge::canvas::*;
+use rtchallenge::color::*;
+use rtchallenge::geometry::*;
+use rtchallenge::light::*;
+use rtchallenge::material::*;
+use rtchallenge::object::*;
+use rtchallenge::ppm::*;
+use rtchallenge::scene::*;
+use rtchallenge::texture::*;
+use rtchallenge::transform::*;
+use rtchallenge::tuple::*;
+
+pub fn render(hsize: usize, vsize: usize) -> Canvas {
+    let mut camera = Camera::new(hsize, vsize, std::f32::consts::FRAC_PI_3);
+    camera.set_transform(Transform::look_at(
+        point3(0., 0., -5.),
+        point3(0., 0., 0.),
+        vector3(0., 1., 0.),
+    ));
+
+    let mut scene = Scene::new();
+    scene.add_light(Light::new(point3(-10., 10., -10.), Color::WHITE));
+    scene.add_object(
+        Object::new()
+            .geometry(Geometry::plane())
+            .transform(
+                Transform::new()
+                    .translate(0., 0., 5.)
+                    .rotate_x(std::f32::consts::FRAC_PI_2),
+            )
+            .material(Material::new().texture(Texture::stripe(Color::WHITE, Color::BLACK))),
+    );
+
+    let glass = Material::new()
+        .color(Color::BLACK)
+        .diffuse(0.1)
+        .specular(1.)
+        .shininess(300)
+        .reflective(0.9)
+        .transparency(0.9)
+        .glass(Glass::BK7);
+    scene.add_object(Object::new().material(glass.clone()));
+    scene.add_object(
+        Object::new()
+            .transform(Transform::new().scale(0.5, 0.5, 0.5))
+            .material(glass.refractive_index(1.0000034)),
+    );
+
+    camera.render(scene)
+}
+
+fn main() {
+    print!("{}", canvas_to_ppm(render(400, 400)));
+}
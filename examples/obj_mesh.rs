@@ -0,0 +1,60 @@
+//! An octahedron loaded from OBJ source, above a floor.
+//!
+//! Run with `cargo run --example obj_mesh > obj_mesh.ppm`.
+
+use rtchallenge::camera::*;
+use rtchallenge::canvas::*;
+use rtchallenge::color::*;
+use rtchallenge::geometry::*;
+use rtchallenge::light::*;
+use rtchallenge::material::*;
+use rtchallenge::obj::*;
+use rtchallenge::object::*;
+use rtchallenge::ppm::*;
+use rtchallenge::scene::*;
+use rtchallenge::transform::*;
+use rtchallenge::tuple::*;
+
+const OCTAHEDRON: &str = "v 1 0 0
+v -1 0 0
+v 0 1 0
+v 0 -1 0
+v 0 0 1
+v 0 0 -1
+f 1 3 5
+f 5 3 2
+f 2 3 6
+f 6 3 1
+f 5 4 1
+f 2 4 5
+f 6 4 2
+f 1 4 6
+";
+
+pub fn render(hsize: usize, vsize: usize) -> Canvas {
+    let mut camera = Camera::new(hsize, vsize, std::f32::consts::FRAC_PI_3);
+    camera.set_transform(Transform::look_at(
+        point3(0., 2., -5.),
+        point3(0., 1., 0.),
+        vector3(0., 1., 0.),
+    ));
+
+    let mut scene = Scene::new();
+    scene.add_light(Light::new(point3(-10., 10., -10.), Color::WHITE));
+    scene.add_object(Object::new().geometry(Geometry::plane()));
+
+    let mut model = parse_obj(OCTAHEDRON).unwrap();
+    model.orient_outward();
+    let material = Material::new().color(Color::new(0.2, 0.4, 0.9));
+    for object in model.to_objects(material) {
+        let transform = Transform::new().rotate_y(0.5).translate(0., 1.2, 0.);
+        scene.add_object(object.transform(transform));
+    }
+    scene.build_bvh();
+
+    camera.render(scene)
+}
+
+fn main() {
+    print!("{}", canvas_to_ppm(render(400, 200)));
+}
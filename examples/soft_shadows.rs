@@ -0,0 +1,55 @@
+//! A cube on a floor, lit by a square area light whose shadow fades out at
+//! its edges.
+//!
+//! Run with `cargo run --example soft_shadows > soft_shadows.ppm`.
+
+use rtchallenge::camera::*;
+use rtchallenge::canvas::*;
+use rtchallenge::color::*;
+use rtchallenge::geometry::*;
+use rtchallenge::light::*;
+use rtchallenge::material::*;
+use rtchallenge::object::*;
+use rtchallenge::ppm::*;
+use rtchallenge::scene::*;
+use rtchallenge::transform::*;
+use rtchallenge::tuple::*;
+
+pub fn render(hsize: usize, vsize: usize) -> Canvas {
+    let mut camera = Camera::new(hsize, vsize, std::f32::consts::FRAC_PI_3);
+    camera.set_transform(Transform::look_at(
+        point3(-3., 4., -6.),
+        point3(0., 0.5, 0.),
+        vector3(0., 1., 0.),
+    ));
+
+    let mut scene = Scene::new();
+    // A 2×2 panel above and behind the cube, sampled in a 4×4 grid.
+    scene.add_light(Light::area(
+        point3(1., 4., 1.),
+        vector3(2., 0., 0.),
+        4,
+        vector3(0., 0., 2.),
+        4,
+        Color::WHITE,
+    ));
+    scene.add_object(
+        Object::new().geometry(Geometry::plane()).material(
+            Material::new()
+                .color(Color::new(0.9, 0.9, 0.8))
+                .specular(0.),
+        ),
+    );
+    scene.add_object(
+        Object::new()
+            .geometry(Geometry::cube())
+            .transform(Transform::new().translate(0., 0.5, 0.).scale(0.5, 0.5, 0.5))
+            .material(Material::new().color(Color::new(0.8, 0.2, 0.1))),
+    );
+
+    camera.render(scene)
+}
+
+fn main() {
+    print!("{}", canvas_to_ppm(render(400, 200)));
+}
@@ -0,0 +1,51 @@
+/// An angle, constructed from either degrees or radians so that the two
+/// can't be mixed up.
+///
+/// Functions taking an angle accept `impl Into<Angle>`, and bare numbers are
+/// taken to be radians.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct Angle {
+    radians: f32,
+}
+
+impl Angle {
+    pub fn radians(radians: f32) -> Self {
+        Angle { radians }
+    }
+
+    pub fn degrees(degrees: f32) -> Self {
+        Angle {
+            radians: degrees.to_radians(),
+        }
+    }
+
+    pub fn to_radians(self) -> f32 {
+        self.radians
+    }
+
+    pub fn to_degrees(self) -> f32 {
+        self.radians.to_degrees()
+    }
+}
+
+impl From<f32> for Angle {
+    fn from(radians: f32) -> Self {
+        Angle::radians(radians)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn converting_between_degrees_and_radians() {
+        assert_eq!(Angle::degrees(180.).to_radians(), std::f32::consts::PI);
+        assert_approx_eq!(
+            Angle::radians(std::f32::consts::FRAC_PI_4).to_degrees(),
+            45.
+        );
+        assert_eq!(Angle::from(0.5), Angle::radians(0.5));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::angle::*;
+
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+/// The shape of a camera's aperture, which is the shape that out of focus
+/// highlights take. Both shapes have a circumradius of 1.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Aperture {
+    /// A round aperture.
+    Circle,
+    /// A regular polygon, as formed by an iris with straight blades, with its
+    /// first corner at the given rotation from the +x axis.
+    Polygon { blades: usize, rotation: Angle },
+}
+
+impl Aperture {
+    pub fn polygon(blades: usize, rotation: impl Into<Angle>) -> Self {
+        assert!(blades >= 3, "an aperture needs at least 3 blades");
+        Aperture::Polygon {
+            blades,
+            rotation: rotation.into(),
+        }
+    }
+
+    /// Maps a point in the unit square to a point in the aperture, such that
+    /// uniformly distributed points in the square are uniformly distributed
+    /// over the aperture.
+    pub fn sample(&self, u: f32, v: f32) -> (f32, f32) {
+        match *self {
+            Aperture::Circle => concentric_disc(u, v),
+            Aperture::Polygon { blades, rotation } => {
+                // u picks one of the equal triangular wedges between the
+                // center and an edge, and then a position along the edge; v
+                // picks the distance from the center, weighted by the width
+                // of the wedge there.
+                let u = u * blades as f32;
+                let wedge = (u.floor() as usize).min(blades - 1);
+                let along = u - wedge as f32;
+                let (x0, y0) = corner(blades, rotation, wedge);
+                let (x1, y1) = corner(blades, rotation, wedge + 1);
+                let r = v.sqrt();
+                (
+                    r * (x1 - x0).mul_add(along, x0),
+                    r * (y1 - y0).mul_add(along, y0),
+                )
+            }
+        }
+    }
+
+    /// Returns true if the point is inside the aperture (or on its edge).
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        const EPSILON: f32 = 1e-5;
+        match *self {
+            Aperture::Circle => x.mul_add(x, y * y) <= 1. + EPSILON,
+            Aperture::Polygon { blades, rotation } => {
+                // The point must be within the apothem of every edge.
+                let apothem = (PI / blades as f32).cos();
+                (0..blades).all(|i| {
+                    let angle = rotation.to_radians() + (i as f32 + 0.5) * 2. * PI / blades as f32;
+                    x.mul_add(angle.cos(), y * angle.sin()) <= apothem + EPSILON
+                })
+            }
+        }
+    }
+}
+
+/// Returns the i-th corner of a regular polygon with a circumradius of 1.
+fn corner(blades: usize, rotation: Angle, i: usize) -> (f32, f32) {
+    let angle = rotation.to_radians() + i as f32 * 2. * PI / blades as f32;
+    (angle.cos(), angle.sin())
+}
+
+/// Shirley and Chiu's concentric mapping from the unit square to the unit
+/// disc, which keeps nearby points nearby.
+fn concentric_disc(u: f32, v: f32) -> (f32, f32) {
+    let a = 2. * u - 1.;
+    let b = 2. * v - 1.;
+    if a == 0. && b == 0. {
+        return (0., 0.);
+    }
+
+    let (r, phi) = if a.abs() > b.abs() {
+        (a, FRAC_PI_4 * (b / a))
+    } else {
+        (b, FRAC_PI_2 - FRAC_PI_4 * (a / b))
+    };
+    (r * phi.cos(), r * phi.sin())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn samples(aperture: Aperture, n: usize) -> Vec<(f32, f32)> {
+        let mut rng = SmallRng::seed_from_u64(0);
+        (0..n)
+            .map(|_| aperture.sample(rng.gen(), rng.gen()))
+            .collect()
+    }
+
+    #[test]
+    fn polygon_samples_lie_inside_the_polygon() {
+        let examples = [
+            Aperture::polygon(3, 0.),
+            Aperture::polygon(5, Angle::degrees(18.)),
+            Aperture::polygon(6, Angle::degrees(-40.)),
+            Aperture::polygon(9, 1.),
+        ];
+        for aperture in examples {
+            for (x, y) in samples(aperture, 2000) {
+                assert!(aperture.contains(x, y), "{:?} {} {}", aperture, x, y);
+            }
+        }
+
+        // The corners of the unit square map to corners of the polygon.
+        let square = Aperture::polygon(4, Angle::degrees(45.));
+        let (x, y) = square.sample(0., 1.);
+        assert_approx_eq!(x, 0.5f32.sqrt());
+        assert_approx_eq!(y, 0.5f32.sqrt());
+        assert!(!square.contains(0.8, 0.));
+    }
+
+    #[test]
+    fn polygon_samples_cover_the_polygon_uniformly() {
+        // Bucket samples in a grid over the bounding square, and compare the
+        // count in each cell that lies wholly inside the hexagon with the
+        // count expected from its share of the area.
+        let aperture = Aperture::polygon(6, Angle::degrees(10.));
+        let n = 200_000;
+        let grid = 10;
+        let cell = 2. / grid as f32;
+        let mut counts = vec![0; grid * grid];
+        for (x, y) in samples(aperture, n) {
+            let i = (((x + 1.) / cell) as usize).min(grid - 1);
+            let j = (((y + 1.) / cell) as usize).min(grid - 1);
+            counts[j * grid + i] += 1;
+        }
+
+        let area = 1.5 * 3f32.sqrt();
+        let expected = n as f32 * cell * cell / area;
+        let mut chi_square = 0.;
+        let mut cells = 0;
+        for j in 0..grid {
+            for i in 0..grid {
+                let x0 = i as f32 * cell - 1.;
+                let y0 = j as f32 * cell - 1.;
+                let inside = [(0., 0.), (cell, 0.), (0., cell), (cell, cell)]
+                    .iter()
+                    .all(|&(dx, dy)| aperture.contains(x0 + dx, y0 + dy));
+                if inside {
+                    let delta = counts[j * grid + i] as f32 - expected;
+                    chi_square += delta * delta / expected;
+                    cells += 1;
+                }
+            }
+        }
+
+        // With around 40 degrees of freedom, the statistic's mean is 40 and
+        // its standard deviation about 9.
+        assert!(cells > 30);
+        assert!(
+            chi_square < 2. * cells as f32,
+            "{} over {} cells",
+            chi_square,
+            cells
+        );
+    }
+
+    #[test]
+    fn circle_samples_use_the_concentric_mapping() {
+        assert_eq!(Aperture::Circle.sample(0.5, 0.5), (0., 0.));
+        let (x, y) = Aperture::Circle.sample(1., 0.5);
+        assert_approx_eq!(x, 1.);
+        assert_approx_eq!(y, 0.);
+        let (x, y) = Aperture::Circle.sample(0.5, 1.);
+        assert_approx_eq!(x, 0.);
+        assert_approx_eq!(y, 1.);
+
+        for (x, y) in samples(Aperture::Circle, 2000) {
+            assert!(Aperture::Circle.contains(x, y));
+        }
+    }
+}
@@ -5,6 +5,7 @@ use rtchallenge::geometry::*;
 use rtchallenge::ppm::*;
 use rtchallenge::ray::*;
 use rtchallenge::tuple::*;
+use std::io;
 
 fn main() {
     let mut canvas = Canvas::new(1000, 1000);
@@ -32,5 +33,5 @@ fn main() {
         }
     }
 
-    print!("{}", canvas_to_ppm(canvas));
+    write_ppm(&canvas, &mut io::stdout().lock()).expect("failed to write the image");
 }
@@ -4,6 +4,7 @@ use rtchallenge::color::*;
 use rtchallenge::ppm::*;
 use rtchallenge::transform::*;
 use rtchallenge::tuple::*;
+use std::io;
 
 fn main() {
     let mut canvas = Canvas::new(1000, 1000);
@@ -23,5 +24,5 @@ fn main() {
         canvas.set_color(x, y + 1, color);
     }
 
-    print!("{}", canvas_to_ppm(canvas));
+    write_ppm(&canvas, &mut io::stdout().lock()).expect("failed to write the image");
 }
@@ -1,22 +1,23 @@
 extern crate rtchallenge;
-use rtchallenge::camera::*;
+use rtchallenge::cli::*;
 use rtchallenge::color::*;
 use rtchallenge::geometry::*;
 use rtchallenge::light::*;
 use rtchallenge::material::*;
 use rtchallenge::object::*;
-use rtchallenge::ppm::*;
 use rtchallenge::scene::*;
 use rtchallenge::transform::*;
 use rtchallenge::tuple::*;
 
 fn main() {
+    let cli = Cli::from_env(&[]);
+
     let mut scene = Scene::new();
 
     // ======================================================
     // the camera
     // ======================================================
-    let mut camera = Camera::new(1000, 1000, 0.785);
+    let mut camera = cli.camera(1000, 1000, 0.785);
     camera.set_transform(Transform::look_at(
         point3(-6., 6., -10.),
         point3(6., 0., 6.),
@@ -249,6 +250,7 @@ fn main() {
     // ======================================================
     // render the scene
     // ======================================================
-    let canvas = camera.render(scene);
-    print!("{}", canvas_to_ppm(canvas));
+    let (canvas, metadata) = cli.render(&camera, &scene);
+    cli.write(&canvas, &metadata)
+        .expect("failed to write the image");
 }
@@ -1,17 +1,17 @@
 extern crate rtchallenge;
-use rtchallenge::camera::*;
+use rtchallenge::cli::*;
 use rtchallenge::color::*;
 use rtchallenge::geometry::*;
 use rtchallenge::light::*;
 use rtchallenge::material::*;
 use rtchallenge::object::*;
-use rtchallenge::ppm::*;
 use rtchallenge::scene::*;
 use rtchallenge::transform::*;
 use rtchallenge::tuple::*;
 
 fn main() {
-    let mut camera = Camera::new(1000, 500, std::f32::consts::FRAC_PI_3);
+    let cli = Cli::from_env(&[]);
+    let mut camera = cli.camera(1000, 500, std::f32::consts::FRAC_PI_3);
     camera.set_transform(Transform::look_at(
         point3(0., 7.0, 0.),
         point3(0., 0., 0.),
@@ -25,7 +25,7 @@ fn main() {
     scene.add_object(
         Object::new()
             .geometry(Geometry::plane())
-            .material(floor_material),
+            .material(floor_material.clone()),
     );
 
     scene.add_object(
@@ -36,7 +36,7 @@ fn main() {
                     .translate(0., 0., 1.5)
                     .rotate_x(std::f32::consts::FRAC_PI_2),
             )
-            .material(floor_material),
+            .material(floor_material.clone()),
     );
     scene.add_object(
         Object::new()
@@ -46,7 +46,7 @@ fn main() {
                     .translate(0., 0., -1.5)
                     .rotate_x(-std::f32::consts::FRAC_PI_2),
             )
-            .material(floor_material),
+            .material(floor_material.clone()),
     );
     scene.add_object(
         Object::new()
@@ -57,7 +57,7 @@ fn main() {
                     .rotate_y(-std::f32::consts::FRAC_PI_4)
                     .rotate_x(std::f32::consts::FRAC_PI_2),
             )
-            .material(floor_material),
+            .material(floor_material.clone()),
     );
     scene.add_object(
         Object::new()
@@ -68,7 +68,7 @@ fn main() {
                     .rotate_y(std::f32::consts::FRAC_PI_4)
                     .rotate_x(-std::f32::consts::FRAC_PI_2),
             )
-            .material(floor_material),
+            .material(floor_material.clone()),
     );
     scene.add_object(
         Object::new()
@@ -79,7 +79,7 @@ fn main() {
                     .rotate_y(std::f32::consts::FRAC_PI_4)
                     .rotate_x(std::f32::consts::FRAC_PI_2),
             )
-            .material(floor_material),
+            .material(floor_material.clone()),
     );
     scene.add_object(
         Object::new()
@@ -142,6 +142,7 @@ fn main() {
             ),
     );
 
-    let canvas = camera.render(scene);
-    print!("{}", canvas_to_ppm(canvas));
+    let (canvas, metadata) = cli.render(&camera, &scene);
+    cli.write(&canvas, &metadata)
+        .expect("failed to write the image");
 }
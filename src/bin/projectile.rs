@@ -3,6 +3,7 @@ use rtchallenge::canvas::*;
 use rtchallenge::color::*;
 use rtchallenge::ppm::*;
 use rtchallenge::tuple::*;
+use std::io;
 
 struct Projectile {
     position: Tuple4,
@@ -40,5 +41,5 @@ fn main() {
         projectile = tick(&environment, &projectile);
     }
 
-    print!("{}", canvas_to_ppm(canvas));
+    write_ppm(&canvas, &mut io::stdout().lock()).expect("failed to write the image");
 }
@@ -1,18 +1,18 @@
 extern crate rtchallenge;
-use rtchallenge::camera::*;
+use rtchallenge::cli::*;
 use rtchallenge::color::*;
 use rtchallenge::geometry::*;
 use rtchallenge::light::*;
 use rtchallenge::material::*;
 use rtchallenge::object::*;
-use rtchallenge::ppm::*;
 use rtchallenge::scene::*;
 use rtchallenge::texture::*;
 use rtchallenge::transform::*;
 use rtchallenge::tuple::*;
 
 fn main() {
-    let mut camera = Camera::new(1000, 500, std::f32::consts::FRAC_PI_3);
+    let cli = Cli::from_env(&[]);
+    let mut camera = cli.camera(1000, 500, std::f32::consts::FRAC_PI_3);
     camera.set_transform(Transform::look_at(
         point3(0., 1.5, -5.),
         point3(0., 1., 0.),
@@ -31,7 +31,7 @@ fn main() {
         Object::new()
             .geometry(Geometry::cube())
             .transform(Transform::new().scale(10., 0.01, 10.))
-            .material(floor_material),
+            .material(floor_material.clone()),
     );
 
     scene.add_object(
@@ -44,7 +44,7 @@ fn main() {
                     .rotate_x(std::f32::consts::FRAC_PI_2)
                     .scale(10., 0.01, 10.),
             )
-            .material(floor_material),
+            .material(floor_material.clone()),
     );
 
     scene.add_object(
@@ -148,6 +148,7 @@ fn main() {
             ),
     );
 
-    let canvas = camera.render(scene);
-    print!("{}", canvas_to_ppm(canvas));
+    let (canvas, metadata) = cli.render(&camera, &scene);
+    cli.write(&canvas, &metadata)
+        .expect("failed to write the image");
 }
@@ -0,0 +1,37 @@
+extern crate rtchallenge;
+use rtchallenge::cli::*;
+use rtchallenge::scene_file::*;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::process;
+
+/// Renders a scene file (see `parse_scene`). The camera settings given on
+/// the command line override the file's.
+fn main() {
+    let mut cli = Cli::from_env(&["SCENE_FILE"]);
+    let path = cli.args[0].clone();
+    let source = std::fs::read_to_string(&path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+    });
+    let (camera, scene) = parse_scene(&source).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+    });
+
+    let mut overridden = cli.camera(camera.hsize, camera.vsize, camera.fov);
+    overridden.set_transform(camera.transform());
+    cli.scene = Some(path);
+    let (canvas, mut metadata) = cli.render(&overridden, &scene);
+
+    // Identify the version of the file too, since it may be edited between
+    // renders.
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    metadata.insert(
+        "scene-hash".to_string(),
+        format!("{:016x}", hasher.finish()),
+    );
+    cli.write(&canvas, &metadata)
+        .expect("failed to write the image");
+}
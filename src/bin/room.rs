@@ -1,18 +1,18 @@
 extern crate rtchallenge;
-use rtchallenge::camera::*;
+use rtchallenge::cli::*;
 use rtchallenge::color::*;
 use rtchallenge::geometry::*;
 use rtchallenge::light::*;
 use rtchallenge::material::*;
 use rtchallenge::object::*;
-use rtchallenge::ppm::*;
 use rtchallenge::scene::*;
 use rtchallenge::texture::*;
 use rtchallenge::transform::*;
 use rtchallenge::tuple::*;
 
 fn main() {
-    let mut camera = Camera::new(1000, 500, std::f32::consts::FRAC_PI_3);
+    let cli = Cli::from_env(&[]);
+    let mut camera = cli.camera(1000, 500, std::f32::consts::FRAC_PI_3);
     camera.set_transform(Transform::look_at(
         point3(-4.8, 0.8, -4.8),
         point3(-2., -3., -2.),
@@ -70,7 +70,7 @@ fn main() {
         Object::new()
             .geometry(Geometry::cube())
             .transform(Transform::new().translate(0., -5.0, 0.).scale(3., 0.3, 2.))
-            .material(table_material),
+            .material(table_material.clone()),
     );
     scene.add_object(
         Object::new()
@@ -80,7 +80,7 @@ fn main() {
                     .translate(-2.8, -7.5, -1.8)
                     .scale(0.2, 2.7, 0.2),
             )
-            .material(table_material),
+            .material(table_material.clone()),
     );
     scene.add_object(
         Object::new()
@@ -90,7 +90,7 @@ fn main() {
                     .translate(2.8, -7.5, -1.8)
                     .scale(0.2, 2.7, 0.2),
             )
-            .material(table_material),
+            .material(table_material.clone()),
     );
     scene.add_object(
         Object::new()
@@ -100,7 +100,7 @@ fn main() {
                     .translate(-2.8, -7.5, 1.8)
                     .scale(0.2, 2.7, 0.2),
             )
-            .material(table_material),
+            .material(table_material.clone()),
     );
     scene.add_object(
         Object::new()
@@ -131,6 +131,7 @@ fn main() {
             ),
     );
 
-    let canvas = camera.render(scene);
-    print!("{}", canvas_to_ppm(canvas));
+    let (canvas, metadata) = cli.render(&camera, &scene);
+    cli.write(&canvas, &metadata)
+        .expect("failed to write the image");
 }
@@ -1,18 +1,18 @@
 extern crate rtchallenge;
-use rtchallenge::camera::*;
+use rtchallenge::cli::*;
 use rtchallenge::color::*;
 use rtchallenge::geometry::*;
 use rtchallenge::light::*;
 use rtchallenge::material::*;
 use rtchallenge::object::*;
-use rtchallenge::ppm::*;
 use rtchallenge::scene::*;
 use rtchallenge::texture::*;
 use rtchallenge::transform::*;
 use rtchallenge::tuple::*;
 
 fn main() {
-    let mut camera = Camera::new(1000, 500, std::f32::consts::FRAC_PI_3);
+    let cli = Cli::from_env(&[]);
+    let mut camera = cli.camera(1000, 500, std::f32::consts::FRAC_PI_3);
     camera.set_transform(Transform::look_at(
         point3(-0.2, 2.8, -1.4),
         point3(0.3, 0.0, 1.),
@@ -41,7 +41,7 @@ fn main() {
                     .translate(0., 0., 1.5)
                     .rotate_x(std::f32::consts::FRAC_PI_2),
             )
-            .material(wall_material),
+            .material(wall_material.clone()),
     );
     scene.add_object(
         Object::new()
@@ -51,7 +51,7 @@ fn main() {
                     .translate(0., 0., -1.5)
                     .rotate_x(-std::f32::consts::FRAC_PI_2),
             )
-            .material(wall_material),
+            .material(wall_material.clone()),
     );
     scene.add_object(
         Object::new()
@@ -62,7 +62,7 @@ fn main() {
                     .rotate_y(-std::f32::consts::FRAC_PI_4)
                     .rotate_x(std::f32::consts::FRAC_PI_2),
             )
-            .material(wall_material),
+            .material(wall_material.clone()),
     );
     scene.add_object(
         Object::new()
@@ -73,7 +73,7 @@ fn main() {
                     .rotate_y(std::f32::consts::FRAC_PI_4)
                     .rotate_x(-std::f32::consts::FRAC_PI_2),
             )
-            .material(wall_material),
+            .material(wall_material.clone()),
     );
     scene.add_object(
         Object::new()
@@ -84,7 +84,7 @@ fn main() {
                     .rotate_y(std::f32::consts::FRAC_PI_4)
                     .rotate_x(std::f32::consts::FRAC_PI_2),
             )
-            .material(wall_material),
+            .material(wall_material.clone()),
     );
     scene.add_object(
         Object::new()
@@ -160,6 +160,7 @@ fn main() {
             ),
     );
 
-    let canvas = camera.render(scene);
-    print!("{}", canvas_to_ppm(canvas));
+    let (canvas, metadata) = cli.render(&camera, &scene);
+    cli.write(&canvas, &metadata)
+        .expect("failed to write the image");
 }
@@ -0,0 +1,298 @@
+use crate::matrix::*;
+use crate::ray::*;
+use crate::tuple::*;
+
+/// An axis-aligned bounding box. Either side may be infinite, for shapes
+/// which extend forever.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bounds3 {
+    pub min: Tuple4,
+    pub max: Tuple4,
+}
+
+impl Bounds3 {
+    /// Constructs the box with the given opposite corners.
+    pub fn new(min: Tuple4, max: Tuple4) -> Self {
+        Bounds3 { min, max }
+    }
+
+    /// Constructs the box enclosing all of space.
+    pub fn infinite() -> Self {
+        let inf = f32::INFINITY;
+        Bounds3::new(point3(-inf, -inf, -inf), point3(inf, inf, inf))
+    }
+
+    /// Constructs the smallest box enclosing all of the points.
+    pub fn from_points(points: &[Tuple4]) -> Self {
+        let inf = f32::INFINITY;
+        let empty = Bounds3::new(point3(inf, inf, inf), point3(-inf, -inf, -inf));
+        points.iter().fold(empty, |bounds, &point| {
+            bounds.union(Bounds3::new(point, point))
+        })
+    }
+
+    /// Whether every side of the box is finite.
+    pub fn is_finite(&self) -> bool {
+        [self.min, self.max]
+            .iter()
+            .all(|p| p.x.is_finite() && p.y.is_finite() && p.z.is_finite())
+    }
+
+    /// Returns the smallest box enclosing both boxes.
+    pub fn union(&self, other: Bounds3) -> Bounds3 {
+        Bounds3::new(
+            point3(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            point3(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        )
+    }
+
+    /// Returns the point at the center of the box.
+    pub fn center(&self) -> Tuple4 {
+        point3(
+            (self.min.x + self.max.x) / 2.,
+            (self.min.y + self.max.y) / 2.,
+            (self.min.z + self.max.z) / 2.,
+        )
+    }
+
+    /// Returns the box grown by the given distance on every side.
+    pub fn pad(&self, distance: f32) -> Bounds3 {
+        let d = vector3(distance, distance, distance);
+        Bounds3::new(self.min - d, self.max + d)
+    }
+
+    /// Whether the point lies inside the box (or on its surface).
+    pub fn contains(&self, point: Tuple4) -> bool {
+        (self.min.x..=self.max.x).contains(&point.x)
+            && (self.min.y..=self.max.y).contains(&point.y)
+            && (self.min.z..=self.max.z).contains(&point.z)
+    }
+
+    /// Returns the box enclosing this one after it's transformed by the
+    /// matrix, found by transforming each of its corners.
+    ///
+    /// The corners of a box which isn't finite can't be transformed, so
+    /// instead each side is found by adding up the extremes of each term of
+    /// the transformed coordinates. That keeps a translated plane flat, for
+    /// example, while a tilted one fills all of space.
+    pub fn transform(&self, matrix: Matrix4) -> Bounds3 {
+        if !self.is_finite() {
+            return self.transform_terms(matrix);
+        }
+
+        let (a, b) = (self.min, self.max);
+        let corners = [
+            point3(a.x, a.y, a.z),
+            point3(a.x, a.y, b.z),
+            point3(a.x, b.y, a.z),
+            point3(a.x, b.y, b.z),
+            point3(b.x, a.y, a.z),
+            point3(b.x, a.y, b.z),
+            point3(b.x, b.y, a.z),
+            point3(b.x, b.y, b.z),
+        ]
+        .map(|corner| matrix * corner);
+        Bounds3::from_points(&corners)
+    }
+
+    fn transform_terms(&self, m: Matrix4) -> Bounds3 {
+        let (a, b) = (self.min, self.max);
+        let side = |row: [f32; 4]| {
+            let mut low = row[3];
+            let mut high = row[3];
+            for (&coefficient, (min, max)) in row.iter().zip([(a.x, b.x), (a.y, b.y), (a.z, b.z)]) {
+                // Skipped when zero, since 0 * inf is NaN.
+                if coefficient != 0. {
+                    let (e, f) = (coefficient * min, coefficient * max);
+                    low += e.min(f);
+                    high += e.max(f);
+                }
+            }
+            (low, high)
+        };
+        let (x, y, z) = (
+            side([m.x0, m.x1, m.x2, m.x3]),
+            side([m.y0, m.y1, m.y2, m.y3]),
+            side([m.z0, m.z1, m.z2, m.z3]),
+        );
+        Bounds3::new(point3(x.0, y.0, z.0), point3(x.1, y.1, z.1))
+    }
+
+    /// Returns the range of t over which the ray lies inside the box (if
+    /// it passes through it at all).
+    pub fn intersect(&self, ray: Ray) -> Option<(f32, f32)> {
+        let mut tmin = -f32::INFINITY;
+        let mut tmax = f32::INFINITY;
+        let axes = [
+            (ray.origin.x, ray.direction.x, self.min.x, self.max.x),
+            (ray.origin.y, ray.direction.y, self.min.y, self.max.y),
+            (ray.origin.z, ray.direction.z, self.min.z, self.max.z),
+        ];
+        for &(origin, direction, min, max) in &axes {
+            if direction == 0. {
+                // Parallel to the slab, so the ray is always or never in it.
+                if origin < min || origin > max {
+                    return None;
+                }
+                continue;
+            }
+
+            let t0 = (min - origin) / direction;
+            let t1 = (max - origin) / direction;
+            tmin = tmin.max(t0.min(t1));
+            tmax = tmax.min(t0.max(t1));
+        }
+
+        if tmin <= tmax {
+            Some((tmin, tmax))
+        } else {
+            None
+        }
+    }
+
+    /// Whether the ray passes through the box ahead of its origin (or
+    /// starts inside it).
+    pub fn intersects_ray(&self, ray: Ray) -> bool {
+        self.intersect(ray).is_some_and(|(_, tmax)| tmax >= 0.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn the_union_of_two_boxes() {
+        let a = Bounds3::new(point3(-1., -2., 3.), point3(4., 5., 6.));
+        let b = Bounds3::new(point3(-5., 1., 0.), point3(2., 8., 4.));
+        assert_eq!(
+            a.union(b),
+            Bounds3::new(point3(-5., -2., 0.), point3(4., 8., 6.))
+        );
+    }
+
+    #[test]
+    fn the_box_enclosing_some_points() {
+        let b = Bounds3::from_points(&[point3(1., 0., -1.), point3(-2., 3., 0.)]);
+        assert_eq!(b, Bounds3::new(point3(-2., 0., -1.), point3(1., 3., 0.)));
+    }
+
+    #[test]
+    fn transforming_a_box() {
+        let b = Bounds3::new(point3(-1., -1., -1.), point3(1., 1., 1.));
+        let m = Transform::new().translate(1., 2., 3.).scale(2., 1., 1.);
+        assert_eq!(
+            b.transform(m.local_to_world),
+            Bounds3::new(point3(-1., 1., 2.), point3(3., 3., 4.))
+        );
+        assert_eq!(
+            Bounds3::infinite().transform(m.local_to_world),
+            Bounds3::infinite()
+        );
+    }
+
+    #[test]
+    fn transforming_a_rotated_cube() {
+        let b = Bounds3::new(point3(-1., -1., -1.), point3(1., 1., 1.));
+        let m = Transform::new().rotate_y(std::f32::consts::FRAC_PI_4);
+        let world = b.transform(m.local_to_world);
+        let sqrt_2 = std::f32::consts::SQRT_2;
+        assert_approx_eq!(world.min.x, -sqrt_2);
+        assert_approx_eq!(world.max.x, sqrt_2);
+        assert_approx_eq!(world.min.y, -1.);
+        assert_approx_eq!(world.max.y, 1.);
+        assert_approx_eq!(world.min.z, -sqrt_2);
+        assert_approx_eq!(world.max.z, sqrt_2);
+    }
+
+    #[test]
+    fn transforming_boxes_containing_infinities() {
+        let inf = f32::INFINITY;
+        let plane = Bounds3::new(point3(-inf, 0., -inf), point3(inf, 0., inf));
+
+        let m = Transform::new().translate(1., 2., 3.).scale(2., 2., 2.);
+        assert_eq!(
+            plane.transform(m.local_to_world),
+            Bounds3::new(point3(-inf, 2., -inf), point3(inf, 2., inf))
+        );
+
+        // Swapping x and y makes it flat in x instead. (A rotation by a
+        // quarter turn isn't exact, so tilts it slightly.)
+        let swap = matrix4(
+            0., 1., 0., 0., 1., 0., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.,
+        );
+        assert_eq!(
+            plane.transform(swap),
+            Bounds3::new(point3(0., -inf, -inf), point3(0., inf, inf))
+        );
+
+        let m = Transform::new().rotate_z(0.5);
+        assert_eq!(plane.transform(m.local_to_world), Bounds3::infinite());
+    }
+
+    #[test]
+    fn a_box_contains_the_points_inside_and_on_it() {
+        let b = Bounds3::new(point3(-1., 0., 2.), point3(1., 3., 4.));
+        assert!(b.contains(point3(0., 1., 3.)));
+        assert!(b.contains(point3(1., 3., 2.)));
+        assert!(!b.contains(point3(0., -0.5, 3.)));
+        assert!(!b.contains(point3(2., 1., 3.)));
+
+        assert!(Bounds3::infinite().contains(point3(1e30, -1e30, 0.)));
+    }
+
+    #[test]
+    fn intersecting_a_ray_with_a_box() {
+        let b = Bounds3::new(point3(-1., -1., -1.), point3(1., 1., 1.));
+        let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
+        assert_eq!(b.intersect(r), Some((4., 6.)));
+        let r = ray(point3(2., 0., -5.), vector3(0., 0., 1.));
+        assert_eq!(b.intersect(r), None);
+    }
+
+    #[test]
+    fn rays_which_hit_and_miss_a_box() {
+        let b = Bounds3::new(point3(-1., -1., -1.), point3(1., 1., 1.));
+        let examples = vec![
+            (point3(5., 0.5, 0.), vector3(-1., 0., 0.), true),
+            (point3(0.5, 0., -5.), vector3(0.1, 0.1, 1.), true),
+            (point3(0., 0., 0.), vector3(0., 1., 0.), true),
+            (point3(-2., 0., -2.), vector3(2., 4., 6.), false),
+            (point3(2., 2., 2.), vector3(1., 1., 1.), false),
+            // Pointing away from the box.
+            (point3(0., 0., -5.), vector3(0., 0., -1.), false),
+            // Parallel to the y and z slabs, and inside or outside them.
+            (point3(-5., 0.5, 0.5), vector3(1., 0., 0.), true),
+            (point3(-5., 1.5, 0.5), vector3(1., 0., 0.), false),
+            (point3(-5., 1., 1.), vector3(1., 0., 0.), true),
+        ];
+        for (origin, direction, expected) in examples {
+            let r = ray(origin, direction);
+            assert_eq!(b.intersects_ray(r), expected, "{:?}", r);
+        }
+    }
+
+    #[test]
+    fn rays_against_boxes_containing_infinities() {
+        let inf = f32::INFINITY;
+        let plane = Bounds3::new(point3(-inf, 0., -inf), point3(inf, 0., inf));
+        assert!(plane.intersects_ray(ray(point3(100., 5., -100.), vector3(0.1, -1., 0.))));
+        assert!(!plane.intersects_ray(ray(point3(0., 5., 0.), vector3(0., 1., 0.))));
+        assert!(!plane.intersects_ray(ray(point3(0., 5., 0.), vector3(1., 0., 0.))));
+        assert!(plane.intersects_ray(ray(point3(0., 0., 0.), vector3(1., 0., 0.))));
+
+        let r = ray(point3(0., 0., 0.), vector3(0., 0., 1.));
+        assert_eq!(Bounds3::infinite().intersect(r), Some((-inf, inf)));
+        assert!(Bounds3::infinite().intersects_ray(r));
+    }
+}
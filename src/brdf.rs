@@ -0,0 +1,545 @@
+use crate::color::*;
+use crate::rng::*;
+use crate::tuple::*;
+
+use std::f32::consts::PI;
+
+/// A bidirectional reflectance distribution function, which says how much of
+/// the light arriving at a surface from one direction leaves it in another.
+///
+/// Reflectances are scaled so that a white Lambertian surface lit head on
+/// reflects all of the light (the convention `Material::lighting` has always
+/// used), which is π times the textbook BRDF.
+pub trait Brdf {
+    /// Returns the fraction of the light arriving along `lightv` which is
+    /// reflected toward the eye along `eyev`, including the cosine of the
+    /// angle of incidence. All three vectors are normalized and point away
+    /// from the surface; light from behind the surface isn't reflected.
+    fn evaluate(&self, lightv: Tuple4, eyev: Tuple4, normalv: Tuple4) -> Color;
+
+    /// Chooses a direction from which to gather the light reflected toward
+    /// the eye, returning it with the weight (the reflectance divided by the
+    /// probability of choosing that direction) to apply to the light found.
+    ///
+    /// Directions are chosen in proportion to the cosine of the angle from
+    /// the normal, which suits diffuse surfaces best.
+    fn sample<R: Rng>(&self, rng: &mut R, eyev: Tuple4, normalv: Tuple4) -> (Tuple4, Color) {
+        let lightv = cosine_weighted_direction(rng, normalv);
+        let cos = lightv.dot(normalv);
+        if cos <= 0. {
+            return (lightv, Color::BLACK);
+        }
+        (lightv, self.evaluate(lightv, eyev, normalv) * (1. / cos))
+    }
+
+    /// Evaluates the BRDF beneath a clear coat of the given (weight,
+    /// roughness), or bare if the weight is 0.
+    fn evaluate_coated(
+        self,
+        (weight, roughness): (f32, f32),
+        lightv: Tuple4,
+        eyev: Tuple4,
+        normalv: Tuple4,
+    ) -> Color
+    where
+        Self: Sized,
+    {
+        if weight > 0. {
+            ClearCoat {
+                base: self,
+                weight,
+                roughness,
+            }
+            .evaluate(lightv, eyev, normalv)
+        } else {
+            self.evaluate(lightv, eyev, normalv)
+        }
+    }
+}
+
+/// Selects the BRDF used to shade a material.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BrdfModel {
+    /// Lambertian diffuse with a Phong specular highlight, as in the book.
+    LambertPhong,
+    /// Lambertian diffuse with a Blinn-Phong specular highlight, which
+    /// (for the same shininess) is broader and stays round at grazing angles.
+    BlinnPhong,
+    /// A physically based microfacet model, with the GGX distribution and
+    /// Smith shadowing.
+    CookTorrance {
+        /// How rough the surface is, from 0 (polished) to 1.
+        roughness: f32,
+        /// How metallic the surface is, from 0 (a dielectric with a white
+        /// highlight) to 1 (a metal tinted by its color, with no diffuse).
+        metallic: f32,
+    },
+}
+
+/// Lambertian diffuse reflection plus a Phong specular highlight.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LambertPhong {
+    pub diffuse: Color,
+    pub specular: f32,
+    pub shininess: i32,
+}
+
+impl LambertPhong {
+    /// Returns the BRDF with its highlight widened by the given angle (in
+    /// radians), as for a light of that angular radius rather than a point,
+    /// and dimmed to reflect the same energy.
+    pub fn widened(self, angle: f32) -> Self {
+        let (shininess, dimming) = widened_phong(self.shininess, angle);
+        LambertPhong {
+            specular: self.specular * dimming,
+            shininess,
+            ..self
+        }
+    }
+}
+
+impl Brdf for LambertPhong {
+    fn evaluate(&self, lightv: Tuple4, eyev: Tuple4, normalv: Tuple4) -> Color {
+        // light_dot_normal represents the cosine of the angle between the light
+        // vector and the normal vector. A negative number means the light is on
+        // the other side of the surface.
+        let light_dot_normal = lightv.dot(normalv);
+        if light_dot_normal < 0. {
+            return Color::BLACK;
+        }
+        let diffuse = self.diffuse * light_dot_normal;
+
+        // reflect_dot_eye represents the cosine of the angle between the
+        // reflection vector and the eye vector. A negative number means the
+        // light reflects away from the eye.
+        let reflectv = (-lightv).reflect(normalv);
+        let reflect_dot_eye = reflectv.dot(eyev);
+        if reflect_dot_eye >= 0. {
+            let factor = reflect_dot_eye.powi(self.shininess);
+            diffuse + Color::WHITE * (self.specular * factor)
+        } else {
+            diffuse
+        }
+    }
+}
+
+/// Lambertian diffuse reflection plus a Blinn-Phong specular highlight,
+/// found from the angle between the normal and the halfway vector.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BlinnPhong {
+    pub diffuse: Color,
+    pub specular: f32,
+    pub shininess: i32,
+}
+
+impl BlinnPhong {
+    /// Returns the BRDF with its highlight widened by the given angle, like
+    /// `LambertPhong::widened`. The halfway vector turns half as far as the
+    /// light does, so the lobe widens by half the angle.
+    pub fn widened(self, angle: f32) -> Self {
+        let (shininess, dimming) = widened_phong(self.shininess, angle / 2.);
+        BlinnPhong {
+            specular: self.specular * dimming,
+            shininess,
+            ..self
+        }
+    }
+}
+
+/// Returns the shininess of a Phong lobe widened by the given angle, and how
+/// much to dim it so that it reflects the same energy. A lobe of shininess s
+/// spreads over about sqrt(2 / s) radians, since cos^s x ≈ exp(-s x² / 2).
+fn widened_phong(shininess: i32, angle: f32) -> (i32, f32) {
+    if angle <= 0. {
+        return (shininess, 1.);
+    }
+    let width = (2. / shininess.max(1) as f32).sqrt();
+    let widened = width + angle;
+    let shininess = (2. / (widened * widened)).round().max(1.) as i32;
+    (shininess, (width / widened).powi(2))
+}
+
+impl Brdf for BlinnPhong {
+    fn evaluate(&self, lightv: Tuple4, eyev: Tuple4, normalv: Tuple4) -> Color {
+        let light_dot_normal = lightv.dot(normalv);
+        if light_dot_normal < 0. {
+            return Color::BLACK;
+        }
+        let diffuse = self.diffuse * light_dot_normal;
+
+        let halfv = (lightv + eyev).normalize();
+        let half_dot_normal = halfv.dot(normalv);
+        if half_dot_normal >= 0. {
+            let factor = half_dot_normal.powi(self.shininess);
+            diffuse + Color::WHITE * (self.specular * factor)
+        } else {
+            diffuse
+        }
+    }
+}
+
+/// The Cook-Torrance microfacet model, with the GGX normal distribution,
+/// Smith's shadowing-masking term, and Schlick's approximation to Fresnel
+/// reflection.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CookTorrance {
+    pub color: Color,
+    pub diffuse: f32,
+    pub specular: f32,
+    pub roughness: f32,
+    pub metallic: f32,
+}
+
+impl CookTorrance {
+    /// The reflectance at normal incidence of a typical dielectric.
+    const DIELECTRIC_REFLECTANCE: f32 = 0.04;
+
+    /// Returns the reflectance at normal incidence, which is tinted by the
+    /// surface color for metals.
+    pub fn normal_reflectance(&self) -> Color {
+        let dielectric = Color::WHITE * Self::DIELECTRIC_REFLECTANCE;
+        dielectric * (1. - self.metallic) + self.color * self.metallic
+    }
+
+    /// The width of the GGX distribution. Squaring the roughness makes it
+    /// vary more evenly to the eye.
+    fn alpha(&self) -> f32 {
+        (self.roughness * self.roughness).max(1e-3)
+    }
+
+    /// Returns the BRDF with its highlight widened by the given angle, like
+    /// `BlinnPhong::widened`: the distribution widens by half the angle, and
+    /// is dimmed to reflect the same energy.
+    pub fn widened(self, angle: f32) -> Self {
+        if angle <= 0. {
+            return self;
+        }
+        let alpha = self.alpha();
+        let widened = (alpha + angle / 2.).min(1.);
+        CookTorrance {
+            specular: self.specular * (alpha / widened).powi(2),
+            roughness: widened.sqrt(),
+            ..self
+        }
+    }
+}
+
+impl Brdf for CookTorrance {
+    fn evaluate(&self, lightv: Tuple4, eyev: Tuple4, normalv: Tuple4) -> Color {
+        let n_dot_l = lightv.dot(normalv);
+        let n_dot_v = eyev.dot(normalv);
+        if n_dot_l <= 0. || n_dot_v <= 0. {
+            return Color::BLACK;
+        }
+
+        let halfv = (lightv + eyev).normalize();
+        let n_dot_h = halfv.dot(normalv).max(0.);
+        let v_dot_h = eyev.dot(halfv).max(0.);
+
+        let alpha2 = self.alpha() * self.alpha();
+        let d = n_dot_h * n_dot_h * (alpha2 - 1.) + 1.;
+        let distribution = alpha2 / (PI * d * d);
+
+        let smith = |cos: f32| 2. * cos / (cos + (alpha2 + (1. - alpha2) * cos * cos).sqrt());
+        let geometry = smith(n_dot_l) * smith(n_dot_v);
+
+        let f0 = self.normal_reflectance();
+        let schlick = |cos: f32| f0 + (Color::WHITE - f0) * (1. - cos).powi(5);
+
+        // The π cancels the one in the distribution, leaving a white
+        // Lambertian surface with a reflectance of 1.
+        let specular =
+            schlick(v_dot_h) * (PI * distribution * geometry / (4. * n_dot_v) * self.specular);
+
+        // The diffuse light is what isn't reflected at the surface on the way
+        // to the eye.
+        let diffuse = (Color::WHITE - schlick(n_dot_v))
+            * self.color
+            * ((1. - self.metallic) * self.diffuse * n_dot_l);
+        diffuse + specular
+    }
+}
+
+/// A thin, clear varnish over a base BRDF, as on car paint. The coat
+/// reflects a white GGX highlight, and the base is lit through it, so the
+/// base only receives (and returns) the light the coat doesn't reflect.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ClearCoat<B> {
+    pub base: B,
+    /// How much coat there is, from 0 (none) to 1.
+    pub weight: f32,
+    /// How rough the coat is, from 0 (polished) to 1.
+    pub roughness: f32,
+}
+
+impl<B> ClearCoat<B> {
+    /// Returns the fraction of the light the coat reflects at the given
+    /// cosine of the angle of incidence, by Schlick's approximation for a
+    /// varnish with a refractive index of 1.5.
+    pub fn reflectance(weight: f32, cos: f32) -> f32 {
+        let f0 = CookTorrance::DIELECTRIC_REFLECTANCE;
+        weight * (f0 + (1. - f0) * (1. - cos.max(0.)).powi(5))
+    }
+}
+
+impl<B: Brdf> Brdf for ClearCoat<B> {
+    fn evaluate(&self, lightv: Tuple4, eyev: Tuple4, normalv: Tuple4) -> Color {
+        let coat = CookTorrance {
+            color: Color::WHITE,
+            diffuse: 0.,
+            specular: self.weight,
+            roughness: self.roughness,
+            metallic: 0.,
+        };
+        let through = (1. - Self::reflectance(self.weight, lightv.dot(normalv)))
+            * (1. - Self::reflectance(self.weight, eyev.dot(normalv)));
+        coat.evaluate(lightv, eyev, normalv) + self.base.evaluate(lightv, eyev, normalv) * through
+    }
+}
+
+/// Returns a random direction in the hemisphere around the normal, chosen in
+/// proportion to the cosine of its angle from the normal.
+pub fn cosine_weighted_direction<R: Rng>(rng: &mut R, normalv: Tuple4) -> Tuple4 {
+    let r = rng.gen::<f32>().sqrt();
+    let theta = 2. * PI * rng.gen::<f32>();
+    let (x, y) = (r * theta.cos(), r * theta.sin());
+    let z = (1. - r * r).max(0.).sqrt();
+
+    // Build an orthonormal basis around the normal.
+    let helper = if normalv.x.abs() > 0.9 {
+        vector3(0., 1., 0.)
+    } else {
+        vector3(1., 0., 0.)
+    };
+    let tangent = helper.cross(normalv).normalize();
+    let bitangent = normalv.cross(tangent);
+    (tangent * x + bitangent * y + normalv * z).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn metal(roughness: f32) -> CookTorrance {
+        CookTorrance {
+            color: Color::new(0.9, 0.6, 0.3),
+            diffuse: 1.,
+            specular: 1.,
+            roughness,
+            metallic: 1.,
+        }
+    }
+
+    #[test]
+    fn cook_torrance_at_normal_incidence() {
+        // With the light and eye along the normal, D = 1 / (π α²), G = 1 and
+        // F = F0, so the reflectance is F0 / (4 α²).
+        let n = vector3(0., 0., 1.);
+        for &roughness in &[0.3, 0.5, 0.8] {
+            let brdf = metal(roughness);
+            let alpha = roughness * roughness;
+            let expected = brdf.color * (1. / (4. * alpha * alpha));
+            let c = brdf.evaluate(n, n, n);
+            assert_approx_eq!(c.r, expected.r, 1e-3 * expected.r);
+            assert_approx_eq!(c.g, expected.g, 1e-3 * expected.g);
+            assert_approx_eq!(c.b, expected.b, 1e-3 * expected.b);
+        }
+
+        // A dielectric adds the diffuse light which isn't reflected at the
+        // surface.
+        let brdf = CookTorrance {
+            metallic: 0.,
+            ..metal(0.5)
+        };
+        let c = brdf.evaluate(n, n, n);
+        assert_approx_eq!(c.r, 0.04 * 4. + 0.96 * 0.9, 1e-4);
+    }
+
+    /// Estimates the fraction of the light arriving from every direction
+    /// which is reflected toward the eye, by integrating over a fine grid of
+    /// directions (evenly spaced in the cosine of the angle from the normal,
+    /// so that each covers the same solid angle).
+    fn directional_albedo<B: Brdf>(brdf: &B, eyev: Tuple4) -> Color {
+        let steps = 400;
+        let mut total = Color::BLACK;
+        for i in 0..steps {
+            let cos = (i as f32 + 0.5) / steps as f32;
+            let sin = (1. - cos * cos).sqrt();
+            for j in 0..steps {
+                let phi = 2. * PI * (j as f32 + 0.5) / steps as f32;
+                let lightv = vector3(sin * phi.cos(), sin * phi.sin(), cos);
+                total = total + brdf.evaluate(lightv, eyev, vector3(0., 0., 1.));
+            }
+        }
+        // Each direction covers 2π / steps² steradians, and the reflectances
+        // are π times the BRDF.
+        total * (2. / (steps * steps) as f32)
+    }
+
+    #[test]
+    fn cook_torrance_never_reflects_more_light_than_arrives() {
+        for &roughness in &[0.3, 0.6, 1.] {
+            for &metallic in &[0., 1.] {
+                let brdf = CookTorrance {
+                    color: Color::WHITE,
+                    metallic,
+                    ..metal(roughness)
+                };
+                for &angle in &[0., 0.5, 1., 1.3, 1.5] {
+                    let (sin, cos) = f32::sin_cos(angle);
+                    let eyev = vector3(sin, 0., cos);
+                    let albedo = directional_albedo(&brdf, eyev);
+                    assert!(
+                        albedo.r <= 1.01,
+                        "roughness {} metallic {} angle {}: {:?}",
+                        roughness,
+                        metallic,
+                        angle,
+                        albedo
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_clear_coat_never_reflects_more_light_than_arrives() {
+        let white = LambertPhong {
+            diffuse: Color::WHITE,
+            specular: 0.,
+            shininess: 200,
+        };
+        for &roughness in &[0.05, 0.3, 1.] {
+            for &angle in &[0., 0.5, 1., 1.3, 1.5] {
+                let (sin, cos) = f32::sin_cos(angle);
+                let eyev = vector3(sin, 0., cos);
+                let coated = ClearCoat {
+                    base: white,
+                    weight: 1.,
+                    roughness,
+                };
+                let albedo = directional_albedo(&coated, eyev);
+                assert!(
+                    albedo.r <= 1.01,
+                    "roughness {} angle {}: {:?}",
+                    roughness,
+                    angle,
+                    albedo
+                );
+
+                // The coat and the base's share of the light sum to at most 1.
+                let coat = ClearCoat::<LambertPhong>::reflectance(1., cos);
+                assert!((0. ..=1.).contains(&coat));
+            }
+        }
+    }
+
+    #[test]
+    fn a_clear_coat_reflects_more_at_grazing_angles() {
+        let reflectance = |cos| ClearCoat::<LambertPhong>::reflectance(1., cos);
+        assert_approx_eq!(reflectance(1.), 0.04);
+        assert!(reflectance(0.5) > reflectance(1.));
+        assert!(reflectance(0.01) > 0.95);
+        assert_approx_eq!(reflectance(0.), 1.);
+        assert_eq!(ClearCoat::<LambertPhong>::reflectance(0., 0.), 0.);
+    }
+
+    #[test]
+    fn a_white_lambertian_surface_reflects_all_of_the_light() {
+        let brdf = LambertPhong {
+            diffuse: Color::WHITE,
+            specular: 0.,
+            shininess: 200,
+        };
+        let albedo = directional_albedo(&brdf, vector3(0., 0., 1.));
+        assert_approx_eq!(albedo.r, 1., 1e-4);
+    }
+
+    #[test]
+    fn sampling_a_lambertian_surface_weighs_every_direction_equally() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let brdf = LambertPhong {
+            diffuse: Color::new(0.5, 0.5, 0.5),
+            specular: 0.,
+            shininess: 200,
+        };
+        let n = vector3(0., 1., 0.).normalize();
+        for _ in 0..100 {
+            let (lightv, weight) = brdf.sample(&mut rng, n, n);
+            assert!(lightv.dot(n) >= 0.);
+            assert_approx_eq!(lightv.magnitude(), 1.);
+            assert_approx_eq!(weight.r, 0.5);
+        }
+    }
+
+    #[test]
+    fn light_from_behind_the_surface_is_not_reflected() {
+        let n = vector3(0., 0., 1.);
+        let eyev = n;
+        let lightv = vector3(0., 0.6, -0.8);
+        let diffuse = Color::WHITE;
+        assert_eq!(
+            LambertPhong {
+                diffuse,
+                specular: 1.,
+                shininess: 10
+            }
+            .evaluate(lightv, eyev, n),
+            Color::BLACK
+        );
+        assert_eq!(
+            BlinnPhong {
+                diffuse,
+                specular: 1.,
+                shininess: 10
+            }
+            .evaluate(lightv, eyev, n),
+            Color::BLACK
+        );
+        assert_eq!(metal(0.5).evaluate(lightv, eyev, n), Color::BLACK);
+    }
+
+    #[test]
+    fn the_blinn_phong_highlight_is_broader_than_phong() {
+        let n = vector3(0., 0., 1.);
+        let lightv = n;
+        let eyev = vector3(0.6, 0., 0.8);
+        let phong = LambertPhong {
+            diffuse: Color::BLACK,
+            specular: 1.,
+            shininess: 50,
+        };
+        let blinn = BlinnPhong {
+            diffuse: Color::BLACK,
+            specular: 1.,
+            shininess: 50,
+        };
+        assert!(blinn.evaluate(lightv, eyev, n).r > phong.evaluate(lightv, eyev, n).r);
+        // Both peak at 1 in the mirror direction.
+        assert_approx_eq!(blinn.evaluate(n, n, n).r, 1.);
+        assert_approx_eq!(phong.evaluate(n, n, n).r, 1.);
+    }
+
+    #[test]
+    fn widening_a_highlight_spreads_it_and_dims_its_peak() {
+        let n = vector3(0., 0., 1.);
+        let off_peak = vector3(0.3, 0., 1.).normalize();
+        let phong = LambertPhong {
+            diffuse: Color::BLACK,
+            specular: 1.,
+            shininess: 200,
+        };
+        assert_eq!(phong.widened(0.), phong);
+
+        let widened = phong.widened(0.2);
+        assert!(widened.shininess < phong.shininess);
+        assert!(widened.evaluate(n, n, n).r < phong.evaluate(n, n, n).r);
+        assert!(widened.evaluate(n, off_peak, n).r > phong.evaluate(n, off_peak, n).r);
+
+        let rough = metal(0.3);
+        assert_eq!(rough.widened(0.), rough);
+        assert!(rough.widened(0.2).roughness > rough.roughness);
+    }
+}
@@ -0,0 +1,280 @@
+use crate::bounds::*;
+use crate::constants::*;
+use crate::object::*;
+use crate::ray::*;
+use crate::tuple::*;
+
+/// The most objects kept together in a single leaf.
+const LEAF_SIZE: usize = 4;
+
+/// The deepest a hierarchy can be traversed. Splitting at the median keeps
+/// the depth logarithmic in the number of objects, so this is never reached.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Debug)]
+enum Node {
+    /// A leaf holding the objects at `objects[start..end]`.
+    Leaf {
+        bounds: Bounds3,
+        start: usize,
+        end: usize,
+    },
+    /// A node whose left child immediately follows it, and whose right child
+    /// is at `right`.
+    Interior { bounds: Bounds3, right: usize },
+}
+
+impl Node {
+    fn bounds(&self) -> Bounds3 {
+        match *self {
+            Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// A bounding volume hierarchy, which finds the objects a ray might hit
+/// without testing every object in the scene.
+#[derive(Clone, Debug)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    /// The objects in the leaves, with their bounds.
+    objects: Vec<(ObjectId, Bounds3)>,
+    /// Objects which extend forever, which are tested against every ray.
+    unbounded: Vec<ObjectId>,
+}
+
+impl Bvh {
+    /// Builds a hierarchy over the objects with the given (world space)
+    /// bounds.
+    pub fn new<I: IntoIterator<Item = (ObjectId, Bounds3)>>(objects: I) -> Self {
+        let mut bounded = vec![];
+        let mut unbounded = vec![];
+        for (object_id, bounds) in objects {
+            if bounds.is_finite() {
+                bounded.push((object_id, bounds.pad(BOUNDS_EPSILON)));
+            } else {
+                unbounded.push(object_id);
+            }
+        }
+
+        let mut bvh = Bvh {
+            nodes: vec![],
+            objects: Vec::with_capacity(bounded.len()),
+            unbounded,
+        };
+        if !bounded.is_empty() {
+            bvh.build(&mut bounded);
+        }
+        bvh
+    }
+
+    /// Appends the subtree holding the objects to the nodes.
+    fn build(&mut self, objects: &mut [(ObjectId, Bounds3)]) {
+        let bounds = objects
+            .iter()
+            .fold(objects[0].1, |acc, &(_, bounds)| acc.union(bounds));
+
+        if objects.len() <= LEAF_SIZE {
+            let start = self.objects.len();
+            self.objects.extend_from_slice(objects);
+            self.nodes.push(Node::Leaf {
+                bounds,
+                start,
+                end: self.objects.len(),
+            });
+            return;
+        }
+
+        // Split at the median along the axis where the objects' centers are
+        // most spread out.
+        let centers: Vec<Tuple4> = objects.iter().map(|(_, bounds)| bounds.center()).collect();
+        let spread = Bounds3::from_points(&centers);
+        let extent = spread.max - spread.min;
+        let axis = |p: Tuple4| {
+            if extent.x >= extent.y && extent.x >= extent.z {
+                p.x
+            } else if extent.y >= extent.z {
+                p.y
+            } else {
+                p.z
+            }
+        };
+        let middle = objects.len() / 2;
+        objects.select_nth_unstable_by(middle, |a, b| {
+            axis(a.1.center()).partial_cmp(&axis(b.1.center())).unwrap()
+        });
+
+        let index = self.nodes.len();
+        self.nodes.push(Node::Interior { bounds, right: 0 });
+        let (left, right) = objects.split_at_mut(middle);
+        self.build(left);
+        let right_index = self.nodes.len();
+        self.build(right);
+        if let Node::Interior { right, .. } = &mut self.nodes[index] {
+            *right = right_index;
+        }
+    }
+
+    /// Returns the number of objects in the hierarchy, including unbounded
+    /// ones.
+    pub fn len(&self) -> usize {
+        self.objects.len() + self.unbounded.len()
+    }
+
+    /// Whether the hierarchy has no objects.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Calls `visit` with each object the ray might hit, starting with the
+    /// unbounded objects and then working (roughly) from near to far.
+    ///
+    /// `visit` returns the distance along the ray beyond which hits are no
+    /// longer wanted, so that objects whose boxes lie entirely beyond it are
+    /// skipped. Returning a negative distance stops the traversal.
+    pub fn traverse<F: FnMut(ObjectId) -> f32>(&self, ray: Ray, mut visit: F) {
+        let mut limit = f32::INFINITY;
+        for &object_id in &self.unbounded {
+            limit = visit(object_id);
+            if limit < 0. {
+                return;
+            }
+        }
+
+        // Returns the distance at which the ray enters the box, if it does
+        // so before the limit.
+        let entry = |bounds: Bounds3, limit: f32| {
+            bounds
+                .intersect(ray)
+                .filter(|&(t0, t1)| t1 >= 0. && t0 <= limit)
+                .map(|(t0, _)| t0)
+        };
+
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = [(0, 0.); MAX_DEPTH];
+        let mut len = 0;
+        if let Some(t) = entry(self.nodes[0].bounds(), limit) {
+            stack[0] = (0, t);
+            len = 1;
+        }
+
+        while len > 0 {
+            len -= 1;
+            let (node, t) = stack[len];
+            if t > limit {
+                continue;
+            }
+
+            match self.nodes[node] {
+                Node::Leaf { start, end, .. } => {
+                    for &(object_id, bounds) in &self.objects[start..end] {
+                        if entry(bounds, limit).is_none() {
+                            continue;
+                        }
+                        limit = visit(object_id);
+                        if limit < 0. {
+                            return;
+                        }
+                    }
+                }
+                Node::Interior { right, .. } => {
+                    // Push the farther child first, so the nearer is visited
+                    // first and can shrink the limit.
+                    let left = node + 1;
+                    let mut children = [
+                        (left, entry(self.nodes[left].bounds(), limit)),
+                        (right, entry(self.nodes[right].bounds(), limit)),
+                    ];
+                    if children[0].1 < children[1].1 {
+                        children.swap(0, 1);
+                    }
+                    for &(child, t) in &children {
+                        if let Some(t) = t {
+                            stack[len] = (child, t);
+                            len += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box_at(x: f32) -> Bounds3 {
+        Bounds3::new(point3(x - 0.5, -0.5, -0.5), point3(x + 0.5, 0.5, 0.5))
+    }
+
+    fn visited(bvh: &Bvh, r: Ray) -> Vec<ObjectId> {
+        let mut ids = vec![];
+        bvh.traverse(r, |object_id| {
+            ids.push(object_id);
+            f32::INFINITY
+        });
+        ids
+    }
+
+    #[test]
+    fn traversing_visits_only_the_boxes_the_ray_passes_through() {
+        let bvh = Bvh::new((0..20).map(|i| (i, unit_box_at(2. * i as f32))));
+        assert_eq!(bvh.len(), 20);
+
+        let r = ray(point3(6., 5., 0.), vector3(0., -1., 0.));
+        assert_eq!(visited(&bvh, r), vec![3]);
+
+        let r = ray(point3(100., 5., 0.), vector3(0., -1., 0.));
+        assert!(visited(&bvh, r).is_empty());
+
+        let r = ray(point3(-5., 0., 0.), vector3(1., 0., 0.));
+        let mut ids = visited(&bvh, r);
+        ids.sort();
+        assert_eq!(ids, (0..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn unbounded_objects_are_always_visited_first() {
+        let objects = vec![
+            (0, unit_box_at(0.)),
+            (1, Bounds3::infinite()),
+            (2, unit_box_at(10.)),
+        ];
+        let bvh = Bvh::new(objects);
+        let r = ray(point3(0., 5., 0.), vector3(0., -1., 0.));
+        assert_eq!(visited(&bvh, r), vec![1, 0]);
+    }
+
+    #[test]
+    fn the_limit_prunes_distant_objects() {
+        let bvh = Bvh::new((0..20).map(|i| (i, unit_box_at(2. * i as f32))));
+        let r = ray(point3(-5., 0., 0.), vector3(1., 0., 0.));
+        let mut ids = vec![];
+        bvh.traverse(r, |object_id| {
+            ids.push(object_id);
+            // Only the first box is entered before this distance, so after
+            // the first visit only it is left.
+            5.
+        });
+        assert!(ids.contains(&0) && ids.len() <= 2, "{:?}", ids);
+
+        // A negative limit stops the traversal.
+        let mut count = 0;
+        bvh.traverse(r, |_| {
+            count += 1;
+            -1.
+        });
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn an_empty_hierarchy() {
+        let bvh = Bvh::new(vec![]);
+        assert!(bvh.is_empty());
+        let r = ray(point3(0., 0., 0.), vector3(1., 0., 0.));
+        assert!(visited(&bvh, r).is_empty());
+    }
+}
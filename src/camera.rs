@@ -1,11 +1,29 @@
+use crate::angle::*;
+use crate::aperture::*;
 use crate::canvas::*;
+use crate::color::*;
+use crate::color_grade::*;
+use crate::filter::*;
+use crate::object::*;
 use crate::ray::*;
+use crate::render_options::*;
+use crate::render_stats::*;
 use crate::scene::*;
 use crate::transform::*;
 use crate::tuple::*;
 
-use rand::rngs::SmallRng;
-use rand::SeedableRng;
+use crate::rng::*;
+use std::ops::{ControlFlow, Range};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The width and height (in pixels) of the tiles rendered by each thread.
+pub const TILE_SIZE: usize = 16;
+
+/// The sample pass from which the seeds of the extra samples taken for
+/// pixels on silhouettes are derived (after the two checkerboard passes).
+const EDGE_PASS: usize = 2;
 
 pub struct Camera {
     pub hsize: usize,
@@ -15,12 +33,17 @@ pub struct Camera {
     half_width: f32,
     half_height: f32,
     pixel_size: f32,
+    lens_radius: f32,
+    focal_distance: f32,
+    aperture: Aperture,
 }
 
 impl Camera {
     /// Constructs a camera with the given horizontal size (in pixels), vertical
-    /// size (in pixels), and field of view (in radians).
-    pub fn new(hsize: usize, vsize: usize, fov: f32) -> Self {
+    /// size (in pixels), and field of view (in radians, if given as a bare
+    /// number).
+    pub fn new(hsize: usize, vsize: usize, fov: impl Into<Angle>) -> Self {
+        let fov = fov.into().to_radians();
         let half_view = (fov / 2.).tan();
         let aspect = hsize as f32 / vsize as f32;
 
@@ -40,15 +63,25 @@ impl Camera {
             half_width,
             half_height,
             pixel_size,
+            lens_radius: 0.,
+            focal_distance: 1.,
+            aperture: Aperture::Circle,
         }
     }
 
     /// Returns a ray that starts at the camera and passes through the indicated
-    /// (x, y) pixel on the canvas.
+    /// (x, y) pixel on the canvas. Its footprint spreads to cover the pixel.
     pub fn ray(&self, x: usize, y: usize) -> Ray {
-        // The offset from the edge of the canvas to the pixel's center.
-        let xoffset = (x as f32 + 0.5) * self.pixel_size;
-        let yoffset = (y as f32 + 0.5) * self.pixel_size;
+        self.ray_through(x as f32 + 0.5, y as f32 + 0.5)
+    }
+
+    /// Returns a ray that starts at the camera and passes through the given
+    /// point on the canvas, in pixels from its top left corner (so the
+    /// center of pixel (x, y) is at (x + 0.5, y + 0.5)).
+    pub fn ray_through(&self, px: f32, py: f32) -> Ray {
+        // The offset from the edge of the canvas to the point.
+        let xoffset = px * self.pixel_size;
+        let yoffset = py * self.pixel_size;
 
         // The untransformed coordinates of the pixel in world space.
         // (The camera looks toward -z, so +x is to the left.)
@@ -60,40 +93,1220 @@ impl Camera {
         // (The canvas is at z = -1.)
         let pixel = self.transform.world_to_local * point3(world_x, world_y, -1.);
         let origin = self.transform.world_to_local * point3(0., 0., 0.);
-        let direction = (pixel - origin).normalize();
+        let direction = pixel - origin;
+
+        // The ray widens by the size of a pixel over the distance to it.
+        let pixel_width = self.transform.world_to_local * vector3(self.pixel_size, 0., 0.);
+        let spread = pixel_width.magnitude() / direction.magnitude();
+
+        ray(origin, direction.normalize()).spread(spread)
+    }
+
+    /// Returns the exposure compensation factor for the indicated (x, y) pixel,
+    /// which is cos³ of the angle between the pixel's ray and the view axis.
+    pub fn compensation(&self, x: usize, y: usize) -> f32 {
+        // The canvas is at z = -1, so the cosine of the angle is the inverse of
+        // the distance from the origin to the pixel.
+        let world_x = self.half_width - (x as f32 + 0.5) * self.pixel_size;
+        let world_y = self.half_height - (y as f32 + 0.5) * self.pixel_size;
+        let cos = 1.
+            / world_x
+                .mul_add(world_x, world_y.mul_add(world_y, 1.))
+                .sqrt();
 
-        ray(origin, direction)
+        cos * cos * cos
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.transform
     }
 
     pub fn set_transform(&mut self, transform: Transform) {
         self.transform = transform;
     }
 
+    /// Returns the point on the canvas, in pixels from its top left corner,
+    /// that the world point appears at (the inverse of `ray_through`), or
+    /// None if the point isn't in front of the camera.
+    pub fn project(&self, point: Tuple4) -> Option<(f32, f32)> {
+        let camera_point = self.transform.local_to_world * point;
+        if camera_point.z >= 0. {
+            return None;
+        }
+
+        // The point on the canvas (at z = -1) in line with it.
+        let world_x = camera_point.x / -camera_point.z;
+        let world_y = camera_point.y / -camera_point.z;
+        Some((
+            (self.half_width - world_x) / self.pixel_size,
+            (self.half_height - world_y) / self.pixel_size,
+        ))
+    }
+
+    /// Moves the camera along its line of sight, keeping the direction it
+    /// looks in, so that it looks at the center of the scene's bounds (see
+    /// `Scene::bounds`) from just far enough away that the whole box fits on
+    /// the canvas, leaving a margin on every side of the given fraction of
+    /// the canvas's width and height.
+    pub fn frame_scene(&mut self, scene: &Scene, margin: f32) -> Result<(), String> {
+        if !(0. ..0.5).contains(&margin) {
+            return Err(format!(
+                "margin must be at least 0 and below 0.5, not {}",
+                margin
+            ));
+        }
+        let bounds = scene
+            .bounds()
+            .ok_or_else(|| "the scene has no bounded objects to frame".to_string())?;
+
+        let camera_to_world = self.transform.world_to_local;
+        let forward = (camera_to_world * vector3(0., 0., -1.)).normalize();
+        let up = (camera_to_world * vector3(0., 1., 0.)).normalize();
+        let side = (camera_to_world * vector3(1., 0., 0.)).normalize();
+        let center = bounds.center();
+
+        // The slopes of the edges of the canvas, inside the margin.
+        let slope_x = self.half_width * (1. - 2. * margin);
+        let slope_y = self.half_height * (1. - 2. * margin);
+
+        // Each corner, `depth` in front of the center and `x` and `y` off
+        // the line of sight, fits when the camera is at least
+        // |x| / slope_x - depth (and |y| / slope_y - depth) from the center.
+        let (min, max) = (bounds.min, bounds.max);
+        let mut distance: f32 = 0.;
+        for &x in [min.x, max.x].iter() {
+            for &y in [min.y, max.y].iter() {
+                for &z in [min.z, max.z].iter() {
+                    let offset = point3(x, y, z) - center;
+                    let depth = offset.dot(forward);
+                    distance = distance
+                        .max(offset.dot(side).abs() / slope_x - depth)
+                        .max(offset.dot(up).abs() / slope_y - depth);
+                }
+            }
+        }
+        // Stay (just) outside the sphere around the box, so that every
+        // corner is in front of the camera, even one right behind the center.
+        let radius = (max - center).magnitude();
+        let distance = distance.max(radius * 1.001);
+
+        let from = center - forward * distance;
+        self.transform = Transform::look_at(from, center, up);
+        Ok(())
+    }
+
+    /// Gives the camera a lens with the given radius (in camera space) and
+    /// aperture shape, focused at the given distance, for depth of field.
+    /// A radius of zero is a pinhole camera, which is the default.
+    pub fn set_lens(&mut self, radius: f32, focal_distance: f32, aperture: Aperture) {
+        assert!(focal_distance > 0., "the focal distance must be positive");
+        self.lens_radius = radius;
+        self.focal_distance = focal_distance;
+        self.aperture = aperture;
+    }
+
+    /// Returns a ray that starts at a random point on the lens and passes
+    /// through the point at the focal distance seen through the indicated
+    /// (x, y) pixel, so that only objects at that distance are sharp. For a
+    /// pinhole camera this is just `ray`.
+    pub fn lens_ray<R: Rng>(&self, rng: &mut R, x: usize, y: usize) -> Ray {
+        self.lens_ray_through(rng, x as f32 + 0.5, y as f32 + 0.5)
+    }
+
+    /// Returns a lens ray through the given point on the canvas, in pixels
+    /// as for `ray_through`.
+    pub fn lens_ray_through<R: Rng>(&self, rng: &mut R, px: f32, py: f32) -> Ray {
+        let pinhole = self.ray_through(px, py);
+        if self.lens_radius == 0. {
+            return pinhole;
+        }
+
+        let world_x = self.half_width - px * self.pixel_size;
+        let world_y = self.half_height - py * self.pixel_size;
+        let d = self.focal_distance;
+        let focus = self.transform.world_to_local * point3(world_x * d, world_y * d, -d);
+        let (lens_x, lens_y) = self.aperture.sample(rng.gen(), rng.gen());
+        let lens = point3(lens_x * self.lens_radius, lens_y * self.lens_radius, 0.);
+
+        let origin = self.transform.world_to_local * lens;
+        ray(origin, (focus - origin).normalize()).spread(pinhole.spread)
+    }
+
     pub fn render(&self, scene: Scene) -> Canvas {
-        let mut rng = SmallRng::from_entropy();
+        self.render_with_options(&scene, &RenderOptions::default())
+    }
+
+    /// Renders the scene with the given options.
+    ///
+    /// The canvas is split into tiles of TILE_SIZE pixels, which are shared
+    /// out between `options.threads` threads. Each pixel gets its own random
+    /// number generator, seeded by `pixel_seed` from the pixel's position and
+    /// `options.seed`, and each tile is copied into its own region of the
+    /// image, so the result doesn't depend on the number of threads, the
+    /// order in which pixels are rendered, or how many samples the other
+    /// pixels take.
+    pub fn render_with_options(&self, scene: &Scene, options: &RenderOptions) -> Canvas {
+        self.render_with_context(scene, &scene.prepare_render(), options)
+    }
+
+    /// Renders the scene using a context previously prepared from it, so that
+    /// repeated renders of an unchanging scene only prepare it once.
+    ///
+    /// Panics if the scene has been modified since the context was prepared.
+    pub fn render_with_context(
+        &self,
+        scene: &Scene,
+        context: &RenderContext,
+        options: &RenderOptions,
+    ) -> Canvas {
+        // A single block covering the whole image.
+        let block_size = self.hsize.max(self.vsize);
+        let (image, _) =
+            self.render_progressive_with_context(scene, context, options, block_size, |_, _| {
+                ControlFlow::Continue(())
+            });
+        image
+    }
+
+    /// Renders the scene a square block of pixels at a time, calling
+    /// `callback` after each block with the region it covers and the image
+    /// so far, in which the blocks not yet rendered are black. Blocks are
+    /// rendered in row-major order, each with `options.threads` threads, and
+    /// the callback can return `ControlFlow::Break` to stop the render.
+    ///
+    /// Blocks are `block_size` pixels across, rounded up to a whole number of
+    /// tiles (so that the image is identical to `render_with_options`), and
+    /// are cut short at the right and bottom edges of the image. Automatic
+    /// exposure and filters wider than a pixel need the whole image, so
+    /// using them when more than one block is needed is an error.
+    ///
+    /// Returns the (possibly partially filled) canvas, and whether every
+    /// block was rendered.
+    pub fn render_progressive(
+        &self,
+        scene: &Scene,
+        options: &RenderOptions,
+        block_size: usize,
+        callback: impl FnMut(Region, &Canvas) -> ControlFlow<()>,
+    ) -> Result<(Canvas, bool), String> {
+        let block_tiles = block_size.div_ceil(TILE_SIZE).max(1);
+        let (tile_cols, tile_rows) = self.tiles();
+        if block_tiles < tile_cols.len() || block_tiles < tile_rows.len() {
+            if options.auto_exposure.is_some() {
+                return Err(
+                    "automatic exposure can't be used when rendering in several blocks".to_string(),
+                );
+            }
+            if options.filter.radius() > 0.5 {
+                return Err(
+                    "filters wider than a pixel can't be used when rendering in several blocks"
+                        .to_string(),
+                );
+            }
+        }
+
+        let context = scene.prepare_render();
+        Ok(self.render_progressive_with_context(scene, &context, options, block_size, callback))
+    }
+
+    /// Renders the scene as `render_progressive` does, with a prepared
+    /// context. The options must allow rendering in blocks of this size.
+    fn render_progressive_with_context(
+        &self,
+        scene: &Scene,
+        context: &RenderContext,
+        options: &RenderOptions,
+        block_size: usize,
+        mut callback: impl FnMut(Region, &Canvas) -> ControlFlow<()>,
+    ) -> (Canvas, bool) {
+        let block_tiles = block_size.div_ceil(TILE_SIZE).max(1);
+        let (tile_cols, tile_rows) = self.tiles();
+        let mut image = Canvas::new_with_encoding(self.hsize, self.vsize, options.encoding);
+        for block_y in tile_rows.clone().step_by(block_tiles) {
+            for block_x in tile_cols.clone().step_by(block_tiles) {
+                let cols = block_x..(block_x + block_tiles).min(tile_cols.end);
+                let rows = block_y..(block_y + block_tiles).min(tile_rows.end);
+                let (x, y) = (block_x * TILE_SIZE, block_y * TILE_SIZE);
+                let region = Region {
+                    x,
+                    y,
+                    width: (cols.end * TILE_SIZE).min(self.hsize) - x,
+                    height: (rows.end * TILE_SIZE).min(self.vsize) - y,
+                    frame_width: self.hsize,
+                    frame_height: self.vsize,
+                };
+                let last = cols.end == tile_cols.end && rows.end == tile_rows.end;
+
+                let (radiance, _) =
+                    self.render_radiance_tiles(scene, context, options, (cols, rows), Pixels::All);
+                let block = self.develop(&radiance, (region.width, region.height), options);
+                image.blit(&block, x as isize, y as isize, None);
+                if callback(region, &image).is_break() && !last {
+                    return (image, false);
+                }
+            }
+        }
+
+        (image, true)
+    }
+
+    /// Renders the scene, also returning the number of samples taken for
+    /// each pixel (in row-major order), to show where adaptive sampling
+    /// spent its effort.
+    pub fn render_with_sample_counts(
+        &self,
+        scene: &Scene,
+        options: &RenderOptions,
+    ) -> (Canvas, Vec<usize>) {
+        let (canvas, costs) = self.render_with_costs(scene, options);
+        (canvas, costs.iter().map(|cost| cost.samples).collect())
+    }
+
+    /// Renders the scene, also returning what each pixel cost to render (in
+    /// row-major order), to find the pixels which are slow to render.
+    pub fn render_with_costs(
+        &self,
+        scene: &Scene,
+        options: &RenderOptions,
+    ) -> (Canvas, Vec<PixelCost>) {
+        let (radiance, costs) = self.render_radiance(scene, &scene.prepare_render(), options);
+        (
+            self.develop(&radiance, (self.hsize, self.vsize), options),
+            costs,
+        )
+    }
+
+    /// Renders a quick preview of the scene by tracing only the pixels on the
+    /// "black squares" of a checkerboard (where x + y is even), which is half
+    /// of them, and filling in each of the others with the mean of its traced
+    /// neighbors above, below, left, and right.
+    ///
+    /// Pass the result to `complete_checkerboard` to trace the remaining
+    /// pixels for the final image. Filters wider than a pixel would need the
+    /// untraced pixels' samples, so using one is an error.
+    pub fn render_checkerboard(
+        &self,
+        scene: &Scene,
+        options: &RenderOptions,
+    ) -> Result<CheckerboardRender, String> {
+        check_checkerboard_filter(options)?;
+
+        let (mut radiance, costs) = self.render_radiance_tiles(
+            scene,
+            &scene.prepare_render(),
+            options,
+            self.tiles(),
+            Pixels::Checker(0),
+        );
+
+        let (width, height) = (self.hsize, self.vsize);
+        for y in 0..height {
+            for x in (1 - y % 2..width).step_by(2) {
+                let neighbors = [
+                    (x > 0).then(|| radiance[width * y + x - 1]),
+                    (x + 1 < width).then(|| radiance[width * y + x + 1]),
+                    (y > 0).then(|| radiance[width * (y - 1) + x]),
+                    (y + 1 < height).then(|| radiance[width * (y + 1) + x]),
+                ];
+                let (sum, count) = neighbors
+                    .iter()
+                    .flatten()
+                    .fold((Color::BLACK, 0), |(sum, count), &c| (sum + c, count + 1));
+                radiance[width * y + x] = sum * (1. / count as f32);
+            }
+        }
+
+        Ok(CheckerboardRender {
+            image: self.develop(&radiance, (self.hsize, self.vsize), options),
+            costs,
+            radiance,
+            complete: false,
+        })
+    }
+
+    /// Traces the pixels that `render_checkerboard` skipped (using the same
+    /// scene and options), replacing their interpolated colors. For scenes
+    /// without random sampling, the result is identical to a full render;
+    /// otherwise the skipped pixels draw different (but equally distributed)
+    /// random numbers.
+    pub fn complete_checkerboard(
+        &self,
+        scene: &Scene,
+        options: &RenderOptions,
+        preview: CheckerboardRender,
+    ) -> Result<CheckerboardRender, String> {
+        check_checkerboard_filter(options)?;
+        if preview.complete {
+            return Ok(preview);
+        }
+
+        let (rest, rest_costs) = self.render_radiance_tiles(
+            scene,
+            &scene.prepare_render(),
+            options,
+            self.tiles(),
+            Pixels::Checker(1),
+        );
+
+        let mut radiance = preview.radiance;
+        let mut costs = preview.costs;
+        for i in 0..radiance.len() {
+            if Pixels::Checker(1).includes(i % self.hsize, i / self.hsize) {
+                radiance[i] = rest[i];
+                costs[i] = rest_costs[i];
+            }
+        }
+
+        Ok(CheckerboardRender {
+            image: self.develop(&radiance, (self.hsize, self.vsize), options),
+            costs,
+            radiance,
+            complete: true,
+        })
+    }
+
+    /// Renders one of `slice_count` slices of the frame, for splitting a
+    /// render between machines, returning the region of the frame it covers
+    /// and its pixels. The slices are bands of whole rows of tiles, and each
+    /// pixel is seeded exactly as in a full render, so `stitch` assembles
+    /// them into an image identical to rendering the frame at once.
+    ///
+    /// Automatic exposure would differ between slices, leaving seams, and
+    /// filters wider than a pixel would need the neighboring slices' samples,
+    /// so using either is an error, as is a slice index out of range.
+    pub fn render_slice(
+        &self,
+        scene: &Scene,
+        options: &RenderOptions,
+        slice_index: usize,
+        slice_count: usize,
+    ) -> Result<(Region, Canvas), String> {
+        if slice_index >= slice_count {
+            return Err(format!(
+                "slice {} is out of range for {} slices",
+                slice_index, slice_count
+            ));
+        }
+        check_partial_render(options, "in slices")?;
+
+        let tiles_y = self.vsize.div_ceil(TILE_SIZE);
+        let tile_rows =
+            tiles_y * slice_index / slice_count..tiles_y * (slice_index + 1) / slice_count;
+        let y = (tile_rows.start * TILE_SIZE).min(self.vsize);
+        let height = (tile_rows.end * TILE_SIZE).min(self.vsize) - y;
+        let region = Region {
+            x: 0,
+            y,
+            width: self.hsize,
+            height,
+            frame_width: self.hsize,
+            frame_height: self.vsize,
+        };
+
+        let (radiance, _) = self.render_radiance_tiles(
+            scene,
+            &scene.prepare_render(),
+            options,
+            (self.tiles().0, tile_rows),
+            Pixels::All,
+        );
+        Ok((
+            region,
+            self.develop(&radiance, (self.hsize, height), options),
+        ))
+    }
+
+    /// Renders the pixels in the rectangle from (x0, y0) up to (but not
+    /// including) (x1, y1), such as one found by `Scene::affected_region`,
+    /// returning the region of the frame it covers and its pixels, to be
+    /// blitted over an earlier render. Each pixel is seeded exactly as in a
+    /// full render, so the pixels match those of rendering the frame again.
+    ///
+    /// As with `render_slice`, automatic exposure and filters wider than a
+    /// pixel are errors, as is a rectangle outside the frame.
+    pub fn render_region(
+        &self,
+        scene: &Scene,
+        options: &RenderOptions,
+        (x0, y0, x1, y1): (usize, usize, usize, usize),
+    ) -> Result<(Region, Canvas), String> {
+        if x0 > x1 || y0 > y1 || x1 > self.hsize || y1 > self.vsize {
+            return Err(format!(
+                "({}, {}) to ({}, {}) isn't a region of the {}x{} frame",
+                x0, y0, x1, y1, self.hsize, self.vsize
+            ));
+        }
+        check_partial_render(options, "by region")?;
+
+        // Render the tiles covering the rectangle, and crop them to it.
+        let tiles = (
+            x0 / TILE_SIZE..x1.div_ceil(TILE_SIZE),
+            y0 / TILE_SIZE..y1.div_ceil(TILE_SIZE),
+        );
+        let (first_col, first_row) = (tiles.0.start * TILE_SIZE, tiles.1.start * TILE_SIZE);
+        let tiles_width = (tiles.0.end * TILE_SIZE).min(self.hsize) - first_col;
+        let (radiance, _) =
+            self.render_radiance_tiles(scene, &scene.prepare_render(), options, tiles, Pixels::All);
+        let radiance: Vec<Color> = (y0..y1)
+            .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+            .map(|(x, y)| radiance[(y - first_row) * tiles_width + x - first_col])
+            .collect();
+
+        let region = Region {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+            frame_width: self.hsize,
+            frame_height: self.vsize,
+        };
+        Ok((
+            region,
+            self.develop(&radiance, (region.width, region.height), options),
+        ))
+    }
+
+    /// Renders the radiance of every pixel, and what each cost to render, in
+    /// row-major order.
+    fn render_radiance(
+        &self,
+        scene: &Scene,
+        context: &RenderContext,
+        options: &RenderOptions,
+    ) -> (Vec<Color>, Vec<PixelCost>) {
+        self.render_radiance_tiles(scene, context, options, self.tiles(), Pixels::All)
+    }
+
+    /// Returns the columns and rows of tiles covering the image.
+    fn tiles(&self) -> (Range<usize>, Range<usize>) {
+        (
+            0..self.hsize.div_ceil(TILE_SIZE),
+            0..self.vsize.div_ceil(TILE_SIZE),
+        )
+    }
+
+    /// Renders the radiance of the given pixels in the given rectangle of
+    /// tiles, and what each cost to render, in row-major order. Pixels that
+    /// aren't rendered are black, and cost nothing.
+    fn render_radiance_tiles(
+        &self,
+        scene: &Scene,
+        context: &RenderContext,
+        options: &RenderOptions,
+        (tile_cols, tile_rows): (Range<usize>, Range<usize>),
+        selection: Pixels,
+    ) -> (Vec<Color>, Vec<PixelCost>) {
+        assert!(
+            context.is_valid_for(scene),
+            "stale render context: the scene was modified after prepare_render"
+        );
+
+        let tiles_x = tile_cols.len();
+        let tiles = tiles_x * tile_rows.len();
+        let (first_col, first_row) = (tile_cols.start * TILE_SIZE, tile_rows.start * TILE_SIZE);
+        let width = (tile_cols.end * TILE_SIZE).min(self.hsize) - first_col;
+        let pixels = width * ((tile_rows.end * TILE_SIZE).min(self.vsize) - first_row);
+        let next_tile = AtomicUsize::new(0);
+        let radiance = Mutex::new(vec![Color::BLACK; pixels]);
+        let costs = Mutex::new(vec![PixelCost::default(); pixels]);
+        let samples = Mutex::new(vec![vec![]; tiles]);
+
+        std::thread::scope(|s| {
+            for _ in 0..options.threads.max(1) {
+                s.spawn(|| loop {
+                    let tile = next_tile.fetch_add(1, Ordering::Relaxed);
+                    if tile >= tiles {
+                        break;
+                    }
+
+                    let tile_x = tile_cols.start + tile % tiles_x;
+                    let tile_y = tile_rows.start + tile / tiles_x;
+                    let (pixels, tile_samples) =
+                        self.render_tile(scene, options, tile_x, tile_y, selection);
+                    samples.lock().unwrap()[tile] = tile_samples;
+                    let (x0, y0) = (tile_x * TILE_SIZE, tile_y * TILE_SIZE);
+                    let tile_width = TILE_SIZE.min(self.hsize - x0);
+
+                    let mut radiance = radiance.lock().unwrap();
+                    let mut costs = costs.lock().unwrap();
+                    for (y, row) in pixels.chunks(tile_width).enumerate() {
+                        let i = width * (y0 + y - first_row) + x0 - first_col;
+                        for (j, &(color, cost)) in row.iter().enumerate() {
+                            radiance[i + j] = color;
+                            costs[i + j] = cost;
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut radiance = radiance.into_inner().unwrap();
+        let mut costs = costs.into_inner().unwrap();
+        let height = pixels / width.max(1);
+        let radiance = if options.filter == Filter::Box {
+            if options.edge_samples > 0 {
+                self.antialias_edges(
+                    scene,
+                    options,
+                    (first_col, first_row, width, height),
+                    selection,
+                    &mut radiance,
+                    &mut costs,
+                );
+            }
+            radiance
+        } else {
+            // Samples are added tile by tile, so that the sums don't depend
+            // on the order in which the tiles finished.
+            let mut film = Film::new(width, height, options.filter);
+            for sample in samples.into_inner().unwrap().into_iter().flatten() {
+                film.add_sample(FilmSample {
+                    x: sample.x - first_col as f32,
+                    y: sample.y - first_row as f32,
+                    ..sample
+                });
+            }
+            film.resolve()
+        };
+        (radiance, costs)
+    }
+
+    /// Takes `options.edge_samples` more samples for each selected pixel of
+    /// the block (at x0, y0, of the given width and height) whose object ID
+    /// differs from that of a pixel above, below, left, or right of it,
+    /// averaging them with those it had.
+    fn antialias_edges(
+        &self,
+        scene: &Scene,
+        options: &RenderOptions,
+        (x0, y0, width, height): (usize, usize, usize, usize),
+        selection: Pixels,
+        radiance: &mut [Color],
+        costs: &mut [PixelCost],
+    ) {
+        // The IDs of the block's pixels, and of those around it.
+        let xs = x0.saturating_sub(1)..(x0 + width + 1).min(self.hsize);
+        let ys = y0.saturating_sub(1)..(y0 + height + 1).min(self.vsize);
+        let ids: Vec<Option<ObjectId>> = ys
+            .clone()
+            .flat_map(|y| xs.clone().map(move |x| self.object_id_at(scene, x, y)))
+            .collect();
+        let id = |x: usize, y: usize| ids[(y - ys.start) * xs.len() + x - xs.start];
+
+        for y in y0..y0 + height {
+            for x in x0..x0 + width {
+                let here = id(x, y);
+                let edge = (x > xs.start && id(x - 1, y) != here)
+                    || (x + 1 < xs.end && id(x + 1, y) != here)
+                    || (y > ys.start && id(x, y - 1) != here)
+                    || (y + 1 < ys.end && id(x, y + 1) != here);
+                if !edge || !selection.includes(x, y) {
+                    continue;
+                }
+
+                let i = (y - y0) * width + x - x0;
+                let cost = &mut costs[i];
+                let mut rng = SmallRng::seed_from_u64(pixel_seed(options.seed, x, y, EDGE_PASS));
+                let mut total = radiance[i] * cost.samples as f32;
+                for _ in 0..options.edge_samples {
+                    let p = (x as f32 + rng.gen::<f32>(), y as f32 + rng.gen::<f32>());
+                    let (color, rays) = self.pixel_radiance(&mut rng, scene, options, x, y, p);
+                    total = total + color;
+                    cost.rays += rays;
+                }
+                cost.samples += options.edge_samples;
+                radiance[i] = total * (1. / cost.samples as f32);
+            }
+        }
+    }
+
+    /// Returns the object seen through the center of each pixel, if any, in
+    /// row-major order: an ID buffer, which shows the objects' silhouettes.
+    pub fn render_object_ids(&self, scene: &Scene) -> Vec<Option<ObjectId>> {
+        (0..self.vsize)
+            .flat_map(|y| (0..self.hsize).map(move |x| self.object_id_at(scene, x, y)))
+            .collect()
+    }
+
+    fn object_id_at(&self, scene: &Scene, x: usize, y: usize) -> Option<ObjectId> {
+        scene
+            .nearest_intersection(self.ray(x, y))
+            .map(|intersection| intersection.object_id)
+    }
+
+    /// Renders a single tile, returning the radiance of its pixels and what
+    /// each cost to render, in row-major order, and (unless box filtering)
+    /// the samples to reconstruct the pixels from.
+    fn render_tile(
+        &self,
+        scene: &Scene,
+        options: &RenderOptions,
+        tile_x: usize,
+        tile_y: usize,
+        selection: Pixels,
+    ) -> (Vec<(Color, PixelCost)>, Vec<FilmSample>) {
+        let pass = selection.pass();
+        let (x0, y0) = (tile_x * TILE_SIZE, tile_y * TILE_SIZE);
+        let width = TILE_SIZE.min(self.hsize - x0);
+        let height = TILE_SIZE.min(self.vsize - y0);
+
+        let mut pixels = Vec::with_capacity(width * height);
+        let mut samples = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                if !selection.includes(x0 + x, y0 + y) {
+                    pixels.push((Color::BLACK, PixelCost::default()));
+                    continue;
+                }
+                let (x, y) = (x0 + x, y0 + y);
+                let mut rng = SmallRng::seed_from_u64(pixel_seed(options.seed, x, y, pass));
+                let pixel = self.sample_pixel(&mut rng, scene, options, x, y, &mut samples);
+                pixels.push(pixel);
+            }
+        }
+
+        (pixels, samples)
+    }
+
+    /// Estimates the radiance arriving at the indicated (x, y) pixel by
+    /// averaging samples, returning the estimate and what it cost to find.
+    ///
+    /// Between `options.min_samples` and `options.max_samples` are taken,
+    /// stopping early once the standard error of the mean luminance (tracked
+    /// with Welford's algorithm) falls below `options.error_target`.
+    ///
+    /// With a box filter every sample passes through the pixel's center.
+    /// Other filters take samples at random points within the pixel, which
+    /// are also added to `samples` for reconstruction.
+    fn sample_pixel(
+        &self,
+        rng: &mut SmallRng,
+        scene: &Scene,
+        options: &RenderOptions,
+        x: usize,
+        y: usize,
+        samples: &mut Vec<FilmSample>,
+    ) -> (Color, PixelCost) {
+        let max_samples = options.max_samples.max(1);
+        let min_samples = options.min_samples.min(max_samples);
+
+        let mut mean = Color::BLACK;
+        let mut mean_luminance = 0.;
+        let mut m2 = 0.;
+        let mut rays = 0;
+        for n in 1..=max_samples {
+            let (px, py) = if options.filter == Filter::Box {
+                (x as f32 + 0.5, y as f32 + 0.5)
+            } else {
+                (x as f32 + rng.gen::<f32>(), y as f32 + rng.gen::<f32>())
+            };
+            let (color, cost) = self.pixel_radiance(rng, scene, options, x, y, (px, py));
+            if options.filter != Filter::Box {
+                samples.push(FilmSample {
+                    x: px,
+                    y: py,
+                    color,
+                });
+            }
+            rays += cost;
+            mean = mean + (color - mean) * (1. / n as f32);
+
+            let l = luminance(color);
+            let delta = l - mean_luminance;
+            mean_luminance += delta / n as f32;
+            m2 += delta * (l - mean_luminance);
+
+            if n >= min_samples.max(2) {
+                let variance = m2 / (n - 1) as f32;
+                if (variance / n as f32).sqrt() < options.error_target {
+                    return (mean, PixelCost { samples: n, rays });
+                }
+            }
+        }
+
+        (
+            mean,
+            PixelCost {
+                samples: max_samples,
+                rays,
+            },
+        )
+    }
+
+    /// Computes the (unclamped) radiance arriving at the indicated (x, y)
+    /// pixel along a single ray through the given point within it, and the
+    /// number of rays traced to find it.
+    fn pixel_radiance(
+        &self,
+        rng: &mut SmallRng,
+        scene: &Scene,
+        options: &RenderOptions,
+        x: usize,
+        y: usize,
+        (px, py): (f32, f32),
+    ) -> (Color, usize) {
+        let ray = self.lens_ray_through(rng, px, py);
+        let (color, rays) = scene.radiance_and_cost_at(rng, ray, options);
+        if options.exposure_compensation {
+            (color * self.compensation(x, y), rays)
+        } else {
+            (color, rays)
+        }
+    }
+
+    /// Converts the rendered radiance (in row-major order, possibly covering
+    /// only the first rows) to a canvas of the given size, applying the
+    /// (automatic or physical) exposure and the color grade.
+    fn develop(
+        &self,
+        radiance: &[Color],
+        (width, height): (usize, usize),
+        options: &RenderOptions,
+    ) -> Canvas {
+        let exposure = match (options.auto_exposure, options.physical_exposure) {
+            (Some(auto_exposure), _) => auto_exposure.scale(radiance),
+            (None, Some(physical_exposure)) => physical_exposure.scale(),
+            (None, None) => 1.,
+        };
+
+        let mut image = Canvas::new_with_encoding(width, height, options.encoding);
+        for (i, &color) in radiance.iter().enumerate() {
+            let color = options.grade.apply(color * exposure);
+            image.set_color(i % width, i / width, color);
+        }
+
+        image
+    }
+
+    /// Renders the scene split into layers for compositing: the background,
+    /// then each of the scene's object layers (see `Scene::layers`) in
+    /// order. Composited over one another (see `composite`), they give back
+    /// the whole render, wherever the layers' objects don't overlap on
+    /// screen.
+    ///
+    /// Each layer's canvas holds the light from its objects premultiplied by
+    /// their coverage, which is the layer's alpha: the fraction of each
+    /// pixel's samples that hit them (found by a second render of the
+    /// layer's matte). Objects in other layers still cast shadows and
+    /// appear in reflections if `others_visible`.
+    ///
+    /// Every pixel takes `options.max_samples` samples, since adaptive
+    /// sampling would stop the matte and the layer after different numbers
+    /// of them. Automatic exposure would expose each layer differently, so
+    /// using it is an error.
+    pub fn render_layers(
+        &self,
+        scene: &Scene,
+        options: &RenderOptions,
+        others_visible: bool,
+    ) -> Result<Vec<LayerImage>, String> {
+        if options.auto_exposure.is_some() {
+            return Err("automatic exposure can't be used when rendering in layers".to_string());
+        }
+
+        let options = &options.clone().min_samples(options.max_samples);
+        let context = scene.prepare_render();
+        let background = RenderLayer::Background;
+        let mut layers = vec![LayerImage {
+            layer: background,
+            canvas: self.render_with_context(scene, &context, &options.clone().layer(background)),
+            alpha: vec![1.; self.hsize * self.vsize],
+        }];
+
+        // The matte is left as it is rendered, unexposed and ungraded.
+        let matte_options = RenderOptions {
+            grade: ColorGrade::new(),
+            physical_exposure: None,
+            exposure_compensation: false,
+            ..options.clone()
+        };
+        for tag in scene.layers() {
+            let layer = RenderLayer::Objects {
+                layer: tag,
+                others_visible,
+            };
+            let matte = RenderLayer::Matte {
+                layer: tag,
+                others_visible,
+            };
+            let matte =
+                self.render_with_context(scene, &context, &matte_options.clone().layer(matte));
+            layers.push(LayerImage {
+                layer,
+                canvas: self.render_with_context(scene, &context, &options.clone().layer(layer)),
+                alpha: matte.data.iter().step_by(3).copied().collect(),
+            });
+        }
+        Ok(layers)
+    }
+
+    /// Renders a diagnostic image of which way the surfaces face: green
+    /// where rays hit the front of a surface, red where they hit the back,
+    /// and black where they miss. Lighting and materials are ignored.
+    pub fn render_normal_audit(&self, scene: &Scene) -> Canvas {
         let mut image = Canvas::new(self.hsize, self.vsize);
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                let color = match scene.facing(self.ray(x, y)) {
+                    Some((_, Facing::Front)) => Color::new(0., 1., 0.),
+                    Some((_, Facing::Back)) => Color::new(1., 0., 0.),
+                    None => Color::BLACK,
+                };
+                image.set_color(x, y, color);
+            }
+        }
+        image
+    }
+
+    /// Renders the scene, calling `cancelled` before each block of tiles and
+    /// stopping as soon as it returns true.
+    ///
+    /// The blocks are rendered as by `render_progressive`, each with enough
+    /// tiles to keep `options.threads` threads busy. Automatic exposure and
+    /// filters wider than a pixel need the whole image at once, so with them
+    /// the render can only be cancelled before it starts.
+    ///
+    /// Returns the (possibly partially filled) canvas, and whether every
+    /// block was rendered. Blocks that weren't rendered are left black.
+    pub fn render_cancellable<F: FnMut() -> bool>(
+        &self,
+        scene: &Scene,
+        options: &RenderOptions,
+        mut cancelled: F,
+    ) -> (Canvas, bool) {
+        let context = scene.prepare_render();
+        self.render_cancellable_with_context(scene, &context, options, &mut cancelled)
+    }
+
+    fn render_cancellable_with_context(
+        &self,
+        scene: &Scene,
+        context: &RenderContext,
+        options: &RenderOptions,
+        cancelled: &mut impl FnMut() -> bool,
+    ) -> (Canvas, bool) {
+        if cancelled() {
+            let image = Canvas::new_with_encoding(self.hsize, self.vsize, options.encoding);
+            return (image, false);
+        }
+
+        let block_size = if options.auto_exposure.is_some() || options.filter.radius() > 0.5 {
+            self.hsize.max(self.vsize)
+        } else {
+            // A square of tiles with at least one for each thread.
+            let side = (options.threads.max(1) as f32).sqrt().ceil() as usize;
+            side * TILE_SIZE
+        };
+        self.render_progressive_with_context(scene, context, options, block_size, |_, _| {
+            if cancelled() {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+    }
+
+    /// Renders the scene for at most the given time, refining the image
+    /// until then.
+    ///
+    /// The first pass renders with the options as given, stopping (as
+    /// `render_cancellable` does) if it runs out of time. Each later pass
+    /// starts over with twice the minimum and maximum samples per pixel,
+    /// and replaces the image if it finishes in time. Refinement stops early
+    /// once a pass leaves the image unchanged, as happens when nothing in the
+    /// scene is sampled randomly.
+    ///
+    /// Returns the image, and whether a pass was completed; if none was, the
+    /// first pass's unfinished blocks are left black.
+    pub fn render_with_budget(
+        &self,
+        scene: &Scene,
+        options: &RenderOptions,
+        budget: Duration,
+    ) -> (Canvas, bool) {
+        let deadline = Instant::now() + budget;
+        let mut out_of_time = || Instant::now() >= deadline;
+        let context = scene.prepare_render();
+
+        let (mut image, complete) =
+            self.render_cancellable_with_context(scene, &context, options, &mut out_of_time);
+        if !complete {
+            return (image, false);
+        }
+
+        let mut options = options.clone();
+        loop {
+            options = RenderOptions {
+                min_samples: options.min_samples.saturating_mul(2),
+                max_samples: options.max_samples.max(1).saturating_mul(2),
+                ..options
+            };
+            let (refined, complete) =
+                self.render_cancellable_with_context(scene, &context, &options, &mut out_of_time);
+            if !complete || refined.data == image.data {
+                return (image, true);
+            }
+            image = refined;
+        }
+    }
+
+    /// Returns the (left, right) eye cameras for stereo rendering, offset by
+    /// half the eye separation either side of this camera along its right
+    /// vector.
+    ///
+    /// With a focus distance the eyes toe in to converge on the point that
+    /// distance ahead of the camera; otherwise they look straight ahead.
+    pub fn stereo_pair(
+        &self,
+        eye_separation: f32,
+        focus_distance: Option<f32>,
+    ) -> (Camera, Camera) {
+        // In camera space the camera looks toward -z, and +x is to the left.
+        // The view transform need not preserve lengths, so distances are
+        // converted from world units first.
+        let world_length = |v: Tuple4| (self.transform.world_to_local * v).magnitude();
+        let unit_x = world_length(vector3(1., 0., 0.));
+        let unit_z = world_length(vector3(0., 0., 1.));
+        let eye = |x: f32| {
+            let from = point3(x / unit_x, 0., 0.);
+            let to = match focus_distance {
+                Some(distance) => point3(0., 0., -distance / unit_z),
+                None => point3(x / unit_x, 0., -1.),
+            };
+            let view = Transform::look_at(from, to, vector3(0., 1., 0.)).local_to_world
+                * self.transform.local_to_world;
+
+            let mut camera = Camera::new(self.hsize, self.vsize, self.fov);
+            camera.set_transform(Transform {
+                local_to_world: view,
+                world_to_local: view.inverse(),
+            });
+            camera
+        };
+
+        (eye(eye_separation / 2.), eye(-eye_separation / 2.))
+    }
+
+    /// Renders the (left, right) eye images of the scene, with the eyes
+    /// looking straight ahead. Use `stereo_pair` for converging eyes, and
+    /// `Canvas::blit` to combine the images side by side.
+    pub fn render_stereo(
+        &self,
+        scene: &Scene,
+        eye_separation: f32,
+        options: &RenderOptions,
+    ) -> (Canvas, Canvas) {
+        let (left, right) = self.stereo_pair(eye_separation, None);
+        let context = scene.prepare_render();
+        (
+            left.render_with_context(scene, &context, options),
+            right.render_with_context(scene, &context, options),
+        )
+    }
+}
+
+/// Which pixels are traced by a render.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Pixels {
+    All,
+    /// The pixels where x + y has the given parity.
+    Checker(usize),
+}
+
+impl Pixels {
+    fn includes(self, x: usize, y: usize) -> bool {
+        match self {
+            Pixels::All => true,
+            Pixels::Checker(parity) => (x + y) % 2 == parity,
+        }
+    }
+
+    /// The sample pass from which tiles' seeds are derived, so that the two
+    /// halves of a checkerboard draw independent random numbers.
+    fn pass(self) -> usize {
+        match self {
+            Pixels::All => 0,
+            Pixels::Checker(parity) => parity,
+        }
+    }
+}
+
+/// Checks that the options don't need the whole frame, for rendering part
+/// of it (in the given way) at a time.
+fn check_partial_render(options: &RenderOptions, how: &str) -> Result<(), String> {
+    if options.auto_exposure.is_some() {
+        return Err(format!(
+            "automatic exposure can't be used when rendering {}",
+            how
+        ));
+    }
+    if options.filter.radius() > 0.5 {
+        return Err(format!(
+            "filters wider than a pixel can't be used when rendering {}",
+            how
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that the filter is narrow enough for a checkerboard render, which
+/// has no samples from the pixels it skips.
+fn check_checkerboard_filter(options: &RenderOptions) -> Result<(), String> {
+    if options.filter.radius() > 0.5 {
+        return Err(
+            "filters wider than a pixel can't be used for checkerboard rendering".to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// A render made by `Camera::render_checkerboard`, with half of its pixels
+/// traced (and the rest interpolated), or all of them once completed.
+#[derive(Clone, Debug)]
+pub struct CheckerboardRender {
+    pub image: Canvas,
+    /// What each pixel cost to render, in row-major order. Interpolated
+    /// pixels cost nothing.
+    pub costs: Vec<PixelCost>,
+    radiance: Vec<Color>,
+    complete: bool,
+}
+
+impl CheckerboardRender {
+    /// Whether every pixel has been traced.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+}
+
+/// The part of a frame covered by a slice of a render.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    /// The size of the whole frame.
+    pub frame_width: usize,
+    pub frame_height: usize,
+}
+
+/// One layer of a render from `Camera::render_layers`.
+#[derive(Clone, Debug)]
+pub struct LayerImage {
+    pub layer: RenderLayer,
+    /// The layer's colors, premultiplied by its alpha.
+    pub canvas: Canvas,
+    /// How much of each pixel the layer covers, from 0 to 1, in row-major
+    /// order.
+    pub alpha: Vec<f32>,
+}
+
+/// Composites the layers, each over those before it, with the "over"
+/// operator for premultiplied colors.
+pub fn composite(layers: &[LayerImage]) -> Result<Canvas, String> {
+    let first = &layers
+        .first()
+        .ok_or("there are no layers to composite")?
+        .canvas;
+    let mut image = Canvas::new_with_encoding(first.width, first.height, first.encoding);
+    for layer in layers {
+        let canvas = &layer.canvas;
+        if (canvas.width, canvas.height) != (image.width, image.height) {
+            return Err(format!(
+                "a {}x{} layer can't be composited into a {}x{} image",
+                canvas.width, canvas.height, image.width, image.height
+            ));
+        }
 
         for y in 0..image.height {
             for x in 0..image.width {
-                let ray = self.ray(x, y);
-                let color = scene.color_at(&mut rng, ray);
-                image.set_color(x, y, color);
+                let alpha = layer.alpha[y * image.width + x];
+                let below = image.get_color(x, y);
+                image.set_color(x, y, canvas.get_color(x, y) + below * (1. - alpha));
             }
         }
+    }
+    Ok(image)
+}
 
-        image
+/// Assembles the slices of a frame (from `Camera::render_slice`) into the
+/// whole image, checking that they belong to the same frame and cover every
+/// pixel exactly once.
+pub fn stitch(slices: &[(Region, Canvas)]) -> Result<Canvas, String> {
+    let (first, first_canvas) = slices.first().ok_or("there are no slices to stitch")?;
+    let (width, height) = (first.frame_width, first.frame_height);
+    let mut covered = vec![false; width * height];
+    let mut image = Canvas::new_with_encoding(width, height, first_canvas.encoding);
+
+    for (region, canvas) in slices {
+        if (region.frame_width, region.frame_height) != (width, height) {
+            return Err(format!(
+                "slice at ({}, {}) belongs to a {}x{} frame, not {}x{}",
+                region.x, region.y, region.frame_width, region.frame_height, width, height
+            ));
+        }
+        if (canvas.width, canvas.height) != (region.width, region.height)
+            || region.x + region.width > width
+            || region.y + region.height > height
+        {
+            return Err(format!(
+                "slice at ({}, {}) doesn't fit its region of the frame",
+                region.x, region.y
+            ));
+        }
+
+        for y in region.y..region.y + region.height {
+            for x in region.x..region.x + region.width {
+                if covered[y * width + x] {
+                    return Err(format!("slices overlap at pixel ({}, {})", x, y));
+                }
+                covered[y * width + x] = true;
+            }
+        }
+        image.blit(canvas, region.x as isize, region.y as isize, None);
+    }
+
+    match covered.iter().position(|&covered| !covered) {
+        Some(i) => Err(format!(
+            "no slice covers pixel ({}, {})",
+            i % width,
+            i / width
+        )),
+        None => Ok(image),
     }
 }
 
+/// Derives the seed for a pixel's random number generator from the frame's
+/// seed, the pixel's position, and the sample pass, so that every pixel
+/// draws an independent, reproducible sequence.
+pub fn pixel_seed(frame_seed: u64, x: usize, y: usize, pass: usize) -> u64 {
+    // The SplitMix64 finalizer, applied after folding in each value.
+    let mix = |mut z: u64| {
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+
+    [x as u64, y as u64, pass as u64]
+        .iter()
+        .fold(mix(frame_seed), |hash, &value| {
+            mix(hash.wrapping_add(0x9e37_79b9_7f4a_7c15) ^ value)
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::color::*;
     use crate::geometry::*;
     use crate::light::*;
     use crate::material::*;
-    use crate::object::*;
+    use crate::obj::*;
+    use crate::sky::*;
+    use crate::texture::*;
     use assert_approx_eq::assert_approx_eq;
+    use std::collections::HashSet;
+    use std::sync::atomic::AtomicBool;
     use test::Bencher;
 
     #[test]
@@ -115,6 +1328,13 @@ mod tests {
         assert_eq!(c.pixel_size, 0.01);
     }
 
+    #[test]
+    fn the_field_of_view_in_degrees() {
+        let c = Camera::new(200, 125, Angle::degrees(90.));
+        assert_eq!(c.fov, std::f32::consts::FRAC_PI_2);
+        assert_eq!(c.pixel_size, 0.01);
+    }
+
     #[test]
     fn the_pixel_size_for_a_vertical_canvas() {
         let c = Camera::new(125, 200, std::f32::consts::FRAC_PI_2);
@@ -166,35 +1386,1637 @@ mod tests {
     }
 
     #[test]
-    fn rendering_a_scene_with_a_camera() {
+    fn projecting_a_point_finds_the_pixel_it_appears_in() {
+        let mut c = Camera::new(201, 101, std::f32::consts::FRAC_PI_2);
+        c.set_transform(Transform::look_at(
+            point3(1., 2., -5.),
+            point3(0., 0., 0.),
+            vector3(0., 1., 0.),
+        ));
+        for &(px, py) in [(100.5, 50.5), (0., 0.), (12.25, 90.75)].iter() {
+            let point = c.ray_through(px, py).position(3.);
+            let (x, y) = c.project(point).unwrap();
+            assert_approx_eq!(x, px, 1e-3);
+            assert_approx_eq!(y, py, 1e-3);
+        }
+        assert_eq!(c.project(point3(2., 4., -10.)), None);
+    }
+
+    fn view_direction(camera: &Camera) -> Tuple4 {
+        (camera.transform().world_to_local * vector3(0., 0., -1.)).normalize()
+    }
+
+    #[test]
+    fn framing_a_scene_fits_its_bounds_within_the_margin() {
         let mut scene = Scene::new();
-        scene.add_light(Light::new(point3(-10., 10., -10.), Color::new(1., 1., 1.)));
+        scene.add_object(Object::new().geometry(Geometry::plane()));
         scene.add_object(
-            Object::new().geometry(Geometry::sphere()).material(
-                Material::new()
-                    .color(Color::new(0.8, 1.0, 0.6))
-                    .diffuse(0.7)
-                    .specular(0.2),
-            ),
+            Object::new()
+                .geometry(Geometry::cube())
+                .transform(Transform::new().translate(2., 1., 0.).scale(3., 1., 0.5)),
         );
         scene.add_object(
-            Object::new()
-                .geometry(Geometry::sphere())
-                .transform(Transform::new().scale(0.5, 0.5, 0.5)),
+            Object::new().transform(Transform::new().translate(-4., 3., 6.).scale(2., 2., 2.)),
         );
+        let bounds = scene.bounds().unwrap();
 
-        let mut camera = Camera::new(11, 11, std::f32::consts::FRAC_PI_2);
-        let from = point3(0., 0., -5.);
-        let to = point3(0., 0., 0.);
-        let up = vector3(0., 1., 0.);
-        camera.set_transform(Transform::look_at(from, to, up));
+        let mut camera = Camera::new(200, 100, std::f32::consts::FRAC_PI_3);
+        camera.set_transform(Transform::look_at(
+            point3(40., 25., -30.),
+            point3(0., 0., 0.),
+            vector3(0., 1., 0.),
+        ));
+        let direction = view_direction(&camera);
+        let margin = 0.1;
+        camera.frame_scene(&scene, margin).unwrap();
 
-        let image = camera.render(scene);
-        let pixel = image.get_color(5, 5);
+        let after = view_direction(&camera);
+        assert_approx_eq!(after.x, direction.x, 1e-4);
+        assert_approx_eq!(after.y, direction.y, 1e-4);
+        assert_approx_eq!(after.z, direction.z, 1e-4);
 
-        assert_approx_eq!(pixel.r, 0.38066, 1e-2);
-        assert_approx_eq!(pixel.g, 0.47583, 1e-2);
-        assert_approx_eq!(pixel.b, 0.2855, 1e-2);
+        let (width, height) = (camera.hsize as f32, camera.vsize as f32);
+        let (mut closest_x, mut closest_y) = (f32::INFINITY, f32::INFINITY);
+        let (min, max) = (bounds.min, bounds.max);
+        for &x in [min.x, max.x].iter() {
+            for &y in [min.y, max.y].iter() {
+                for &z in [min.z, max.z].iter() {
+                    let (px, py) = camera.project(point3(x, y, z)).unwrap();
+                    assert!(px >= margin * width - 1e-3 && px <= (1. - margin) * width + 1e-3);
+                    assert!(py >= margin * height - 1e-3 && py <= (1. - margin) * height + 1e-3);
+                    closest_x = closest_x.min(px.min(width - px));
+                    closest_y = closest_y.min(py.min(height - py));
+                }
+            }
+        }
+        // The camera is no farther than it needs to be: the box touches the
+        // margin on one side or another.
+        let touches = (closest_x - margin * width)
+            .abs()
+            .min((closest_y - margin * height).abs());
+        assert!(touches < 1e-2, "{}", touches);
+    }
+
+    #[test]
+    fn framing_needs_a_bounded_scene_and_a_margin_below_half() {
+        let mut camera = Camera::new(10, 10, std::f32::consts::FRAC_PI_2);
+        let mut scene = Scene::new();
+        scene.add_object(Object::new().geometry(Geometry::plane()));
+        assert!(camera.frame_scene(&scene, 0.1).is_err());
+
+        scene.add_object(Object::new());
+        assert!(camera.frame_scene(&scene, 0.5).is_err());
+        assert!(camera.frame_scene(&scene, -0.1).is_err());
+        assert_eq!(camera.transform(), Transform::new());
+        assert!(camera.frame_scene(&scene, 0.).is_ok());
+    }
+
+    #[test]
+    fn lens_rays_converge_at_the_focal_distance() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut c = Camera::new(11, 11, std::f32::consts::FRAC_PI_2);
+        assert_eq!(c.lens_ray(&mut rng, 3, 7), c.ray(3, 7));
+
+        let aperture = Aperture::polygon(6, Angle::degrees(15.));
+        c.set_lens(0.5, 4., aperture);
+        let focus = c.ray(3, 7);
+        let focus = focus.position(4. / -focus.direction.z);
+        let mut origins = HashSet::new();
+        for _ in 0..100 {
+            let r = c.lens_ray(&mut rng, 3, 7);
+            assert_approx_eq!(r.origin.z, 0.);
+            assert!(aperture.contains(r.origin.x / 0.5, r.origin.y / 0.5));
+            let p = r.position(4. / -r.direction.z);
+            assert_approx_eq!(p.x, focus.x, 1e-4);
+            assert_approx_eq!(p.y, focus.y, 1e-4);
+            origins.insert((r.origin.x.to_bits(), r.origin.y.to_bits()));
+        }
+        assert_eq!(origins.len(), 100);
+    }
+
+    #[test]
+    fn rays_spread_to_cover_a_pixel() {
+        let mut c = Camera::new(201, 101, std::f32::consts::FRAC_PI_2);
+        assert_approx_eq!(c.ray(100, 50).spread, c.pixel_size);
+        // Pixels off axis are further away, so subtend a narrower angle.
+        assert!(c.ray(0, 0).spread < c.pixel_size);
+
+        // A scaled camera transform doesn't change the spread.
+        c.set_transform(Transform::new().scale(2., 2., 2.));
+        assert_approx_eq!(c.ray(100, 50).spread, c.pixel_size);
+    }
+
+    /// Two spheres side by side, in layers 1 and 2, reflecting each other
+    /// in front of a gradient, and (if `floor`) a floor in layer 0 beneath
+    /// them.
+    fn layered_scene(floor: bool) -> (Scene, Camera) {
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(-10., 10., -10.), Color::WHITE));
+        scene.set_background(Background::VerticalGradient(
+            Color::new(0.1, 0.1, 0.1),
+            Color::new(0.4, 0.5, 0.7),
+        ));
+        if floor {
+            scene.add_object(
+                Object::new()
+                    .geometry(Geometry::plane())
+                    .transform(Transform::new().translate(0., -1., 0.)),
+            );
+        }
+        for (x, layer, color) in [
+            (-1.5, 1, Color::new(1., 0.2, 0.2)),
+            (1.5, 2, Color::new(0.2, 0.2, 1.)),
+        ] {
+            scene.add_object(
+                Object::new()
+                    .transform(Transform::new().translate(x, 0., 0.))
+                    .material(Material::new().color(color).reflective(0.3))
+                    .layer(layer),
+            );
+        }
+        let mut camera = Camera::new(32, 16, std::f32::consts::FRAC_PI_2);
+        camera.set_transform(Transform::look_at(
+            point3(0., 1.5, -5.),
+            point3(0., 0., 0.),
+            vector3(0., 1., 0.),
+        ));
+        (scene, camera)
+    }
+
+    #[test]
+    fn compositing_the_layers_reproduces_the_whole_render() {
+        let (scene, camera) = layered_scene(false);
+        // Where a sphere covers part of a pixel, its background layer is
+        // averaged over the whole pixel rather than the part left uncovered,
+        // which matters a little for a filter wider than the pixel.
+        for (options, tolerance) in [
+            (RenderOptions::new(), 1e-5),
+            (
+                RenderOptions::new()
+                    .min_samples(4)
+                    .max_samples(4)
+                    .filter(Filter::tent()),
+                5e-3,
+            ),
+        ] {
+            let layers = camera.render_layers(&scene, &options, true).unwrap();
+            let tags: Vec<RenderLayer> = layers.iter().map(|layer| layer.layer).collect();
+            let layer = |layer| RenderLayer::Objects {
+                layer,
+                others_visible: true,
+            };
+            assert_eq!(tags, vec![RenderLayer::Background, layer(1), layer(2)]);
+
+            let expected = camera.render_with_options(&scene, &options);
+            let actual = composite(&layers).unwrap();
+            for (a, e) in actual.data.iter().zip(&expected.data) {
+                assert_approx_eq!(a, e, tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn other_layers_can_be_left_out_of_shadows_and_reflections() {
+        let (scene, camera) = layered_scene(true);
+        let options = RenderOptions::new();
+        let floor = |others_visible| {
+            let layers = camera
+                .render_layers(&scene, &options, others_visible)
+                .unwrap();
+            layers[1].canvas.data.iter().sum::<f32>()
+        };
+        // Without the spheres' shadows, the floor is brighter.
+        assert!(floor(false) > floor(true));
+    }
+
+    #[test]
+    fn rendering_layers_with_auto_exposure_is_an_error() {
+        let (scene, camera) = layered_scene(true);
+        let options = RenderOptions::new().auto_exposure(AutoExposure::new());
+        assert_eq!(
+            camera.render_layers(&scene, &options, true).unwrap_err(),
+            "automatic exposure can't be used when rendering in layers"
+        );
+    }
+
+    #[test]
+    fn compositing_layers_of_different_sizes_fails() {
+        let layer = |width| LayerImage {
+            layer: RenderLayer::All,
+            canvas: Canvas::new(width, 2),
+            alpha: vec![1.; width * 2],
+        };
+        assert!(composite(&[layer(2), layer(3)]).is_err());
+        assert!(composite(&[]).is_err());
+    }
+
+    #[test]
+    fn the_horizon_over_a_plane_has_no_isolated_shadowed_pixels() {
+        // A tilted plane, seen from above along a strip of rays parallel to
+        // it, which rounding error leaves a hair above or below parallel in
+        // its space. Hitting it around t = 1e8, where the hit point is only
+        // known to within several units, would put some of them under the
+        // plane and so in its shadow.
+        let tilt = 0.3f32;
+        let normal = vector3(-tilt.sin(), tilt.cos(), 0.);
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(
+            point3(-100. * tilt.sin(), 100. * tilt.cos(), 0.),
+            Color::WHITE,
+        ));
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::plane())
+                .transform(Transform::new().rotate_z(tilt)),
+        );
+        scene.set_background(Background::Solid(Color::new(0.8, 0.8, 0.8)));
+
+        let mut camera = Camera::new(200, 1, std::f32::consts::FRAC_PI_2);
+        let from = point3(0., 0., 0.) + normal;
+        camera.set_transform(Transform::look_at(from, from + vector3(0., 0., 1.), normal));
+        let image = camera.render(scene);
+        let brightness: Vec<f32> = (0..200).map(|x| image.get_color(x, 0).r).collect();
+        for x in 1..199 {
+            let darker = |other: f32| brightness[x] < other - 0.1;
+            assert!(
+                !(darker(brightness[x - 1]) && darker(brightness[x + 1])),
+                "pixel {} is shadowed: {:?}",
+                x,
+                &brightness[x - 1..=x + 1]
+            );
+        }
+    }
+
+    #[test]
+    fn rendering_a_scene_with_a_camera() {
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(-10., 10., -10.), Color::new(1., 1., 1.)));
+        scene.add_object(
+            Object::new().geometry(Geometry::sphere()).material(
+                Material::new()
+                    .color(Color::new(0.8, 1.0, 0.6))
+                    .diffuse(0.7)
+                    .specular(0.2),
+            ),
+        );
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::sphere())
+                .transform(Transform::new().scale(0.5, 0.5, 0.5)),
+        );
+
+        let mut camera = Camera::new(11, 11, std::f32::consts::FRAC_PI_2);
+        let from = point3(0., 0., -5.);
+        let to = point3(0., 0., 0.);
+        let up = vector3(0., 1., 0.);
+        camera.set_transform(Transform::look_at(from, to, up));
+
+        let image = camera.render(scene);
+        let pixel = image.get_color(5, 5);
+
+        assert_approx_eq!(pixel.r, 0.38066, 1e-2);
+        assert_approx_eq!(pixel.g, 0.47583, 1e-2);
+        assert_approx_eq!(pixel.b, 0.2855, 1e-2);
+    }
+
+    fn default_scene() -> Scene {
+        scene_lit_by(Color::new(1., 1., 1.))
+    }
+
+    fn scene_lit_by(intensity: Color) -> Scene {
+        scene_with_light(Light::new(point3(-10., 10., -10.), intensity))
+    }
+
+    fn scene_with_light(light: Light) -> Scene {
+        let mut scene = Scene::new();
+        scene.add_light(light);
+        scene.add_object(
+            Object::new().geometry(Geometry::sphere()).material(
+                Material::new()
+                    .color(Color::new(0.8, 1.0, 0.6))
+                    .diffuse(0.7)
+                    .specular(0.2),
+            ),
+        );
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::sphere())
+                .transform(Transform::new().scale(0.5, 0.5, 0.5)),
+        );
+        scene
+    }
+
+    fn default_camera() -> Camera {
+        let mut camera = Camera::new(11, 11, std::f32::consts::FRAC_PI_2);
+        camera.set_transform(Transform::look_at(
+            point3(0., 0., -5.),
+            point3(0., 0., 0.),
+            vector3(0., 1., 0.),
+        ));
+        camera
+    }
+
+    #[test]
+    fn rendering_with_the_default_options() {
+        // The lit pixels of the reference render, from before rendering took
+        // options; every other pixel is black.
+        let lit = [
+            ((5, 4), Color::new(0.52157, 0.65098, 0.39216)),
+            ((4, 5), Color::new(0.52157, 0.65098, 0.39216)),
+            ((5, 5), Color::new(0.38039, 0.47451, 0.28627)),
+            ((6, 5), Color::new(0.07843, 0.10196, 0.05882)),
+            ((5, 6), Color::new(0.07843, 0.10196, 0.05882)),
+        ];
+        let image =
+            default_camera().render_with_options(&default_scene(), &RenderOptions::default());
+
+        for y in 0..11 {
+            for x in 0..11 {
+                let expected = lit
+                    .iter()
+                    .find(|&&(pixel, _)| pixel == (x, y))
+                    .map_or(Color::BLACK, |&(_, color)| color);
+                let actual = image.get_color(x, y);
+                assert_approx_eq!(actual.r, expected.r, 1e-2);
+                assert_approx_eq!(actual.g, expected.g, 1e-2);
+                assert_approx_eq!(actual.b, expected.b, 1e-2);
+            }
+        }
+    }
+
+    #[test]
+    fn rendering_with_exposure_compensation() {
+        let camera = default_camera();
+        let options = RenderOptions::new().exposure_compensation(true);
+        let a = camera.render(default_scene());
+        let b = camera.render_with_options(&default_scene(), &options);
+
+        // The center pixel is on the view axis.
+        assert_eq!(a.get_color(5, 5), b.get_color(5, 5));
+        assert!(b.get_color(5, 4).g < a.get_color(5, 4).g);
+    }
+
+    #[test]
+    fn the_exposure_compensation_at_the_center_of_the_canvas() {
+        let c = Camera::new(201, 101, std::f32::consts::FRAC_PI_2);
+        assert_approx_eq!(c.compensation(100, 50), 1., 1e-5);
+    }
+
+    #[test]
+    fn the_exposure_compensation_for_a_pixel_45_degrees_off_axis() {
+        // The center of pixel (0, 0) lies at (1, 0, -1) on the canvas.
+        let c = Camera::new(3, 1, 2. * 1.5_f32.atan());
+        let cos = std::f32::consts::FRAC_PI_4.cos();
+        assert_approx_eq!(c.compensation(0, 0), cos * cos * cos, 1e-5);
+        assert_approx_eq!(c.compensation(1, 0), 1., 1e-5);
+    }
+
+    #[test]
+    fn the_color_grade_is_applied_before_quantization() {
+        // Just under half of one 8 bit step, so it rounds to black unless
+        // the gain is applied first.
+        let dim = 0.4 / 255.;
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(-10., 10., -10.), Color::WHITE));
+        scene.add_object(
+            Object::new().material(
+                Material::new()
+                    .color(Color::new(dim, dim, dim))
+                    .ambient(1.)
+                    .diffuse(0.)
+                    .specular(0.),
+            ),
+        );
+        let mut camera = Camera::new(1, 1, 0.1);
+        camera.set_transform(Transform::look_at(
+            point3(0., 0., -5.),
+            point3(0., 0., 0.),
+            vector3(0., 1., 0.),
+        ));
+
+        let plain = camera.render_with_options(&scene, &RenderOptions::new());
+        assert_eq!(plain.to_rgb8(), vec![0, 0, 0]);
+
+        let grade = ColorGrade::new().gain(Color::new(2., 2., 2.));
+        let graded = camera.render_with_options(&scene, &RenderOptions::new().grade(grade));
+        assert_eq!(graded.to_rgb8(), vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn auto_exposure_compensates_for_light_intensity() {
+        let camera = default_camera();
+        let options = RenderOptions::new().auto_exposure(AutoExposure::new());
+
+        let bright = scene_lit_by(Color::new(4., 4., 4.));
+        let a = camera.render_with_options(&default_scene(), &options);
+        let b = camera.render_with_options(&bright, &options);
+        for (a, b) in a.to_rgb8().iter().zip(&b.to_rgb8()) {
+            assert!((*a as i32 - *b as i32).abs() <= 1);
+        }
+
+        // Without auto exposure, the brighter scene is visibly brighter.
+        let a = camera.render_with_options(&default_scene(), &RenderOptions::new());
+        let b = camera.render_with_options(&bright, &RenderOptions::new());
+        assert!(b.get_color(5, 5).g > a.get_color(5, 5).g + 0.1);
+    }
+
+    #[test]
+    fn doubling_the_lumens_and_halving_the_exposure_gives_the_same_image() {
+        let camera = default_camera();
+        let lit_by = |lumens: f32| {
+            scene_with_light(Light::new(point3(-10., 10., -10.), Color::WHITE).lumens(lumens))
+        };
+        let exposure = PhysicalExposure::new().iso(1000.).shutter(0.1).f_number(1.);
+
+        let a = camera.render_with_options(
+            &lit_by(3000.),
+            &RenderOptions::new().physical_exposure(exposure),
+        );
+        let b = camera.render_with_options(
+            &lit_by(6000.),
+            &RenderOptions::new().physical_exposure(exposure.shutter(0.05)),
+        );
+        assert_eq!(a.data, b.data);
+
+        // The image is neither black nor blown out, and changes if only the
+        // lumens do.
+        let center = a.get_color(5, 5);
+        assert!(center.g > 0.2 && center.g < 0.9, "{:?}", center);
+        let c = camera.render_with_options(
+            &lit_by(6000.),
+            &RenderOptions::new().physical_exposure(exposure),
+        );
+        assert!(c.get_color(5, 5).g > center.g + 0.1);
+    }
+
+    #[test]
+    fn legacy_scenes_are_unaffected_by_physical_units() {
+        // Legacy lights don't fall off, and no physical exposure is applied
+        // unless asked for.
+        let camera = default_camera();
+        assert_eq!(RenderOptions::new().physical_exposure, None);
+        let pixel = camera
+            .render(scene_with_light(Light::new(
+                point3(-10., 10., -10.),
+                Color::WHITE,
+            )))
+            .get_color(5, 5);
+        assert_approx_eq!(pixel.g, 0.47583, 1e-2);
+
+        // Lights behind the camera shine straight at the center of the
+        // sphere, from the same direction at any distance.
+        let near = scene_with_light(Light::new(point3(0., 0., -10.), Color::WHITE));
+        let far = scene_with_light(Light::new(point3(0., 0., -40.), Color::WHITE));
+        let a = camera.render(near);
+        let b = camera.render(far);
+        assert_eq!(a.get_color(5, 5), b.get_color(5, 5));
+    }
+
+    #[test]
+    fn rendering_several_frames_with_one_context() {
+        let scene = default_scene();
+        let context = scene.prepare_render();
+        let options = RenderOptions::default();
+
+        for i in 0..3 {
+            let mut camera = default_camera();
+            camera.set_transform(Transform::look_at(
+                point3(i as f32, 0., -5.),
+                point3(0., 0., 0.),
+                vector3(0., 1., 0.),
+            ));
+            let shared = camera.render_with_context(&scene, &context, &options);
+            let fresh = camera.render_with_context(&scene, &scene.prepare_render(), &options);
+            assert_eq!(shared.data, fresh.data);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "stale render context")]
+    fn rendering_with_a_stale_context() {
+        let mut scene = default_scene();
+        let context = scene.prepare_render();
+        scene.add_object(Object::new());
+        default_camera().render_with_context(&scene, &context, &RenderOptions::default());
+    }
+
+    #[test]
+    fn cancelling_a_render_after_the_first_block() {
+        let camera = progressive_camera();
+        let scene = noisy_scene();
+        let options = RenderOptions::new().seed(42);
+        let full = camera.render_with_options(&scene, &options);
+
+        let mut checks = 0;
+        let (image, complete) = camera.render_cancellable(&scene, &options, || {
+            checks += 1;
+            checks > 1
+        });
+
+        // With one thread, the first block is the top left tile.
+        assert!(!complete);
+        assert_eq!(checks, 2);
+        for y in 0..40 {
+            for x in 0..50 {
+                if x < TILE_SIZE && y < TILE_SIZE {
+                    assert_eq!(image.get_color(x, y), full.get_color(x, y));
+                } else {
+                    assert_eq!(image.get_color(x, y), Color::BLACK, "({}, {})", x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cancellable_renders_share_blocks_between_threads() {
+        let camera = progressive_camera();
+        let scene = noisy_scene();
+        let options = RenderOptions::new().seed(42).threads(4);
+        let full = camera.render_with_options(&scene, &options);
+
+        // Four threads get blocks of two by two tiles.
+        let mut checks = 0;
+        let (image, complete) = camera.render_cancellable(&scene, &options, || {
+            checks += 1;
+            checks > 1
+        });
+        assert!(!complete);
+        assert_eq!(image.get_color(31, 31), full.get_color(31, 31));
+        assert_eq!(image.get_color(32, 0), Color::BLACK);
+        assert_eq!(image.get_color(0, 32), Color::BLACK);
+
+        let (image, complete) = camera.render_cancellable(&scene, &options, || false);
+        assert!(complete);
+        assert_eq!(image.data, full.data);
+    }
+
+    #[test]
+    fn cancelling_a_render_with_a_flag() {
+        let camera = default_camera();
+        let flag = AtomicBool::new(true);
+        let (image, complete) =
+            camera.render_cancellable(&default_scene(), &RenderOptions::default(), || {
+                flag.load(Ordering::Relaxed)
+            });
+
+        assert!(!complete);
+        assert!(image.data.iter().all(|&c| c == 0.));
+    }
+
+    #[test]
+    fn rendering_with_a_generous_budget_completes() {
+        let camera = default_camera();
+        let (image, complete) = camera.render_with_budget(
+            &default_scene(),
+            &RenderOptions::default(),
+            Duration::from_secs(60),
+        );
+
+        assert!(complete);
+        assert_eq!(image.data, camera.render(default_scene()).data);
+    }
+
+    #[test]
+    fn rendering_with_no_budget_is_incomplete() {
+        let camera = default_camera();
+        let (image, complete) =
+            camera.render_with_budget(&noisy_scene(), &RenderOptions::default(), Duration::ZERO);
+        assert!(!complete);
+        assert!(image.data.iter().all(|&c| c == 0.));
+    }
+
+    fn noisy_scene() -> Scene {
+        let mut scene = default_scene();
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::plane())
+                .transform(Transform::new().translate(0., -1., 0.))
+                .material(Material::new().texture(Texture::white_noise())),
+        );
+        scene
+    }
+
+    #[test]
+    fn rendering_with_one_or_many_threads_is_identical() {
+        let mut camera = Camera::new(50, 40, std::f32::consts::FRAC_PI_2);
+        camera.set_transform(Transform::look_at(
+            point3(0., 1., -5.),
+            point3(0., -1., 0.),
+            vector3(0., 1., 0.),
+        ));
+        let scene = noisy_scene();
+        let options = RenderOptions::new().seed(42);
+
+        let single = camera.render_with_options(&scene, &options.clone().threads(1));
+        for _ in 0..4 {
+            let multi = camera.render_with_options(&scene, &options.clone().threads(8));
+            assert_eq!(single.data, multi.data);
+        }
+
+        let reseeded = camera.render_with_options(&scene, &options.seed(43));
+        assert_ne!(single.data, reseeded.data);
+    }
+
+    fn perlin_scene(texture: Texture) -> Scene {
+        let mut scene = default_scene();
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::plane())
+                .transform(Transform::new().translate(0., -1., 0.))
+                .material(Material::new().texture(texture).reflective(0.5)),
+        );
+        scene
+    }
+
+    #[test]
+    fn caching_a_texture_does_not_change_the_render() {
+        let mut camera = Camera::new(16, 16, std::f32::consts::FRAC_PI_2);
+        camera.set_transform(Transform::look_at(
+            point3(0., 1., -5.),
+            point3(0., -1., 0.),
+            vector3(0., 1., 0.),
+        ));
+        let texture = Texture {
+            transform: Transform::new().scale(0.25, 0.25, 0.25),
+            ..Texture::perlin(Color::BLACK, Color::WHITE, 3)
+        };
+        let options = RenderOptions::new().seed(42);
+
+        let uncached = camera.render_with_options(&perlin_scene(texture.clone()), &options);
+        let scene = perlin_scene(texture.cacheable());
+        let cached = camera.render_with_options(&scene, &options);
+        for (a, b) in uncached.data.iter().zip(&cached.data) {
+            assert_approx_eq!(a, b, 1e-3);
+        }
+        assert_ne!(uncached.data, vec![0.; uncached.data.len()]);
+
+        // Which thread evaluates a cell first doesn't matter.
+        let threaded = camera.render_with_options(&scene, &options.threads(4));
+        assert_eq!(cached.data, threaded.data);
+    }
+
+    #[test]
+    fn editing_a_cached_texture_changes_the_render() {
+        let texture = Texture::perlin(Color::BLACK, Color::WHITE, 3).cacheable();
+        let mut scene = perlin_scene(texture);
+        let floor = scene.object_count() - 1;
+        let r = ray(point3(0., 1., -5.), vector3(0., -1., 1.).normalize());
+        let mut rng = SmallRng::seed_from_u64(0);
+        let before = scene.color_at(&mut rng, r);
+
+        let red = Color::new(1., 0., 0.);
+        scene.material_mut(floor).texture.spec = TextureSpec::Perlin {
+            a: red,
+            b: red,
+            seed: 1,
+        };
+        let after = scene.color_at(&mut rng, r);
+        assert_ne!(before, after);
+        assert!(after.r > 0. && after.g < before.g);
+
+        let uncached = perlin_scene(Texture::perlin(red, red, 1));
+        assert_eq!(after, uncached.color_at(&mut rng, r));
+    }
+
+    #[test]
+    fn the_order_in_which_pixels_are_rendered_does_not_matter() {
+        let camera = progressive_camera();
+        let scene = noisy_scene();
+        let options = RenderOptions::new().seed(42);
+
+        // All at once with several threads, and one tile at a time.
+        let tiled = camera.render_with_options(&scene, &options.clone().threads(4));
+        let (blocks, complete) = camera
+            .render_progressive(
+                &scene,
+                &options,
+                TILE_SIZE,
+                |_, _| ControlFlow::Continue(()),
+            )
+            .unwrap();
+        assert!(complete);
+        assert_eq!(tiled.data, blocks.data);
+    }
+
+    #[test]
+    fn drawing_more_random_numbers_in_one_pixel_leaves_the_others_unchanged() {
+        let mut camera = Camera::new(20, 20, std::f32::consts::FRAC_PI_2);
+        camera.set_transform(Transform::look_at(
+            point3(0., 1., -5.),
+            point3(0., -1., 0.),
+            vector3(0., 1., 0.),
+        ));
+        let mut scene = noisy_scene();
+        let options = RenderOptions::new().seed(42);
+        let before = camera.render_with_options(&scene, &options);
+
+        // A speck of noisy glass, seen by only one pixel, draws more random
+        // numbers there (for each surface its ray passes through).
+        let center = camera.ray(5, 5).position(3.);
+        scene.add_object(
+            Object::new()
+                .transform(
+                    Transform::new()
+                        .translate(center.x, center.y, center.z)
+                        .scale(0.01, 0.01, 0.01),
+                )
+                .material(
+                    Material::new()
+                        .texture(Texture::white_noise())
+                        .transparency(1.),
+                )
+                .casts_shadow(false),
+        );
+        let after = camera.render_with_options(&scene, &options);
+
+        assert_ne!(before.get_color(5, 5), after.get_color(5, 5));
+        for y in 0..20 {
+            for x in 0..20 {
+                if (x, y) != (5, 5) {
+                    assert_eq!(before.get_color(x, y), after.get_color(x, y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_checkerboard_preview_traces_half_the_pixels() {
+        let camera = default_camera();
+        let preview = camera
+            .render_checkerboard(&default_scene(), &RenderOptions::new())
+            .unwrap();
+        assert!(!preview.is_complete());
+
+        let traced: usize = preview.costs.iter().map(|cost| cost.samples).sum();
+        assert_eq!(traced, (11_usize * 11).div_ceil(2));
+        for (i, cost) in preview.costs.iter().enumerate() {
+            let (x, y) = (i % 11, i / 11);
+            assert_eq!(cost.samples, 1 - (x + y) % 2, "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn skipped_pixels_are_the_mean_of_their_traced_neighbors() {
+        let camera = default_camera();
+        let full = camera.render(default_scene());
+        let image = camera
+            .render_checkerboard(&default_scene(), &RenderOptions::new())
+            .unwrap()
+            .image;
+
+        for y in 0..11 {
+            for x in 0..11 {
+                if (x + y) % 2 == 0 {
+                    assert_eq!(image.get_color(x, y), full.get_color(x, y));
+                    continue;
+                }
+                let neighbors: Vec<Color> = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                    .iter()
+                    .map(|&(dx, dy)| (x as isize + dx, y as isize + dy))
+                    .filter(|&(x, y)| (0..11).contains(&x) && (0..11).contains(&y))
+                    .map(|(x, y)| full.get_color(x as usize, y as usize))
+                    .collect();
+                let mean = neighbors.iter().fold(Color::BLACK, |sum, &c| sum + c)
+                    * (1. / neighbors.len() as f32);
+                let color = image.get_color(x, y);
+                assert_approx_eq!(color.r, mean.r);
+                assert_approx_eq!(color.g, mean.g);
+                assert_approx_eq!(color.b, mean.b);
+            }
+        }
+    }
+
+    #[test]
+    fn completing_a_checkerboard_render_gives_the_full_render() {
+        let camera = default_camera();
+        let scene = default_scene();
+        let full = camera.render(default_scene());
+        for threads in [1, 4] {
+            let options = RenderOptions::new().threads(threads);
+            let preview = camera.render_checkerboard(&scene, &options).unwrap();
+            let complete = camera
+                .complete_checkerboard(&scene, &options, preview)
+                .unwrap();
+            assert!(complete.is_complete());
+            assert_eq!(complete.image.data, full.data);
+            assert!(complete.costs.iter().all(|cost| cost.samples == 1));
+        }
+    }
+
+    #[test]
+    fn checkerboard_rendering_with_a_wide_filter_is_an_error() {
+        let camera = default_camera();
+        let scene = default_scene();
+        let preview = camera
+            .render_checkerboard(&scene, &RenderOptions::new())
+            .unwrap();
+        let wide = RenderOptions::new().filter(Filter::gaussian());
+        assert_eq!(
+            camera.render_checkerboard(&scene, &wide).unwrap_err(),
+            "filters wider than a pixel can't be used for checkerboard rendering"
+        );
+        assert!(camera
+            .complete_checkerboard(&scene, &wide, preview)
+            .is_err());
+    }
+
+    fn silhouette_scene() -> Scene {
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(0., 0., -10.), Color::WHITE));
+        scene.add_object(
+            Object::new().geometry(Geometry::sphere()).material(
+                Material::new()
+                    .ambient(1.)
+                    .diffuse(0.)
+                    .specular(0.)
+                    .color(Color::WHITE),
+            ),
+        );
+        scene
+    }
+
+    #[test]
+    fn reconstruction_filters_blend_a_silhouette() {
+        let mut camera = Camera::new(21, 21, std::f32::consts::FRAC_PI_3);
+        camera.set_transform(Transform::look_at(
+            point3(0., 0., -5.),
+            point3(0., 0., 0.),
+            vector3(0., 1., 0.),
+        ));
+        let scene = silhouette_scene();
+        let options = RenderOptions::new().min_samples(16).max_samples(16);
+        let blended = |image: &Canvas| {
+            (0..21 * 21)
+                .filter(|&i| (0.05..0.95).contains(&image.get_color(i % 21, i / 21).r))
+                .count()
+        };
+
+        // Box filtering samples pixel centers, which are either on or off
+        // the sphere.
+        let boxed = camera.render_with_options(&scene, &options);
+        assert_eq!(blended(&boxed), 0);
+        assert_eq!(
+            boxed.data,
+            camera
+                .render_with_options(&scene, &options.clone().filter(Filter::Box))
+                .data
+        );
+
+        for filter in [Filter::tent(), Filter::gaussian(), Filter::mitchell()] {
+            let image = camera.render_with_options(&scene, &options.clone().filter(filter));
+            assert!(blended(&image) > 20, "{:?}", filter);
+            assert_approx_eq!(image.get_color(10, 10).r, 1.);
+            assert_approx_eq!(image.get_color(0, 0).r, 0.);
+        }
+    }
+
+    #[test]
+    fn reconstruction_filters_preserve_a_uniform_image() {
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(0., 0., -10.), Color::WHITE));
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::plane())
+                .transform(
+                    Transform::new()
+                        .translate(0., 0., 5.)
+                        .rotate_x(std::f32::consts::FRAC_PI_2),
+                )
+                .material(
+                    Material::new()
+                        .color(Color::new(0.5, 0.5, 0.5))
+                        .ambient(1.)
+                        .diffuse(0.)
+                        .specular(0.),
+                ),
+        );
+        let camera = default_camera();
+
+        // Each pixel is normalized by the weight reaching it, including
+        // those at the edges, which miss the samples beyond the image.
+        for filter in [Filter::tent(), Filter::gaussian(), Filter::mitchell()] {
+            let options = RenderOptions::new().max_samples(4).filter(filter);
+            let image = camera.render_with_options(&scene, &options);
+            for i in 0..11 * 11 {
+                assert_approx_eq!(image.get_color(i % 11, i / 11).r, 0.5, 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn filtered_renders_are_independent_of_the_thread_count() {
+        let camera = default_camera();
+        let scene = noisy_scene();
+        let options = RenderOptions::new().max_samples(2).filter(Filter::tent());
+
+        let single = camera.render_with_options(&scene, &options.clone().threads(1));
+        let multi = camera.render_with_options(&scene, &options.threads(8));
+        assert_eq!(single.data, multi.data);
+    }
+
+    #[test]
+    fn invalid_slices_are_errors() {
+        let (camera, scene) = (default_camera(), default_scene());
+        let wide = RenderOptions::new().filter(Filter::gaussian());
+        assert_eq!(
+            camera.render_slice(&scene, &wide, 0, 2).unwrap_err(),
+            "filters wider than a pixel can't be used when rendering in slices"
+        );
+        let auto = RenderOptions::new().auto_exposure(AutoExposure::new());
+        assert!(camera.render_slice(&scene, &auto, 0, 2).is_err());
+        assert_eq!(
+            camera
+                .render_slice(&scene, &RenderOptions::new(), 2, 2)
+                .unwrap_err(),
+            "slice 2 is out of range for 2 slices"
+        );
+    }
+
+    fn progressive_camera() -> Camera {
+        let mut camera = Camera::new(50, 40, std::f32::consts::FRAC_PI_2);
+        camera.set_transform(Transform::look_at(
+            point3(0., 1., -5.),
+            point3(0., -1., 0.),
+            vector3(0., 1., 0.),
+        ));
+        camera
+    }
+
+    #[test]
+    fn rendering_progressively_in_blocks() {
+        let camera = progressive_camera();
+        let scene = noisy_scene();
+        let options = RenderOptions::new().seed(42).threads(2);
+        let full = camera.render_with_options(&scene, &options);
+
+        // 20 rounds up to 32 pixels (two tiles), leaving narrower blocks on
+        // the right and bottom.
+        for block_size in [20, 32] {
+            let mut regions = vec![];
+            let (image, complete) = camera
+                .render_progressive(&scene, &options, block_size, |region, image| {
+                    assert_eq!((image.width, image.height), (50, 40));
+                    regions.push((region.x, region.y, region.width, region.height));
+                    ControlFlow::Continue(())
+                })
+                .unwrap();
+
+            assert!(complete);
+            assert_eq!(
+                regions,
+                vec![
+                    (0, 0, 32, 32),
+                    (32, 0, 18, 32),
+                    (0, 32, 32, 8),
+                    (32, 32, 18, 8)
+                ]
+            );
+            assert_eq!(image.data, full.data);
+        }
+
+        let mut blocks = 0;
+        let (image, complete) = camera
+            .render_progressive(&scene, &options, 0, |_, _| {
+                blocks += 1;
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert!(complete);
+        assert_eq!(blocks, 4 * 3);
+        assert_eq!(image.data, full.data);
+    }
+
+    #[test]
+    fn cancelling_a_progressive_render_after_the_first_block() {
+        let camera = progressive_camera();
+        let scene = noisy_scene();
+        let options = RenderOptions::new().seed(42);
+        let full = camera.render_with_options(&scene, &options);
+
+        let mut blocks = 0;
+        let (image, complete) = camera
+            .render_progressive(&scene, &options, 32, |_, _| {
+                blocks += 1;
+                ControlFlow::Break(())
+            })
+            .unwrap();
+
+        assert!(!complete);
+        assert_eq!(blocks, 1);
+        for y in 0..40 {
+            for x in 0..50 {
+                if x < 32 && y < 32 {
+                    assert_eq!(image.get_color(x, y), full.get_color(x, y));
+                } else {
+                    assert_eq!(image.get_color(x, y), Color::BLACK, "({}, {})", x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stopping_after_the_last_block_still_completes_the_render() {
+        let camera = progressive_camera();
+        let (_, complete) = camera
+            .render_progressive(&default_scene(), &RenderOptions::new(), 64, |_, _| {
+                ControlFlow::Break(())
+            })
+            .unwrap();
+        assert!(complete);
+    }
+
+    #[test]
+    fn options_that_need_the_whole_image_in_several_blocks_are_errors() {
+        let camera = progressive_camera();
+        let scene = default_scene();
+        let render = |options: RenderOptions, block_size| {
+            camera.render_progressive(&scene, &options, block_size, |_, _| {
+                ControlFlow::Continue(())
+            })
+        };
+
+        let auto = RenderOptions::new().auto_exposure(AutoExposure::new());
+        assert_eq!(
+            render(auto.clone(), 16).unwrap_err(),
+            "automatic exposure can't be used when rendering in several blocks"
+        );
+        let wide = RenderOptions::new().filter(Filter::gaussian());
+        assert!(render(wide.clone(), 16).is_err());
+
+        // A single block covering the image is fine.
+        assert!(render(auto, 64).is_ok());
+        assert!(render(wide, 64).is_ok());
+    }
+
+    fn penumbra_scene() -> Scene {
+        let mut scene = Scene::new();
+        scene.add_light(Light::area(
+            point3(-1., 5., -1.),
+            vector3(2., 0., 0.),
+            2,
+            vector3(0., 0., 2.),
+            2,
+            Color::WHITE,
+        ));
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::plane())
+                .transform(Transform::new().translate(0., -1., 0.))
+                .material(Material::new().specular(0.)),
+        );
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::sphere())
+                .transform(Transform::new().translate(0., 2., 0.).scale(0.5, 0.5, 0.5)),
+        );
+        scene
+    }
+
+    fn penumbra_camera() -> Camera {
+        let mut camera = Camera::new(20, 1, 2.);
+        camera.set_transform(Transform::look_at(
+            point3(0., 0.5, 0.),
+            point3(0., -1., 0.),
+            vector3(0., 0., 1.),
+        ));
+        camera
+    }
+
+    #[test]
+    fn adaptive_sampling_concentrates_samples_in_the_penumbra() {
+        let scene = penumbra_scene();
+        let camera = penumbra_camera();
+        let options = RenderOptions::new()
+            .min_samples(4)
+            .max_samples(64)
+            .error_target(0.005);
+        let (_, samples) = camera.render_with_sample_counts(&scene, &options);
+
+        // The fully lit edges of the strip converge immediately, while the
+        // partially shadowed pixels use every sample.
+        assert_eq!(samples[0], 4);
+        assert_eq!(samples[19], 4);
+        assert!(samples.contains(&64));
+    }
+
+    #[test]
+    fn adaptive_sampling_approaches_a_high_sample_reference() {
+        let scene = penumbra_scene();
+        let camera = penumbra_camera();
+        let options = RenderOptions::new()
+            .min_samples(16)
+            .max_samples(64)
+            .error_target(0.01);
+        let reference = camera.render_with_options(
+            &scene,
+            &options.clone().min_samples(512).max_samples(512).seed(1),
+        );
+        let adaptive = camera.render_with_options(&scene, &options);
+
+        // Each pixel stops with a standard error of about 0.01 in luminance,
+        // or 0.02 in red, so its error is bounded at 3σ and the mean error
+        // more tightly.
+        let mut total_error = 0.;
+        for x in 0..20 {
+            let (a, r) = (adaptive.get_color(x, 0), reference.get_color(x, 0));
+            assert_approx_eq!(a.r, r.r, 0.075);
+            total_error += (a.r - r.r).abs();
+        }
+        assert!(total_error / 20. < 0.025);
+    }
+
+    #[test]
+    fn only_pixels_on_silhouettes_get_edge_samples() {
+        // Two overlapping spheres of the same color, whose edge the color
+        // contrast wouldn't show.
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(-10., 10., -10.), Color::WHITE));
+        for x in [-0.7, 0.7] {
+            scene.add_object(
+                Object::new()
+                    .transform(Transform::new().translate(x, 0., x))
+                    .material(Material::new().color(Color::new(0.8, 0.3, 0.3))),
+            );
+        }
+        let mut camera = Camera::new(40, 24, std::f32::consts::FRAC_PI_3);
+        camera.set_transform(Transform::look_at(
+            point3(0., 0., -5.),
+            point3(0., 0., 0.),
+            vector3(0., 1., 0.),
+        ));
+
+        let options = RenderOptions::new();
+        let (plain, _) = camera.render_with_sample_counts(&scene, &options);
+        let (smoothed, samples) =
+            camera.render_with_sample_counts(&scene, &options.edge_samples(8));
+
+        let ids = camera.render_object_ids(&scene);
+        let (w, h) = (camera.hsize, camera.vsize);
+        let id = |x: usize, y: usize| ids[y * w + x];
+        let (plain_bytes, smoothed_bytes) = (plain.to_rgb8(), smoothed.to_rgb8());
+        let mut between_spheres = 0;
+        for y in 0..h {
+            for x in 0..w {
+                let neighbors = [
+                    (x.wrapping_sub(1), y),
+                    (x + 1, y),
+                    (x, y.wrapping_sub(1)),
+                    (x, y + 1),
+                ];
+                let differing: Vec<Option<ObjectId>> = neighbors
+                    .iter()
+                    .filter(|&&(nx, ny)| nx < w && ny < h)
+                    .map(|&(nx, ny)| id(nx, ny))
+                    .filter(|&other| other != id(x, y))
+                    .collect();
+                if differing.is_empty() {
+                    assert_eq!(samples[y * w + x], 1);
+                    let i = 3 * (y * w + x);
+                    assert_eq!(plain.data[i..i + 3], smoothed.data[i..i + 3]);
+                    assert_eq!(plain_bytes[i..i + 3], smoothed_bytes[i..i + 3]);
+                } else {
+                    assert_eq!(samples[y * w + x], 9);
+                    if id(x, y).is_some() && differing.iter().all(|other| other.is_some()) {
+                        between_spheres += 1;
+                    }
+                }
+            }
+        }
+        assert!(between_spheres > 0);
+        assert_ne!(plain.data, smoothed.data);
+    }
+
+    #[test]
+    fn the_id_buffer_shows_the_object_through_each_pixel() {
+        let mut scene = Scene::new();
+        let sphere = scene.add_object(Object::new());
+        let mut camera = Camera::new(11, 11, std::f32::consts::FRAC_PI_2);
+        camera.set_transform(Transform::look_at(
+            point3(0., 0., -5.),
+            point3(0., 0., 0.),
+            vector3(0., 1., 0.),
+        ));
+        let ids = camera.render_object_ids(&scene);
+        assert_eq!(ids.len(), 121);
+        assert_eq!(ids[5 * 11 + 5], Some(sphere));
+        assert_eq!(ids[0], None);
+    }
+
+    #[test]
+    fn a_single_sample_per_pixel_is_the_default() {
+        let scene = penumbra_scene();
+        let (_, samples) =
+            penumbra_camera().render_with_sample_counts(&scene, &RenderOptions::new());
+        assert!(samples.iter().all(|&n| n == 1));
+    }
+
+    #[test]
+    fn pixels_through_nested_glass_cost_more_than_a_diffuse_wall() {
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(-10., 10., -10.), Color::WHITE));
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::plane())
+                .transform(
+                    Transform::new()
+                        .translate(0., 0., 10.)
+                        .rotate_x(std::f32::consts::FRAC_PI_2),
+                )
+                .material(Material::new().specular(0.)),
+        );
+        for &radius in &[1., 0.5] {
+            scene.add_object(
+                Object::new()
+                    .geometry(Geometry::sphere())
+                    .transform(Transform::new().scale(radius, radius, radius))
+                    .material(Material::new().transparency(1.).refractive_index(1.5)),
+            );
+        }
+        let mut camera = Camera::new(11, 1, std::f32::consts::FRAC_PI_2);
+        camera.set_transform(Transform::look_at(
+            point3(0., 0., -5.),
+            point3(0., 0., 0.),
+            vector3(0., 1., 0.),
+        ));
+
+        let (_, costs) = camera.render_with_costs(&scene, &RenderOptions::new());
+        // The edge of the image sees only the wall: the primary ray and its
+        // shadow ray.
+        assert!((1..=2).contains(&costs[0].rays), "{:?}", costs[0]);
+        assert!(costs[5].rays >= 4 * costs[0].rays, "{:?}", costs[5]);
+
+        let histogram = CostHistogram::new(&costs);
+        assert_eq!(histogram.total(), 11);
+    }
+
+    /// A cube whose +x and +z faces are wound the wrong way, so that their
+    /// normals point into it.
+    const CUBE_WITH_FLIPPED_FACES: &str = "\
+v -1 -1 -1
+v 1 -1 -1
+v 1 1 -1
+v -1 1 -1
+v -1 -1 1
+v 1 -1 1
+v 1 1 1
+v -1 1 1
+f 1 2 3 4
+f 5 6 7 8
+f 4 8 5 1
+f 2 3 7 6
+f 5 6 2 1
+f 3 7 8 4
+";
+
+    fn scene_of_model(model: &ObjModel) -> Scene {
+        let mut scene = Scene::new();
+        for object in model.to_objects(Material::new()) {
+            scene.add_object(object);
+        }
+        scene
+    }
+
+    fn count_red_and_green(image: &Canvas) -> (usize, usize) {
+        let mut counts = (0, 0);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                match image.get_color(x, y) {
+                    c if c == Color::new(1., 0., 0.) => counts.0 += 1,
+                    c if c == Color::new(0., 1., 0.) => counts.1 += 1,
+                    _ => {}
+                }
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn the_normal_audit_flags_flipped_faces() {
+        let mut model = parse_obj(CUBE_WITH_FLIPPED_FACES).unwrap();
+        let scene = scene_of_model(&model);
+
+        // Look at each face from outside, away from the diagonal where its
+        // two triangles meet.
+        let faces = [
+            (vector3(1., 0., 0.), Facing::Back),
+            (vector3(-1., 0., 0.), Facing::Front),
+            (vector3(0., 1., 0.), Facing::Front),
+            (vector3(0., -1., 0.), Facing::Front),
+            (vector3(0., 0., 1.), Facing::Back),
+            (vector3(0., 0., -1.), Facing::Front),
+        ];
+        let offset = vector3(0.3, -0.2, 0.1);
+        for &(normal, expected) in &faces {
+            let origin = point3(0., 0., 0.) + normal * 5. + offset;
+            let (_, facing) = scene.facing(ray(origin, -normal)).unwrap();
+            assert_eq!(facing, expected, "{:?}", normal);
+        }
+
+        // A view showing the +x, +y, and +z faces.
+        let mut camera = Camera::new(30, 30, std::f32::consts::FRAC_PI_3);
+        camera.set_transform(Transform::look_at(
+            point3(4., 3., 5.),
+            point3(0., 0., 0.),
+            vector3(0., 1., 0.),
+        ));
+        let (red, green) = count_red_and_green(&camera.render_normal_audit(&scene));
+        assert!(red > 0 && green > 0);
+
+        assert_eq!(model.orient_outward(), 4);
+        assert_eq!(model.orient_outward(), 0);
+        let scene = scene_of_model(&model);
+        let (red, green) = count_red_and_green(&camera.render_normal_audit(&scene));
+        assert_eq!(red, 0);
+        assert!(green > 0);
+    }
+
+    #[test]
+    fn a_mesh_renders_as_its_triangles_do() {
+        let model = parse_obj(CUBE_WITH_FLIPPED_FACES).unwrap();
+        let mut triangles = scene_of_model(&model);
+        let mut mesh = Scene::new();
+        mesh.add_object(Object::new().geometry(Geometry::mesh(model.to_mesh())));
+        for scene in [&mut triangles, &mut mesh] {
+            scene.add_light(Light::new(point3(-4., 6., 8.), Color::new(1., 1., 1.)));
+        }
+
+        let mut camera = Camera::new(30, 30, std::f32::consts::FRAC_PI_3);
+        camera.set_transform(Transform::look_at(
+            point3(4., 3., 5.),
+            point3(0., 0., 0.),
+            vector3(0., 1., 0.),
+        ));
+        let options = RenderOptions::new();
+        let expected = camera.render_with_options(&triangles, &options);
+        let actual = camera.render_with_options(&mesh, &options);
+        assert!(expected.data.iter().any(|&c| c > 0.5));
+        for (&a, &b) in actual.data.iter().zip(&expected.data) {
+            assert_approx_eq!(a, b, 1e-5);
+        }
+        assert_eq!(
+            count_red_and_green(&camera.render_normal_audit(&mesh)),
+            count_red_and_green(&camera.render_normal_audit(&triangles))
+        );
+    }
+
+    #[test]
+    fn stitching_slices_matches_a_full_render() {
+        let mut camera = Camera::new(50, 70, std::f32::consts::FRAC_PI_2);
+        camera.set_transform(Transform::look_at(
+            point3(0., 1., -5.),
+            point3(0., -1., 0.),
+            vector3(0., 1., 0.),
+        ));
+        let scene = noisy_scene();
+        let options = RenderOptions::new().seed(7);
+        let full = camera.render_with_options(&scene, &options);
+
+        let slices: Vec<_> = (0..4)
+            .map(|i| camera.render_slice(&scene, &options, i, 4))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(slices[0].0.y, 0);
+        assert_eq!(slices[3].0.y + slices[3].0.height, 70);
+        assert_eq!(stitch(&slices).unwrap().data, full.data);
+
+        // More slices than rows of tiles leaves some empty.
+        let slices: Vec<_> = (0..7)
+            .map(|i| camera.render_slice(&scene, &options, i, 7))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(stitch(&slices).unwrap().data, full.data);
+    }
+
+    #[test]
+    fn re_rendering_the_affected_region_matches_a_full_render() {
+        let mut scene = noisy_scene();
+        let sphere = scene.add_object(
+            Object::new()
+                .transform(Transform::new().translate(1., 0., 0.).scale(0.4, 0.4, 0.4))
+                .material(Material::new().color(Color::new(1., 0., 0.))),
+        );
+        let mut camera = Camera::new(50, 40, std::f32::consts::FRAC_PI_2);
+        camera.set_transform(Transform::look_at(
+            point3(0., 1., -5.),
+            point3(0., -1., 0.),
+            vector3(0., 1., 0.),
+        ));
+        let options = RenderOptions::new().seed(7);
+        let mut image = camera.render_with_options(&scene, &options);
+
+        scene.material_mut(sphere).texture = Texture::constant(Color::new(0., 0., 1.));
+        let rectangle = scene.affected_region(&camera, sphere).unwrap();
+        let (region, pixels) = camera.render_region(&scene, &options, rectangle).unwrap();
+        let (x0, y0, x1, y1) = rectangle;
+        assert_eq!((region.x, region.y), (x0, y0));
+        assert_eq!((pixels.width, pixels.height), (x1 - x0, y1 - y0));
+        assert!(pixels.width < camera.hsize && pixels.height < camera.vsize);
+
+        let full = camera.render_with_options(&scene, &options);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                assert_eq!(pixels.get_color(x - x0, y - y0), full.get_color(x, y));
+            }
+        }
+        // Nothing else reflects the sphere, so patching the region updates
+        // the whole image.
+        assert_ne!(image.data, full.data);
+        image.blit(&pixels, x0 as isize, y0 as isize, None);
+        assert_eq!(image.data, full.data);
+
+        assert!(camera
+            .render_region(&scene, &options, (0, 0, 51, 10))
+            .is_err());
+    }
+
+    #[test]
+    fn stitching_detects_missing_and_overlapping_slices() {
+        let camera = Camera::new(20, 40, std::f32::consts::FRAC_PI_2);
+        let scene = default_scene();
+        let options = RenderOptions::new();
+        let slices: Vec<_> = (0..3)
+            .map(|i| camera.render_slice(&scene, &options, i, 3))
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        let missing = [slices[0].clone(), slices[2].clone()];
+        assert_eq!(
+            stitch(&missing).unwrap_err(),
+            "no slice covers pixel (0, 16)"
+        );
+
+        let overlapping = [slices[0].clone(), slices[1].clone(), slices[1].clone()];
+        assert_eq!(
+            stitch(&overlapping).unwrap_err(),
+            "slices overlap at pixel (0, 16)"
+        );
+
+        let other = Camera::new(20, 30, std::f32::consts::FRAC_PI_2);
+        let mixed = [
+            slices[0].clone(),
+            other.render_slice(&scene, &options, 1, 3).unwrap(),
+        ];
+        assert!(stitch(&mixed).unwrap_err().contains("20x30 frame"));
+
+        assert!(stitch(&[]).is_err());
+    }
+
+    #[test]
+    fn pixel_seeds_are_distinct() {
+        let mut seeds = HashSet::new();
+        for pass in 0..4 {
+            for y in 0..16 {
+                for x in 0..16 {
+                    assert!(seeds.insert(pixel_seed(0, x, y, pass)));
+                }
+            }
+        }
+        assert_ne!(pixel_seed(0, 1, 2, 0), pixel_seed(0, 2, 1, 0));
+        assert_ne!(pixel_seed(0, 1, 2, 0), pixel_seed(1, 1, 2, 0));
+        assert_eq!(pixel_seed(7, 1, 2, 3), pixel_seed(7, 1, 2, 3));
+    }
+
+    #[test]
+    fn stereo_eyes_are_separated_along_the_right_vector() {
+        let mut camera = Camera::new(11, 11, std::f32::consts::FRAC_PI_2);
+        camera.set_transform(Transform::look_at(
+            point3(1., 2., -5.),
+            point3(0., 0., 0.),
+            vector3(0., 1., 0.),
+        ));
+        let right_vector = (camera.ray(10, 5).direction - camera.ray(0, 5).direction).normalize();
+
+        for focus_distance in [None, Some(5.)] {
+            let (left, right) = camera.stereo_pair(0.5, focus_distance);
+            let (l, r) = (left.ray(5, 5), right.ray(5, 5));
+            let offset = r.origin - l.origin;
+            assert_approx_eq!(offset.magnitude(), 0.5);
+            assert_approx_eq!(offset.normalize().dot(right_vector), 1.);
+
+            let center = camera.ray(5, 5);
+            assert_approx_eq!(
+                ((l.origin + r.origin) * 0.5 - center.origin).magnitude(),
+                0.
+            );
+            match focus_distance {
+                None => {
+                    assert_approx_eq!(l.direction.dot(center.direction), 1.);
+                    assert_approx_eq!(r.direction.dot(center.direction), 1.);
+                }
+                Some(distance) => {
+                    // Both eyes converge on the focus point.
+                    let focus = center.position(distance);
+                    assert_approx_eq!(l.direction.dot((focus - l.origin).normalize()), 1.);
+                    assert_approx_eq!(r.direction.dot((focus - r.origin).normalize()), 1.);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rendering_in_stereo_with_no_separation() {
+        let camera = default_camera();
+        let scene = default_scene();
+        let options = RenderOptions::new();
+        let (left, right) = camera.render_stereo(&scene, 0., &options);
+        let mono = camera.render_with_options(&scene, &options);
+        assert_eq!(left.data, mono.data);
+        assert_eq!(right.data, mono.data);
+
+        let (left, right) = camera.render_stereo(&scene, 1., &options);
+        assert_ne!(left.data, right.data);
+    }
+
+    /// A scene of just the floor, lit only by ambient light so that each
+    /// pixel shows its color.
+    fn floor_scene(geometry: Geometry, texture: Texture) -> Scene {
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(0., 10., 0.), Color::WHITE));
+        scene.add_object(
+            Object::new().geometry(geometry).material(
+                Material::new()
+                    .texture(texture)
+                    .ambient(1.)
+                    .diffuse(0.)
+                    .specular(0.),
+            ),
+        );
+        scene
+    }
+
+    #[test]
+    fn a_checker_floor_converges_to_its_mean_color_toward_the_horizon() {
+        let mut camera = Camera::new(80, 40, std::f32::consts::FRAC_PI_3);
+        camera.set_transform(Transform::look_at(
+            point3(0., 1., 0.),
+            point3(0., 1., 1.),
+            vector3(0., 1., 0.),
+        ));
+        let deviation_beyond = |scene: &Scene, distance: f32| {
+            let image = camera.render_with_options(scene, &RenderOptions::new());
+            let mut deviation: f32 = 0.;
+            for y in 0..camera.vsize {
+                for x in 0..camera.hsize {
+                    let hit = scene.nearest_intersection(camera.ray(x, y));
+                    if hit.is_some_and(|hit| hit.t > distance) {
+                        deviation = deviation.max((image.get_color(x, y).r - 0.5).abs());
+                    }
+                }
+            }
+            deviation
+        };
+        // The same, shading rays without a footprint.
+        let point_sampled_deviation_beyond = |scene: &Scene, distance: f32| {
+            let mut rng = SmallRng::seed_from_u64(0);
+            let mut deviation: f32 = 0.;
+            for y in 0..camera.vsize {
+                for x in 0..camera.hsize {
+                    let r = camera.ray(x, y).spread(0.);
+                    let hit = scene.nearest_intersection(r);
+                    if hit.is_some_and(|hit| hit.t > distance) {
+                        deviation = deviation.max((scene.color_at(&mut rng, r).r - 0.5).abs());
+                    }
+                }
+            }
+            deviation
+        };
+
+        let floor = floor_scene(
+            Geometry::checker_floor(Color::WHITE, Color::BLACK, 0.25),
+            Texture::constant(Color::WHITE),
+        );
+        assert!(deviation_beyond(&floor, 20.) < 0.02);
+
+        // So does a plane with a checkerboard texture, which is filtered
+        // over each camera ray's footprint, though it aliases when point
+        // sampled.
+        let mut checkerboard = Texture::checkerboard_2d(Color::WHITE, Color::BLACK);
+        checkerboard.transform = Transform::new().scale(0.25, 0.25, 0.25);
+        let plane = floor_scene(Geometry::plane(), checkerboard);
+        assert!(deviation_beyond(&plane, 20.) < 0.02);
+        assert!(point_sampled_deviation_beyond(&plane, 20.) > 0.4);
+    }
+
+    #[test]
+    fn a_checker_floor_is_sharp_close_up() {
+        let mut camera = Camera::new(40, 40, std::f32::consts::FRAC_PI_3);
+        camera.set_transform(Transform::look_at(
+            point3(0., 1., 0.),
+            point3(0., 0., 0.),
+            vector3(0., 0., 1.),
+        ));
+        let floor = floor_scene(
+            Geometry::checker_floor(Color::WHITE, Color::BLACK, 0.25),
+            Texture::constant(Color::WHITE),
+        );
+        let image = camera.render_with_options(&floor, &RenderOptions::new());
+
+        // Only pixels on the edges between checks are blended.
+        let sharp = (0..40 * 40)
+            .map(|i| image.quantize(image.get_color(i % 40, i / 40).r))
+            .filter(|&c| c == 0 || c == 255)
+            .count();
+        assert!(sharp > 40 * 40 * 3 / 4, "{}", sharp);
     }
 
     #[bench]
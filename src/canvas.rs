@@ -1,41 +1,232 @@
 use crate::color::*;
 
+/// How (linear) colors are encoded when a canvas is quantized to 8 bit
+/// pixels for export.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Encoding {
+    /// Each channel is stored as is, scaled to 0-255.
+    Linear,
+    /// Each channel is stored with the sRGB transfer curve, as displays
+    /// expect, which gives the midtones more of the 256 levels.
+    Srgb,
+}
+
+/// The corner colors of `Canvas::uv_test_grid`: upper left, upper right,
+/// bottom left, and bottom right, as in `AlignCheck`.
+pub const UV_GRID_CORNERS: [Color; 4] = [
+    Color::WHITE,
+    Color {
+        r: 1.,
+        g: 0.,
+        b: 1.,
+    },
+    Color {
+        r: 0.,
+        g: 1.,
+        b: 1.,
+    },
+    Color {
+        r: 0.,
+        g: 0.,
+        b: 1.,
+    },
+];
+
+#[derive(Clone, Debug)]
 pub struct Canvas {
     pub width: usize,
     pub height: usize,
-    pub data: Vec<u8>,
+    /// The linear colors of the pixels, as interleaved RGB. They are kept
+    /// unclamped, so they can be accumulated and averaged without loss.
+    pub data: Vec<f32>,
+    /// How colors are encoded when they are quantized.
+    pub encoding: Encoding,
+    /// The factor by which colors are scaled when they are quantized, to
+    /// brighten or darken the whole image.
+    pub exposure: f32,
 }
 
 impl Canvas {
-    /// Constructs a Canvas of the given width and height.
-    ///
-    /// Pixel data is stored as interleaved 8 bit RGB.
+    /// Constructs a Canvas of the given width and height, which is quantized
+    /// linearly.
     pub fn new(width: usize, height: usize) -> Self {
+        Canvas::new_with_encoding(width, height, Encoding::Linear)
+    }
+
+    /// Constructs a Canvas which is quantized sRGB encoded, for display.
+    pub fn new_with_gamma(width: usize, height: usize) -> Self {
+        Canvas::new_with_encoding(width, height, Encoding::Srgb)
+    }
+
+    pub fn new_with_encoding(width: usize, height: usize, encoding: Encoding) -> Self {
         Canvas {
             width,
             height,
-            data: vec![0; 3 * width * height],
+            data: vec![0.; 3 * width * height],
+            encoding,
+            exposure: 1.,
         }
     }
 
+    pub fn exposure(mut self, exposure: f32) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    /// Returns the color of the pixel, exactly as it was set.
     pub fn get_color(&self, x: usize, y: usize) -> Color {
         let i = 3 * (self.width * y + x);
-        let r = self.data[i + 0] as f32 / 255.0;
-        let g = self.data[i + 1] as f32 / 255.0;
-        let b = self.data[i + 2] as f32 / 255.0;
-
-        Color { r, g, b }
+        Color {
+            r: self.data[i + 0],
+            g: self.data[i + 1],
+            b: self.data[i + 2],
+        }
     }
 
     pub fn set_color(&mut self, x: usize, y: usize, color: Color) {
-        let r = (color.r.max(0.0).min(1.0) * 255.0).round() as u8;
-        let g = (color.g.max(0.0).min(1.0) * 255.0).round() as u8;
-        let b = (color.b.max(0.0).min(1.0) * 255.0).round() as u8;
         let i = 3 * (self.width * y + x);
+        self.data[i..i + 3].copy_from_slice(&[color.r, color.g, color.b]);
+    }
+
+    /// Converts a sample to 8 bits, applying the exposure, clamping, and
+    /// then encoding it.
+    pub fn quantize(&self, sample: f32) -> u8 {
+        let c = (sample * self.exposure).clamp(0., 1.);
+        let c = match self.encoding {
+            Encoding::Linear => c,
+            Encoding::Srgb => linear_to_srgb(c),
+        };
+        (c * 255.).round() as u8
+    }
+
+    /// Returns the pixels quantized to interleaved 8 bit RGB, as image files
+    /// store them.
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.data
+            .iter()
+            .map(|&sample| self.quantize(sample))
+            .collect()
+    }
+
+    /// Copies the source canvas onto this one with its top left corner at
+    /// (x, y), clipping anything that falls outside. Source pixels which
+    /// quantize to the same 8 bit color as the color key (if any) are
+    /// skipped, leaving the destination visible.
+    pub fn blit(&mut self, src: &Canvas, x: isize, y: isize, key: Option<Color>) {
+        let key = key.map(|key| [key.r, key.g, key.b].map(|c| src.quantize(c)));
+        for src_y in 0..src.height {
+            let dst_y = y + src_y as isize;
+            if dst_y < 0 || dst_y >= self.height as isize {
+                continue;
+            }
+
+            for src_x in 0..src.width {
+                let dst_x = x + src_x as isize;
+                if dst_x < 0 || dst_x >= self.width as isize {
+                    continue;
+                }
+
+                let i = 3 * (src.width * src_y + src_x);
+                let pixel = &src.data[i..i + 3];
+                if key.is_some_and(|key| key == [0, 1, 2].map(|c| src.quantize(pixel[c]))) {
+                    continue;
+                }
+
+                let j = 3 * (self.width * dst_y as usize + dst_x as usize);
+                self.data[j..j + 3].copy_from_slice(pixel);
+            }
+        }
+    }
+
+    /// Returns an image for checking how textures are mapped: a grid of
+    /// cells, colored by the (u, v) of their centers as red and green (like
+    /// `Texture::uv_debug`) with alternate cells tinted blue, and a marker
+    /// of a distinct color in each corner (see `UV_GRID_CORNERS`). v
+    /// increases upward, so the bottom row of pixels is v = 0.
+    pub fn uv_test_grid(width: usize, height: usize, cells: usize) -> Canvas {
+        let mut canvas = Canvas::new(width, height);
+        for y in 0..height {
+            let row = cells - 1 - y * cells / height;
+            for x in 0..width {
+                let column = x * cells / width;
+                let color = Color::new(
+                    (column as f32 + 0.5) / cells as f32,
+                    (row as f32 + 0.5) / cells as f32,
+                    ((row + column) % 2) as f32 * 0.5,
+                );
+                canvas.set_color(x, y, color);
+            }
+        }
+
+        let size = (width.min(height) / cells / 4).max(1);
+        let corners = [
+            (0, 0),
+            (width - size, 0),
+            (0, height - size),
+            (width - size, height - size),
+        ];
+        for (&(left, top), &color) in corners.iter().zip(UV_GRID_CORNERS.iter()) {
+            for y in top..top + size {
+                for x in left..left + size {
+                    canvas.set_color(x, y, color);
+                }
+            }
+        }
 
-        self.data[i + 0] = r;
-        self.data[i + 1] = g;
-        self.data[i + 2] = b;
+        canvas
+    }
+
+    /// Returns a copy of the canvas resized by the given factor, using
+    /// nearest-neighbor sampling. Useful for making thumbnails. Fails unless
+    /// the factor is finite and positive; an empty canvas stays empty.
+    pub fn scale_nearest(&self, factor: f32) -> Result<Canvas, String> {
+        if !(factor.is_finite() && factor > 0.) {
+            return Err(format!(
+                "scale factor {} is not finite and positive",
+                factor
+            ));
+        }
+        if self.width == 0 || self.height == 0 {
+            let mut result = Canvas::new_with_encoding(0, 0, self.encoding);
+            result.exposure = self.exposure;
+            return Ok(result);
+        }
+
+        let width = ((self.width as f32 * factor).round() as usize).max(1);
+        let height = ((self.height as f32 * factor).round() as usize).max(1);
+        let mut result = Canvas::new_with_encoding(width, height, self.encoding);
+        result.exposure = self.exposure;
+
+        for y in 0..height {
+            let src_y = ((y as f32 / factor) as usize).min(self.height - 1);
+            for x in 0..width {
+                let src_x = ((x as f32 / factor) as usize).min(self.width - 1);
+                let i = 3 * (self.width * src_y + src_x);
+                let j = 3 * (width * y + x);
+                result.data[j..j + 3].copy_from_slice(&self.data[i..i + 3]);
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+/// Applies the piecewise sRGB transfer curve to a linear value in [0, 1].
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Inverts the sRGB transfer curve, for an encoded value in [0, 1].
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
     }
 }
 
@@ -49,7 +240,7 @@ mod tests {
         assert_eq!(c.width, 10);
         assert_eq!(c.height, 20);
         for component in c.data {
-            assert_eq!(component, 0);
+            assert_eq!(component, 0.0);
         }
     }
 
@@ -60,4 +251,218 @@ mod tests {
         c.set_color(2, 3, red);
         assert_eq!(c.get_color(2, 3), red);
     }
+
+    #[test]
+    fn writing_pixels_to_a_gamma_encoded_canvas() {
+        let mut c = Canvas::new_with_gamma(1, 1);
+        c.set_color(0, 0, Color::new(0.5, 0., 1.));
+        assert_eq!(c.to_rgb8(), vec![188, 0, 255]);
+        assert_eq!(c.get_color(0, 0), Color::new(0.5, 0., 1.));
+
+        let mut linear = Canvas::new(1, 1);
+        linear.set_color(0, 0, Color::new(0.5, 0., 1.));
+        assert_eq!(linear.to_rgb8(), vec![128, 0, 255]);
+    }
+
+    #[test]
+    fn the_srgb_curve_fixes_black_and_white() {
+        assert_eq!(linear_to_srgb(0.), 0.);
+        assert!((linear_to_srgb(1.) - 1.).abs() < 1e-6);
+        assert_eq!(srgb_to_linear(0.), 0.);
+        assert!((srgb_to_linear(1.) - 1.).abs() < 1e-6);
+
+        let mut c = Canvas::new_with_gamma(2, 1);
+        c.set_color(0, 0, Color::new(0., 0., 0.));
+        c.set_color(1, 0, Color::new(1., 1., 1.));
+        assert_eq!(c.to_rgb8(), vec![0, 0, 0, 255, 255, 255]);
+
+        for i in 0..=100 {
+            let c = i as f32 / 100.;
+            assert!((srgb_to_linear(linear_to_srgb(c)) - c).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn colors_are_read_back_exactly_as_they_were_written() {
+        let colors = [
+            Color::new(0.1, 0.2, 0.3),
+            Color::new(1. / 3., 2. / 3., 0.123_456_7),
+            Color::new(1.5, -0.5, 100.),
+        ];
+        for encoding in [Encoding::Linear, Encoding::Srgb] {
+            let mut c = Canvas::new_with_encoding(3, 1, encoding).exposure(2.);
+            for (x, &color) in colors.iter().enumerate() {
+                c.set_color(x, 0, color);
+            }
+            for (x, &color) in colors.iter().enumerate() {
+                assert_eq!(c.get_color(x, 0), color, "{:?}", encoding);
+                c.set_color(x, 0, c.get_color(x, 0));
+                assert_eq!(c.get_color(x, 0), color, "{:?}", encoding);
+            }
+        }
+    }
+
+    #[test]
+    fn accumulating_samples_in_a_canvas() {
+        // Sums past 1 aren't clamped, so the mean comes out exactly.
+        let mut c = Canvas::new(1, 1);
+        for i in 0..16 {
+            let sample = Color::new(i as f32 / 16., 1. - i as f32 / 16., 0.25);
+            c.set_color(0, 0, c.get_color(0, 0) + sample);
+        }
+        c.set_color(0, 0, c.get_color(0, 0) * (1. / 16.));
+        assert_eq!(c.get_color(0, 0), Color::new(15. / 32., 17. / 32., 0.25));
+    }
+
+    #[test]
+    fn exposure_scales_colors_as_they_are_quantized() {
+        let mut c = Canvas::new(1, 1).exposure(2.);
+        c.set_color(0, 0, Color::new(0.25, 0.5, 0.75));
+        assert_eq!(c.to_rgb8(), vec![128, 255, 255]);
+        assert_eq!(c.get_color(0, 0), Color::new(0.25, 0.5, 0.75));
+    }
+
+    fn filled(width: usize, height: usize, color: Color) -> Canvas {
+        let mut c = Canvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                c.set_color(x, y, color);
+            }
+        }
+        c
+    }
+
+    #[test]
+    fn blitting_a_canvas() {
+        let red = Color::new(1.0, 0.0, 0.0);
+        let mut c = Canvas::new(5, 5);
+        c.blit(&filled(2, 2, red), 1, 2, None);
+        for y in 0..5 {
+            for x in 0..5 {
+                let inside = (1..3).contains(&x) && (2..4).contains(&y);
+                let expected = if inside { red } else { Color::BLACK };
+                assert_eq!(c.get_color(x, y), expected, "({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn blitting_clips_at_each_edge() {
+        let red = Color::new(1.0, 0.0, 0.0);
+        let src = filled(3, 3, red);
+        let examples = vec![
+            // Left, right, top, and bottom.
+            (-2, 1, (0, 1), (1, 4)),
+            (4, 1, (4, 5), (1, 4)),
+            (1, -2, (1, 4), (0, 1)),
+            (1, 4, (1, 4), (4, 5)),
+            // Entirely outside.
+            (-3, 0, (0, 0), (0, 0)),
+            (5, 5, (0, 0), (0, 0)),
+        ];
+        for (x0, y0, xs, ys) in examples {
+            let mut c = Canvas::new(5, 5);
+            c.blit(&src, x0, y0, None);
+            for y in 0..5 {
+                for x in 0..5 {
+                    let inside = (xs.0..xs.1).contains(&x) && (ys.0..ys.1).contains(&y);
+                    let expected = if inside { red } else { Color::BLACK };
+                    assert_eq!(c.get_color(x, y), expected, "({}, {})", x0, y0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blitting_skips_pixels_matching_the_color_key() {
+        let red = Color::new(1.0, 0.0, 0.0);
+        let mut src = filled(2, 1, Color::WHITE);
+        src.set_color(1, 0, red);
+        let mut c = filled(2, 1, Color::BLACK);
+        c.blit(&src, 0, 0, Some(Color::WHITE));
+        assert_eq!(c.get_color(0, 0), Color::BLACK);
+        assert_eq!(c.get_color(1, 0), red);
+    }
+
+    #[test]
+    fn scaling_a_canvas_with_nearest_neighbor_sampling() {
+        let red = Color::new(1.0, 0.0, 0.0);
+        let mut c = Canvas::new(4, 2);
+        c.set_color(0, 0, red);
+        c.set_color(1, 0, red);
+        c.set_color(2, 0, Color::WHITE);
+
+        let half = c.scale_nearest(0.5).unwrap();
+        assert_eq!((half.width, half.height), (2, 1));
+        assert_eq!(half.get_color(0, 0), red);
+        assert_eq!(half.get_color(1, 0), Color::WHITE);
+
+        let double = c.scale_nearest(2.0).unwrap();
+        assert_eq!((double.width, double.height), (8, 4));
+        assert_eq!(double.get_color(3, 1), red);
+        assert_eq!(double.get_color(5, 1), Color::WHITE);
+        assert_eq!(double.get_color(5, 3), Color::BLACK);
+    }
+
+    #[test]
+    fn scaling_by_an_invalid_factor_is_an_error() {
+        let c = Canvas::new(4, 2);
+        for &factor in &[0., -1., f32::NAN, f32::INFINITY] {
+            assert!(c.scale_nearest(factor).is_err());
+        }
+    }
+
+    #[test]
+    fn scaling_an_empty_canvas() {
+        let empty = Canvas::new(0, 0).scale_nearest(2.0).unwrap();
+        assert_eq!((empty.width, empty.height), (0, 0));
+        let empty = Canvas::new(4, 0).scale_nearest(0.5).unwrap();
+        assert_eq!((empty.width, empty.height), (0, 0));
+    }
+
+    #[test]
+    fn blitting_a_thumbnail_of_a_canvas_into_itself() {
+        let red = Color::new(1.0, 0.0, 0.0);
+        let mut c = Canvas::new(4, 4);
+        for y in 0..4 {
+            c.set_color(0, y, red);
+            c.set_color(1, y, red);
+        }
+
+        // The thumbnail is taken before the blit, so stamping it over the
+        // area it was sampled from doesn't feed back into itself.
+        let thumbnail = c.scale_nearest(0.5).unwrap();
+        c.blit(&thumbnail, 1, 0, None);
+        for y in 0..2 {
+            assert_eq!(c.get_color(0, y), red);
+            assert_eq!(c.get_color(1, y), red);
+            assert_eq!(c.get_color(2, y), Color::BLACK);
+            assert_eq!(c.get_color(3, y), Color::BLACK);
+        }
+        for y in 2..4 {
+            assert_eq!(c.get_color(1, y), red);
+            assert_eq!(c.get_color(2, y), Color::BLACK);
+        }
+    }
+
+    #[test]
+    fn the_uv_test_grid_marks_its_corners() {
+        // Cells of 20x10 pixels, with corner markers of 2x2.
+        let c = Canvas::uv_test_grid(80, 40, 4);
+        let [upper_left, upper_right, bottom_left, bottom_right] = UV_GRID_CORNERS;
+        assert_eq!(c.get_color(0, 0), upper_left);
+        assert_eq!(c.get_color(1, 1), upper_left);
+        assert_eq!(c.get_color(79, 0), upper_right);
+        assert_eq!(c.get_color(78, 1), upper_right);
+        assert_eq!(c.get_color(0, 39), bottom_left);
+        assert_eq!(c.get_color(1, 38), bottom_left);
+        assert_eq!(c.get_color(79, 39), bottom_right);
+        assert_eq!(c.get_color(78, 38), bottom_right);
+
+        // Beyond the markers, the corner cells show their (u, v).
+        assert_eq!(c.get_color(2, 2), Color::new(0.125, 0.875, 0.5));
+        assert_eq!(c.get_color(77, 37), Color::new(0.875, 0.125, 0.5));
+        assert_eq!(c.get_color(30, 25), Color::new(0.375, 0.375, 0.));
+        assert_eq!(c.get_color(50, 25), Color::new(0.625, 0.375, 0.5));
+    }
 }
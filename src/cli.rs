@@ -0,0 +1,350 @@
+use crate::camera::*;
+use crate::canvas::*;
+use crate::ppm::*;
+use crate::render_options::*;
+use crate::render_stats::*;
+use crate::scene::*;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, Instant};
+
+/// The options shared by the render binaries.
+const OPTIONS: &str = "\
+options:
+  --width N         the image width, in pixels
+  --height N        the image height, in pixels (with only one of the two
+                    given, the other keeps the default aspect ratio)
+  --fov RADIANS     the camera's field of view
+  --samples N       the number of samples per pixel
+  --max-depth N     the most bounces for reflected and refracted rays
+  --output PATH     write the image to a file (.ppm) instead of stdout
+  --format p3|p6    plain or binary PPM (by default, P6 for files and P3
+                    for stdout)
+  --preview         skip shadows, reflection, and refraction
+  --clay            shade every object with a plain clay material
+  --srgb            encode the image as sRGB rather than linear
+  --stats           print a histogram of the cost of each pixel to stderr";
+
+/// The PPM variant an image is written as.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PpmFormat {
+    /// Plain text.
+    P3,
+    /// Binary.
+    P6,
+}
+
+/// The settings given on the command line of a render binary. Anything
+/// not given keeps the binary's own default.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cli {
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub fov: Option<f32>,
+    pub samples: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub output: Option<PathBuf>,
+    pub format: Option<PpmFormat>,
+    pub preview: bool,
+    pub clay: bool,
+    pub srgb: bool,
+    pub stats: bool,
+    /// The arguments which aren't options, such as a scene file.
+    pub args: Vec<String>,
+    /// The name of the scene, recorded in the image's metadata.
+    pub scene: Option<String>,
+}
+
+impl Cli {
+    /// Parses the process's arguments, which must include the named
+    /// positional arguments. If they can't be parsed, prints the error and
+    /// usage and exits.
+    pub fn from_env(positional: &[&str]) -> Self {
+        let args: Vec<String> = std::env::args().collect();
+        let (program, args) = args.split_first().expect("missing the program name");
+        let mut cli = parse_args(args, positional.len()).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            eprintln!(
+                "usage: {} [options] {}\n{}",
+                program,
+                positional.join(" "),
+                OPTIONS
+            );
+            process::exit(2);
+        });
+        cli.scene = Path::new(program)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned());
+        cli
+    }
+
+    /// Returns a camera with the given defaults, overridden by the command
+    /// line.
+    pub fn camera(&self, width: usize, height: usize, fov: f32) -> Camera {
+        let (width, height) = match (self.width, self.height) {
+            (Some(w), Some(h)) => (w, h),
+            (Some(w), None) => (w, (w * height).div_ceil(width).max(1)),
+            (None, Some(h)) => ((h * width).div_ceil(height).max(1), h),
+            (None, None) => (width, height),
+        };
+        Camera::new(width, height, self.fov.unwrap_or(fov))
+    }
+
+    /// Returns the options to render with, using every available thread.
+    pub fn render_options(&self) -> RenderOptions {
+        let mut options = RenderOptions::default();
+        if self.preview {
+            options.quality = RenderQuality::Preview;
+        }
+        if self.clay {
+            options = options.clay();
+        }
+        if self.srgb {
+            options = options.encoding(Encoding::Srgb);
+        }
+        if let Some(samples) = self.samples {
+            options = options.min_samples(samples).max_samples(samples);
+        }
+        if let Some(max_depth) = self.max_depth {
+            options = options.max_depth(max_depth);
+        }
+        if let Ok(threads) = std::thread::available_parallelism() {
+            options.threads = threads.get();
+        }
+        options
+    }
+
+    /// Renders the scene with the options from the command line, printing
+    /// the cost histogram if asked to. Returns the image, and metadata which
+    /// records how it was made.
+    pub fn render(&self, camera: &Camera, scene: &Scene) -> (Canvas, Metadata) {
+        let options = self.render_options();
+        let start = Instant::now();
+        let canvas = if self.stats {
+            let (canvas, costs) = camera.render_with_costs(scene, &options);
+            eprint!("{}", CostHistogram::new(&costs));
+            canvas
+        } else {
+            camera.render_with_options(scene, &options)
+        };
+        let elapsed = start.elapsed();
+        (canvas, self.metadata(camera, &options, elapsed))
+    }
+
+    /// Returns the metadata for an image rendered with the camera and
+    /// options, which took the given time.
+    pub fn metadata(
+        &self,
+        camera: &Camera,
+        options: &RenderOptions,
+        elapsed: Duration,
+    ) -> Metadata {
+        let mut metadata = Metadata::new();
+        let mut record = |key: &str, value: String| metadata.insert(key.to_string(), value);
+        if let Some(scene) = &self.scene {
+            record("scene", scene.clone());
+        }
+        record("resolution", format!("{}x{}", camera.hsize, camera.vsize));
+        record("fov", camera.fov.to_string());
+        record("time", options.time.to_string());
+        record("seed", options.seed.to_string());
+        record(
+            "samples",
+            format!("{}-{}", options.min_samples, options.max_samples),
+        );
+        record("max-depth", options.effective_max_depth().to_string());
+        record("render-time", format!("{:.3}s", elapsed.as_secs_f64()));
+        metadata
+    }
+
+    /// Writes the image, with its metadata, to the output file or to stdout.
+    pub fn write(&self, canvas: &Canvas, metadata: &Metadata) -> io::Result<()> {
+        let (mut writer, default_format): (Box<dyn Write>, _) = match &self.output {
+            Some(path) => (Box::new(BufWriter::new(File::create(path)?)), PpmFormat::P6),
+            None => (Box::new(io::stdout().lock()), PpmFormat::P3),
+        };
+        match self.format.unwrap_or(default_format) {
+            PpmFormat::P3 => write_ppm_with_metadata(canvas, metadata, &mut writer)?,
+            PpmFormat::P6 => write_ppm_p6_with_metadata(canvas, metadata, &mut writer)?,
+        }
+        writer.flush()
+    }
+}
+
+/// Parses the arguments (without the program name), which must include the
+/// given number of positional arguments.
+pub fn parse_args(args: &[String], positional: usize) -> Result<Cli, String> {
+    let mut cli = Cli::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(String::as_str)
+                .ok_or_else(|| format!("{} needs a value", arg))
+        };
+        match arg.as_str() {
+            "--width" => cli.width = Some(parse_size(arg, value()?)?),
+            "--height" => cli.height = Some(parse_size(arg, value()?)?),
+            "--fov" => {
+                let fov = value()?;
+                cli.fov = match fov.parse() {
+                    Ok(fov) if fov > 0. && fov < std::f32::consts::PI => Some(fov),
+                    _ => return Err(format!("invalid {}: {}", arg, fov)),
+                }
+            }
+            "--samples" => cli.samples = Some(parse_size(arg, value()?)?),
+            "--max-depth" => {
+                let depth = value()?;
+                let depth = depth
+                    .parse()
+                    .map_err(|_| format!("invalid {}: {}", arg, depth))?;
+                cli.max_depth = Some(depth);
+            }
+            "--output" => {
+                let path = PathBuf::from(value()?);
+                match path.extension().and_then(|extension| extension.to_str()) {
+                    Some("ppm") | Some("pnm") => cli.output = Some(path),
+                    _ => return Err(format!("can only write .ppm files, not {}", path.display())),
+                }
+            }
+            "--format" => {
+                cli.format = match value()? {
+                    "p3" | "P3" => Some(PpmFormat::P3),
+                    "p6" | "P6" => Some(PpmFormat::P6),
+                    format => return Err(format!("unknown format: {}", format)),
+                }
+            }
+            "--preview" => cli.preview = true,
+            "--clay" => cli.clay = true,
+            "--srgb" => cli.srgb = true,
+            "--stats" => cli.stats = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option: {}", arg)),
+            _ => cli.args.push(arg.clone()),
+        }
+    }
+
+    if cli.args.len() != positional {
+        return Err(format!(
+            "expected {} arguments, not {}",
+            positional,
+            cli.args.len()
+        ));
+    }
+    Ok(cli)
+}
+
+/// Parses a count which must be at least 1.
+fn parse_size(option: &str, value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(size) if size > 0 => Ok(size),
+        _ => Err(format!("invalid {}: {}", option, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str], positional: usize) -> Result<Cli, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_args(&args, positional)
+    }
+
+    #[test]
+    fn parsing_the_render_options() {
+        let cli = parse(
+            &[
+                "--width",
+                "200",
+                "--samples",
+                "4",
+                "scene.yml",
+                "--max-depth",
+                "2",
+                "--output",
+                "out.ppm",
+                "--format",
+                "p3",
+                "--preview",
+            ],
+            1,
+        )
+        .unwrap();
+        assert_eq!(cli.width, Some(200));
+        assert_eq!(cli.height, None);
+        assert_eq!(cli.samples, Some(4));
+        assert_eq!(cli.max_depth, Some(2));
+        assert_eq!(cli.output, Some(PathBuf::from("out.ppm")));
+        assert_eq!(cli.format, Some(PpmFormat::P3));
+        assert!(cli.preview && !cli.clay);
+        assert_eq!(cli.args, vec!["scene.yml"]);
+
+        let options = cli.render_options();
+        assert_eq!((options.min_samples, options.max_samples), (4, 4));
+        assert_eq!(options.max_depth, 2);
+        assert_eq!(options.quality, RenderQuality::Preview);
+
+        // The height follows the default aspect ratio.
+        let camera = cli.camera(1000, 500, 1.);
+        assert_eq!((camera.hsize, camera.vsize, camera.fov), (200, 100, 1.));
+    }
+
+    #[test]
+    fn no_arguments_keeps_the_defaults() {
+        let cli = parse(&[], 0).unwrap();
+        assert_eq!(cli, Cli::default());
+        let camera = cli.camera(1000, 500, 1.);
+        assert_eq!((camera.hsize, camera.vsize), (1000, 500));
+        assert_eq!(cli.render_options().max_samples, 1);
+    }
+
+    #[test]
+    fn metadata_records_the_render_settings() {
+        let cli = Cli {
+            scene: Some("room".to_string()),
+            ..parse(&["--samples", "4", "--max-depth", "2"], 0).unwrap()
+        };
+        let camera = cli.camera(100, 50, 1.);
+        let metadata = cli.metadata(&camera, &cli.render_options(), Duration::from_millis(1500));
+        let entries: Vec<(&str, &str)> = metadata
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("fov", "1"),
+                ("max-depth", "2"),
+                ("render-time", "1.500s"),
+                ("resolution", "100x50"),
+                ("samples", "4-4"),
+                ("scene", "room"),
+                ("seed", "0"),
+                ("time", "0"),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_arguments_are_errors() {
+        let cases: [(&[&str], &str); 9] = [
+            (&["--width"], "--width needs a value"),
+            (&["--width", "0"], "invalid --width: 0"),
+            (&["--height", "tall"], "invalid --height: tall"),
+            (&["--fov", "4"], "invalid --fov: 4"),
+            (&["--max-depth", "-1"], "invalid --max-depth: -1"),
+            (
+                &["--output", "out.png"],
+                "can only write .ppm files, not out.png",
+            ),
+            (&["--format", "p5"], "unknown format: p5"),
+            (&["--size", "10"], "unknown option: --size"),
+            (&["scene.yml"], "expected 0 arguments, not 1"),
+        ];
+        for (args, error) in cases {
+            assert_eq!(parse(args, 0), Err(error.to_string()));
+        }
+    }
+}
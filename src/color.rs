@@ -1,6 +1,7 @@
 use std::ops;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Color {
     pub r: f32,
     pub g: f32,
@@ -31,6 +32,34 @@ impl Color {
             b: self.b.min(1.).max(0.),
         }
     }
+
+    /// Approximates the color of a black body at the given temperature (in
+    /// kelvin, from 1000 to 40000), normalized so the brightest channel is 1.
+    ///
+    /// Uses Tanner Helland's curve fit to the CIE 1964 color matching data.
+    pub fn blackbody(kelvin: f32) -> Color {
+        let t = kelvin.clamp(1000., 40000.) / 100.;
+
+        let r = if t <= 66. {
+            255.
+        } else {
+            329.69873 * (t - 60.).powf(-0.13320476)
+        };
+        let g = if t <= 66. {
+            99.4708 * t.ln() - 161.11957
+        } else {
+            288.12216 * (t - 60.).powf(-0.07551485)
+        };
+        let b = if t >= 66. {
+            255.
+        } else if t <= 19. {
+            0.
+        } else {
+            138.51773 * (t - 10.).ln() - 305.0448
+        };
+
+        Color::new(r / 255., g / 255., b / 255.).clamp()
+    }
 }
 
 impl ops::Add for Color {
@@ -132,4 +161,21 @@ mod tests {
         assert_approx_eq!(res.g, 0.2);
         assert_approx_eq!(res.b, 0.04);
     }
+
+    #[test]
+    fn the_color_of_a_black_body() {
+        // Daylight is close to white.
+        let daylight = Color::blackbody(6500.);
+        assert_approx_eq!(daylight.r, 1.0);
+        assert_approx_eq!(daylight.g, 1.0, 1e-2);
+        assert_approx_eq!(daylight.b, 1.0, 3e-2);
+
+        // Cooler bodies are redder, hotter ones bluer.
+        let candle = Color::blackbody(1900.);
+        assert_eq!(candle.r, 1.0);
+        assert_eq!(candle.b, 0.0);
+        let sky = Color::blackbody(15000.);
+        assert_eq!(sky.b, 1.0);
+        assert!(sky.r < 1.0);
+    }
 }
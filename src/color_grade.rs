@@ -0,0 +1,404 @@
+use crate::color::*;
+
+/// How automatic exposure measures the brightness of an image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Metering {
+    /// The geometric mean of the pixels' luminance.
+    LogAverage,
+    /// The luminance below which the given fraction (from 0 to 1) of pixels
+    /// fall.
+    Percentile(f32),
+}
+
+/// Scales an image so that its measured brightness maps to a target gray.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AutoExposure {
+    pub metering: Metering,
+    /// The luminance the measured brightness is mapped to.
+    pub target: f32,
+}
+
+impl AutoExposure {
+    pub fn new() -> Self {
+        AutoExposure {
+            metering: Metering::LogAverage,
+            target: 0.18,
+        }
+    }
+
+    pub fn metering(mut self, metering: Metering) -> Self {
+        self.metering = metering;
+        self
+    }
+
+    pub fn target(mut self, target: f32) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Returns the factor by which the pixels should be scaled, or 1 if they
+    /// are all black. Pixels with NaN or infinite luminance are ignored.
+    pub fn scale(&self, pixels: &[Color]) -> f32 {
+        let mut values: Vec<f32> = pixels
+            .iter()
+            .map(|&c| luminance(c))
+            .filter(|l| l.is_finite())
+            .collect();
+        if values.is_empty() {
+            return 1.;
+        }
+
+        let key = match self.metering {
+            Metering::LogAverage => {
+                // Offset to avoid taking the log of zero for black pixels.
+                let delta = 1e-4;
+                let sum: f32 = values.iter().map(|&l| (delta + l).ln()).sum();
+                (sum / values.len() as f32).exp() - delta
+            }
+            Metering::Percentile(p) => {
+                values.sort_by(f32::total_cmp);
+                let i = (p.clamp(0., 1.) * (values.len() - 1) as f32).round() as usize;
+                values[i]
+            }
+        };
+
+        if key > 0. {
+            self.target / key
+        } else {
+            1.
+        }
+    }
+}
+
+impl Default for AutoExposure {
+    fn default() -> Self {
+        AutoExposure::new()
+    }
+}
+
+/// The settings of a physical camera, which convert luminance (from
+/// photometric lights) to pixel values as film or a sensor would.
+///
+/// Follows the usual saturation-based convention: the exposure value at ISO
+/// 100 is EV100 = log2(N² / t · 100 / S), and a luminance of 1.2 · 2^EV100
+/// maps to white. Doubling the shutter time therefore brightens the image
+/// exactly as much as doubling every light's output.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PhysicalExposure {
+    /// The sensitivity of the film or sensor.
+    pub iso: f32,
+    /// The time the shutter is open, in seconds.
+    pub shutter: f32,
+    /// The f-number (focal length over aperture diameter) of the lens.
+    pub f_number: f32,
+}
+
+impl PhysicalExposure {
+    /// Returns the "sunny 16" exposure (f/16, 1/100 s, ISO 100), which suits
+    /// scenes lit as brightly as by the midday sun.
+    pub fn new() -> Self {
+        PhysicalExposure {
+            iso: 100.,
+            shutter: 0.01,
+            f_number: 16.,
+        }
+    }
+
+    pub fn iso(mut self, iso: f32) -> Self {
+        self.iso = iso;
+        self
+    }
+
+    pub fn shutter(mut self, shutter: f32) -> Self {
+        self.shutter = shutter;
+        self
+    }
+
+    pub fn f_number(mut self, f_number: f32) -> Self {
+        self.f_number = f_number;
+        self
+    }
+
+    /// Returns the exposure value, normalized to ISO 100.
+    pub fn ev100(&self) -> f32 {
+        (self.f_number * self.f_number / self.shutter * 100. / self.iso).log2()
+    }
+
+    /// Returns the factor by which luminance is scaled to give pixel values.
+    pub fn scale(&self) -> f32 {
+        1. / (1.2 * self.ev100().exp2())
+    }
+}
+
+impl Default for PhysicalExposure {
+    fn default() -> Self {
+        PhysicalExposure::new()
+    }
+}
+
+/// Returns the relative luminance of the (linear) color.
+pub fn luminance(color: Color) -> f32 {
+    0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b
+}
+
+/// The white point for which white balance has no effect.
+pub const NEUTRAL_TEMPERATURE: f32 = 6500.;
+
+/// The smallest gamma a grade applies; lower (or NaN) gammas are raised to
+/// it, since zero or negative gammas would turn pixels infinite or NaN.
+pub const MIN_GAMMA: f32 = 0.01;
+
+/// Adjustments applied to each pixel's color before it is written to the
+/// canvas.
+///
+/// White balance is applied first, as per-channel gains relative to a
+/// black body at NEUTRAL_TEMPERATURE, followed by a lift/gamma/gain grade:
+/// `gain * (x + lift * (1 - x))^(1 / gamma)`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ColorGrade {
+    /// The color temperature (in kelvin) of the white point. Values below
+    /// NEUTRAL_TEMPERATURE warm the image, and values above cool it.
+    pub temperature: f32,
+    /// Shifts the image toward magenta (positive) or green (negative).
+    pub tint: f32,
+    /// Raises the shadows, leaving white unchanged.
+    pub lift: Color,
+    /// Brightens (above 1) or darkens (below 1) the midtones. Channels
+    /// below MIN_GAMMA are treated as MIN_GAMMA.
+    pub gamma: Color,
+    /// Scales the highlights, leaving black unchanged.
+    pub gain: Color,
+}
+
+impl ColorGrade {
+    pub fn new() -> Self {
+        ColorGrade {
+            temperature: NEUTRAL_TEMPERATURE,
+            tint: 0.,
+            lift: Color::BLACK,
+            gamma: Color::WHITE,
+            gain: Color::WHITE,
+        }
+    }
+
+    pub fn white_balance(mut self, temperature: f32, tint: f32) -> Self {
+        self.temperature = temperature;
+        self.tint = tint;
+        self
+    }
+
+    pub fn lift(mut self, lift: Color) -> Self {
+        self.lift = lift;
+        self
+    }
+
+    /// Sets the gamma, raising any channel below MIN_GAMMA to it.
+    pub fn gamma(mut self, gamma: Color) -> Self {
+        self.gamma = Color::new(
+            gamma.r.max(MIN_GAMMA),
+            gamma.g.max(MIN_GAMMA),
+            gamma.b.max(MIN_GAMMA),
+        );
+        self
+    }
+
+    pub fn gain(mut self, gain: Color) -> Self {
+        self.gain = gain;
+        self
+    }
+
+    /// Whether applying the grade leaves colors unchanged.
+    pub fn is_neutral(&self) -> bool {
+        *self == ColorGrade::new()
+    }
+
+    /// The per-channel gains applied for white balance, normalized so that
+    /// green (before tint) is unchanged.
+    pub fn white_balance_gains(&self) -> Color {
+        let white = Color::blackbody(self.temperature);
+        let neutral = Color::blackbody(NEUTRAL_TEMPERATURE);
+        let g = white.g / neutral.g;
+        Color::new(
+            white.r / neutral.r / g,
+            1. - self.tint,
+            white.b / neutral.b / g,
+        )
+    }
+
+    /// Applies the grade to the color.
+    pub fn apply(&self, color: Color) -> Color {
+        if self.is_neutral() {
+            return color;
+        }
+
+        let balanced = color * self.white_balance_gains();
+        let grade = |x: f32, lift: f32, gamma: f32, gain: f32| {
+            gain * (x + lift * (1. - x))
+                .max(0.)
+                .powf(1. / gamma.max(MIN_GAMMA))
+        };
+        Color::new(
+            grade(balanced.r, self.lift.r, self.gamma.r, self.gain.r),
+            grade(balanced.g, self.lift.g, self.gamma.g, self.gain.g),
+            grade(balanced.b, self.lift.b, self.gamma.b, self.gain.b),
+        )
+    }
+}
+
+impl Default for ColorGrade {
+    fn default() -> Self {
+        ColorGrade::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn gray_ramp() -> Vec<Color> {
+        (0..=10)
+            .map(|i| {
+                let v = i as f32 / 10.;
+                Color::new(v, v, v)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn auto_exposure_maps_the_log_average_to_the_target() {
+        let pixels = vec![Color::new(0.1, 0.1, 0.1), Color::new(0.4, 0.4, 0.4)];
+        let scale = AutoExposure::new().scale(&pixels);
+        // The geometric mean of 0.1 and 0.4 is 0.2.
+        assert_approx_eq!(scale, 0.18 / 0.2, 1e-3);
+    }
+
+    #[test]
+    fn auto_exposure_with_percentile_metering() {
+        let pixels: Vec<Color> = gray_ramp();
+        let exposure = AutoExposure::new()
+            .metering(Metering::Percentile(0.9))
+            .target(0.5);
+        assert_approx_eq!(exposure.scale(&pixels), 0.5 / 0.9);
+    }
+
+    #[test]
+    fn auto_exposure_ignores_non_finite_pixels() {
+        let mut pixels: Vec<Color> = gray_ramp();
+        pixels.push(Color::new(f32::NAN, 0., 0.));
+        pixels.push(Color::new(f32::INFINITY, 1., 1.));
+        for &metering in &[Metering::LogAverage, Metering::Percentile(0.9)] {
+            let exposure = AutoExposure::new().metering(metering);
+            assert_eq!(exposure.scale(&pixels), exposure.scale(&gray_ramp()));
+        }
+        let exposure = AutoExposure::new().metering(Metering::Percentile(0.5));
+        assert_eq!(exposure.scale(&[Color::new(f32::NAN, 0., 0.)]), 1.);
+    }
+
+    #[test]
+    fn auto_exposure_leaves_black_images_alone() {
+        assert_eq!(AutoExposure::new().scale(&[Color::BLACK; 4]), 1.);
+        assert_eq!(AutoExposure::new().scale(&[]), 1.);
+    }
+
+    #[test]
+    fn the_exposure_value_of_a_physical_camera() {
+        // Sunny 16 is about EV 14.6.
+        assert_approx_eq!(PhysicalExposure::new().ev100(), 14.644, 1e-3);
+        let exposure = PhysicalExposure::new().iso(100.).shutter(1.).f_number(1.);
+        assert_approx_eq!(exposure.ev100(), 0.);
+        assert_approx_eq!(exposure.scale(), 1. / 1.2);
+
+        // Doubling the shutter time or the ISO each gain a stop, and closing
+        // the aperture by a stop loses one.
+        let a = PhysicalExposure::new().shutter(0.02).iso(200.);
+        assert_approx_eq!(a.ev100(), PhysicalExposure::new().ev100() - 2.);
+        let b = a.f_number(16. * 2f32.sqrt()).shutter(0.04);
+        assert_approx_eq!(b.scale(), a.scale(), 1e-9);
+    }
+
+    #[test]
+    fn the_neutral_grade_is_the_identity() {
+        let grade = ColorGrade::default();
+        assert!(grade.is_neutral());
+        for c in gray_ramp() {
+            assert_eq!(grade.apply(c), c);
+        }
+        assert_eq!(
+            grade.apply(Color::new(2., -0.5, 0.3)),
+            Color::new(2., -0.5, 0.3)
+        );
+    }
+
+    #[test]
+    fn a_neutral_white_balance_has_unit_gains() {
+        let gains = ColorGrade::new().white_balance_gains();
+        assert_approx_eq!(gains.r, 1.);
+        assert_approx_eq!(gains.g, 1.);
+        assert_approx_eq!(gains.b, 1.);
+    }
+
+    #[test]
+    fn a_warm_white_balance_raises_red_and_lowers_blue() {
+        let grade = ColorGrade::new().white_balance(3200., 0.);
+        let white = Color::blackbody(3200.);
+        let neutral = Color::blackbody(NEUTRAL_TEMPERATURE);
+        let g = white.g / neutral.g;
+        let (r_gain, b_gain) = (white.r / neutral.r / g, white.b / neutral.b / g);
+        assert!(r_gain > 1.);
+        assert!(b_gain < 1.);
+
+        for c in gray_ramp() {
+            let graded = grade.apply(c);
+            assert_approx_eq!(graded.r, c.r * r_gain);
+            assert_approx_eq!(graded.g, c.g);
+            assert_approx_eq!(graded.b, c.b * b_gain);
+        }
+    }
+
+    #[test]
+    fn tint_shifts_green() {
+        let c = ColorGrade::new()
+            .white_balance(NEUTRAL_TEMPERATURE, 0.2)
+            .apply(Color::new(0.5, 0.5, 0.5));
+        assert_approx_eq!(c.g, 0.4);
+        assert_approx_eq!(c.r, 0.5);
+    }
+
+    #[test]
+    fn non_positive_gammas_are_clamped() {
+        let grade = ColorGrade::new().gamma(Color::new(0., -1., f32::NAN));
+        assert_eq!(grade.gamma, Color::new(MIN_GAMMA, MIN_GAMMA, MIN_GAMMA));
+
+        // Gammas set directly are clamped when applied.
+        let mut direct = ColorGrade::new();
+        direct.gamma = Color::new(0., -1., f32::NAN);
+        for color in [Color::BLACK, Color::new(0.5, 0.5, 0.5), Color::WHITE].iter() {
+            let c = direct.apply(*color);
+            assert!(c.r.is_finite() && c.g.is_finite() && c.b.is_finite());
+            assert_eq!(c, grade.apply(*color));
+        }
+    }
+
+    #[test]
+    fn lift_gamma_and_gain() {
+        let lift = ColorGrade::new().lift(Color::new(0.2, 0.2, 0.2));
+        assert_approx_eq!(lift.apply(Color::BLACK).r, 0.2);
+        assert_approx_eq!(lift.apply(Color::WHITE).r, 1.);
+
+        let gamma = ColorGrade::new().gamma(Color::new(2., 2., 2.));
+        assert_approx_eq!(gamma.apply(Color::new(0.25, 0.25, 0.25)).r, 0.5);
+        assert_approx_eq!(gamma.apply(Color::WHITE).r, 1.);
+
+        let gain = ColorGrade::new().gain(Color::new(0.5, 1., 2.));
+        assert_eq!(gain.apply(Color::BLACK), Color::BLACK);
+        let c = gain.apply(Color::new(0.4, 0.4, 0.4));
+        assert_approx_eq!(c.r, 0.2);
+        assert_approx_eq!(c.g, 0.4);
+        assert_approx_eq!(c.b, 0.8);
+    }
+}
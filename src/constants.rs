@@ -0,0 +1,56 @@
+//! Tolerances used throughout the renderer.
+//!
+//! Keeping these in one place makes their relationships visible: the surface
+//! offset must exceed the intersection error of every shape (or surfaces
+//! shadow themselves), while the geometric tolerances should stay small
+//! enough not to visibly distort edges and caps.
+
+/// The least distance a hit point is pushed off the surface before spawning
+/// shadow, reflection, and refraction rays. Overridable per render via
+/// `RenderOptions::surface_offset`.
+pub const SURFACE_OFFSET: f32 = 1e-3;
+
+/// The surface offset as a fraction of a hit point's distance from the
+/// origin (or along its ray), since f32 rounding error grows with both.
+/// Below 100 units, SURFACE_OFFSET is the larger.
+pub const RELATIVE_SURFACE_OFFSET: f32 = 1e-5;
+
+/// Below this, a direction component (or quadratic coefficient) is treated
+/// as zero, i.e. the ray is parallel to the surface or axis in question.
+pub const PARALLEL_EPSILON: f32 = 1e-5;
+
+/// How close to a cap a point must be to count as lying on it, and how far
+/// past the radius a cap intersection may land.
+pub const CAP_EPSILON: f32 = 1e-5;
+
+/// Boundaries closer together than this along a ray are treated as a single
+/// interface, e.g. where a glass sphere rests on a glass slab.
+pub const CONTACT_EPSILON: f32 = 1e-4;
+
+/// Nudge applied to texture coordinates so that points lying exactly on a
+/// cell boundary fall consistently into one cell.
+pub const PATTERN_EPSILON: f32 = 1e-5;
+
+/// The side of the cells of texture space which cacheable textures are
+/// evaluated at the centers of: far below anything visible.
+pub const TEXTURE_CACHE_STEP: f32 = 1e-4;
+
+/// How precisely the hits on displaced surfaces are found, as a distance
+/// along the ray in local space.
+pub const DISPLACEMENT_EPSILON: f32 = 1e-5;
+
+/// Padding added to each side of an object's world-space bounding box, so
+/// that rounding in its transform never leaves part of the object outside.
+pub const BOUNDS_EPSILON: f32 = 1e-4;
+
+/// Triangles with less area than this are dropped from OBJ models, since
+/// their normals can't be found. Rounding in the cross product leaves
+/// collapsed triangles of unit size with areas of around 1e-10.
+pub const DEGENERATE_AREA_EPSILON: f32 = 1e-8;
+
+// The surface offset must clear the intersection error of every shape.
+const _: () = assert!(
+    SURFACE_OFFSET > PARALLEL_EPSILON
+        && SURFACE_OFFSET > CAP_EPSILON
+        && SURFACE_OFFSET > DISPLACEMENT_EPSILON
+);
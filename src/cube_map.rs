@@ -0,0 +1,326 @@
+use crate::canvas::*;
+use crate::color::*;
+use crate::ppm::*;
+use crate::tuple::*;
+use std::path::Path;
+
+/// The faces of a cube map, in the order they are stored.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CubeFace {
+    PositiveX = 0,
+    NegativeX = 1,
+    PositiveY = 2,
+    NegativeY = 3,
+    PositiveZ = 4,
+    NegativeZ = 5,
+}
+
+/// An environment made of six images, one for each face of a cube surrounding
+/// the scene.
+///
+/// Faces follow the usual skybox convention (posx, negx, posy, negy, posz,
+/// negz), with each image viewed from inside the cube.
+#[derive(Clone, Debug)]
+pub struct CubeMap {
+    faces: [Canvas; 6],
+}
+
+impl CubeMap {
+    /// Constructs a cube map from the posx, negx, posy, negy, posz, and negz
+    /// images. Fails unless the faces are square, not empty, and all the
+    /// same size, as filtering across their edges relies on.
+    pub fn new(faces: [Canvas; 6]) -> Result<Self, String> {
+        const NAMES: [&str; 6] = ["posx", "negx", "posy", "negy", "posz", "negz"];
+        let size = faces[0].width;
+        for (face, name) in faces.iter().zip(&NAMES) {
+            if face.width == 0 || face.height == 0 {
+                return Err(format!("the {} face is empty", name));
+            }
+            if face.width != face.height {
+                return Err(format!(
+                    "the {} face is {}x{}, not square",
+                    name, face.width, face.height
+                ));
+            }
+            if face.width != size {
+                return Err(format!(
+                    "the {} face is {}x{}, but the posx face is {}x{}",
+                    name, face.width, face.height, size, size
+                ));
+            }
+        }
+        Ok(CubeMap { faces })
+    }
+
+    /// Loads a cube map from six PPM files, in the order posx, negx, posy,
+    /// negy, posz, negz.
+    pub fn load<P: AsRef<Path>>(paths: [P; 6]) -> Result<Self, String> {
+        let load_face = |path: &P| -> Result<Canvas, String> {
+            let path = path.as_ref();
+            let ppm = std::fs::read_to_string(path)
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+            canvas_from_ppm(&ppm).map_err(|err| format!("{}: {}", path.display(), err))
+        };
+
+        CubeMap::new([
+            load_face(&paths[0])?,
+            load_face(&paths[1])?,
+            load_face(&paths[2])?,
+            load_face(&paths[3])?,
+            load_face(&paths[4])?,
+            load_face(&paths[5])?,
+        ])
+    }
+
+    /// Returns the color seen looking along the given direction, filtered
+    /// bilinearly. Near an edge the filter reaches onto the neighboring
+    /// face, so the seams between faces don't show.
+    pub fn sample(&self, direction: Tuple4) -> Color {
+        let (face, u, v) = face_uv(direction);
+        let canvas = &self.faces[face as usize];
+
+        // Pixel centers lie at half-integer coordinates.
+        let x = u * canvas.width as f32 - 0.5;
+        let y = v * canvas.height as f32 - 0.5;
+        let (x0, y0) = (x.floor() as isize, y.floor() as isize);
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+        let top = self.texel(face, x0, y0) * (1. - fx) + self.texel(face, x0 + 1, y0) * fx;
+        let bottom =
+            self.texel(face, x0, y0 + 1) * (1. - fx) + self.texel(face, x0 + 1, y0 + 1) * fx;
+        top * (1. - fy) + bottom * fy
+    }
+
+    /// Returns the pixel at (x, y) on the face. Pixels just past the edge of
+    /// the face are looked up on the neighboring face, through the direction
+    /// of their center.
+    fn texel(&self, face: CubeFace, x: isize, y: isize) -> Color {
+        let canvas = &self.faces[face as usize];
+        let (width, height) = (canvas.width as isize, canvas.height as isize);
+        if (0..width).contains(&x) && (0..height).contains(&y) {
+            return canvas.get_color(x as usize, y as usize);
+        }
+
+        let u = (x as f32 + 0.5) / width as f32;
+        let v = (y as f32 + 0.5) / height as f32;
+        let (face, u, v) = face_uv(face_direction(face, u, v));
+        let canvas = &self.faces[face as usize];
+        let x = ((u * canvas.width as f32) as usize).min(canvas.width - 1);
+        let y = ((v * canvas.height as f32) as usize).min(canvas.height - 1);
+        canvas.get_color(x, y)
+    }
+}
+
+/// Returns the cube face hit by the direction, and the (u, v) coordinates in
+/// [0, 1] within that face, with v increasing downward.
+pub fn face_uv(direction: Tuple4) -> (CubeFace, f32, f32) {
+    let (x, y, z) = (direction.x, direction.y, direction.z);
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+
+    let (face, major, sc, tc) = if ax >= ay && ax >= az {
+        if x > 0. {
+            (CubeFace::PositiveX, ax, -z, -y)
+        } else {
+            (CubeFace::NegativeX, ax, z, -y)
+        }
+    } else if ay >= az {
+        if y > 0. {
+            (CubeFace::PositiveY, ay, x, z)
+        } else {
+            (CubeFace::NegativeY, ay, x, -z)
+        }
+    } else if z > 0. {
+        (CubeFace::PositiveZ, az, x, -y)
+    } else {
+        (CubeFace::NegativeZ, az, -x, -y)
+    };
+
+    (face, 0.5 * (sc / major + 1.), 0.5 * (tc / major + 1.))
+}
+
+/// Returns the direction through (u, v) on the face, the inverse of
+/// `face_uv`. Coordinates outside [0, 1] give directions past the face's
+/// edges.
+fn face_direction(face: CubeFace, u: f32, v: f32) -> Tuple4 {
+    let (sc, tc) = (2. * u - 1., 2. * v - 1.);
+    match face {
+        CubeFace::PositiveX => vector3(1., -tc, -sc),
+        CubeFace::NegativeX => vector3(-1., -tc, sc),
+        CubeFace::PositiveY => vector3(sc, 1., tc),
+        CubeFace::NegativeY => vector3(sc, -1., -tc),
+        CubeFace::PositiveZ => vector3(sc, -tc, 1.),
+        CubeFace::NegativeZ => vector3(-sc, -tc, -1.),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn solid(color: Color) -> Canvas {
+        let mut canvas = Canvas::new(2, 2);
+        for y in 0..2 {
+            for x in 0..2 {
+                canvas.set_color(x, y, color);
+            }
+        }
+        canvas
+    }
+
+    fn face_colors() -> [Color; 6] {
+        [
+            Color::new(1., 0., 0.),
+            Color::new(0., 1., 1.),
+            Color::new(0., 1., 0.),
+            Color::new(1., 0., 1.),
+            Color::new(0., 0., 1.),
+            Color::new(1., 1., 0.),
+        ]
+    }
+
+    fn solid_cube_map() -> CubeMap {
+        let c = face_colors();
+        CubeMap::new([
+            solid(c[0]),
+            solid(c[1]),
+            solid(c[2]),
+            solid(c[3]),
+            solid(c[4]),
+            solid(c[5]),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn rays_along_each_axis_return_the_face_color() {
+        let cube_map = solid_cube_map();
+        let c = face_colors();
+        assert_eq!(cube_map.sample(vector3(1., 0., 0.)), c[0]);
+        assert_eq!(cube_map.sample(vector3(-1., 0., 0.)), c[1]);
+        assert_eq!(cube_map.sample(vector3(0., 1., 0.)), c[2]);
+        assert_eq!(cube_map.sample(vector3(0., -1., 0.)), c[3]);
+        assert_eq!(cube_map.sample(vector3(0., 0., 1.)), c[4]);
+        assert_eq!(cube_map.sample(vector3(0., 0., -1.)), c[5]);
+    }
+
+    #[test]
+    fn sampling_blends_across_the_edges_between_faces() {
+        let cube_map = solid_cube_map();
+        let c = face_colors();
+
+        // Exactly on an edge, the two faces are weighted equally.
+        let edge = cube_map.sample(vector3(1., 1., 0.));
+        let expected = (c[0] + c[2]) * 0.5;
+        assert_approx_eq!(edge.r, expected.r);
+        assert_approx_eq!(edge.g, expected.g);
+        assert_approx_eq!(edge.b, expected.b);
+
+        // Either side of an edge, the colors meet without a seam.
+        let below = cube_map.sample(vector3(1., 0.999, 0.));
+        let above = cube_map.sample(vector3(0.999, 1., 0.));
+        assert_approx_eq!(below.r, above.r, 1e-2);
+        assert_approx_eq!(below.g, above.g, 1e-2);
+        assert_approx_eq!(below.b, above.b, 1e-2);
+
+        // Away from the edges, each face keeps its own color.
+        assert_eq!(cube_map.sample(vector3(1., 0.4, 0.)), c[0]);
+        assert_eq!(cube_map.sample(vector3(0., -0.4, -1.)), c[5]);
+    }
+
+    #[test]
+    fn face_direction_inverts_face_uv() {
+        for &direction in &[
+            vector3(1., 0.2, -0.5),
+            vector3(-1., -0.7, 0.1),
+            vector3(0.3, 1., 0.9),
+            vector3(-0.6, -1., 0.4),
+            vector3(0.8, -0.1, 1.),
+            vector3(-0.2, 0.5, -1.),
+        ] {
+            let (face, u, v) = face_uv(direction);
+            let inverse = face_direction(face, u, v);
+            assert_approx_eq!(inverse.x, direction.x);
+            assert_approx_eq!(inverse.y, direction.y);
+            assert_approx_eq!(inverse.z, direction.z);
+        }
+    }
+
+    #[test]
+    fn the_uv_coordinates_on_each_face() {
+        assert_eq!(
+            face_uv(vector3(1., 0., 0.)),
+            (CubeFace::PositiveX, 0.5, 0.5)
+        );
+        assert_eq!(face_uv(vector3(1., 1., 1.)), (CubeFace::PositiveX, 0., 0.));
+        assert_eq!(
+            face_uv(vector3(-1., -1., 1.)),
+            (CubeFace::NegativeX, 1., 1.)
+        );
+        assert_eq!(
+            face_uv(vector3(0.5, 1., 1.)),
+            (CubeFace::PositiveY, 0.75, 1.)
+        );
+        assert_eq!(
+            face_uv(vector3(0.5, -1., 1.)),
+            (CubeFace::NegativeY, 0.75, 0.)
+        );
+        assert_eq!(
+            face_uv(vector3(0.5, 0.5, 1.)),
+            (CubeFace::PositiveZ, 0.75, 0.25)
+        );
+        assert_eq!(
+            face_uv(vector3(0.5, 0.5, -1.)),
+            (CubeFace::NegativeZ, 0.25, 0.25)
+        );
+    }
+
+    #[test]
+    fn sampling_blends_between_pixels() {
+        let mut face = Canvas::new(2, 2);
+        for y in 0..2 {
+            face.set_color(0, y, Color::BLACK);
+            face.set_color(1, y, Color::WHITE);
+        }
+        let cube_map = CubeMap::new([
+            face.clone(),
+            face.clone(),
+            face.clone(),
+            face.clone(),
+            face.clone(),
+            face,
+        ])
+        .unwrap();
+
+        // The face center lies halfway between the two pixel centers.
+        let c = cube_map.sample(vector3(0., 0., 1.));
+        assert_approx_eq!(c.r, 0.5);
+        assert_approx_eq!(c.g, 0.5);
+        assert_approx_eq!(c.b, 0.5);
+    }
+
+    #[test]
+    fn faces_must_be_square_and_the_same_size() {
+        let faces = |last: Canvas| {
+            let face = solid(Color::WHITE);
+            [
+                face.clone(),
+                face.clone(),
+                face.clone(),
+                face.clone(),
+                face,
+                last,
+            ]
+        };
+        assert!(CubeMap::new(faces(Canvas::new(2, 2))).is_ok());
+        assert!(CubeMap::new(faces(Canvas::new(0, 0))).is_err());
+        assert!(CubeMap::new(faces(Canvas::new(2, 1))).is_err());
+        assert!(CubeMap::new(faces(Canvas::new(4, 4))).is_err());
+    }
+
+    #[test]
+    fn loading_a_cube_map_from_missing_files() {
+        let paths = ["/nonexistent/posx.ppm"; 6];
+        assert!(CubeMap::load(paths).is_err());
+    }
+}
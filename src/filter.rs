@@ -0,0 +1,280 @@
+use crate::color::*;
+
+/// A reconstruction filter, which weighs how much each sample contributes to
+/// the pixels around it. Filters are separable: the weight of a sample is
+/// the product of the weights of its horizontal and vertical offsets (in
+/// pixels) from a pixel's center.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Filter {
+    /// Each sample counts equally toward the pixel it lies in, and no other.
+    #[default]
+    Box,
+    /// The weight falls off linearly to zero at the radius.
+    Tent { radius: f32 },
+    /// A Gaussian falloff, shifted down so that it reaches zero at the
+    /// radius. Larger alphas fall off faster.
+    Gaussian { radius: f32, alpha: f32 },
+    /// Mitchell and Netravali's cubic, which sharpens a little with its
+    /// negative lobes. B and C trade blurring against ringing.
+    Mitchell { radius: f32, b: f32, c: f32 },
+}
+
+impl Filter {
+    pub fn tent() -> Self {
+        Filter::Tent { radius: 1. }
+    }
+
+    pub fn gaussian() -> Self {
+        Filter::Gaussian {
+            radius: 1.5,
+            alpha: 2.,
+        }
+    }
+
+    /// The cubic with B = C = 1/3, which Mitchell and Netravali recommend.
+    pub fn mitchell() -> Self {
+        Filter::Mitchell {
+            radius: 2.,
+            b: 1. / 3.,
+            c: 1. / 3.,
+        }
+    }
+
+    /// The furthest a sample can be (along either axis) from the center of a
+    /// pixel it contributes to.
+    pub fn radius(&self) -> f32 {
+        match *self {
+            Filter::Box => 0.5,
+            Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. } => radius,
+        }
+    }
+
+    /// Returns the (unnormalized) weight of a sample offset by (dx, dy) from
+    /// the center of a pixel.
+    pub fn weight(&self, dx: f32, dy: f32) -> f32 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, d: f32) -> f32 {
+        match *self {
+            // Half open, so that a sample on the edge between two pixels
+            // counts toward only one of them.
+            Filter::Box => {
+                if (-0.5..0.5).contains(&d) {
+                    1.
+                } else {
+                    0.
+                }
+            }
+            Filter::Tent { radius } => (1. - d.abs() / radius).max(0.),
+            Filter::Gaussian { radius, alpha } => {
+                ((-alpha * d * d).exp() - (-alpha * radius * radius).exp()).max(0.)
+            }
+            Filter::Mitchell { radius, b, c } => mitchell(2. * d.abs() / radius, b, c),
+        }
+    }
+}
+
+/// The Mitchell-Netravali cubic, which is zero from x = 2.
+fn mitchell(x: f32, b: f32, c: f32) -> f32 {
+    if x >= 2. {
+        0.
+    } else if x > 1. {
+        ((-b - 6. * c) * x * x * x
+            + (6. * b + 30. * c) * x * x
+            + (-12. * b - 48. * c) * x
+            + (8. * b + 24. * c))
+            / 6.
+    } else {
+        ((12. - 9. * b - 6. * c) * x * x * x + (-18. + 12. * b + 6. * c) * x * x + (6. - 2. * b))
+            / 6.
+    }
+}
+
+/// A sample of the radiance at a point on the image, in pixel coordinates,
+/// so that pixel (x, y) covers [x, x + 1) × [y, y + 1).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FilmSample {
+    pub x: f32,
+    pub y: f32,
+    pub color: Color,
+}
+
+/// Accumulates samples into pixels through a reconstruction filter.
+///
+/// Each sample is spread over the pixels within the filter's radius, with
+/// its weights normalized to sum to 1 (over every pixel in reach, including
+/// any beyond the edges), and each pixel resolves to the weighted mean of
+/// the samples reaching it.
+#[derive(Clone, Debug)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub filter: Filter,
+    sums: Vec<Color>,
+    weights: Vec<f32>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize, filter: Filter) -> Self {
+        Film {
+            width,
+            height,
+            filter,
+            sums: vec![Color::BLACK; width * height],
+            weights: vec![0.; width * height],
+        }
+    }
+
+    pub fn add_sample(&mut self, sample: FilmSample) {
+        let (xs, wx) = self.weights_1d(sample.x);
+        let (ys, wy) = self.weights_1d(sample.y);
+        let total = wx.iter().sum::<f32>() * wy.iter().sum::<f32>();
+        if total <= 0. {
+            return;
+        }
+
+        for (y, &wy) in ys.zip(&wy) {
+            if !(0..self.height as isize).contains(&y) {
+                continue;
+            }
+            for (x, &wx) in xs.clone().zip(&wx) {
+                if !(0..self.width as isize).contains(&x) {
+                    continue;
+                }
+                let i = self.width * y as usize + x as usize;
+                let weight = wx * wy / total;
+                self.sums[i] = self.sums[i] + sample.color * weight;
+                self.weights[i] += weight;
+            }
+        }
+    }
+
+    /// Returns the range of pixel coordinates along one axis within reach of
+    /// a sample at the given coordinate, and the filter's weight at each.
+    fn weights_1d(&self, coordinate: f32) -> (std::ops::Range<isize>, Vec<f32>) {
+        let radius = self.filter.radius();
+        let first = (coordinate - 0.5 - radius).ceil() as isize;
+        let last = (coordinate - 0.5 + radius).floor() as isize;
+        let weights = (first..=last)
+            .map(|i| self.filter.weight_1d(coordinate - (i as f32 + 0.5)))
+            .collect();
+        (first..last + 1, weights)
+    }
+
+    /// Returns the total weight of the samples reaching the pixel.
+    pub fn weight(&self, x: usize, y: usize) -> f32 {
+        self.weights[self.width * y + x]
+    }
+
+    /// Returns the color of every pixel, in row-major order. Pixels that no
+    /// sample reaches are black.
+    pub fn resolve(&self) -> Vec<Color> {
+        self.sums
+            .iter()
+            .zip(&self.weights)
+            .map(|(&sum, &weight)| {
+                if weight > 0. {
+                    sum * (1. / weight)
+                } else {
+                    Color::BLACK
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn a_tent_filter_spills_a_sample_into_its_neighbors() {
+        let mut film = Film::new(5, 5, Filter::Tent { radius: 1.5 });
+        film.add_sample(FilmSample {
+            x: 2.5,
+            y: 2.5,
+            color: Color::WHITE,
+        });
+
+        // Along each axis the weights are 1 at the center and 1/3 a pixel
+        // away, normalized by (1 + 2/3)² = 25/9 overall.
+        let w = [0., 1., 3., 1., 0.];
+        for y in 0..5 {
+            for x in 0..5 {
+                assert_approx_eq!(film.weight(x, y), w[x] * w[y] / 25.);
+            }
+        }
+
+        let colors = film.resolve();
+        assert_eq!(colors[5 * 2 + 1], Color::WHITE);
+        assert_eq!(colors[0], Color::BLACK);
+    }
+
+    #[test]
+    fn the_weights_of_a_sample_sum_to_one() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let filters = [
+            Filter::Box,
+            Filter::tent(),
+            Filter::Tent { radius: 1.5 },
+            Filter::gaussian(),
+            Filter::mitchell(),
+        ];
+        for filter in filters {
+            let mut film = Film::new(9, 9, filter);
+            film.add_sample(FilmSample {
+                x: 3. + 3. * rng.gen::<f32>(),
+                y: 3. + 3. * rng.gen::<f32>(),
+                color: Color::WHITE,
+            });
+            let total: f32 = (0..81).map(|i| film.weight(i % 9, i / 9)).sum();
+            assert_approx_eq!(total, 1., 1e-5);
+        }
+    }
+
+    #[test]
+    fn box_filtering_averages_the_samples_in_each_pixel() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut film = Film::new(2, 1, Filter::Box);
+        let samples: Vec<Color> = (0..16)
+            .map(|i| Color::new(i as f32 / 16., 1. - i as f32 / 16., 0.25))
+            .collect();
+        for &color in &samples {
+            film.add_sample(FilmSample {
+                x: rng.gen(),
+                y: rng.gen(),
+                color,
+            });
+        }
+        // A sample on the left edge of the second pixel stays there.
+        film.add_sample(FilmSample {
+            x: 1.,
+            y: 0.5,
+            color: Color::WHITE,
+        });
+
+        let colors = film.resolve();
+        assert_eq!(colors[0], Color::new(15. / 32., 17. / 32., 0.25));
+        assert_eq!(colors[1], Color::WHITE);
+    }
+
+    #[test]
+    fn filters_are_symmetric_and_vanish_at_their_radius() {
+        let filters = [Filter::tent(), Filter::gaussian(), Filter::mitchell()];
+        for filter in filters {
+            let r = filter.radius();
+            assert!(filter.weight(0., 0.) > 0.);
+            assert_eq!(filter.weight(r, 0.), 0.);
+            assert_eq!(filter.weight(0., -r), 0.);
+            assert_eq!(filter.weight(0.3, -0.2), filter.weight(-0.3, 0.2));
+        }
+        // Mitchell's negative lobes lie in the outer half of its radius.
+        assert!(Filter::mitchell().weight(1.5, 0.) < 0.);
+    }
+}
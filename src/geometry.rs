@@ -1,18 +1,38 @@
+use crate::bounds::*;
+use crate::color::*;
+use crate::constants::*;
 use crate::intersection::*;
+use crate::object::*;
 use crate::ray::*;
 use crate::tuple::*;
 
+pub mod checker_floor;
 pub mod cone;
+pub mod csg;
 pub mod cube;
 pub mod cylinder;
+pub mod displaced_plane;
+pub mod mesh;
 pub mod plane;
+pub mod smooth_triangle;
 pub mod sphere;
+pub mod triangle;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+use csg::CsgOperation;
+use displaced_plane::HeightField;
+use mesh::Mesh;
+use std::sync::Arc;
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Geometry {
     Plane,
     Sphere,
-    Cube,
+    Cube {
+        /// The distance from each edge over which the face normals blend
+        /// into each other, catching highlights as a rounded edge would. In
+        /// local space, so it stretches with the cube. Only affects shading.
+        bevel_radius: f32,
+    },
     Cone {
         /// Minimum y-value for the cone.
         min: f32,
@@ -20,6 +40,9 @@ pub enum Geometry {
         max: f32,
         /// Whether to close the cone on the end.
         closed: bool,
+        /// The height over which the wall's normal bends toward the cap's near
+        /// each closed end, softening the seam. Only affects shading.
+        fillet: f32,
     },
     Cylinder {
         /// Minimum y-value for the cylinder.
@@ -28,6 +51,79 @@ pub enum Geometry {
         max: f32,
         /// Whether to close the cylinder on each end.
         closed: bool,
+        /// The height over which the wall's normal bends toward the cap's near
+        /// each closed end, softening the seam. Only affects shading.
+        fillet: f32,
+    },
+    Triangle {
+        /// The first vertex.
+        p1: Tuple4,
+        /// The second vertex.
+        p2: Tuple4,
+        /// The third vertex.
+        p3: Tuple4,
+        /// The edge from p1 to p2.
+        e1: Tuple4,
+        /// The edge from p1 to p3.
+        e2: Tuple4,
+        /// The face normal.
+        normal: Tuple4,
+    },
+    SmoothTriangle {
+        /// The first vertex.
+        p1: Tuple4,
+        /// The second vertex.
+        p2: Tuple4,
+        /// The third vertex.
+        p3: Tuple4,
+        /// The normal at the first vertex.
+        n1: Tuple4,
+        /// The normal at the second vertex.
+        n2: Tuple4,
+        /// The normal at the third vertex.
+        n3: Tuple4,
+        /// The edge from p1 to p2.
+        e1: Tuple4,
+        /// The edge from p1 to p3.
+        e2: Tuple4,
+    },
+    /// A triangle mesh whose vertices are shared by its triangles (see
+    /// `Mesh`). The mesh itself is shared too, so that instances of it don't
+    /// copy it.
+    Mesh {
+        mesh: Arc<Mesh>,
+    },
+    /// A plane (like `Plane`) which colors itself with a checker pattern,
+    /// filtered over each ray's footprint so that it doesn't alias however
+    /// far away it's seen.
+    CheckerFloor {
+        /// The color of the check at the origin.
+        a: Color,
+        /// The color of the other checks.
+        b: Color,
+        /// The width of each check.
+        size: f32,
+    },
+    /// A plane (like `Plane`) raised and lowered by a height field, for
+    /// terrain and water. It's intersected by marching each ray along in
+    /// steps of the given length (in local space), which must be short
+    /// enough not to step over the bumps.
+    DisplacedPlane {
+        height: HeightField,
+        step: f32,
+    },
+    /// The combination of two other objects in the scene (either of which
+    /// may itself be a CSG object). The children are placed relative to the
+    /// CSG object, and its surface is made up of the parts of theirs which
+    /// the operation keeps.
+    ///
+    /// The scene intersects the children, so the geometry on its own has no
+    /// surface; the hits are reported on the children, which give their own
+    /// normals and materials.
+    Csg {
+        operation: CsgOperation,
+        left: ObjectId,
+        right: ObjectId,
     },
     TestShape,
 }
@@ -42,7 +138,19 @@ impl Geometry {
     }
 
     pub fn cube() -> Self {
-        Geometry::Cube
+        Geometry::Cube { bevel_radius: 0. }
+    }
+
+    /// Constructs a cube whose edges are shaded as if rounded over the given
+    /// radius. Fails unless the radius is at least 0 and less than 1, the
+    /// half-width of the cube.
+    pub fn beveled_cube(radius: f32) -> Result<Self, String> {
+        if !(0. ..1.).contains(&radius) {
+            return Err(format!("bevel radius {} is not in [0, 1)", radius));
+        }
+        Ok(Geometry::Cube {
+            bevel_radius: radius,
+        })
     }
 
     pub fn cone() -> Self {
@@ -50,6 +158,7 @@ impl Geometry {
             min: -std::f32::INFINITY,
             max: std::f32::INFINITY,
             closed: false,
+            fillet: 0.,
         }
     }
 
@@ -58,6 +167,141 @@ impl Geometry {
             min: -std::f32::INFINITY,
             max: std::f32::INFINITY,
             closed: false,
+            fillet: 0.,
+        }
+    }
+
+    /// Constructs a cylinder from y = min to y = max, closed at both ends.
+    pub fn capped_cylinder(min: f32, max: f32) -> Self {
+        Geometry::Cylinder {
+            min,
+            max,
+            closed: true,
+            fillet: 0.,
+        }
+    }
+
+    /// Constructs a cone from y = min to y = max, closed at both ends.
+    pub fn capped_cone(min: f32, max: f32) -> Self {
+        Geometry::Cone {
+            min,
+            max,
+            closed: true,
+            fillet: 0.,
+        }
+    }
+
+    /// Truncates a cylinder or cone below y = min. Fails for other geometry,
+    /// or if min is above the maximum.
+    pub fn min(mut self, y: f32) -> Result<Self, String> {
+        let (min, max, _) = self.conic_mut("min")?;
+        if y > *max {
+            return Err(format!("min {} is above max {}", y, max));
+        }
+        *min = y;
+        Ok(self)
+    }
+
+    /// Truncates a cylinder or cone above y = max. Fails for other geometry,
+    /// or if max is below the minimum.
+    pub fn max(mut self, y: f32) -> Result<Self, String> {
+        let (min, max, _) = self.conic_mut("max")?;
+        if y < *min {
+            return Err(format!("max {} is below min {}", y, min));
+        }
+        *max = y;
+        Ok(self)
+    }
+
+    /// Sets whether a cylinder or cone is closed at its ends. Fails for
+    /// other geometry.
+    pub fn closed(mut self, closed: bool) -> Result<Self, String> {
+        *self.conic_mut("closed")?.2 = closed;
+        Ok(self)
+    }
+
+    /// Returns the extent and closedness of a cylinder or cone, or an error
+    /// naming the setting which only applies to them.
+    fn conic_mut(&mut self, setting: &str) -> Result<(&mut f32, &mut f32, &mut bool), String> {
+        match self {
+            Geometry::Cylinder {
+                min, max, closed, ..
+            }
+            | Geometry::Cone {
+                min, max, closed, ..
+            } => Ok((min, max, closed)),
+            other => Err(format!(
+                "{} only applies to cylinders and cones, not {:?}",
+                setting, other
+            )),
+        }
+    }
+
+    /// Constructs a triangle with the given vertices. The face normal follows
+    /// from their winding order.
+    pub fn triangle(p1: Tuple4, p2: Tuple4, p3: Tuple4) -> Self {
+        let e1 = p2 - p1;
+        let e2 = p3 - p1;
+        let normal = e2.cross(e1);
+        let normal = if normal.magnitude() > 0. {
+            normal.normalize()
+        } else {
+            normal
+        };
+        Geometry::Triangle {
+            p1,
+            p2,
+            p3,
+            e1,
+            e2,
+            normal,
+        }
+    }
+
+    /// Constructs a triangle from (vertex, normal) pairs, whose shading
+    /// normal is interpolated between the vertex normals.
+    pub fn smooth_triangle(
+        (p1, n1): (Tuple4, Tuple4),
+        (p2, n2): (Tuple4, Tuple4),
+        (p3, n3): (Tuple4, Tuple4),
+    ) -> Self {
+        Geometry::SmoothTriangle {
+            p1,
+            p2,
+            p3,
+            n1,
+            n2,
+            n3,
+            e1: p2 - p1,
+            e2: p3 - p1,
+        }
+    }
+
+    /// Constructs the geometry of a triangle mesh.
+    pub fn mesh(mesh: Mesh) -> Self {
+        Geometry::Mesh {
+            mesh: Arc::new(mesh),
+        }
+    }
+
+    /// Constructs a checker floor in the x-z plane, alternating between the
+    /// given colors every `size` units.
+    pub fn checker_floor(a: Color, b: Color, size: f32) -> Self {
+        Geometry::CheckerFloor { a, b, size }
+    }
+
+    /// Constructs a plane displaced by the height field, marched through in
+    /// steps of the given length.
+    pub fn displaced_plane(height: HeightField, step: f32) -> Self {
+        Geometry::DisplacedPlane { height, step }
+    }
+
+    /// Constructs a CSG object combining the left and right objects.
+    pub fn csg(operation: CsgOperation, left: ObjectId, right: ObjectId) -> Self {
+        Geometry::Csg {
+            operation,
+            left,
+            right,
         }
     }
 
@@ -67,43 +311,208 @@ impl Geometry {
 
     /// Returns the collection of Intersections where the ray intersects the
     /// geometry.
-    pub fn intersect(self, ray: Ray) -> Intersections {
-        match self {
+    pub fn intersect(&self, ray: Ray) -> Intersections {
+        match *self {
             Geometry::Plane => plane::intersect(ray),
             Geometry::Sphere => sphere::intersect(ray),
-            Geometry::Cube => cube::intersect(ray),
-            Geometry::Cone { min, max, closed } => cone::intersect(ray, min, max, closed),
-            Geometry::Cylinder { min, max, closed } => cylinder::intersect(ray, min, max, closed),
-            Geometry::TestShape => Intersections::new(),
+            Geometry::Cube { .. } => cube::intersect(ray),
+            Geometry::Cone {
+                min, max, closed, ..
+            } => cone::intersect(ray, min, max, closed),
+            Geometry::Cylinder {
+                min, max, closed, ..
+            } => cylinder::intersect(ray, min, max, closed),
+            Geometry::Triangle { p1, p2, p3, .. } => triangle::intersect(ray, p1, p2, p3),
+            Geometry::SmoothTriangle { p1, p2, p3, .. } => {
+                smooth_triangle::intersect(ray, p1, p2, p3)
+            }
+            Geometry::Mesh { ref mesh } => mesh.intersect(ray),
+            Geometry::CheckerFloor { .. } => checker_floor::intersect(ray),
+            Geometry::DisplacedPlane { ref height, step } => {
+                displaced_plane::intersect(ray, height, step)
+            }
+            Geometry::Csg { .. } | Geometry::TestShape => Intersections::new(),
         }
     }
 
-    /// Returns the surface normal at the given point.
-    pub fn normal_at(self, point: Tuple4) -> Tuple4 {
-        match self {
+    /// Returns the surface normal at the given point. For a mesh, this has
+    /// to find the triangle the point lies on; `normal_at_uv` is quicker
+    /// given the intersection.
+    pub fn normal_at(&self, point: Tuple4) -> Tuple4 {
+        match *self {
             Geometry::Plane => plane::normal_at(point),
             Geometry::Sphere => sphere::normal_at(point),
-            Geometry::Cube => cube::normal_at(point),
-            Geometry::Cone { min, max, closed } => cone::normal_at(point, min, max, closed),
-            Geometry::Cylinder { min, max, closed } => cylinder::normal_at(point, min, max, closed),
-            Geometry::TestShape => vector3(0., 0., 0.),
+            Geometry::Cube { bevel_radius } => cube::normal_at(point, bevel_radius),
+            Geometry::Cone {
+                min,
+                max,
+                closed,
+                fillet,
+            } => cone::normal_at(point, min, max, closed, fillet),
+            Geometry::Cylinder {
+                min,
+                max,
+                closed,
+                fillet,
+            } => cylinder::normal_at(point, min, max, closed, fillet),
+            Geometry::Triangle { normal, .. } => triangle::normal_at(point, normal),
+            Geometry::SmoothTriangle { p1, e1, e2, .. } => {
+                let uv = smooth_triangle::barycentric(point, p1, e1, e2);
+                self.normal_at_uv(point, Some(uv), 0)
+            }
+            Geometry::Mesh { ref mesh } => mesh.normal_at(mesh.face_at(point), None),
+            Geometry::CheckerFloor { .. } => checker_floor::normal_at(point),
+            Geometry::DisplacedPlane { ref height, .. } => {
+                displaced_plane::normal_at(point, height)
+            }
+            Geometry::Csg { .. } | Geometry::TestShape => vector3(0., 0., 0.),
+        }
+    }
+
+    /// Returns the box enclosing the geometry in its own (local) space. Planes
+    /// and untruncated cylinders and cones extend forever, as (as far as the
+    /// geometry alone knows) do CSG objects.
+    pub fn bounds(&self) -> Bounds3 {
+        let inf = f32::INFINITY;
+        match *self {
+            Geometry::Csg { .. } => Bounds3::infinite(),
+            Geometry::Plane | Geometry::CheckerFloor { .. } => {
+                Bounds3::new(point3(-inf, 0., -inf), point3(inf, 0., inf))
+            }
+            Geometry::DisplacedPlane { ref height, .. } => {
+                let max_height = height.max_height();
+                Bounds3::new(
+                    point3(-inf, -max_height, -inf),
+                    point3(inf, max_height, inf),
+                )
+            }
+            Geometry::Sphere | Geometry::Cube { .. } | Geometry::TestShape => {
+                Bounds3::new(point3(-1., -1., -1.), point3(1., 1., 1.))
+            }
+            Geometry::Cylinder { min, max, .. } => {
+                Bounds3::new(point3(-1., min, -1.), point3(1., max, 1.))
+            }
+            Geometry::Cone { min, max, .. } => {
+                let radius = min.abs().max(max.abs());
+                Bounds3::new(point3(-radius, min, -radius), point3(radius, max, radius))
+            }
+            Geometry::Triangle { p1, p2, p3, .. } | Geometry::SmoothTriangle { p1, p2, p3, .. } => {
+                Bounds3::from_points(&[p1, p2, p3])
+            }
+            Geometry::Mesh { ref mesh } => mesh.bounds(),
+        }
+    }
+
+    /// Returns the normal of the surface itself at the given point (on the
+    /// given triangle, for a mesh), ignoring any interpolated shading
+    /// normals. A triangle's follows from its winding order.
+    pub fn geometric_normal_at(&self, point: Tuple4, face: usize) -> Tuple4 {
+        match *self {
+            Geometry::SmoothTriangle { e1, e2, .. } => e2.cross(e1).normalize(),
+            Geometry::Mesh { ref mesh } => mesh.geometric_normal(face),
+            _ => self.normal_at(point),
+        }
+    }
+
+    /// Maps a point in the unit square to a point on the surface, uniformly
+    /// distributed over its area in local space, for geometry whose surface
+    /// can be sampled: spheres, cubes and triangles.
+    pub fn sample_surface(&self, u: f32, v: f32) -> Option<Tuple4> {
+        match *self {
+            Geometry::Sphere => Some(sphere::sample_surface(u, v)),
+            Geometry::Cube { .. } => Some(cube::sample_surface(u, v)),
+            Geometry::Triangle { p1, e1, e2, .. } | Geometry::SmoothTriangle { p1, e1, e2, .. } => {
+                Some(triangle::sample_surface(p1, e1, e2, u, v))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the geometry facing the other way. Triangles have their
+    /// winding (and vertex normals) reversed; other geometry is unchanged.
+    pub fn flipped(&self) -> Self {
+        match *self {
+            Geometry::Triangle { p1, p2, p3, .. } => Geometry::triangle(p1, p3, p2),
+            Geometry::SmoothTriangle {
+                p1,
+                p2,
+                p3,
+                n1,
+                n2,
+                n3,
+                ..
+            } => Geometry::smooth_triangle((p1, -n1), (p3, -n3), (p2, -n2)),
+            Geometry::Mesh { ref mesh } => Geometry::mesh(mesh.flipped()),
+            _ => self.clone(),
+        }
+    }
+
+    /// For geometry which interpolates its normals, returns the point on the
+    /// curved surface they describe at the hit with the given (u, v)
+    /// coordinates (on the given triangle, for a mesh), for shadow rays to
+    /// start from.
+    pub fn shading_point(
+        &self,
+        point: Tuple4,
+        uv: Option<(f32, f32)>,
+        face: usize,
+    ) -> Option<Tuple4> {
+        match (self, uv) {
+            (Geometry::Mesh { mesh }, Some(uv)) => mesh.shading_point(point, uv, face),
+            (
+                &Geometry::SmoothTriangle {
+                    p1,
+                    p2,
+                    p3,
+                    n1,
+                    n2,
+                    n3,
+                    ..
+                },
+                Some(uv),
+            ) => Some(smooth_triangle::shading_point(
+                point,
+                uv,
+                (p1, n1),
+                (p2, n2),
+                (p3, n3),
+            )),
+            _ => None,
         }
     }
+
+    /// Returns the surface normal at the given point, using the (u, v)
+    /// coordinates of the intersection (if any) for geometry which
+    /// interpolates its normals, and the triangle hit for a mesh.
+    pub fn normal_at_uv(&self, point: Tuple4, uv: Option<(f32, f32)>, face: usize) -> Tuple4 {
+        match (self, uv) {
+            (Geometry::Mesh { mesh }, _) => mesh.normal_at(face, uv),
+            (&Geometry::SmoothTriangle { n1, n2, n3, .. }, Some(uv)) => {
+                smooth_triangle::normal_at(uv, n1, n2, n3)
+            }
+            _ => self.normal_at(point),
+        }
+    }
+}
+
+/// Bends the wall normal toward the bisector of the wall and cap normals as
+/// the point approaches a cap, reaching it at the seam (distance 0).
+fn fillet_normal(wall: Tuple4, cap: Tuple4, distance: f32, fillet: f32) -> Tuple4 {
+    let wall = wall.normalize();
+    let bisector = (wall + cap).normalize();
+    let s = 1. - (distance / fillet).max(0.);
+    (wall * (1. - s) + bisector * s).normalize()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use assert_approx_eq::assert_approx_eq;
 
     #[test]
     fn the_default_minimum_and_maximum_for_a_cylinder() {
         let cyl = Geometry::cylinder();
-        if let Geometry::Cylinder {
-            min,
-            max,
-            closed: _,
-        } = cyl
-        {
+        if let Geometry::Cylinder { min, max, .. } = cyl {
             assert_eq!(min, -std::f32::INFINITY);
             assert_eq!(max, std::f32::INFINITY);
         } else {
@@ -114,15 +523,213 @@ mod tests {
     #[test]
     fn the_default_closed_value_for_a_cylinder() {
         let cyl = Geometry::cylinder();
-        if let Geometry::Cylinder {
-            min: _,
-            max: _,
-            closed,
-        } = cyl
-        {
+        if let Geometry::Cylinder { closed, .. } = cyl {
             assert_eq!(closed, false);
         } else {
             panic!();
         }
     }
+
+    #[test]
+    fn truncating_and_closing_a_cylinder() {
+        let cylinder = Geometry::cylinder()
+            .min(0.)
+            .unwrap()
+            .max(1.)
+            .unwrap()
+            .closed(true)
+            .unwrap();
+        assert_eq!(
+            cylinder,
+            Geometry::Cylinder {
+                min: 0.,
+                max: 1.,
+                closed: true,
+                fillet: 0.,
+            }
+        );
+        assert_eq!(cylinder, Geometry::capped_cylinder(0., 1.));
+
+        let cone = Geometry::cone().max(0.).unwrap().min(-1.).unwrap();
+        assert_eq!(
+            cone,
+            Geometry::Cone {
+                min: -1.,
+                max: 0.,
+                closed: false,
+                fillet: 0.,
+            }
+        );
+        assert_eq!(cone.closed(true), Ok(Geometry::capped_cone(-1., 0.)));
+    }
+
+    #[test]
+    fn truncating_other_geometry_is_an_error() {
+        let error = Geometry::sphere().min(0.).unwrap_err();
+        assert!(error.contains("min only applies to cylinders and cones"));
+        assert!(Geometry::cube().max(1.).is_err());
+        assert!(Geometry::plane().closed(true).is_err());
+    }
+
+    #[test]
+    fn the_minimum_cant_pass_the_maximum() {
+        let cylinder = Geometry::capped_cylinder(0., 1.);
+        assert!(cylinder.clone().min(2.).is_err());
+        assert!(cylinder.clone().max(-1.).is_err());
+        assert!(cylinder.min(1.).is_ok());
+    }
+
+    #[test]
+    fn the_bevel_radius_must_fit_the_cube() {
+        assert_eq!(Geometry::beveled_cube(0.), Ok(Geometry::cube()));
+        assert_eq!(
+            Geometry::beveled_cube(0.25),
+            Ok(Geometry::Cube { bevel_radius: 0.25 })
+        );
+        assert!(Geometry::beveled_cube(-0.1).is_err());
+        assert!(Geometry::beveled_cube(1.).is_err());
+        assert!(Geometry::beveled_cube(f32::NAN).is_err());
+    }
+
+    #[test]
+    fn rays_through_the_caps_hit_a_capped_cylinder_and_cone() {
+        // Straight down the axis, through both caps.
+        let r = ray(point3(0., 3., 0.), vector3(0., -1., 0.));
+        let xs = Geometry::capped_cylinder(0., 1.).intersect(r);
+        assert_eq!(xs.len(), 2);
+        assert_approx_eq!(xs[0], 2.);
+        assert_approx_eq!(xs[1], 3.);
+        assert_eq!(
+            Geometry::capped_cylinder(0., 1.).normal_at(point3(0.5, 1., 0.)),
+            vector3(0., 1., 0.)
+        );
+
+        // Through the cone's upper cap, then its wall (with the apex at the
+        // origin).
+        let r = ray(point3(0.5, 3., 0.), vector3(0., -1., 0.));
+        let xs = Geometry::capped_cone(0., 1.).intersect(r);
+        assert_eq!(xs.len(), 2);
+        assert_approx_eq!(xs[0], 2.);
+        assert_approx_eq!(xs[1], 2.5);
+
+        // Open, the caps let the ray through to the walls, or miss entirely.
+        let open = Geometry::capped_cylinder(0., 1.).closed(false).unwrap();
+        assert_eq!(
+            open.intersect(ray(point3(0., 3., 0.), vector3(0., -1., 0.)))
+                .len(),
+            0
+        );
+    }
+
+    #[test]
+    fn the_caps_of_a_cone_grow_with_their_height() {
+        // The top cap at y = 2 has radius 2: a ray down at x = 1.8 hits it,
+        // and then the wall.
+        let cone = Geometry::capped_cone(0., 2.);
+        let xs = cone.intersect(ray(point3(1.8, 5., 0.), vector3(0., -1., 0.)));
+        assert_eq!(xs.len(), 2);
+        assert_approx_eq!(xs[0], 3.);
+        assert_approx_eq!(xs[1], 3.2);
+        assert_eq!(cone.normal_at(point3(1.5, 2., 0.)), vector3(0., 1., 0.));
+        assert_ne!(cone.normal_at(point3(1.5, 1.5, 0.)), vector3(0., 1., 0.));
+
+        // And the bottom cap at y = -3 has radius 3.
+        let cone = Geometry::capped_cone(-3., 0.);
+        let xs = cone.intersect(ray(point3(0., -5., 2.5), vector3(0., 1., 0.)));
+        assert_eq!(xs.len(), 2);
+        assert_approx_eq!(xs[0], 2.);
+        assert_approx_eq!(xs[1], 2.5);
+        assert_eq!(cone.normal_at(point3(0., -3., 2.9)), vector3(0., -1., 0.));
+
+        // Beyond the radius, the cap is missed.
+        let xs = cone.intersect(ray(point3(0., -5., 3.1), vector3(0., 1., 0.)));
+        assert_eq!(xs.len(), 0);
+    }
+
+    #[test]
+    fn the_bounds_of_bounded_shapes() {
+        let unit = Bounds3::new(point3(-1., -1., -1.), point3(1., 1., 1.));
+        assert_eq!(Geometry::sphere().bounds(), unit);
+        assert_eq!(Geometry::cube().bounds(), unit);
+
+        let triangle = Geometry::triangle(
+            point3(-3., 7., 2.),
+            point3(6., 2., -4.),
+            point3(2., -1., -1.),
+        );
+        assert_eq!(
+            triangle.bounds(),
+            Bounds3::new(point3(-3., -1., -4.), point3(6., 7., 2.))
+        );
+    }
+
+    #[test]
+    fn the_bounds_of_truncated_cylinders_and_cones() {
+        let cylinder = Geometry::capped_cylinder(-5., 3.);
+        assert_eq!(
+            cylinder.bounds(),
+            Bounds3::new(point3(-1., -5., -1.), point3(1., 3., 1.))
+        );
+
+        let cone = Geometry::cone().min(-5.).unwrap().max(3.).unwrap();
+        assert_eq!(
+            cone.bounds(),
+            Bounds3::new(point3(-5., -5., -5.), point3(5., 3., 5.))
+        );
+    }
+
+    #[test]
+    fn the_bounds_of_unbounded_shapes() {
+        let inf = f32::INFINITY;
+        assert_eq!(
+            Geometry::plane().bounds(),
+            Bounds3::new(point3(-inf, 0., -inf), point3(inf, 0., inf))
+        );
+        assert_eq!(
+            Geometry::cylinder().bounds(),
+            Bounds3::new(point3(-1., -inf, -1.), point3(1., inf, 1.))
+        );
+        assert_eq!(Geometry::cone().bounds(), Bounds3::infinite());
+    }
+
+    #[test]
+    fn flipping_a_triangle_reverses_its_normal() {
+        let (p1, p2, p3) = (point3(0., 1., 0.), point3(-1., 0., 0.), point3(1., 0., 0.));
+        let t = Geometry::triangle(p1, p2, p3);
+        let point = point3(0., 0.5, 0.);
+        assert_eq!(t.flipped().normal_at(point), -t.normal_at(point));
+        assert_eq!(t.flipped().flipped(), t);
+
+        let n = vector3(0., 0., -1.);
+        let smooth = Geometry::smooth_triangle((p1, n), (p2, n), (p3, n));
+        assert_eq!(smooth.geometric_normal_at(point, 0), t.normal_at(point));
+        let flipped = smooth.flipped();
+        assert_eq!(flipped.geometric_normal_at(point, 0), -t.normal_at(point));
+        assert_eq!(flipped.normal_at(point), -n);
+
+        assert_eq!(Geometry::sphere().flipped(), Geometry::sphere());
+    }
+
+    #[test]
+    fn sampled_points_lie_on_the_surface() {
+        let triangle =
+            Geometry::triangle(point3(0., 1., 0.), point3(-1., 0., 0.), point3(1., 0., 0.));
+        let grid = (0..=8).flat_map(|i| (0..=8).map(move |j| (i as f32 / 8., j as f32 / 8.)));
+        for (u, v) in grid {
+            let p = Geometry::sphere().sample_surface(u, v).unwrap();
+            assert_approx_eq!((p - point3(0., 0., 0.)).magnitude(), 1.);
+
+            let p = Geometry::cube().sample_surface(u, v).unwrap();
+            assert_eq!(p.x.abs().max(p.y.abs()).max(p.z.abs()), 1.);
+
+            let p = triangle.sample_surface(u, v).unwrap();
+            assert!(
+                p.z == 0. && p.y >= 0. && p.x.abs() <= 1. - p.y + 1e-6,
+                "{:?}",
+                p
+            );
+        }
+
+        assert_eq!(Geometry::plane().sample_surface(0.5, 0.5), None);
+    }
 }
@@ -0,0 +1,115 @@
+use crate::geometry::*;
+use crate::texture::*;
+
+pub fn intersect(ray: Ray) -> Intersections {
+    plane::intersect(ray)
+}
+
+pub fn normal_at(point: Tuple4) -> Tuple4 {
+    plane::normal_at(point)
+}
+
+/// Returns the color of the floor where the (local space) ray hits it at t,
+/// averaged over the ray's footprint. Rays without a spread are point
+/// sampled.
+pub fn color_at(ray: Ray, t: f32, a: Color, b: Color, size: f32) -> Color {
+    let point = ray.position(t);
+    let (x, z) = (point.x / size, point.z / size);
+    let width = ray.width_at(t) / size;
+
+    if width <= 0. {
+        return if (x.floor() + z.floor()).rem_euclid(2.) == 0. {
+            a
+        } else {
+            b
+        };
+    }
+
+    // The footprint is stretched along the direction of travel as the ray
+    // grazes the floor, and keeps its width across it.
+    let length = ray.direction.magnitude();
+    let cos = (ray.direction.y / length).abs();
+    let horizontal = ray.direction.x.hypot(ray.direction.z);
+    let (ux, uz) = if horizontal > 0. {
+        (ray.direction.x / horizontal, ray.direction.z / horizontal)
+    } else {
+        (1., 0.)
+    };
+    let along = width / cos;
+    let width_x = (along * ux).hypot(width * uz);
+    let width_z = (along * uz).hypot(width * ux);
+
+    // A check is b where exactly one of the x and z square waves is high.
+    let fx = filtered_square_wave(x, width_x);
+    let fz = filtered_square_wave(z, width_z);
+    let t = fx * (1. - fz) + fz * (1. - fx);
+    a * (1. - t) + b * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn down(x: f32, z: f32, spread: f32) -> Ray {
+        ray(point3(x, 1., z), vector3(0., -1., 0.)).spread(spread)
+    }
+
+    #[test]
+    fn a_checker_floor_intersects_like_a_plane() {
+        let r = down(0.5, 0.5, 0.);
+        assert_eq!(intersect(r), plane::intersect(r));
+        assert_eq!(normal_at(point3(3., 0., -2.)), vector3(0., 1., 0.));
+    }
+
+    #[test]
+    fn narrow_rays_see_sharp_checks() {
+        let examples = vec![
+            (0.5, 0.5, Color::WHITE),
+            (1.5, 0.5, Color::BLACK),
+            (0.5, 1.5, Color::BLACK),
+            (1.5, 1.5, Color::WHITE),
+            (-0.5, 0.5, Color::BLACK),
+            (-0.5, -0.5, Color::WHITE),
+        ];
+        for (x, z, expected) in examples {
+            for &spread in &[0., 0.01] {
+                let c = color_at(down(x, z, spread), 1., Color::WHITE, Color::BLACK, 1.);
+                assert_approx_eq!(c.r, expected.r, 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn checks_are_scaled_by_their_size() {
+        let c = color_at(down(1.5, 0.5, 0.), 1., Color::WHITE, Color::BLACK, 2.);
+        assert_eq!(c, Color::WHITE);
+        let c = color_at(down(2.5, 0.5, 0.), 1., Color::WHITE, Color::BLACK, 2.);
+        assert_eq!(c, Color::BLACK);
+    }
+
+    #[test]
+    fn wide_footprints_blend_to_the_mean_color() {
+        let c = color_at(down(0.5, 0.5, 100.), 1., Color::WHITE, Color::BLACK, 1.);
+        assert_approx_eq!(c.r, 0.5, 1e-2);
+
+        // Straddling the corner where four checks meet.
+        let c = color_at(down(0., 0., 0.5), 1., Color::WHITE, Color::BLACK, 1.);
+        assert_approx_eq!(c.r, 0.5);
+    }
+
+    #[test]
+    fn grazing_rays_are_blurred_along_their_direction() {
+        // The same spread which sees a sharp check from above covers several
+        // checks when the floor is seen nearly edge on.
+        let from_above = down(0.125, 0.125, 0.01);
+        let c = color_at(from_above, 1., Color::WHITE, Color::BLACK, 0.25);
+        assert_approx_eq!(c.r, 1.);
+
+        let direction = vector3(0., -0.01, 1.).normalize();
+        let grazing = ray(point3(0.125, 0.01, -0.875), direction).spread(0.01);
+        let t = intersect(grazing)[0];
+        let c = color_at(grazing, t, Color::WHITE, Color::BLACK, 0.25);
+        assert_approx_eq!(c.r, 0.5, 1e-2);
+    }
+}
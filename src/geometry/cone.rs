@@ -22,8 +22,8 @@ pub fn intersect(ray: Ray, min: f32, max: f32, closed: bool) -> Intersections {
             .mul_add(ray.origin.z, -ray.origin.y * ray.origin.y),
     );
 
-    if a.abs() < 1e-5 {
-        if b.abs() < 1e-5 {
+    if a.abs() < PARALLEL_EPSILON {
+        if b.abs() < PARALLEL_EPSILON {
             return result;
         }
 
@@ -32,7 +32,7 @@ pub fn intersect(ray: Ray, min: f32, max: f32, closed: bool) -> Intersections {
     } else {
         let mut discriminant = b.mul_add(b, -a * c);
 
-        if discriminant.abs() < 1e-5 {
+        if discriminant.abs() < PARALLEL_EPSILON {
             discriminant = 0.;
         }
 
@@ -69,14 +69,14 @@ pub fn intersect(ray: Ray, min: f32, max: f32, closed: bool) -> Intersections {
     result
 }
 
-pub fn normal_at(point: Tuple4, min: f32, max: f32, _closed: bool) -> Tuple4 {
+pub fn normal_at(point: Tuple4, min: f32, max: f32, closed: bool, fillet: f32) -> Tuple4 {
     // The square of the distance from the y axis.
     let d2 = point.x.mul_add(point.x, point.z * point.z);
 
-    if d2 < max.abs() && point.y >= max - 1e-5 {
+    if d2 < max * max && point.y >= max - CAP_EPSILON {
         // Hitting the top cap.
         vector3(0., 1., 0.)
-    } else if d2 < min.abs() && point.y <= min + 1e-5 {
+    } else if d2 < min * min && point.y <= min + CAP_EPSILON {
         // Hitting the bottom cap.
         vector3(0., -1., 0.)
     } else {
@@ -86,7 +86,14 @@ pub fn normal_at(point: Tuple4, min: f32, max: f32, _closed: bool) -> Tuple4 {
             y = -y;
         }
 
-        vector3(point.x, y, point.z)
+        let wall = vector3(point.x, y, point.z);
+        if closed && fillet > 0. && point.y > max - fillet {
+            fillet_normal(wall, vector3(0., 1., 0.), max - point.y, fillet)
+        } else if closed && fillet > 0. && point.y < min + fillet {
+            fillet_normal(wall, vector3(0., -1., 0.), point.y - min, fillet)
+        } else {
+            wall
+        }
     }
 }
 
@@ -96,12 +103,12 @@ fn check_cap(ray: Ray, t: f32, radius: f32) -> bool {
     let x = ray.direction.x.mul_add(t, ray.origin.x);
     let z = ray.direction.z.mul_add(t, ray.origin.z);
 
-    x.mul_add(x, z * z) <= radius + 1e-5
+    x.mul_add(x, z * z) <= radius.mul_add(radius, CAP_EPSILON)
 }
 
 // Helper which adds capped cone intersections.
 fn intersect_caps(ray: Ray, xs: &mut Intersections, min: f32, max: f32, closed: bool) {
-    if !closed || ray.direction.y.abs() < 1e-5 {
+    if !closed || ray.direction.y.abs() < PARALLEL_EPSILON {
         // Caps only matter if the cone is closed, and might possibly be intersected by
         // the ray.
         return;
@@ -143,8 +150,8 @@ mod tests {
             let r = ray(origin, direction.normalize());
             let xs = intersect(r, -std::f32::INFINITY, std::f32::INFINITY, false);
             assert_eq!(xs.len(), 2);
-            assert_approx_eq!(xs.t0, t0, 1e-3);
-            assert_approx_eq!(xs.t1, t1, 1e-3);
+            assert_approx_eq!(xs[0], t0, 1e-3);
+            assert_approx_eq!(xs[1], t1, 1e-3);
         }
     }
 
@@ -154,7 +161,7 @@ mod tests {
         let r = ray(point3(0., 0., -1.), direction);
         let xs = intersect(r, -std::f32::INFINITY, std::f32::INFINITY, false);
         assert_eq!(xs.len(), 1);
-        assert_approx_eq!(xs.t0, 0.35355, 1e-3);
+        assert_approx_eq!(xs[0], 0.35355, 1e-3);
     }
 
     #[test]
@@ -162,15 +169,18 @@ mod tests {
         let examples = vec![
             (point3(0., 0., -5.), vector3(0., 1., 0.), 0),
             (point3(0., 0., -0.25), vector3(0., 1., 1.), 2),
-            (point3(0., 0., -0.25), vector3(0., 1., 0.), 2), /* XXX: Should be 4 intersections,
-                                                              * but we only capture the nearest
-                                                              * 2. */
+            (point3(0., 0., -0.25), vector3(0., 1., 0.), 4),
         ];
         for (origin, direction, count) in examples {
             let r = ray(origin, direction.normalize());
             let xs = intersect(r, -0.5, 0.5, true);
             assert_eq!(xs.len(), count);
         }
+
+        // The hits through both caps and the wall come out in order.
+        let r = ray(point3(0., 0., -0.25), vector3(0., 1., 0.));
+        let ts: Vec<f32> = intersect(r, -0.5, 0.5, true).collect();
+        assert_eq!(ts, [-0.5, -0.25, 0.25, 0.5]);
     }
 
     #[test]
@@ -185,9 +195,28 @@ mod tests {
         ];
         for (point, normal) in examples {
             assert_eq!(
-                normal_at(point, -std::f32::INFINITY, std::f32::INFINITY, false),
+                normal_at(point, -std::f32::INFINITY, std::f32::INFINITY, false, 0.),
                 normal
             );
         }
     }
+
+    #[test]
+    fn the_normal_vector_near_the_caps_of_a_filleted_cone() {
+        // Halfway through the fillet, the normal is halfway between the wall
+        // normal and the bisector of the wall and cap normals.
+        let n = normal_at(point3(0.45, 0.45, 0.), -0.5, 0.5, true, 0.1);
+        let wall = vector3(1., -1., 0.).normalize();
+        let bisector = (wall + vector3(0., 1., 0.)).normalize();
+        let expected = (wall + bisector).normalize();
+        assert_approx_eq!(n.x, expected.x);
+        assert_approx_eq!(n.y, expected.y);
+        assert_approx_eq!(n.z, expected.z);
+
+        // Outside the fillet the wall normal is unchanged.
+        assert_eq!(
+            normal_at(point3(0.25, 0.25, 0.), -0.5, 0.5, true, 0.1),
+            normal_at(point3(0.25, 0.25, 0.), -0.5, 0.5, true, 0.)
+        );
+    }
 }
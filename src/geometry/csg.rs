@@ -0,0 +1,130 @@
+use crate::intersection::*;
+
+/// How the two children of a CSG object are combined.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CsgOperation {
+    /// Everything inside either child.
+    Union,
+    /// Everything inside both children.
+    Intersection,
+    /// Everything inside the left child but not the right.
+    Difference,
+}
+
+/// Whether a hit on a child's surface is part of the combined surface, given
+/// which child was hit and whether the hit is inside each child.
+pub fn intersection_allowed(
+    operation: CsgOperation,
+    left_hit: bool,
+    inside_left: bool,
+    inside_right: bool,
+) -> bool {
+    match operation {
+        CsgOperation::Union => (left_hit && !inside_right) || (!left_hit && !inside_left),
+        CsgOperation::Intersection => (left_hit && inside_right) || (!left_hit && inside_left),
+        CsgOperation::Difference => (left_hit && !inside_right) || (!left_hit && inside_left),
+    }
+}
+
+/// Returns the hits on the combined surface, in order of t, given every hit
+/// on the children along the ray (behind its origin as well as in front of
+/// it) and whether each was on the left child.
+pub fn filter_intersections(
+    operation: CsgOperation,
+    mut hits: Vec<(Intersection, bool)>,
+) -> Vec<Intersection> {
+    hits.sort_by(|a, b| a.0.t.partial_cmp(&b.0.t).unwrap());
+
+    let mut inside_left = false;
+    let mut inside_right = false;
+    let mut result = Vec::with_capacity(hits.len());
+    for (intersection, left_hit) in hits {
+        if intersection_allowed(operation, left_hit, inside_left, inside_right) {
+            result.push(intersection);
+        }
+
+        if left_hit {
+            inside_left = !inside_left;
+        } else {
+            inside_right = !inside_right;
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluating_the_rule_for_a_csg_operation() {
+        use CsgOperation::*;
+        let examples = vec![
+            (Union, true, true, true, false),
+            (Union, true, true, false, true),
+            (Union, true, false, true, false),
+            (Union, true, false, false, true),
+            (Union, false, true, true, false),
+            (Union, false, true, false, false),
+            (Union, false, false, true, true),
+            (Union, false, false, false, true),
+            (Intersection, true, true, true, true),
+            (Intersection, true, true, false, false),
+            (Intersection, true, false, true, true),
+            (Intersection, true, false, false, false),
+            (Intersection, false, true, true, true),
+            (Intersection, false, true, false, true),
+            (Intersection, false, false, true, false),
+            (Intersection, false, false, false, false),
+            (Difference, true, true, true, false),
+            (Difference, true, true, false, true),
+            (Difference, true, false, true, false),
+            (Difference, true, false, false, true),
+            (Difference, false, true, true, true),
+            (Difference, false, true, false, true),
+            (Difference, false, false, true, false),
+            (Difference, false, false, false, false),
+        ];
+        for (operation, left_hit, inside_left, inside_right, expected) in examples {
+            assert_eq!(
+                intersection_allowed(operation, left_hit, inside_left, inside_right),
+                expected,
+                "{:?} {} {} {}",
+                operation,
+                left_hit,
+                inside_left,
+                inside_right
+            );
+        }
+    }
+
+    #[test]
+    fn filtering_a_list_of_intersections() {
+        // Hits at t = 1 and 3 on the left child, and 2 and 4 on the right.
+        let hit = |t: f32, object_id| Intersection {
+            t,
+            object_id,
+            uv: None,
+            face: 0,
+        };
+        let hits = vec![
+            (hit(1., 0), true),
+            (hit(2., 1), false),
+            (hit(3., 0), true),
+            (hit(4., 1), false),
+        ];
+        let examples = vec![
+            (CsgOperation::Union, [1., 4.]),
+            (CsgOperation::Intersection, [2., 3.]),
+            (CsgOperation::Difference, [1., 2.]),
+        ];
+        for (operation, expected) in examples {
+            let ts: Vec<f32> = filter_intersections(operation, hits.clone())
+                .iter()
+                .map(|i| i.t)
+                .collect();
+            assert_eq!(ts, expected, "{:?}", operation);
+        }
+    }
+}
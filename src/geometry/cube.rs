@@ -17,20 +17,67 @@ pub fn intersect(ray: Ray) -> Intersections {
     result
 }
 
-pub fn normal_at(point: Tuple4) -> Tuple4 {
+pub fn normal_at(point: Tuple4, bevel_radius: f32) -> Tuple4 {
     let maxc = point.x.abs().max(point.y.abs()).max(point.z.abs());
-    if maxc == point.x.abs() {
+    let face = if maxc == point.x.abs() {
         vector3(point.x, 0., 0.)
     } else if maxc == point.y.abs() {
         vector3(0., point.y, 0.)
     } else {
         vector3(0., 0., point.z)
+    };
+
+    if bevel_radius > 0. {
+        bevel_normal(point, face, bevel_radius)
+    } else {
+        face
+    }
+}
+
+/// Tilts the face normal toward the normal of each neighboring face whose
+/// edge is within the bevel radius, reaching the bisector of the two at the
+/// edge (and of all three at a corner). Away from the edges, the face normal
+/// is returned unchanged.
+fn bevel_normal(point: Tuple4, face: Tuple4, bevel_radius: f32) -> Tuple4 {
+    let point = [point.x, point.y, point.z];
+    let mut n = [face.x.signum(), face.y.signum(), face.z.signum()];
+    let mut bent = false;
+    for axis in 0..3 {
+        if [face.x, face.y, face.z][axis] != 0. {
+            continue;
+        }
+
+        // 1 at the edge, falling to 0 at the bevel radius from it.
+        let weight = 1. - (1. - point[axis].abs()) / bevel_radius;
+        if weight > 0. {
+            n[axis] = point[axis].signum() * weight;
+            bent = true;
+        } else {
+            n[axis] = 0.;
+        }
+    }
+
+    if bent {
+        vector3(n[0], n[1], n[2]).normalize()
+    } else {
+        face
     }
 }
 
 // Cube intersection helper.
 #[inline]
 fn check_axis(origin: f32, direction: f32) -> (f32, f32) {
+    if direction == 0. {
+        // Parallel to the slab, so the ray is always or never in it. Dividing
+        // would give infinities of the wrong sign for a direction of -0, and
+        // NaN for an origin on a face.
+        return if origin.abs() <= 1. {
+            (-f32::INFINITY, f32::INFINITY)
+        } else {
+            (f32::INFINITY, -f32::INFINITY)
+        };
+    }
+
     let t0: f32;
     let t1: f32;
     if direction >= 0. {
@@ -43,9 +90,28 @@ fn check_axis(origin: f32, direction: f32) -> (f32, f32) {
     (t0, t1)
 }
 
+/// Maps a point in the unit square to a point uniformly distributed over the
+/// cube's surface. u picks one of the six faces and then the first
+/// coordinate on it.
+pub fn sample_surface(u: f32, v: f32) -> Tuple4 {
+    let u = u * 6.;
+    let face = (u.floor() as usize).min(5);
+    let s = 2. * (u - face as f32) - 1.;
+    let t = 2. * v - 1.;
+    match face {
+        0 => point3(1., s, t),
+        1 => point3(-1., s, t),
+        2 => point3(s, 1., t),
+        3 => point3(s, -1., t),
+        4 => point3(s, t, 1.),
+        _ => point3(s, t, -1.),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use assert_approx_eq::assert_approx_eq;
     use test::Bencher;
 
     #[test]
@@ -63,8 +129,8 @@ mod tests {
             let r = ray(origin, direction);
             let xs = intersect(r);
             assert_eq!(xs.len(), 2);
-            assert_eq!(xs.t0, t0);
-            assert_eq!(xs.t1, t1);
+            assert_eq!(xs[0], t0);
+            assert_eq!(xs[1], t1);
         }
     }
 
@@ -85,6 +151,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn rays_parallel_to_a_pair_of_faces() {
+        // A direction of -0 is as parallel as +0.
+        for direction in [vector3(-1., 0., 0.), vector3(-1., -0., -0.)] {
+            let xs = intersect(ray(point3(5., 0.5, 0.), direction));
+            assert_eq!(xs.len(), 2);
+            assert_eq!((xs[0], xs[1]), (4., 6.));
+
+            // Grazing a face, rather than dividing 0 by 0.
+            let xs = intersect(ray(point3(5., 1., 0.), direction));
+            assert_eq!(xs.len(), 2);
+            assert_eq!((xs[0], xs[1]), (4., 6.));
+
+            assert_eq!(intersect(ray(point3(5., 1.5, 0.), direction)).len(), 0);
+        }
+    }
+
     #[test]
     fn the_normal_on_the_surface_of_a_cube() {
         let examples = vec![
@@ -98,13 +181,63 @@ mod tests {
             (point3(-1., -1., -1.), vector3(-1., 0., 0.)),
         ];
         for (point, normal) in examples {
-            assert_eq!(normal_at(point), normal);
+            assert_eq!(normal_at(point, 0.), normal);
+        }
+    }
+
+    #[test]
+    fn the_normal_on_a_beveled_cube() {
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+
+        // Away from the edges, and without a bevel, the face normal is
+        // unchanged.
+        let p = point3(1., 0.5, -0.8);
+        assert_eq!(normal_at(p, 0.1), normal_at(p, 0.));
+        assert_eq!(normal_at(p, 0.1), vector3(1., 0., 0.));
+        let p = point3(-0.999, 1., 0.);
+        assert_eq!(normal_at(p, 0.), vector3(0., 1., 0.));
+
+        // Within the band, the normal leans toward the neighboring face,
+        // reaching the bisector at the edge.
+        let n = normal_at(point3(1., 0.95, 0.), 0.1);
+        let expected = vector3(1., 0.5, 0.).normalize();
+        assert_approx_eq!(n.x, expected.x);
+        assert_approx_eq!(n.y, expected.y);
+        assert_eq!(n.z, 0.);
+        let n = normal_at(point3(0.3, 1., -1.), 0.1);
+        assert_approx_eq!(n.y, half);
+        assert_approx_eq!(n.z, -half);
+        assert_approx_eq!(n.magnitude(), 1.);
+
+        // At a corner, it leans equally toward all three faces.
+        let n = normal_at(point3(-1., 1., 1.), 0.1);
+        let third = 1. / 3f32.sqrt();
+        assert_approx_eq!(n.x, -third);
+        assert_approx_eq!(n.y, third);
+        assert_approx_eq!(n.z, third);
+    }
+
+    #[test]
+    fn the_normal_of_a_beveled_cube_is_continuous_across_edges() {
+        // Approach the edge between the +x and +y faces from either side.
+        let mut previous = normal_at(point3(1., 0.8, 0.2), 0.1);
+        for i in 1..=400 {
+            let s = i as f32 * 0.001;
+            let p = if s <= 0.2 {
+                point3(1., 0.8 + s, 0.2)
+            } else {
+                point3(1.2 - s, 1., 0.2)
+            };
+            let n = normal_at(p, 0.1);
+            assert!(previous.dot(n) > 0.999, "{:?}", p);
+            previous = n;
         }
+        assert_eq!(previous, vector3(0., 1., 0.));
     }
 
     #[bench]
     fn bench_cube_intersection(bencher: &mut Bencher) {
         let r = ray(point3(5., 0.5, 0.), vector3(-1., 0., 0.));
-        bencher.iter(|| intersect(r));
+        bencher.iter(|| intersect(test::black_box(r)));
     }
 }
@@ -8,7 +8,7 @@ pub fn intersect(ray: Ray, min: f32, max: f32, closed: bool) -> Intersections {
         .x
         .mul_add(ray.direction.x, ray.direction.z * ray.direction.z);
 
-    if a.abs() >= 1e-5 {
+    if a.abs() >= PARALLEL_EPSILON {
         // Ray is not parallel to the y-axis.
 
         let b = ray
@@ -22,7 +22,7 @@ pub fn intersect(ray: Ray, min: f32, max: f32, closed: bool) -> Intersections {
 
         let mut discriminant = b.mul_add(b, -a * c);
 
-        if discriminant.abs() < 1e-5 {
+        if discriminant.abs() < PARALLEL_EPSILON {
             discriminant = 0.;
         }
 
@@ -59,18 +59,25 @@ pub fn intersect(ray: Ray, min: f32, max: f32, closed: bool) -> Intersections {
     result
 }
 
-pub fn normal_at(point: Tuple4, min: f32, max: f32, _closed: bool) -> Tuple4 {
+pub fn normal_at(point: Tuple4, min: f32, max: f32, closed: bool, fillet: f32) -> Tuple4 {
     // The square of the distance from the y axis.
     let d2 = point.x.mul_add(point.x, point.z * point.z);
 
-    if d2 < 1. && point.y >= max - 1e-5 {
+    if d2 < 1. && point.y >= max - CAP_EPSILON {
         // Hitting the top cap.
         vector3(0., 1., 0.)
-    } else if d2 < 1. && point.y <= min + 1e-5 {
+    } else if d2 < 1. && point.y <= min + CAP_EPSILON {
         // Hitting the bottom cap.
         vector3(0., -1., 0.)
     } else {
-        vector3(point.x, 0., point.z)
+        let wall = vector3(point.x, 0., point.z);
+        if closed && fillet > 0. && point.y > max - fillet {
+            fillet_normal(wall, vector3(0., 1., 0.), max - point.y, fillet)
+        } else if closed && fillet > 0. && point.y < min + fillet {
+            fillet_normal(wall, vector3(0., -1., 0.), point.y - min, fillet)
+        } else {
+            wall
+        }
     }
 }
 
@@ -79,12 +86,12 @@ fn check_cap(ray: Ray, t: f32) -> bool {
     let x = ray.direction.x.mul_add(t, ray.origin.x);
     let z = ray.direction.z.mul_add(t, ray.origin.z);
 
-    x.mul_add(x, z * z) <= 1. + 1e-5
+    x.mul_add(x, z * z) <= 1. + CAP_EPSILON
 }
 
 // Helper which adds capped cylinder intersections.
 fn intersect_caps(ray: Ray, xs: &mut Intersections, min: f32, max: f32, closed: bool) {
-    if !closed || ray.direction.y.abs() < 1e-5 {
+    if !closed || ray.direction.y.abs() < PARALLEL_EPSILON {
         // Caps only matter if the cylinder is closed, and might possibly be intersected
         // by the ray.
         return;
@@ -122,8 +129,8 @@ mod tests {
             let r = ray(origin, direction);
             let xs = intersect(r, -std::f32::INFINITY, std::f32::INFINITY, false);
             assert_eq!(xs.len(), 2);
-            assert_approx_eq!(xs.t0, t0, 1e-4);
-            assert_approx_eq!(xs.t1, t1, 1e-4);
+            assert_approx_eq!(xs[0], t0, 1e-4);
+            assert_approx_eq!(xs[1], t1, 1e-4);
         }
     }
 
@@ -152,7 +159,7 @@ mod tests {
         ];
         for (point, normal) in examples {
             assert_eq!(
-                normal_at(point, -std::f32::INFINITY, std::f32::INFINITY, false),
+                normal_at(point, -std::f32::INFINITY, std::f32::INFINITY, false, 0.),
                 normal
             );
         }
@@ -202,7 +209,67 @@ mod tests {
             (point3(0., 2., 0.5), vector3(0., 1., 0.)),
         ];
         for (point, normal) in examples {
-            assert_eq!(normal_at(point, 1., 2., true), normal, "{:?}", point);
+            assert_eq!(normal_at(point, 1., 2., true, 0.), normal, "{:?}", point);
+        }
+    }
+
+    #[test]
+    fn points_within_the_cap_epsilon_lie_on_the_caps() {
+        let n = normal_at(point3(0.5, 2. - 0.5 * CAP_EPSILON, 0.), 1., 2., true, 0.);
+        assert_eq!(n, vector3(0., 1., 0.));
+        let n = normal_at(point3(0.5, 2. - 2. * CAP_EPSILON, 0.), 1., 2., true, 0.);
+        assert_eq!(n, vector3(0.5, 0., 0.));
+
+        let n = normal_at(point3(0.5, 1. + 0.5 * CAP_EPSILON, 0.), 1., 2., true, 0.);
+        assert_eq!(n, vector3(0., -1., 0.));
+        let n = normal_at(point3(0.5, 1. + 2. * CAP_EPSILON, 0.), 1., 2., true, 0.);
+        assert_eq!(n, vector3(0.5, 0., 0.));
+    }
+
+    #[test]
+    fn cap_intersections_within_the_cap_epsilon_of_the_edge() {
+        let inside = (1. + 0.5 * CAP_EPSILON).sqrt();
+        let r = ray(point3(inside, 3., 0.), vector3(0., -1., 0.));
+        assert_eq!(intersect(r, 1., 2., true).len(), 2);
+
+        let outside = (1. + 2. * CAP_EPSILON).sqrt();
+        let r = ray(point3(outside, 3., 0.), vector3(0., -1., 0.));
+        assert_eq!(intersect(r, 1., 2., true).len(), 0);
+    }
+
+    #[test]
+    fn the_normal_vector_near_the_caps_of_a_filleted_cylinder() {
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+
+        // Exactly at the seams, the normal is halfway between wall and cap.
+        let n = normal_at(point3(1., 2., 0.), 1., 2., true, 0.2);
+        assert_approx_eq!(n.x, half);
+        assert_approx_eq!(n.y, half);
+        let n = normal_at(point3(0., 1., 1.), 1., 2., true, 0.2);
+        assert_approx_eq!(n.y, -half);
+        assert_approx_eq!(n.z, half);
+
+        // Outside the fillet, and on open cylinders, the wall normal is
+        // unchanged.
+        assert_eq!(
+            normal_at(point3(1., 1.5, 0.), 1., 2., true, 0.2),
+            vector3(1., 0., 0.)
+        );
+        assert_eq!(
+            normal_at(point3(1., 2., 0.), 1., 2., false, 0.2),
+            vector3(1., 0., 0.)
+        );
+    }
+
+    #[test]
+    fn the_normal_of_a_filleted_cylinder_varies_continuously() {
+        let mut previous = normal_at(point3(1., 1.5, 0.), 1., 2., true, 0.2);
+        for i in 1..=50 {
+            let y = 1.5 + 0.01 * i as f32;
+            let n = normal_at(point3(1., y, 0.), 1., 2., true, 0.2);
+            assert!(previous.dot(n) > 0.999, "{}", y);
+            assert!(n.y >= previous.y);
+            previous = n;
         }
     }
 }
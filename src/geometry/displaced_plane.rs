@@ -0,0 +1,278 @@
+use crate::geometry::*;
+use crate::rng::*;
+use crate::texture::*;
+use crate::transform::*;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// The most steps a ray is marched through a displaced plane. Longer
+/// crossings (by rays grazing the plane) take longer steps.
+const MAX_STEPS: usize = 10_000;
+
+/// The spacing of the finite differences from which normals are found.
+const NORMAL_DELTA: f32 = 1e-3;
+
+/// The height of a displaced plane above each point of the x-z plane.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HeightField {
+    /// Parallel waves along x, of the given amplitude (above and below the
+    /// plane) and wavelength.
+    Waves { amplitude: f32, wavelength: f32 },
+    /// The brightness of the texture (from 0 to 1) at each point of the
+    /// plane, times the scale. The texture is shared, to keep geometry
+    /// small.
+    Texture { texture: Arc<Texture>, scale: f32 },
+}
+
+impl HeightField {
+    /// Returns the height above the point (x, 0, z).
+    pub fn height(&self, x: f32, z: f32) -> f32 {
+        match *self {
+            HeightField::Waves {
+                amplitude,
+                wavelength,
+            } => amplitude * (2. * PI * x / wavelength).sin(),
+            HeightField::Texture { ref texture, scale } => {
+                // Deterministic textures ignore the generator.
+                let mut rng = SmallRng::seed_from_u64(0);
+                let color = texture.evaluate(&mut rng, Transform::new(), point3(x, 0., z));
+                scale * (color.r + color.g + color.b) / 3.
+            }
+        }
+    }
+
+    /// Returns the greatest distance of the surface from the plane.
+    pub fn max_height(&self) -> f32 {
+        match *self {
+            HeightField::Waves { amplitude, .. } => amplitude.abs(),
+            HeightField::Texture { scale, .. } => scale.abs(),
+        }
+    }
+}
+
+/// Intersects the ray with the surface by marching along it in steps of the
+/// given length, through the slab the surface lies within, and refining each
+/// crossing by bisection. Crossings closer together than a step may be
+/// missed. With no displacement, this is a plane.
+///
+/// Rays grazing the surface may cross it many more times than an
+/// `Intersections` holds, so only the nearest crossing behind the ray's
+/// origin is kept, and marching stops once the rest are full of crossings
+/// in front of it.
+pub fn intersect(ray: Ray, height: &HeightField, step: f32) -> Intersections {
+    let max_height = height.max_height();
+    if max_height == 0. {
+        return plane::intersect(ray);
+    }
+
+    // The slab is padded, so that rays end strictly beyond the surface even
+    // where it touches the slab's sides.
+    let slab = max_height + DISPLACEMENT_EPSILON;
+    let (o, d) = (ray.origin, ray.direction);
+    let speed = d.magnitude();
+    let mut dt = step / speed;
+    let (t0, t1) = if d.y.abs() > PARALLEL_EPSILON {
+        let a = (-slab - o.y) / d.y;
+        let b = (slab - o.y) / d.y;
+        (a.min(b), a.max(b))
+    } else if o.y.abs() <= slab {
+        let reach = MAX_STEPS as f32 / 2. * dt;
+        (-reach, reach)
+    } else {
+        return Intersections::new();
+    };
+    let steps = ((t1 - t0) / dt).ceil() as usize;
+    if steps > MAX_STEPS {
+        dt = (t1 - t0) / MAX_STEPS as f32;
+    }
+
+    // Positive above the surface, and negative below it.
+    let above = |t: f32| o.y + t * d.y - height.height(o.x + t * d.x, o.z + t * d.z);
+
+    let mut result = Intersections::new();
+    let mut behind = None;
+    let (mut ta, mut fa) = (t0, above(t0));
+    let mut i = 1;
+    while ta < t1 && result.len() + behind.iter().count() < MAX_INTERSECTIONS {
+        let tb = (t0 + i as f32 * dt).min(t1);
+        let fb = above(tb);
+        if (fa < 0.) != (fb < 0.) {
+            let (mut lo, mut hi, mut flo) = (ta, tb, fa);
+            while (hi - lo) * speed > DISPLACEMENT_EPSILON {
+                let mid = (lo + hi) / 2.;
+                if mid <= lo || mid >= hi {
+                    break;
+                }
+                let fmid = above(mid);
+                if (flo < 0.) == (fmid < 0.) {
+                    lo = mid;
+                    flo = fmid;
+                } else {
+                    hi = mid;
+                }
+            }
+            let t = (lo + hi) / 2.;
+            if t < 0. {
+                behind = Some(t);
+            } else {
+                result.push(t);
+            }
+        }
+        ta = tb;
+        fa = fb;
+        i += 1;
+    }
+
+    if let Some(t) = behind {
+        result.push(t);
+    }
+    result
+}
+
+/// Returns the normal at the point, from the slope of the height field
+/// there.
+pub fn normal_at(point: Tuple4, height: &HeightField) -> Tuple4 {
+    let (x, z) = (point.x, point.z);
+    let dx = (height.height(x + NORMAL_DELTA, z) - height.height(x - NORMAL_DELTA, z))
+        / (2. * NORMAL_DELTA);
+    let dz = (height.height(x, z + NORMAL_DELTA) - height.height(x, z - NORMAL_DELTA))
+        / (2. * NORMAL_DELTA);
+    vector3(-dx, 1., -dz).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn waves(amplitude: f32) -> HeightField {
+        HeightField::Waves {
+            amplitude,
+            wavelength: 4.,
+        }
+    }
+
+    #[test]
+    fn without_displacement_the_surface_is_a_plane() {
+        let rays = [
+            ray(point3(0., 1., 0.), vector3(0., -1., 0.)),
+            ray(point3(0.3, -2., 5.), vector3(0.2, 0.7, -0.1)),
+            ray(point3(0., 10., 0.), vector3(0., 0., 1.)),
+        ];
+        for r in rays {
+            let xs = intersect(r, &waves(0.), 0.1);
+            let expected = plane::intersect(r);
+            assert_eq!(xs.len(), expected.len());
+            for i in 0..xs.len() {
+                assert_eq!(xs[i], expected[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn waves_are_hit_at_the_height_of_the_sine() {
+        let height = waves(0.5);
+        for &x in &[0., 0.5, 1., 1.7, 3., -2.2] {
+            let r = ray(point3(x, 2., 0.3), vector3(0., -1., 0.));
+            let xs = intersect(r, &height, 0.05);
+            assert_eq!(xs.len(), 1);
+            let expected = 0.5 * (2. * PI * x / 4.).sin();
+            assert_approx_eq!(r.position(xs[0]).y, expected, 1e-3);
+        }
+
+        // A slanting ray crosses the waves where they meet it.
+        let r = ray(point3(-3., 1., 0.), vector3(1., -0.25, 0.2));
+        let xs = intersect(r, &height, 0.05);
+        assert!(!xs.is_empty());
+        for i in 0..xs.len() {
+            let p = r.position(xs[i]);
+            assert_approx_eq!(p.y, height.height(p.x, p.z), 1e-3);
+        }
+    }
+
+    /// Asserts that the first of the intersections in front of the ray is
+    /// where it first crosses the surface, found by marching finely.
+    fn assert_first_crossing(r: Ray, height: HeightField, xs: Intersections) {
+        let first = (0..xs.len()).map(|i| xs[i]).find(|&t| t >= 0.).unwrap();
+        let above = |t: f32| {
+            let p = r.position(t);
+            p.y > height.height(p.x, p.z)
+        };
+        let (start, steps) = (above(0.), 10_000);
+        for i in 1..steps {
+            let t = first * i as f32 / steps as f32;
+            if above(t) != start {
+                assert_approx_eq!(t, first, first / steps as f32 * 2.);
+                return;
+            }
+        }
+        let p = r.position(first);
+        assert_approx_eq!(p.y, height.height(p.x, p.z), 1e-3);
+    }
+
+    #[test]
+    fn shallow_rays_keep_the_nearest_of_many_crossings() {
+        let height = waves(0.5);
+        let rays = [
+            ray(point3(0., 2., 0.), vector3(1., -0.1, 0.)),
+            ray(point3(0., 2., 0.), vector3(1., -0.01, 0.)),
+            ray(point3(0., 0.6, 0.), vector3(1., -0.01, 0.)),
+            ray(point3(0., 0.6, 0.), vector3(1., -0.001, 0.3)),
+        ];
+        for r in rays {
+            let xs = intersect(r, &height, 0.05);
+            assert_eq!(xs.len(), MAX_INTERSECTIONS);
+            assert_first_crossing(r, height.clone(), xs);
+        }
+    }
+
+    #[test]
+    fn grazing_rays_inside_the_waves_keep_crossings_in_front() {
+        let height = waves(0.5);
+        // Level rays within the slab cross every wave, in both directions.
+        for &y in &[0., 0.2, -0.45] {
+            let r = ray(point3(0.3, y, 0.), vector3(1., 0., 0.));
+            let xs = intersect(r, &height, 0.05);
+            assert_eq!(xs.len(), MAX_INTERSECTIONS);
+            assert!(xs[0] < 0.);
+            assert!((1..xs.len()).all(|i| xs[i] >= 0.));
+            assert_first_crossing(r, height.clone(), xs);
+        }
+    }
+
+    #[test]
+    fn rays_beyond_the_waves_miss_them() {
+        let r = ray(point3(0., 1., 0.), vector3(1., 0., 0.));
+        assert_eq!(intersect(r, &waves(0.5), 0.05).len(), 0);
+    }
+
+    #[test]
+    fn the_normal_follows_the_slope_of_the_waves() {
+        let height = waves(0.5);
+        // The slope is steepest (π/4) where the sine crosses zero.
+        let n = normal_at(point3(0., 0., 0.), &height);
+        let slope = 0.5 * 2. * PI / 4.;
+        let expected = vector3(-slope, 1., 0.).normalize();
+        assert_approx_eq!(n.x, expected.x, 1e-3);
+        assert_approx_eq!(n.y, expected.y, 1e-3);
+        assert_approx_eq!(n.z, 0.);
+
+        // And flat at the crests.
+        let n = normal_at(point3(1., 0.5, 0.), &height);
+        assert_approx_eq!(n.x, 0., 1e-3);
+        assert_approx_eq!(n.y, 1., 1e-3);
+    }
+
+    #[test]
+    fn a_texture_raises_the_plane_by_its_brightness() {
+        let gray = Texture::constant(Color::new(0.5, 0.5, 0.5));
+        let height = HeightField::Texture {
+            texture: Arc::new(gray),
+            scale: 2.,
+        };
+        let r = ray(point3(0.2, 3., 0.7), vector3(0., -1., 0.));
+        let xs = intersect(r, &height, 0.1);
+        assert_eq!(xs.len(), 1);
+        assert_approx_eq!(xs[0], 2., 1e-4);
+    }
+}
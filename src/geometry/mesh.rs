@@ -0,0 +1,578 @@
+use crate::geometry::*;
+
+/// The most triangles kept together in a single leaf of a mesh's hierarchy.
+/// Triangles are quick to test, so leaves hold more of them than those of a
+/// scene's, which keeps the nodes from outweighing the triangles.
+const LEAF_SIZE: usize = 8;
+
+/// The deepest a mesh's hierarchy can be traversed. Splitting at the median
+/// keeps the depth logarithmic in the number of triangles, so this is never
+/// reached.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    /// A leaf holding the triangles at `indices[start..end]`.
+    Leaf {
+        bounds: Bounds3,
+        start: u32,
+        end: u32,
+    },
+    /// A node whose left child immediately follows it, and whose right child
+    /// is at `right`.
+    Interior { bounds: Bounds3, right: u32 },
+}
+
+impl Node {
+    fn bounds(&self) -> Bounds3 {
+        match *self {
+            Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// A triangle mesh which stores each vertex once, shared by the triangles
+/// around it, and addresses the triangles by their vertices' indices.
+///
+/// The whole mesh is one object in the scene, so a large model costs a few
+/// dozen bytes per triangle, rather than an object (with its own geometry,
+/// transform and material) each. Its triangles are wound as those made by
+/// `Geometry::triangle` are, and if it has vertex normals they are
+/// interpolated across each triangle as by `Geometry::smooth_triangle`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mesh {
+    /// The position of each vertex.
+    positions: Vec<Tuple4>,
+    /// The normal at each vertex, or none for a faceted mesh.
+    normals: Vec<Tuple4>,
+    /// The vertices of each triangle, in the order of the hierarchy.
+    indices: Vec<[u32; 3]>,
+    /// A bounding volume hierarchy over the triangles, like `Bvh`, whose
+    /// leaves hold ranges of `indices`.
+    nodes: Vec<Node>,
+}
+
+impl Mesh {
+    /// Constructs a faceted mesh of the triangles with the given vertices,
+    /// as indices into the positions. The triangles are reordered to build
+    /// the mesh's hierarchy. Fails if an index is out of range.
+    pub fn new(mut positions: Vec<Tuple4>, mut indices: Vec<[u32; 3]>) -> Result<Self, String> {
+        if positions.len() > u32::MAX as usize {
+            return Err(format!("{} vertices can't be indexed", positions.len()));
+        }
+        if let Some(index) = indices
+            .iter()
+            .flatten()
+            .find(|&&index| index as usize >= positions.len())
+        {
+            return Err(format!(
+                "vertex index {} is out of range for {} vertices",
+                index,
+                positions.len()
+            ));
+        }
+
+        positions.shrink_to_fit();
+        indices.shrink_to_fit();
+        let mut mesh = Mesh {
+            positions,
+            normals: vec![],
+            indices,
+            nodes: vec![],
+        };
+        if !mesh.indices.is_empty() {
+            mesh.build(0, mesh.indices.len());
+        }
+        mesh.nodes.shrink_to_fit();
+        Ok(mesh)
+    }
+
+    /// Gives each vertex a normal, to be interpolated across the triangles
+    /// around it. Fails unless there's one normal for every vertex.
+    pub fn normals(mut self, normals: Vec<Tuple4>) -> Result<Self, String> {
+        if normals.len() != self.positions.len() {
+            return Err(format!(
+                "{} normals given for {} vertices",
+                normals.len(),
+                self.positions.len()
+            ));
+        }
+        self.normals = normals;
+        self.normals.shrink_to_fit();
+        Ok(self)
+    }
+
+    /// Returns the number of triangles in the mesh.
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Whether the mesh has no triangles.
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Returns the number of bytes the mesh occupies, including its buffers.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.positions.capacity() * std::mem::size_of::<Tuple4>()
+            + self.normals.capacity() * std::mem::size_of::<Tuple4>()
+            + self.indices.capacity() * std::mem::size_of::<[u32; 3]>()
+            + self.nodes.capacity() * std::mem::size_of::<Node>()
+    }
+
+    /// Returns the given triangle of the mesh as geometry of its own, smooth
+    /// if the mesh has vertex normals.
+    pub fn triangle(&self, face: usize) -> Geometry {
+        let [a, b, c] = self.indices[face];
+        let (p1, p2, p3) = self.vertices(face);
+        if self.normals.is_empty() {
+            Geometry::triangle(p1, p2, p3)
+        } else {
+            let normal = |index: u32| self.normals[index as usize];
+            Geometry::smooth_triangle((p1, normal(a)), (p2, normal(b)), (p3, normal(c)))
+        }
+    }
+
+    /// Returns the box enclosing the mesh.
+    pub fn bounds(&self) -> Bounds3 {
+        self.nodes
+            .first()
+            .map_or_else(|| Bounds3::from_points(&[]), Node::bounds)
+    }
+
+    /// Intersects the ray with the mesh's triangles, recording the triangle
+    /// hit and the barycentric (u, v) coordinates of each hit.
+    ///
+    /// A ray may cross a mesh more times than an `Intersections` holds, so
+    /// (as through a displaced plane) only the nearest hit behind the ray's
+    /// origin is kept, along with the nearest in front of it.
+    pub fn intersect(&self, ray: Ray) -> Intersections {
+        let mut ahead = Intersections::new();
+        let mut behind: Option<(f32, f32, f32, usize)> = None;
+
+        // Whether hits in the range of t might still be kept, beyond the
+        // nearest hit behind the origin found so far.
+        let wanted = |(t0, t1): (f32, f32), ahead: &Intersections, behind: f32| {
+            let limit = if ahead.is_full() {
+                ahead[MAX_INTERSECTIONS - 1]
+            } else {
+                f32::INFINITY
+            };
+            t1 >= behind && t0 <= limit
+        };
+        // How far the range of t is from the ray's origin, so that boxes are
+        // visited nearest first in either direction.
+        let distance = |(t0, t1): (f32, f32)| {
+            if t0 > 0. {
+                t0
+            } else if t1 < 0. {
+                -t1
+            } else {
+                0.
+            }
+        };
+
+        let mut stack = [(0, (0., 0.)); MAX_DEPTH];
+        let mut len = 0;
+        if let Some(range) = self
+            .nodes
+            .first()
+            .and_then(|root| root.bounds().intersect(ray))
+        {
+            stack[0] = (0, range);
+            len = 1;
+        }
+
+        while len > 0 {
+            len -= 1;
+            let (node, range) = stack[len];
+            if !wanted(range, &ahead, behind.map_or(-f32::INFINITY, |(t, ..)| t)) {
+                continue;
+            }
+
+            match self.nodes[node] {
+                Node::Leaf { start, end, .. } => {
+                    for face in start as usize..end as usize {
+                        let (p1, p2, p3) = self.vertices(face);
+                        match triangle::hit(ray, p1, p2, p3) {
+                            Some((t, u, v)) if t < 0. => {
+                                if behind.is_none_or(|(nearest, ..)| t > nearest) {
+                                    behind = Some((t, u, v, face));
+                                }
+                            }
+                            Some((t, u, v)) => ahead.push_on_face(t, u, v, face),
+                            None => {}
+                        }
+                    }
+                }
+                Node::Interior { right, .. } => {
+                    // Push the farther child first, so the nearer is visited
+                    // first and can narrow the range that's wanted.
+                    let left = node + 1;
+                    let right = right as usize;
+                    let mut children = [
+                        (left, self.nodes[left].bounds().intersect(ray)),
+                        (right, self.nodes[right].bounds().intersect(ray)),
+                    ];
+                    if children[0].1.map(distance) < children[1].1.map(distance) {
+                        children.swap(0, 1);
+                    }
+                    for &(child, range) in &children {
+                        if let Some(range) = range {
+                            stack[len] = (child, range);
+                            len += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut result = Intersections::new();
+        if let Some((t, u, v, face)) = behind {
+            result.push_on_face(t, u, v, face);
+        }
+        for (t, uv, face) in ahead.with_uv_and_face() {
+            if result.is_full() {
+                break;
+            }
+            let (u, v) = uv.unwrap();
+            result.push_on_face(t, u, v, face);
+        }
+        result
+    }
+
+    /// Returns the surface normal of the triangle, interpolated between its
+    /// vertex normals at the (u, v) coordinates if the mesh has them.
+    pub fn normal_at(&self, face: usize, uv: Option<(f32, f32)>) -> Tuple4 {
+        match uv {
+            Some(uv) if !self.normals.is_empty() => {
+                let [a, b, c] = self.indices[face];
+                let normal = |index: u32| self.normals[index as usize];
+                smooth_triangle::normal_at(uv, normal(a), normal(b), normal(c))
+            }
+            _ => self.geometric_normal(face),
+        }
+    }
+
+    /// Returns the normal of the triangle itself, ignoring any vertex
+    /// normals.
+    pub fn geometric_normal(&self, face: usize) -> Tuple4 {
+        let (p1, p2, p3) = self.vertices(face);
+        (p3 - p1).cross(p2 - p1).normalize()
+    }
+
+    /// Returns the point on the curved surface the vertex normals describe
+    /// at the hit with the given (u, v) coordinates on the triangle (see
+    /// `smooth_triangle::shading_point`), or None for a faceted mesh.
+    pub fn shading_point(&self, point: Tuple4, uv: (f32, f32), face: usize) -> Option<Tuple4> {
+        if self.normals.is_empty() {
+            return None;
+        }
+        let [a, b, c] = self.indices[face];
+        let vertex = |index: u32| (self.positions[index as usize], self.normals[index as usize]);
+        Some(smooth_triangle::shading_point(
+            point,
+            uv,
+            vertex(a),
+            vertex(b),
+            vertex(c),
+        ))
+    }
+
+    /// Returns the triangle which the point lies on (or nearest to), for
+    /// when the triangle hit isn't known. This tests every triangle.
+    pub fn face_at(&self, point: Tuple4) -> usize {
+        let miss = |face: usize| {
+            let (p1, p2, p3) = self.vertices(face);
+            let (u, v) = smooth_triangle::barycentric(point, p1, p2 - p1, p3 - p1);
+            let outside = (-u).max(0.) + (-v).max(0.) + (u + v - 1.).max(0.);
+            let height = (point - p1).dot(self.geometric_normal(face)).abs();
+            (outside, height)
+        };
+        (0..self.len())
+            .map(|face| (face, miss(face)))
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .map_or(0, |(face, _)| face)
+    }
+
+    /// Returns the mesh facing the other way, with its triangles' winding
+    /// and its vertex normals reversed.
+    pub fn flipped(&self) -> Self {
+        let mut mesh = self.clone();
+        for [_, b, c] in &mut mesh.indices {
+            std::mem::swap(b, c);
+        }
+        for normal in &mut mesh.normals {
+            *normal = -*normal;
+        }
+        mesh
+    }
+
+    /// Returns the vertices of the triangle.
+    fn vertices(&self, face: usize) -> (Tuple4, Tuple4, Tuple4) {
+        let [a, b, c] = self.indices[face];
+        (
+            self.positions[a as usize],
+            self.positions[b as usize],
+            self.positions[c as usize],
+        )
+    }
+
+    /// Appends the subtree holding the triangles at `indices[start..end]`
+    /// to the nodes, reordering them.
+    fn build(&mut self, start: usize, end: usize) {
+        let positions = &self.positions;
+        let triangle_bounds = |&[a, b, c]: &[u32; 3]| {
+            Bounds3::from_points(&[
+                positions[a as usize],
+                positions[b as usize],
+                positions[c as usize],
+            ])
+        };
+        let triangles = &mut self.indices[start..end];
+        let bounds = triangles
+            .iter()
+            .map(triangle_bounds)
+            .reduce(|a, b| a.union(b))
+            .unwrap();
+
+        if triangles.len() <= LEAF_SIZE {
+            self.nodes.push(Node::Leaf {
+                bounds,
+                start: start as u32,
+                end: end as u32,
+            });
+            return;
+        }
+
+        // Split at the median along the axis where the triangles' centers
+        // are most spread out.
+        let centers: Vec<Tuple4> = triangles
+            .iter()
+            .map(|triangle| triangle_bounds(triangle).center())
+            .collect();
+        let spread = Bounds3::from_points(&centers);
+        let extent = spread.max - spread.min;
+        let axis = |p: Tuple4| {
+            if extent.x >= extent.y && extent.x >= extent.z {
+                p.x
+            } else if extent.y >= extent.z {
+                p.y
+            } else {
+                p.z
+            }
+        };
+        let middle = triangles.len() / 2;
+        triangles.select_nth_unstable_by(middle, |a, b| {
+            let (a, b) = (triangle_bounds(a).center(), triangle_bounds(b).center());
+            axis(a).partial_cmp(&axis(b)).unwrap()
+        });
+
+        let index = self.nodes.len();
+        self.nodes.push(Node::Interior { bounds, right: 0 });
+        self.build(start, start + middle);
+        let right_index = self.nodes.len() as u32;
+        self.build(start + middle, end);
+        if let Node::Interior { right, .. } = &mut self.nodes[index] {
+            *right = right_index;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    /// The corners of a cube from -1 to 1, and its faces, each split into
+    /// two triangles wound as `Geometry::triangle` expects.
+    fn cube() -> (Vec<Tuple4>, Vec<[u32; 3]>) {
+        let positions = (0..8)
+            .map(|i| {
+                let side = |bit: usize| if i & bit == 0 { -1. } else { 1. };
+                point3(side(1), side(2), side(4))
+            })
+            .collect();
+        let quads = [
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+        ];
+        let indices = quads
+            .iter()
+            .flat_map(|&[a, b, c, d]| [[a, b, c], [a, c, d]])
+            .collect();
+        (positions, indices)
+    }
+
+    #[test]
+    fn a_mesh_cube_intersects_as_its_triangles_do() {
+        let (positions, indices) = cube();
+        let triangles: Vec<Geometry> = indices
+            .iter()
+            .map(|&[a, b, c]| {
+                let p = |i: u32| positions[i as usize];
+                Geometry::triangle(p(a), p(b), p(c))
+            })
+            .collect();
+        let mesh = Mesh::new(positions, indices).unwrap();
+        assert_eq!(mesh.len(), 12);
+
+        let mut rng = SmallRng::seed_from_u64(7);
+        let mut random_point = |scale: f32| {
+            let mut coordinate = || (rng.gen::<f32>() * 2. - 1.) * scale;
+            point3(coordinate(), coordinate(), coordinate())
+        };
+        for _ in 0..1000 {
+            let origin = random_point(3.);
+            let target = random_point(1.5);
+            let r = ray(origin, target - origin);
+
+            let mut expected: Vec<(f32, Tuple4)> = triangles
+                .iter()
+                .flat_map(|triangle| {
+                    triangle
+                        .intersect(r)
+                        .map(move |t| (t, triangle.normal_at(r.position(t))))
+                })
+                .collect();
+            expected.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            // Of the hits behind the ray's origin, the mesh keeps the nearest.
+            let behind = expected.iter().filter(|&&(t, _)| t < 0.).count();
+            expected.drain(..behind.saturating_sub(1));
+            let actual: Vec<(f32, Tuple4)> = mesh
+                .intersect(r)
+                .with_uv_and_face()
+                .map(|(t, uv, face)| (t, mesh.normal_at(face, uv)))
+                .collect();
+
+            assert_eq!(actual.len(), expected.len(), "{:?}", r);
+            for (&(t, normal), &(expected_t, expected_normal)) in actual.iter().zip(&expected) {
+                assert_approx_eq!(t, expected_t);
+                assert_eq!(normal, expected_normal);
+            }
+        }
+    }
+
+    #[test]
+    fn a_mesh_keeps_the_nearest_hits_on_either_side_of_the_ray_origin() {
+        // A stack of ten parallel squares, with the ray starting among them.
+        let mut positions = vec![];
+        let mut indices = vec![];
+        for i in 0..10 {
+            let z = i as f32;
+            let first = positions.len() as u32;
+            positions.extend_from_slice(&[
+                point3(-1., -1., z),
+                point3(1., -1., z),
+                point3(1., 1., z),
+                point3(-1., 1., z),
+            ]);
+            indices.push([first, first + 1, first + 2]);
+            indices.push([first, first + 2, first + 3]);
+        }
+        let mesh = Mesh::new(positions, indices).unwrap();
+        let r = ray(point3(0.1, 0.2, 4.5), vector3(0., 0., 1.));
+        let xs = mesh.intersect(r);
+        assert_eq!(xs.len(), 4);
+        for (t, expected) in xs.zip(&[-0.5, 0.5, 1.5, 2.5]) {
+            assert_approx_eq!(t, expected);
+        }
+    }
+
+    #[test]
+    fn a_smooth_mesh_interpolates_its_vertex_normals() {
+        let positions = vec![point3(0., 1., 0.), point3(-1., 0., 0.), point3(1., 0., 0.)];
+        let normals = vec![
+            vector3(0., 1., 0.),
+            vector3(-1., 0., 0.),
+            vector3(1., 0., 0.),
+        ];
+        let mesh = Mesh::new(positions.clone(), vec![[0, 1, 2]])
+            .unwrap()
+            .normals(normals.clone())
+            .unwrap();
+        let triangle = Geometry::smooth_triangle(
+            (positions[0], normals[0]),
+            (positions[1], normals[1]),
+            (positions[2], normals[2]),
+        );
+        assert_eq!(mesh.triangle(0), triangle);
+
+        let r = ray(point3(-0.2, 0.3, -2.), vector3(0., 0., 1.));
+        let (t, uv, face) = mesh.intersect(r).with_uv_and_face().next().unwrap();
+        let point = r.position(t);
+        assert_eq!(
+            mesh.normal_at(face, uv),
+            triangle.normal_at_uv(point, uv, 0)
+        );
+        assert_eq!(
+            mesh.shading_point(point, uv.unwrap(), face),
+            triangle.shading_point(point, uv, 0)
+        );
+        assert_eq!(mesh.face_at(point), face);
+    }
+
+    #[test]
+    fn flipping_a_mesh_reverses_its_normals() {
+        let (positions, indices) = cube();
+        let mesh = Mesh::new(positions, indices).unwrap();
+        let flipped = mesh.flipped();
+        for face in 0..mesh.len() {
+            assert_eq!(flipped.geometric_normal(face), -mesh.geometric_normal(face));
+        }
+        assert_eq!(flipped.bounds(), mesh.bounds());
+    }
+
+    #[test]
+    fn invalid_meshes_are_errors() {
+        let positions = vec![point3(0., 1., 0.), point3(-1., 0., 0.), point3(1., 0., 0.)];
+        assert!(Mesh::new(positions.clone(), vec![[0, 1, 3]]).is_err());
+        let mesh = Mesh::new(positions, vec![[0, 1, 2]]).unwrap();
+        assert!(mesh.normals(vec![vector3(0., 0., 1.)]).is_err());
+    }
+
+    #[test]
+    fn a_meshs_triangles_cost_a_few_dozen_bytes_each() {
+        // A smooth grid of 100 by 100 squares, each split in two.
+        let n = 100;
+        let mut positions = vec![];
+        let mut normals = vec![];
+        for i in 0..=n {
+            for j in 0..=n {
+                positions.push(point3(i as f32, 0., j as f32));
+                normals.push(vector3(0., 1., 0.));
+            }
+        }
+        let vertex = |i: u32, j: u32| i * (n + 1) + j;
+        let mut indices = vec![];
+        for i in 0..n {
+            for j in 0..n {
+                let (a, b) = (vertex(i, j), vertex(i, j + 1));
+                let (c, d) = (vertex(i + 1, j), vertex(i + 1, j + 1));
+                indices.push([a, b, d]);
+                indices.push([a, d, c]);
+            }
+        }
+        let mesh = Mesh::new(positions, indices)
+            .unwrap()
+            .normals(normals)
+            .unwrap();
+
+        let per_triangle = mesh.size_in_bytes() / mesh.len();
+        assert!(per_triangle <= 48, "{} bytes per triangle", per_triangle);
+        // As objects, each triangle would need at least an object of its
+        // own (and an entry in the scene's hierarchy besides).
+        let per_object = std::mem::size_of::<Object>();
+        assert!(
+            per_object >= 20 * per_triangle,
+            "{} bytes per object",
+            per_object
+        );
+    }
+}
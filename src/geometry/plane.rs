@@ -3,7 +3,7 @@ use crate::geometry::*;
 pub fn intersect(ray: Ray) -> Intersections {
     let mut result = Intersections::new();
 
-    if ray.direction.y.abs() > 1e-5 {
+    if ray.direction.y.abs() > PARALLEL_EPSILON {
         result.push(-ray.origin.y / ray.direction.y);
     }
 
@@ -37,7 +37,7 @@ mod tests {
         let r = ray(point3(0., 1., 0.), vector3(0., -1., 0.));
         let xs = intersect(r);
         assert_eq!(xs.len(), 1);
-        assert_eq!(xs.t0, 1.);
+        assert_eq!(xs[0], 1.);
     }
 
     #[test]
@@ -45,7 +45,7 @@ mod tests {
         let r = ray(point3(0., -1., 0.), vector3(0., 1., 0.));
         let xs = intersect(r);
         assert_eq!(xs.len(), 1);
-        assert_eq!(xs.t0, 1.);
+        assert_eq!(xs[0], 1.);
     }
 
     #[test]
@@ -57,4 +57,23 @@ mod tests {
         assert_eq!(n2, vector3(0., 1., 0.,));
         assert_eq!(n3, vector3(0., 1., 0.,));
     }
+
+    #[test]
+    fn rays_within_the_parallel_epsilon_miss_the_plane() {
+        let r = ray(point3(0., 1., 0.), vector3(0., -0.5 * PARALLEL_EPSILON, 1.));
+        assert_eq!(intersect(r).len(), 0);
+        let r = ray(point3(0., 1., 0.), vector3(0., -2. * PARALLEL_EPSILON, 1.));
+        assert_eq!(intersect(r).len(), 1);
+    }
+
+    #[test]
+    fn nearly_parallel_rays_dont_hit_the_plane_far_away() {
+        // As from rounding error, which would put the hit around t = 1e8.
+        let r = ray(point3(0., 1., 0.), vector3(0., -1e-8, 1.));
+        assert_eq!(intersect(r).len(), 0);
+        let r = ray(point3(0., 1., 0.), vector3(0., 0., 1.));
+        assert_eq!(intersect(r).len(), 0);
+        let r = ray(point3(0., 1., 0.), vector3(0., -0., 1.));
+        assert_eq!(intersect(r).len(), 0);
+    }
 }
@@ -0,0 +1,145 @@
+use crate::geometry::*;
+
+/// Intersects the ray with the triangle, recording the barycentric (u, v)
+/// coordinates of the hit for normal interpolation.
+pub fn intersect(ray: Ray, p1: Tuple4, p2: Tuple4, p3: Tuple4) -> Intersections {
+    let mut result = Intersections::new();
+    if let Some((t, u, v)) = triangle::hit(ray, p1, p2, p3) {
+        result.push_with_uv(t, u, v);
+    }
+    result
+}
+
+/// Returns the normal interpolated from the vertex normals at the given
+/// (u, v) coordinates.
+pub fn normal_at(uv: (f32, f32), n1: Tuple4, n2: Tuple4, n3: Tuple4) -> Tuple4 {
+    let (u, v) = uv;
+    n2 * u + n3 * v + n1 * (1. - u - v)
+}
+
+/// Returns the point at the given (u, v) coordinates on the curved surface
+/// the vertex normals describe, by Hanika's method: the point is lifted onto
+/// the tangent plane of each vertex it lies below, and the lifted points are
+/// blended by the barycentric weights. Shadow rays started there don't catch
+/// on the neighboring facets, which would cut a faceted shadow terminator
+/// into a low-poly mesh.
+pub fn shading_point(
+    point: Tuple4,
+    uv: (f32, f32),
+    (p1, n1): (Tuple4, Tuple4),
+    (p2, n2): (Tuple4, Tuple4),
+    (p3, n3): (Tuple4, Tuple4),
+) -> Tuple4 {
+    let (u, v) = uv;
+    let lift = |vertex: Tuple4, normal: Tuple4| {
+        let normal = normal.normalize();
+        normal * -(point - vertex).dot(normal).min(0.)
+    };
+    point + lift(p2, n2) * u + lift(p3, n3) * v + lift(p1, n1) * (1. - u - v)
+}
+
+/// Returns the barycentric (u, v) coordinates of a point on the triangle.
+pub fn barycentric(point: Tuple4, p1: Tuple4, e1: Tuple4, e2: Tuple4) -> (f32, f32) {
+    let p = point - p1;
+    let d11 = e1.dot(e1);
+    let d12 = e1.dot(e2);
+    let d22 = e2.dot(e2);
+    let dp1 = p.dot(e1);
+    let dp2 = p.dot(e2);
+    let denom = d11.mul_add(d22, -d12 * d12);
+    if denom.abs() < PARALLEL_EPSILON {
+        // The triangle is degenerate.
+        return (0., 0.);
+    }
+
+    (
+        d22.mul_add(dp1, -d12 * dp2) / denom,
+        d11.mul_add(dp2, -d12 * dp1) / denom,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn test_triangle() -> Geometry {
+        Geometry::smooth_triangle(
+            (point3(0., 1., 0.), vector3(0., 1., 0.)),
+            (point3(-1., 0., 0.), vector3(-1., 0., 0.)),
+            (point3(1., 0., 0.), vector3(1., 0., 0.)),
+        )
+    }
+
+    #[test]
+    fn an_intersection_with_a_smooth_triangle_stores_u_and_v() {
+        let r = ray(point3(-0.2, 0.3, -2.), vector3(0., 0., 1.));
+        let (t, uv) = test_triangle().intersect(r).with_uv().next().unwrap();
+        let (u, v) = uv.unwrap();
+        assert_approx_eq!(t, 2.);
+        assert_approx_eq!(u, 0.45);
+        assert_approx_eq!(v, 0.25);
+    }
+
+    #[test]
+    fn a_smooth_triangle_uses_u_and_v_to_interpolate_the_normal() {
+        let n = test_triangle().normal_at_uv(point3(0., 0., 0.), Some((0.45, 0.25)), 0);
+        assert_approx_eq!(n.x, -0.2);
+        assert_approx_eq!(n.y, 0.3);
+        assert_approx_eq!(n.z, 0.);
+    }
+
+    #[test]
+    fn the_normal_at_the_corners_and_centroid() {
+        let t = test_triangle();
+        assert_eq!(
+            t.normal_at_uv(point3(0., 0., 0.), Some((0., 0.)), 0),
+            vector3(0., 1., 0.)
+        );
+        assert_eq!(
+            t.normal_at_uv(point3(0., 0., 0.), Some((1., 0.)), 0),
+            vector3(-1., 0., 0.)
+        );
+        assert_eq!(
+            t.normal_at_uv(point3(0., 0., 0.), Some((0., 1.)), 0),
+            vector3(1., 0., 0.)
+        );
+
+        let third = 1. / 3.;
+        let n = t.normal_at_uv(point3(0., 0., 0.), Some((third, third)), 0);
+        assert_approx_eq!(n.x, 0.);
+        assert_approx_eq!(n.y, third);
+        assert_approx_eq!(n.z, 0.);
+    }
+
+    #[test]
+    fn the_shading_point_lies_on_the_surface_the_normals_describe() {
+        // Normals tilted outward, as on a sphere, lift the middle of the face.
+        let a = (point3(0., 1., 0.), vector3(0., 1., 1.));
+        let b = (point3(-1., 0., 0.), vector3(-1., 0., 1.));
+        let c = (point3(1., 0., 0.), vector3(1., 0., 1.));
+        let third = 1. / 3.;
+        let lifted = shading_point(point3(0., third, 0.), (third, third), a, b, c);
+        assert_approx_eq!(lifted.x, 0.);
+        assert_approx_eq!(lifted.y, 4. / 9.);
+        assert_approx_eq!(lifted.z, 4. / 9.);
+
+        // At a vertex, and wherever the normals match the face, the point
+        // stays put.
+        assert_eq!(shading_point(a.0, (0., 0.), a, b, c), a.0);
+        let flat = vector3(0., 0., 1.);
+        let p = point3(0.1, 0.2, 0.);
+        let (a, b, c) = ((a.0, flat), (b.0, flat), (c.0, flat));
+        assert_eq!(shading_point(p, (0.3, 0.2), a, b, c), p);
+    }
+
+    #[test]
+    fn the_normal_without_u_and_v_is_interpolated_from_the_point() {
+        let t = test_triangle();
+        let n = t.normal_at(point3(-0.2, 0.3, 0.));
+        assert_approx_eq!(n.x, -0.2);
+        assert_approx_eq!(n.y, 0.3);
+        assert_approx_eq!(n.z, 0.);
+        assert_eq!(t.normal_at(point3(-1., 0., 0.)), vector3(-1., 0., 0.));
+    }
+}
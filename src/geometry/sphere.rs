@@ -28,6 +28,16 @@ pub fn normal_at(point: Tuple4) -> Tuple4 {
     sphere_to_point
 }
 
+/// Maps a point in the unit square to a point uniformly distributed over the
+/// sphere's surface.
+pub fn sample_surface(u: f32, v: f32) -> Tuple4 {
+    // Archimedes: equal bands of height have equal areas.
+    let y = 2f32.mul_add(-u, 1.);
+    let r = y.mul_add(-y, 1.).max(0.).sqrt();
+    let phi = 2. * std::f32::consts::PI * v;
+    point3(r * phi.cos(), y, r * phi.sin())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -39,8 +49,8 @@ mod tests {
         let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
         let xs = intersect(r);
         assert_eq!(xs.len(), 2);
-        assert_eq!(xs.t0, 4.0);
-        assert_eq!(xs.t1, 6.0);
+        assert_eq!(xs[0], 4.0);
+        assert_eq!(xs[1], 6.0);
     }
 
     #[test]
@@ -48,8 +58,8 @@ mod tests {
         let r = ray(point3(0., 1., -5.), vector3(0., 0., 1.));
         let xs = intersect(r);
         assert_eq!(xs.len(), 2);
-        assert_eq!(xs.t0, 5.0);
-        assert_eq!(xs.t1, 5.0);
+        assert_eq!(xs[0], 5.0);
+        assert_eq!(xs[1], 5.0);
     }
 
     #[test]
@@ -64,8 +74,8 @@ mod tests {
         let r = ray(point3(0., 0., 0.), vector3(0., 0., 1.));
         let xs = intersect(r);
         assert_eq!(xs.len(), 2);
-        assert_eq!(xs.t0, -1.0);
-        assert_eq!(xs.t1, 1.0);
+        assert_eq!(xs[0], -1.0);
+        assert_eq!(xs[1], 1.0);
     }
 
     #[test]
@@ -73,8 +83,8 @@ mod tests {
         let r = ray(point3(0., 0., 5.), vector3(0., 0., 1.));
         let xs = intersect(r);
         assert_eq!(xs.len(), 2);
-        assert_eq!(xs.t0, -6.0);
-        assert_eq!(xs.t1, -4.0);
+        assert_eq!(xs[0], -6.0);
+        assert_eq!(xs[1], -4.0);
     }
 
     #[test]
@@ -107,6 +117,6 @@ mod tests {
     #[bench]
     fn bench_sphere_intersection(bencher: &mut Bencher) {
         let r = ray(point3(0., 0., 5.), vector3(0., 0., 1.));
-        bencher.iter(|| intersect(r));
+        bencher.iter(|| intersect(test::black_box(r)));
     }
 }
@@ -0,0 +1,293 @@
+use crate::geometry::*;
+
+/// Intersects the ray with the triangle.
+///
+/// Points exactly on an edge count as hits, and a ray through an edge
+/// shared by two triangles hits at least one of them.
+pub fn intersect(ray: Ray, p1: Tuple4, p2: Tuple4, p3: Tuple4) -> Intersections {
+    let mut result = Intersections::new();
+    if let Some((t, _, _)) = hit(ray, p1, p2, p3) {
+        result.push(t);
+    }
+    result
+}
+
+/// Returns the distance along the ray to the triangle, and the barycentric
+/// (u, v) coordinates of the hit (the weights of the second and third
+/// vertices), if the ray hits the triangle.
+///
+/// This is Woop, Benthin and Wald's watertight algorithm. The vertices are
+/// sheared into a space where the ray runs along the z axis from the
+/// origin, and each edge test depends only on the edge's own two vertices,
+/// so the triangles on either side of an edge agree about which side of it
+/// the ray passes. Unlike `moller_trumbore`, no ray slips between them.
+pub fn hit(ray: Ray, p1: Tuple4, p2: Tuple4, p3: Tuple4) -> Option<(f32, f32, f32)> {
+    let d = [ray.direction.x, ray.direction.y, ray.direction.z];
+
+    // Permute the axes so the ray's largest component is z, keeping the
+    // winding of the triangle.
+    let kz = (0..3)
+        .max_by(|&a, &b| d[a].abs().partial_cmp(&d[b].abs()).unwrap())
+        .unwrap();
+    let (mut kx, mut ky) = ((kz + 1) % 3, (kz + 2) % 3);
+    if d[kz] < 0. {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    // Shear the ray onto the z axis.
+    let sx = d[kx] / d[kz];
+    let sy = d[ky] / d[kz];
+    let sz = 1. / d[kz];
+    let relative = |p: Tuple4| {
+        let p = p - ray.origin;
+        [p.x, p.y, p.z]
+    };
+    let (a, b, c) = (relative(p1), relative(p2), relative(p3));
+    let shear = |p: [f32; 3]| (sx.mul_add(-p[kz], p[kx]), sy.mul_add(-p[kz], p[ky]));
+    let (ax, ay) = shear(a);
+    let (bx, by) = shear(b);
+    let (cx, cy) = shear(c);
+
+    // The scaled barycentric coordinates: twice the signed areas of the
+    // triangles between the ray and each edge.
+    let mut u = cx * by - cy * bx;
+    let mut v = ax * cy - ay * cx;
+    let mut w = bx * ay - by * ax;
+
+    // On an edge, recompute in double precision so that both triangles
+    // sharing it come to the same answer.
+    if u == 0. || v == 0. || w == 0. {
+        let edge = |px: f32, py: f32, qx: f32, qy: f32| {
+            (px as f64 * qy as f64 - py as f64 * qx as f64) as f32
+        };
+        u = edge(cx, cy, bx, by);
+        v = edge(ax, ay, cx, cy);
+        w = edge(bx, by, ax, ay);
+    }
+
+    if (u < 0. || v < 0. || w < 0.) && (u > 0. || v > 0. || w > 0.) {
+        // The ray passes outside one of the edges.
+        return None;
+    }
+
+    let det = u + v + w;
+    if det == 0. {
+        // The ray is parallel to the triangle, or the triangle is degenerate.
+        return None;
+    }
+
+    let t = (u * sz * a[kz] + v * sz * b[kz] + w * sz * c[kz]) / det;
+    Some((t, v / det, w / det))
+}
+
+/// Returns the distance along the ray to the triangle and the barycentric
+/// (u, v) coordinates of the hit, as `hit` does, using the Möller–Trumbore
+/// algorithm. It's a little simpler, but rays along an edge shared by two
+/// triangles can miss both.
+pub fn moller_trumbore(ray: Ray, p1: Tuple4, e1: Tuple4, e2: Tuple4) -> Option<(f32, f32, f32)> {
+    let dir_cross_e2 = ray.direction.cross(e2);
+    let det = e1.dot(dir_cross_e2);
+    if det.abs() < PARALLEL_EPSILON {
+        // The ray is parallel to the triangle, or the triangle is degenerate.
+        return None;
+    }
+
+    let f = 1. / det;
+    let p1_to_origin = ray.origin - p1;
+    let u = f * p1_to_origin.dot(dir_cross_e2);
+    if !(0. ..=1.).contains(&u) {
+        // The ray misses over the p1-p3 edge.
+        return None;
+    }
+
+    let origin_cross_e1 = p1_to_origin.cross(e1);
+    let v = f * ray.direction.dot(origin_cross_e1);
+    if v < 0. || u + v > 1. {
+        // The ray misses over the p1-p2 or p2-p3 edge.
+        return None;
+    }
+
+    Some((f * e2.dot(origin_cross_e1), u, v))
+}
+
+pub fn normal_at(_point: Tuple4, normal: Tuple4) -> Tuple4 {
+    normal
+}
+
+/// Maps a point in the unit square to a point uniformly distributed over the
+/// triangle.
+pub fn sample_surface(p1: Tuple4, e1: Tuple4, e2: Tuple4, u: f32, v: f32) -> Tuple4 {
+    let su = u.sqrt();
+    p1 + e1 * (su * (1. - v)) + e2 * (su * v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::*;
+    use assert_approx_eq::assert_approx_eq;
+    use test::Bencher;
+
+    fn test_triangle() -> Geometry {
+        Geometry::triangle(point3(0., 1., 0.), point3(-1., 0., 0.), point3(1., 0., 0.))
+    }
+
+    #[test]
+    fn constructing_a_triangle() {
+        if let Geometry::Triangle { e1, e2, normal, .. } = test_triangle() {
+            assert_eq!(e1, vector3(-1., -1., 0.));
+            assert_eq!(e2, vector3(1., -1., 0.));
+            assert_eq!(normal, vector3(0., 0., -1.));
+        } else {
+            panic!();
+        }
+    }
+
+    #[test]
+    fn finding_the_normal_on_a_triangle() {
+        let t = test_triangle();
+        assert_eq!(t.normal_at(point3(0., 0.5, 0.)), vector3(0., 0., -1.));
+        assert_eq!(t.normal_at(point3(-0.5, 0.75, 0.)), vector3(0., 0., -1.));
+        assert_eq!(t.normal_at(point3(0.5, 0.25, 0.)), vector3(0., 0., -1.));
+    }
+
+    #[test]
+    fn intersecting_a_ray_parallel_to_the_triangle() {
+        let r = ray(point3(0., -1., -2.), vector3(0., 1., 0.));
+        assert_eq!(test_triangle().intersect(r).len(), 0);
+    }
+
+    #[test]
+    fn a_ray_misses_the_p1_p3_edge() {
+        let r = ray(point3(1., 1., -2.), vector3(0., 0., 1.));
+        assert_eq!(test_triangle().intersect(r).len(), 0);
+    }
+
+    #[test]
+    fn a_ray_misses_the_p1_p2_edge() {
+        let r = ray(point3(-1., 1., -2.), vector3(0., 0., 1.));
+        assert_eq!(test_triangle().intersect(r).len(), 0);
+    }
+
+    #[test]
+    fn a_ray_misses_the_p2_p3_edge() {
+        let r = ray(point3(0., -1., -2.), vector3(0., 0., 1.));
+        assert_eq!(test_triangle().intersect(r).len(), 0);
+    }
+
+    #[test]
+    fn a_ray_strikes_a_triangle() {
+        let r = ray(point3(0., 0.5, -2.), vector3(0., 0., 1.));
+        let xs = test_triangle().intersect(r);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0], 2.);
+    }
+
+    #[test]
+    fn a_ray_striking_an_edge_hits_the_triangle() {
+        // The p2-p3 edge, and the p1 corner.
+        let r = ray(point3(0., 0., -2.), vector3(0., 0., 1.));
+        assert_eq!(test_triangle().intersect(r).len(), 1);
+        let r = ray(point3(0., 1., -2.), vector3(0., 0., 1.));
+        assert_eq!(test_triangle().intersect(r).len(), 1);
+    }
+
+    #[test]
+    fn a_degenerate_triangle_is_never_intersected() {
+        let t = Geometry::triangle(point3(0., 0., 0.), point3(1., 1., 0.), point3(2., 2., 0.));
+        let r = ray(point3(1., 1., -2.), vector3(0., 0., 1.));
+        assert_eq!(t.intersect(r).len(), 0);
+        let r = ray(point3(0.5, 0.5, 0.), vector3(1., 1., 0.).normalize());
+        assert_eq!(t.intersect(r).len(), 0);
+    }
+
+    /// Returns the vertices of a tilted quad split into a grid of triangles,
+    /// with each cell split along a diagonal.
+    fn tilted_grid(n: usize) -> Vec<(Tuple4, Tuple4, Tuple4)> {
+        let tilt = Transform::new()
+            .translate(0.1, 0.2, 0.3)
+            .rotate_y(0.7)
+            .rotate_x(0.3)
+            .scale(1.3, 1.3, 1.3);
+        let vertex = |i: usize, j: usize| {
+            let (x, y) = (i as f32 / n as f32, j as f32 / n as f32);
+            tilt.local_to_world * point3(x.mul_add(2., -1.), y.mul_add(2., -1.), 0.)
+        };
+        let mut triangles = vec![];
+        for i in 0..n {
+            for j in 0..n {
+                let (a, b) = (vertex(i, j), vertex(i + 1, j));
+                let (c, d) = (vertex(i, j + 1), vertex(i + 1, j + 1));
+                triangles.push((a, b, d));
+                triangles.push((a, d, c));
+            }
+        }
+        triangles
+    }
+
+    /// Returns rays from a point off to the side toward points spaced along
+    /// each edge shared by two of the triangles.
+    fn rays_along_shared_edges(triangles: &[(Tuple4, Tuple4, Tuple4)]) -> Vec<Ray> {
+        let key = |p: Tuple4| (p.x.to_bits(), p.y.to_bits(), p.z.to_bits());
+        let edges: Vec<(Tuple4, Tuple4)> = triangles
+            .iter()
+            .flat_map(|&(a, b, c)| [(a, b), (b, c), (c, a)])
+            .collect();
+        let shared = |&(p, q): &(Tuple4, Tuple4)| {
+            edges
+                .iter()
+                .any(|&(r, s)| key(r) == key(q) && key(s) == key(p))
+        };
+
+        let origin = point3(0.37, -0.61, -4.3);
+        let mut rays = vec![];
+        for (p, q) in edges.iter().copied().filter(shared) {
+            for k in 1..16 {
+                let target = p + (q - p) * (k as f32 / 16.);
+                rays.push(ray(origin, (target - origin).normalize()));
+            }
+        }
+        rays
+    }
+
+    #[test]
+    fn rays_along_shared_edges_never_slip_through_a_mesh() {
+        let triangles = tilted_grid(8);
+        let rays = rays_along_shared_edges(&triangles);
+        let misses = |hit: &dyn Fn(Ray, Tuple4, Tuple4, Tuple4) -> bool| {
+            rays.iter()
+                .filter(|&&r| !triangles.iter().any(|&(a, b, c)| hit(r, a, b, c)))
+                .count()
+        };
+
+        assert_eq!(misses(&|r, a, b, c| hit(r, a, b, c).is_some()), 0);
+        let naive = misses(&|r, a, b, c| moller_trumbore(r, a, b - a, c - a).is_some());
+        assert!(naive > 0, "{} of {} rays missed", naive, rays.len());
+    }
+
+    #[test]
+    fn both_algorithms_agree_away_from_the_edges() {
+        let (p1, p2, p3) = (point3(0., 1., 0.), point3(-1., 0., 0.), point3(1., 0., 0.));
+        let r = ray(point3(-0.2, 0.3, -2.), vector3(0.1, 0.05, 1.).normalize());
+        let (t, u, v) = hit(r, p1, p2, p3).unwrap();
+        let (expected_t, expected_u, expected_v) =
+            moller_trumbore(r, p1, p2 - p1, p3 - p1).unwrap();
+        assert_approx_eq!(t, expected_t);
+        assert_approx_eq!(u, expected_u);
+        assert_approx_eq!(v, expected_v);
+    }
+
+    #[bench]
+    fn bench_triangle_intersection(bencher: &mut Bencher) {
+        let (p1, p2, p3) = (point3(0., 1., 0.), point3(-1., 0., 0.), point3(1., 0., 0.));
+        let r = ray(point3(-0.2, 0.3, -2.), vector3(0.1, 0.05, 1.).normalize());
+        bencher.iter(|| hit(test::black_box(r), p1, p2, p3));
+    }
+
+    #[bench]
+    fn bench_moller_trumbore_triangle_intersection(bencher: &mut Bencher) {
+        let (p1, p2, p3) = (point3(0., 1., 0.), point3(-1., 0., 0.), point3(1., 0., 0.));
+        let r = ray(point3(-0.2, 0.3, -2.), vector3(0.1, 0.05, 1.).normalize());
+        bencher.iter(|| moller_trumbore(test::black_box(r), p1, p2 - p1, p3 - p1));
+    }
+}
@@ -1,45 +1,131 @@
 use crate::object::*;
 
+use std::ops::Index;
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Intersection {
     pub t: f32,
     pub object_id: ObjectId,
+    /// The barycentric (u, v) coordinates of the hit, for geometry which
+    /// interpolates across its surface.
+    pub uv: Option<(f32, f32)>,
+    /// The index of the triangle hit, for meshes (see `Mesh`). Always 0 for
+    /// other geometry.
+    pub face: usize,
 }
 
+/// The most intersections kept for a single object, which is the four of a
+/// closed cone pierced through its wall and both caps. Objects which could
+/// have more (like displaced surfaces) keep the nearest.
+pub const MAX_INTERSECTIONS: usize = 4;
+
+/// A container for the intersections of a ray with a single object, in order
+/// of t.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Intersections {
-    pub t0: f32,
-    pub t1: f32,
+    len: usize,
+    ts: [f32; MAX_INTERSECTIONS],
+    uvs: [Option<(f32, f32)>; MAX_INTERSECTIONS],
+    faces: [usize; MAX_INTERSECTIONS],
     iterator: usize,
 }
 
-/// A container for the two nearest intersections with a single object.
 impl Intersections {
     pub fn new() -> Self {
         Intersections {
-            t0: std::f32::INFINITY,
-            t1: std::f32::INFINITY,
+            len: 0,
+            ts: [f32::INFINITY; MAX_INTERSECTIONS],
+            uvs: [None; MAX_INTERSECTIONS],
+            faces: [0; MAX_INTERSECTIONS],
             iterator: 0,
         }
     }
 
     pub fn len(&self) -> usize {
-        if self.t1 < std::f32::INFINITY {
-            2
-        } else if self.t0 < std::f32::INFINITY {
-            1
-        } else {
-            0
-        }
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether MAX_INTERSECTIONS intersections have been added, so that any
+    /// more will only be kept in place of farther ones.
+    pub fn is_full(&self) -> bool {
+        self.len == MAX_INTERSECTIONS
     }
 
     pub fn push(&mut self, t: f32) {
-        if t < self.t0 {
-            self.t1 = self.t0;
-            self.t0 = t;
-        } else if t < self.t1 {
-            self.t1 = t;
+        self.push_hit(t, None, 0);
+    }
+
+    /// Adds an intersection along with the (u, v) coordinates of the hit.
+    pub fn push_with_uv(&mut self, t: f32, u: f32, v: f32) {
+        self.push_hit(t, Some((u, v)), 0);
+    }
+
+    /// Adds an intersection with a mesh's triangle, along with the (u, v)
+    /// coordinates of the hit on it.
+    pub fn push_on_face(&mut self, t: f32, u: f32, v: f32, face: usize) {
+        self.push_hit(t, Some((u, v)), face);
+    }
+
+    /// Returns an iterator of each intersection and its (u, v) coordinates,
+    /// if any.
+    pub fn with_uv(self) -> impl Iterator<Item = (f32, Option<(f32, f32)>)> {
+        let uvs = self.uvs;
+        self.zip(uvs)
+    }
+
+    /// Returns an iterator of each intersection, its (u, v) coordinates (if
+    /// any), and the triangle hit (for meshes).
+    pub fn with_uv_and_face(self) -> impl Iterator<Item = (f32, Option<(f32, f32)>, usize)> {
+        let faces = self.faces;
+        self.with_uv()
+            .zip(faces)
+            .map(|((t, uv), face)| (t, uv, face))
+    }
+
+    fn push_hit(&mut self, t: f32, uv: Option<(f32, f32)>, face: usize) {
+        // Degenerate rays can give t values that are never hits.
+        if t.is_nan() || t == f32::INFINITY {
+            return;
+        }
+
+        // When full, keep the nearest intersections and drop the farthest.
+        if self.is_full() {
+            if t >= self.ts[MAX_INTERSECTIONS - 1] {
+                return;
+            }
+            self.len -= 1;
         }
+
+        // Insert the intersection in order, shifting any after it along.
+        let mut i = self.len;
+        while i > 0 && t < self.ts[i - 1] {
+            self.ts[i] = self.ts[i - 1];
+            self.uvs[i] = self.uvs[i - 1];
+            self.faces[i] = self.faces[i - 1];
+            i -= 1;
+        }
+        self.ts[i] = t;
+        self.uvs[i] = uv;
+        self.faces[i] = face;
+        self.len += 1;
+    }
+}
+
+impl Default for Intersections {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Index<usize> for Intersections {
+    type Output = f32;
+
+    fn index(&self, i: usize) -> &f32 {
+        &self.ts[..self.len][i]
     }
 }
 
@@ -47,14 +133,74 @@ impl Iterator for Intersections {
     type Item = f32;
 
     fn next(&mut self) -> Option<f32> {
-        if self.iterator == 0 && self.t0 < std::f32::INFINITY {
-            self.iterator = 1;
-            Some(self.t0)
-        } else if self.iterator == 1 && self.t1 < std::f32::INFINITY {
-            self.iterator = 2;
-            Some(self.t1)
+        if self.iterator < self.len {
+            self.iterator += 1;
+            Some(self.ts[self.iterator - 1])
         } else {
             None
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intersections_are_kept_in_order() {
+        let mut xs = Intersections::new();
+        assert!(xs.is_empty());
+        xs.push(3.);
+        xs.push_with_uv(1., 0.25, 0.5);
+        xs.push(4.);
+        xs.push(-2.);
+        assert_eq!(xs.len(), 4);
+        assert_eq!(xs[0], -2.);
+        assert_eq!(xs[3], 4.);
+        assert_eq!(
+            xs.with_uv().collect::<Vec<_>>(),
+            vec![(-2., None), (1., Some((0.25, 0.5))), (3., None), (4., None)]
+        );
+    }
+
+    #[test]
+    fn only_the_nearest_intersections_are_kept_when_full() {
+        let mut xs = Intersections::new();
+        for &t in [5., 2., 7., 3.].iter() {
+            xs.push(t);
+        }
+        assert!(xs.is_full());
+        xs.push(8.);
+        xs.push_with_uv(1., 0.5, 0.5);
+        assert_eq!(xs.len(), MAX_INTERSECTIONS);
+        assert_eq!(
+            xs.with_uv().collect::<Vec<_>>(),
+            vec![(1., Some((0.5, 0.5))), (2., None), (3., None), (5., None)]
+        );
+    }
+
+    #[test]
+    fn intersections_keep_the_faces_they_hit() {
+        let mut xs = Intersections::new();
+        xs.push_on_face(2., 0.25, 0.5, 7);
+        xs.push(3.);
+        xs.push_on_face(1., 0.5, 0., 3);
+        assert_eq!(
+            xs.with_uv_and_face().collect::<Vec<_>>(),
+            vec![
+                (1., Some((0.5, 0.)), 3),
+                (2., Some((0.25, 0.5)), 7),
+                (3., None, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_intersections_are_not_kept() {
+        let mut xs = Intersections::new();
+        xs.push(f32::NAN);
+        xs.push(f32::INFINITY);
+        assert_eq!(xs.len(), 0);
+        assert_eq!(xs.count(), 0);
+    }
+}
@@ -2,18 +2,36 @@
 
 extern crate test;
 
+pub mod angle;
+pub mod aperture;
+pub mod bounds;
+pub mod brdf;
+pub mod bvh;
 pub mod camera;
 pub mod canvas;
+pub mod cli;
 pub mod color;
+pub mod color_grade;
+pub mod constants;
+pub mod cube_map;
+pub mod filter;
 pub mod geometry;
 pub mod intersection;
 pub mod light;
 pub mod material;
 pub mod matrix;
+pub mod obj;
 pub mod object;
 pub mod ppm;
+pub mod presets;
+pub mod probes;
 pub mod ray;
+pub mod render_options;
+pub mod render_stats;
+pub mod rng;
 pub mod scene;
+pub mod scene_file;
+pub mod sky;
 pub mod texture;
 pub mod transform;
 pub mod tuple;
@@ -1,24 +1,422 @@
+use crate::angle::*;
 use crate::color::*;
+use crate::geometry::*;
+use crate::object::*;
+use crate::ray::*;
+use crate::rng::*;
+use crate::texture::*;
+use crate::transform::*;
 use crate::tuple::*;
+use std::f32::consts::PI;
 
+/// The maximum number of points in an angular intensity profile.
+pub const MAX_PROFILE_POINTS: usize = 32;
+
+/// How a light's intensity varies with the angle from its axis, as in the
+/// photometric data published for real fixtures: a piecewise-linear curve of
+/// (angle, multiplier) points.
+///
+/// The profile holds at most MAX_PROFILE_POINTS points so that it stays
+/// Copy. Before the first and after the last point the multiplier is
+/// constant.
 #[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AngularProfile {
+    len: usize,
+    /// The (angle in radians, multiplier) points, sorted by angle.
+    points: [(f32, f32); MAX_PROFILE_POINTS],
+}
+
+impl AngularProfile {
+    /// Constructs a profile from (angle, multiplier) points sorted by angle.
+    pub fn new(points: &[(Angle, f32)]) -> Self {
+        assert!(!points.is_empty(), "a profile needs at least one point");
+        assert!(
+            points.len() <= MAX_PROFILE_POINTS,
+            "a profile can have at most {} points",
+            MAX_PROFILE_POINTS
+        );
+        debug_assert!(points.windows(2).all(|w| w[0].0 <= w[1].0));
+
+        let mut result = AngularProfile {
+            len: points.len(),
+            points: [(0., 0.); MAX_PROFILE_POINTS],
+        };
+        for (point, &(angle, multiplier)) in result.points.iter_mut().zip(points) {
+            *point = (angle.to_radians(), multiplier);
+        }
+        result
+    }
+
+    /// Returns the multiplier at the given angle from the axis.
+    pub fn value_at(&self, angle: impl Into<Angle>) -> f32 {
+        let angle = angle.into().to_radians();
+        let points = &self.points[..self.len];
+        let (first_angle, first_value) = points[0];
+        let (last_angle, last_value) = points[self.len - 1];
+
+        if angle <= first_angle {
+            return first_value;
+        } else if angle >= last_angle {
+            return last_value;
+        }
+
+        let i = points.iter().position(|&(a, _)| a > angle).unwrap();
+        let (a0, v0) = points[i - 1];
+        let (a1, v1) = points[i];
+        let fraction = (angle - a0) / (a1 - a0);
+        (v1 - v0).mul_add(fraction, v0)
+    }
+}
+
+/// Parses an angular profile from a simplified IES-like text format: one
+/// point per line, as an angle from the axis in degrees followed by the
+/// multiplier, with angles increasing. Blank lines and lines starting with
+/// `#` are skipped.
+///
+/// ```text
+/// # A narrow downlight.
+/// 0   1.0
+/// 15  0.8
+/// 30  0.0
+/// ```
+pub fn parse_profile(source: &str) -> Result<AngularProfile, String> {
+    let mut points = vec![];
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let point = parse_profile_point(line, points.last().map(|&(angle, _)| angle))
+            .map_err(|err| format!("line {}: {}", line_number, err))?;
+        points.push(point);
+    }
+
+    if points.is_empty() {
+        return Err("a profile needs at least one point".to_string());
+    } else if points.len() > MAX_PROFILE_POINTS {
+        return Err(format!(
+            "a profile can have at most {} points",
+            MAX_PROFILE_POINTS
+        ));
+    }
+
+    let points: Vec<(Angle, f32)> = points
+        .into_iter()
+        .map(|(degrees, multiplier)| (Angle::degrees(degrees), multiplier))
+        .collect();
+    Ok(AngularProfile::new(&points))
+}
+
+/// Parses an (angle in degrees, multiplier) line, which must follow the
+/// previous angle (if any).
+fn parse_profile_point(line: &str, previous: Option<f32>) -> Result<(f32, f32), String> {
+    let mut tokens = line.split_whitespace();
+    let mut next = |name: &str| -> Result<f32, String> {
+        let token = tokens
+            .next()
+            .ok_or_else(|| format!("expected an angle and a multiplier, missing the {}", name))?;
+        token
+            .parse()
+            .map_err(|_| format!("invalid {}: {}", name, token))
+    };
+    let angle = next("angle")?;
+    let multiplier = next("multiplier")?;
+
+    if !(0. ..=180.).contains(&angle) {
+        return Err(format!("angle {} is outside 0 to 180 degrees", angle));
+    }
+    if previous.is_some_and(|previous| angle <= previous) {
+        return Err(format!("angle {} doesn't increase", angle));
+    }
+    if multiplier < 0. {
+        return Err(format!("multiplier {} is negative", multiplier));
+    }
+    Ok((angle, multiplier))
+}
+
+/// The surface of an object which gives off light, so that a light can take
+/// its shape and (textured) color from the object.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Emitter {
+    /// The object, which doesn't shadow its own light.
+    pub object_id: ObjectId,
+    pub geometry: Geometry,
+    pub transform: Transform,
+    /// The color given off at each point, which scales the light's intensity.
+    pub texture: Texture,
+}
+
+impl Emitter {
+    /// Returns the color given off at the given (world space) point on the
+    /// surface, and how much area the point stands for. Points are sampled
+    /// uniformly over the local surface, so where the transform stretches
+    /// the surface they stand for more of it.
+    pub fn emission_at<R: Rng>(&self, rng: &mut R, point: Tuple4) -> (Color, f32) {
+        let color = self.texture.evaluate(rng, self.transform, point);
+        let local_point = self.transform.world_to_local * point;
+        let local_normal = self
+            .geometry
+            .geometric_normal_at(local_point, 0)
+            .normalize();
+        let mut world_normal = self.transform.world_to_local.transpose() * local_normal;
+        world_normal.w = 0.;
+
+        // An area element scales by |det M| |M⁻ᵀn|, and the determinant is the
+        // same everywhere on the surface.
+        (color, world_normal.magnitude())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Light {
+    /// The position of a point light, or the center of an area light.
     pub position: Tuple4,
     pub intensity: Color,
+    /// The corner of an area light's panel.
+    pub corner: Tuple4,
+    /// The size of one cell of the panel along its first edge.
+    pub uvec: Tuple4,
+    /// The number of cells along the first edge.
+    pub usteps: usize,
+    /// The size of one cell of the panel along its second edge.
+    pub vvec: Tuple4,
+    /// The number of cells along the second edge.
+    pub vsteps: usize,
+    /// For adaptive shadow sampling, the number of cells along each edge of
+    /// the grid sampled first (see `adaptive_shadows`), or None to sample
+    /// every cell.
+    pub adaptive_shadow_steps: Option<usize>,
+    /// The direction the light points, for its angular profile.
+    pub axis: Tuple4,
+    /// How the intensity varies with the angle from the axis, if it does.
+    pub profile: Option<AngularProfile>,
+    /// The surface giving off the light, for a light taken from an object.
+    pub emitter: Option<Emitter>,
+    /// Whether the intensity is in candela and falls off with the inverse
+    /// square of the distance. Otherwise it is a unitless multiplier which
+    /// is the same at every distance.
+    pub photometric: bool,
 }
 
 impl Light {
+    /// Constructs a point light.
     pub fn new(position: Tuple4, intensity: Color) -> Self {
         Light {
             position,
             intensity,
+            corner: position,
+            uvec: vector3(0., 0., 0.),
+            usteps: 1,
+            vvec: vector3(0., 0., 0.),
+            vsteps: 1,
+            adaptive_shadow_steps: None,
+            axis: vector3(0., -1., 0.),
+            profile: None,
+            emitter: None,
+            photometric: false,
+        }
+    }
+
+    /// Constructs a rectangular area light with a corner at the given point,
+    /// spanning the two edge vectors, which are divided into the given
+    /// number of cells.
+    pub fn area(
+        corner: Tuple4,
+        full_uvec: Tuple4,
+        usteps: usize,
+        full_vvec: Tuple4,
+        vsteps: usize,
+        intensity: Color,
+    ) -> Self {
+        Light {
+            position: corner + full_uvec * 0.5 + full_vvec * 0.5,
+            intensity,
+            corner,
+            uvec: full_uvec * (1. / usteps as f32),
+            usteps,
+            vvec: full_vvec * (1. / vsteps as f32),
+            vsteps,
+            adaptive_shadow_steps: None,
+            axis: vector3(0., -1., 0.),
+            profile: None,
+            emitter: None,
+            photometric: false,
+        }
+    }
+
+    /// Constructs a light given off by an object's surface, which is sampled
+    /// in a grid of usteps by vsteps cells (see `Geometry::sample_surface`).
+    /// Each point gives off the intensity times the emitter's texture there.
+    pub fn emitter(emitter: Emitter, usteps: usize, vsteps: usize, intensity: Color) -> Self {
+        assert!(
+            emitter.geometry.sample_surface(0.5, 0.5).is_some(),
+            "{:?} can't be sampled as a light",
+            emitter.geometry
+        );
+        let mut light = Light::new(
+            emitter.transform.local_to_world * point3(0., 0., 0.),
+            intensity,
+        );
+        light.usteps = usteps;
+        light.vsteps = vsteps;
+        light.emitter = Some(emitter);
+        light
+    }
+
+    /// Samples the light's shadows adaptively: a coarse grid of cells, the
+    /// given number along each edge (at least one, and at most usteps or
+    /// vsteps) spread evenly from corner to corner, is sampled first, and
+    /// the rest only if those shadow rays disagree, as they do in a
+    /// penumbra. Points which the coarse grid finds fully lit or fully
+    /// shadowed cost only its samples.
+    ///
+    /// This is biased: an occluder small enough to slip between the coarse
+    /// grid's rays casts no shadow, and where only a sliver of the light is
+    /// blocked (at the outer edge of a penumbra) it can be missed, so soft
+    /// shadows can come out slightly smaller. Use more steps for small
+    /// occluders.
+    pub fn adaptive_shadows(mut self, steps: usize) -> Self {
+        self.adaptive_shadow_steps = Some(steps.max(1));
+        self
+    }
+
+    /// Gives the light an angular intensity profile around the axis.
+    pub fn profile(mut self, axis: Tuple4, profile: AngularProfile) -> Self {
+        self.axis = axis.normalize();
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Makes the light photometric, with the given luminous intensity (in
+    /// candela) in every direction. The intensity the light was constructed
+    /// with becomes its color.
+    pub fn candela(mut self, candela: f32) -> Self {
+        self.intensity = self.intensity * candela;
+        self.photometric = true;
+        self
+    }
+
+    /// Makes the light photometric, giving off the given luminous flux (in
+    /// lumens) evenly in every direction, as a bare bulb does. The intensity
+    /// the light was constructed with becomes its color.
+    pub fn lumens(self, lumens: f32) -> Self {
+        self.candela(lumens / (4. * PI))
+    }
+
+    /// Returns the factor by which the light dims on its way to the given
+    /// point: the inverse square of the distance from the light's position
+    /// (the center of an area light) for photometric lights, and 1 for
+    /// others.
+    pub fn falloff(&self, point: Tuple4) -> f32 {
+        if self.photometric {
+            let v = point - self.position;
+            1. / v.dot(v)
+        } else {
+            1.
+        }
+    }
+
+    /// Returns the intensity of the light shining toward the given point,
+    /// scaled by the profile at the angle between the axis and the direction
+    /// from the light to the point, and by the falloff.
+    pub fn intensity_toward(&self, point: Tuple4) -> Color {
+        let intensity = self.intensity * self.falloff(point);
+        match self.profile {
+            Some(profile) => {
+                let direction = (point - self.position).normalize();
+                let cos = direction.dot(self.axis).clamp(-1., 1.);
+                intensity * profile.value_at(cos.acos())
+            }
+            None => intensity,
+        }
+    }
+
+    /// Whether the light is a single point.
+    pub fn is_point(&self) -> bool {
+        self.emitter.is_none()
+            && self.uvec == vector3(0., 0., 0.)
+            && self.vvec == vector3(0., 0., 0.)
+    }
+
+    /// For a rectangular area light, returns the point on the panel nearest
+    /// the given ray (reflected from a surface), which stands in for the
+    /// whole panel in the surface's specular highlight, with the angular
+    /// radius of the panel seen from the ray's origin (taken as that of a
+    /// disc of the same area). None for point lights, emitters, and panels
+    /// with no area.
+    pub fn representative_point(&self, reflection: Ray) -> Option<(Tuple4, f32)> {
+        if self.emitter.is_some() {
+            return None;
         }
+        let full_uvec = self.uvec * self.usteps as f32;
+        let full_vvec = self.vvec * self.vsteps as f32;
+        let normal = full_uvec.cross(full_vvec);
+        let area = normal.magnitude();
+        if area <= 0. {
+            return None;
+        }
+
+        // Where the ray crosses the panel's plane, or if it runs parallel to
+        // or away from the plane, the point on the ray nearest the center.
+        let to_center = self.position - reflection.origin;
+        let across = reflection.direction.dot(normal);
+        let t = if across != 0. {
+            to_center.dot(normal) / across
+        } else {
+            -1.
+        };
+        let t = if t > 0. {
+            t
+        } else {
+            to_center.dot(reflection.direction).max(0.)
+        };
+        let offset = reflection.position(t) - self.corner;
+        let u = (offset.dot(full_uvec) / full_uvec.dot(full_uvec)).clamp(0., 1.);
+        let v = (offset.dot(full_vvec) / full_vvec.dot(full_vvec)).clamp(0., 1.);
+        let point = self.corner + full_uvec * u + full_vvec * v;
+
+        let radius = (area / PI).sqrt();
+        Some((point, radius.atan2(to_center.magnitude())))
+    }
+
+    /// Returns the number of cells sampled on the light.
+    pub fn samples(&self) -> usize {
+        self.usteps * self.vsteps
+    }
+
+    /// Returns a point in the given cell of the panel (or the emitter's
+    /// surface), jittered randomly within it. Point lights always return
+    /// their position.
+    pub fn point_on<R: Rng>(&self, rng: &mut R, u: usize, v: usize) -> Tuple4 {
+        if let Some(emitter) = &self.emitter {
+            let u = (u as f32 + rng.gen::<f32>()) / self.usteps as f32;
+            let v = (v as f32 + rng.gen::<f32>()) / self.vsteps as f32;
+            let local_point = emitter.geometry.sample_surface(u, v).unwrap();
+            return emitter.transform.local_to_world * local_point;
+        } else if self.is_point() {
+            return self.position;
+        }
+
+        self.corner
+            + self.uvec * (u as f32 + rng.gen::<f32>())
+            + self.vvec * (v as f32 + rng.gen::<f32>())
+    }
+
+    /// Returns a jittered point in each cell of the panel.
+    pub fn sample_points<'a, R: Rng>(
+        &'a self,
+        rng: &'a mut R,
+    ) -> impl Iterator<Item = Tuple4> + 'a {
+        (0..self.samples()).map(move |i| self.point_on(rng, i % self.usteps, i / self.usteps))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use assert_approx_eq::assert_approx_eq;
 
     #[test]
     fn a_point_light_has_a_position_and_intensity() {
@@ -27,5 +425,206 @@ mod tests {
         let light = Light::new(position, intensity);
         assert_eq!(light.position, position);
         assert_eq!(light.intensity, intensity);
+        assert!(light.is_point());
+        assert_eq!(light.samples(), 1);
+    }
+
+    #[test]
+    fn creating_an_area_light() {
+        let light = Light::area(
+            point3(0., 0., 0.),
+            vector3(2., 0., 0.),
+            4,
+            vector3(0., 0., 1.),
+            2,
+            Color::WHITE,
+        );
+        assert_eq!(light.corner, point3(0., 0., 0.));
+        assert_eq!(light.uvec, vector3(0.5, 0., 0.));
+        assert_eq!(light.vvec, vector3(0., 0., 0.5));
+        assert_eq!(light.samples(), 8);
+        assert_eq!(light.position, point3(1., 0., 0.5));
+        assert!(!light.is_point());
+    }
+
+    #[test]
+    fn sample_points_are_jittered_within_their_cells() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let light = Light::area(
+            point3(0., 0., 0.),
+            vector3(2., 0., 0.),
+            4,
+            vector3(0., 0., 1.),
+            2,
+            Color::WHITE,
+        );
+        let points: Vec<Tuple4> = light.sample_points(&mut rng).collect();
+        assert_eq!(points.len(), 8);
+        for (i, p) in points.into_iter().enumerate() {
+            let (u, v) = ((i % 4) as f32, (i / 4) as f32);
+            assert!(p.x >= u * 0.5 && p.x <= (u + 1.) * 0.5);
+            assert!(p.z >= v * 0.5 && p.z <= (v + 1.) * 0.5);
+            assert_eq!(p.y, 0.);
+        }
+    }
+
+    #[test]
+    fn adaptive_shadows_take_at_least_one_step() {
+        let light = Light::area(
+            point3(0., 0., 0.),
+            vector3(1., 0., 0.),
+            4,
+            vector3(0., 0., 1.),
+            4,
+            Color::WHITE,
+        );
+        assert_eq!(light.adaptive_shadow_steps, None);
+        assert_eq!(
+            light.clone().adaptive_shadows(2).adaptive_shadow_steps,
+            Some(2)
+        );
+        assert_eq!(light.adaptive_shadows(0).adaptive_shadow_steps, Some(1));
+    }
+
+    #[test]
+    fn a_point_light_has_a_single_sample_at_its_position() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let light = Light::new(point3(1., 2., 3.), Color::WHITE);
+        let points: Vec<Tuple4> = light.sample_points(&mut rng).collect();
+        assert_eq!(points, vec![point3(1., 2., 3.)]);
+    }
+
+    #[test]
+    fn the_representative_point_is_the_nearest_point_on_the_panel() {
+        let light = Light::area(
+            point3(-1., 5., -1.),
+            vector3(2., 0., 0.),
+            2,
+            vector3(0., 0., 2.),
+            2,
+            Color::WHITE,
+        );
+        let up = |x: f32, z: f32| ray(point3(0., 0., 0.), vector3(x, 5., z).normalize());
+
+        // Straight through the panel, past its edge, and away from it.
+        let (point, angle) = light.representative_point(up(0.5, -0.25)).unwrap();
+        assert_approx_eq!(point.x, 0.5);
+        assert_approx_eq!(point.y, 5.);
+        assert_approx_eq!(point.z, -0.25);
+        assert_approx_eq!(angle, (2. / PI.sqrt()).atan2(5.));
+        let (point, _) = light.representative_point(up(3., 0.)).unwrap();
+        assert_eq!(point, point3(1., 5., 0.));
+        let down = ray(point3(0., 0., 0.), vector3(0., -1., 0.));
+        assert_eq!(light.representative_point(down).unwrap().0, light.position);
+
+        assert_eq!(
+            Light::new(point3(0., 5., 0.), Color::WHITE).representative_point(down),
+            None
+        );
+    }
+
+    fn on_and_off_axis_profile() -> AngularProfile {
+        AngularProfile::new(&[(Angle::degrees(0.), 1.), (Angle::degrees(45.), 0.)])
+    }
+
+    #[test]
+    fn a_profile_is_interpolated_between_its_points() {
+        let profile = on_and_off_axis_profile();
+        assert_eq!(profile.value_at(Angle::degrees(0.)), 1.);
+        assert_approx_eq!(profile.value_at(Angle::degrees(22.5)), 0.5);
+        assert_approx_eq!(profile.value_at(Angle::degrees(45.)), 0.);
+        assert_approx_eq!(profile.value_at(Angle::degrees(90.)), 0.);
+    }
+
+    #[test]
+    fn the_intensity_of_a_light_with_a_profile() {
+        let light = Light::new(point3(0., 10., 0.), Color::WHITE)
+            .profile(vector3(0., -2., 0.), on_and_off_axis_profile());
+        assert_eq!(light.intensity_toward(point3(0., 0., 0.)), Color::WHITE);
+        assert_approx_eq!(light.intensity_toward(point3(10., 0., 0.)).r, 0.);
+        let x = 10. * Angle::degrees(22.5).to_radians().tan();
+        assert_approx_eq!(light.intensity_toward(point3(0., 0., x)).g, 0.5);
+
+        let plain = Light::new(point3(0., 10., 0.), Color::WHITE);
+        assert_eq!(plain.intensity_toward(point3(10., 0., 0.)), Color::WHITE);
+    }
+
+    #[test]
+    fn photometric_lights_fall_off_with_the_square_of_the_distance() {
+        let light = Light::new(point3(0., 0., 0.), Color::new(1., 0.5, 0.)).candela(100.);
+        assert!(light.photometric);
+        let near = light.intensity_toward(point3(0., 2., 0.));
+        let far = light.intensity_toward(point3(0., 0., -4.));
+        assert_approx_eq!(near.r, 25.);
+        assert_approx_eq!(near.g, 12.5);
+        assert_approx_eq!(far.r, 6.25);
+
+        // A bulb's flux spreads over the whole sphere.
+        let bulb = Light::new(point3(0., 0., 0.), Color::WHITE).lumens(4. * PI);
+        assert_approx_eq!(bulb.intensity_toward(point3(3., 0., 0.)).g, 1. / 9.);
+
+        let legacy = Light::new(point3(0., 0., 0.), Color::WHITE);
+        assert!(!legacy.photometric);
+        assert_eq!(legacy.falloff(point3(0., 100., 0.)), 1.);
+    }
+
+    #[test]
+    fn parsing_a_profile() {
+        let source = "\
+# A narrow downlight.
+
+0   1.0
+15  0.8
+30  0
+";
+        let profile = parse_profile(source).unwrap();
+        assert_eq!(profile.value_at(0.), 1.);
+        assert_approx_eq!(profile.value_at(Angle::degrees(15.)), 0.8);
+        assert_approx_eq!(profile.value_at(Angle::degrees(22.5)), 0.4);
+        assert_eq!(profile.value_at(Angle::degrees(60.)), 0.);
+    }
+
+    #[test]
+    fn malformed_profiles_are_errors() {
+        let examples = vec![
+            ("", "a profile needs at least one point"),
+            (
+                "0 1\n10",
+                "line 2: expected an angle and a multiplier, missing the multiplier",
+            ),
+            ("0 x", "line 1: invalid multiplier: x"),
+            ("0 1\n10 0.5\n5 0", "line 3: angle 5 doesn't increase"),
+            ("200 1", "line 1: angle 200 is outside 0 to 180 degrees"),
+            ("0 -1", "line 1: multiplier -1 is negative"),
+        ];
+        for (source, expected) in examples {
+            assert_eq!(parse_profile(source).unwrap_err(), expected);
+        }
+    }
+
+    #[test]
+    fn emitter_samples_stand_for_the_area_around_them() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let emitter = Emitter {
+            object_id: 0,
+            geometry: Geometry::cube(),
+            transform: Transform::new().translate(0., 5., 0.).scale(2., 0.5, 1.),
+            texture: Texture::constant(Color::WHITE),
+        };
+        let light = Light::emitter(emitter.clone(), 6, 1, Color::WHITE);
+        assert_eq!(light.position, point3(0., 5., 0.));
+        assert_eq!(light.samples(), 6);
+        assert!(!light.is_point());
+
+        // One sample per face: the x faces are 0.5 by 1, the y faces 2 by 1,
+        // and the z faces 2 by 0.5.
+        let points: Vec<Tuple4> = light.sample_points(&mut rng).collect();
+        let weights = points
+            .into_iter()
+            .map(|point| emitter.emission_at(&mut rng, point).1);
+        let expected = [0.5, 0.5, 2., 2., 1., 1.];
+        for (weight, expected) in weights.zip(expected) {
+            assert_approx_eq!(weight, expected);
+        }
     }
 }
@@ -1,11 +1,128 @@
+use crate::brdf::*;
 use crate::color::*;
 use crate::light::*;
+use crate::ray::*;
+use crate::rng::*;
 use crate::texture::*;
 use crate::transform::*;
 use crate::tuple::*;
-use rand::Rng;
 
+/// Wavelengths (in micrometers) of the Fraunhofer C, d, and F spectral lines,
+/// used as the red, green, and blue channels for dispersion.
+const WAVELENGTH_C: f32 = 0.6563;
+const WAVELENGTH_D: f32 = 0.5876;
+const WAVELENGTH_F: f32 = 0.4861;
+
+/// Common optical glasses, for use with Material::glass.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Glass {
+    /// Schott N-BK7 borosilicate crown glass.
+    BK7,
+    /// Schott F2 flint glass.
+    F2,
+    /// Schott SF10 dense flint glass.
+    SF10,
+}
+
+impl Glass {
+    /// The refractive index at the d line (587.6 nm).
+    pub fn refractive_index(self) -> f32 {
+        match self {
+            Glass::BK7 => 1.5168,
+            Glass::F2 => 1.6200,
+            Glass::SF10 => 1.7283,
+        }
+    }
+
+    /// The Abbe number, which measures how little the refractive index varies
+    /// with wavelength.
+    pub fn abbe(self) -> f32 {
+        match self {
+            Glass::BK7 => 64.17,
+            Glass::F2 => 36.37,
+            Glass::SF10 => 28.53,
+        }
+    }
+}
+
+/// The maximum number of keyframes in an animation curve.
+pub const MAX_KEYFRAMES: usize = 8;
+
+/// A piecewise-linear animation curve of (time, value) pairs.
+///
+/// The curve holds at most MAX_KEYFRAMES keyframes so that it stays Copy.
+/// Before the first and after the last keyframe the value is constant.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Keyframes {
+    len: usize,
+    frames: [(f32, f32); MAX_KEYFRAMES],
+}
+
+impl Keyframes {
+    /// Constructs an animation curve from (time, value) pairs sorted by time.
+    ///
+    /// Returns an error if there are no keyframes, more than MAX_KEYFRAMES,
+    /// or their times aren't finite and sorted.
+    pub fn new(frames: &[(f32, f32)]) -> Result<Self, String> {
+        if frames.is_empty() {
+            return Err("an animation needs at least one keyframe".to_string());
+        }
+        if frames.len() > MAX_KEYFRAMES {
+            return Err(format!(
+                "an animation can have at most {} keyframes, not {}",
+                MAX_KEYFRAMES,
+                frames.len()
+            ));
+        }
+        if !frames.iter().all(|&(time, _)| time.is_finite()) {
+            return Err("keyframe times must be finite".to_string());
+        }
+        if !frames.windows(2).all(|w| w[0].0 <= w[1].0) {
+            return Err("keyframes must be sorted by time".to_string());
+        }
+
+        let mut result = Keyframes {
+            len: frames.len(),
+            frames: [(0., 0.); MAX_KEYFRAMES],
+        };
+        result.frames[..frames.len()].copy_from_slice(frames);
+        Ok(result)
+    }
+
+    /// Returns the value of the curve at the given time.
+    pub fn value_at(&self, time: f32) -> f32 {
+        let frames = &self.frames[..self.len];
+        let (first_time, first_value) = frames[0];
+        let (last_time, last_value) = frames[self.len - 1];
+
+        if time <= first_time {
+            return first_value;
+        } else if time >= last_time {
+            return last_value;
+        }
+
+        let i = frames.iter().position(|&(t, _)| t > time).unwrap();
+        let (t0, v0) = frames[i - 1];
+        let (t1, v1) = frames[i];
+        let fraction = (time - t0) / (t1 - t0);
+        (v1 - v0).mul_add(fraction, v0)
+    }
+}
+
+/// A scalar material property that can be animated.
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Property {
+    Ambient,
+    Diffuse,
+    Specular,
+    Reflective,
+    Transparency,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Material {
     pub texture: Texture,
     pub ambient: f32,
@@ -15,6 +132,24 @@ pub struct Material {
     pub reflective: f32,
     pub transparency: f32,
     pub refractive_index: f32,
+    /// The Abbe number, or 0.0 to disable dispersion.
+    pub abbe: f32,
+    /// Animation curves for the scalar properties, at most one per property.
+    pub animation: Vec<(Property, Keyframes)>,
+    /// Optional textures whose brightness scales the diffuse, specular, and
+    /// reflective properties across the surface.
+    pub diffuse_texture: Option<Texture>,
+    pub specular_texture: Option<Texture>,
+    pub reflective_texture: Option<Texture>,
+    /// How light is reflected from the surface.
+    pub brdf: BrdfModel,
+    /// How much clear coat covers the surface, from 0 (none) to 1. The coat
+    /// adds a white highlight and a mirror reflection, both strongest at
+    /// grazing angles, and dims what lies beneath accordingly.
+    pub clearcoat: f32,
+    /// How rough the clear coat's highlight is, from 0 (polished) to 1. Its
+    /// reflection is always a mirror.
+    pub clearcoat_roughness: f32,
 }
 
 impl Material {
@@ -28,6 +163,14 @@ impl Material {
             reflective: 0.0,
             transparency: 0.0,
             refractive_index: 1.0,
+            abbe: 0.0,
+            animation: Vec::new(),
+            diffuse_texture: None,
+            specular_texture: None,
+            reflective_texture: None,
+            brdf: BrdfModel::LambertPhong,
+            clearcoat: 0.,
+            clearcoat_roughness: 0.1,
         }
     }
 
@@ -76,63 +219,235 @@ impl Material {
         self
     }
 
-    /// Computes the color of the surface at the given point.
+    pub fn abbe(mut self, abbe: f32) -> Self {
+        self.abbe = abbe;
+        self
+    }
+
+    /// Sets the refractive index and Abbe number to those of the given glass.
+    pub fn glass(mut self, glass: Glass) -> Self {
+        self.refractive_index = glass.refractive_index();
+        self.abbe = glass.abbe();
+        self
+    }
+
+    pub fn diffuse_texture(mut self, texture: Texture) -> Self {
+        self.diffuse_texture = Some(texture);
+        self
+    }
+
+    pub fn specular_texture(mut self, texture: Texture) -> Self {
+        self.specular_texture = Some(texture);
+        self
+    }
+
+    pub fn reflective_texture(mut self, texture: Texture) -> Self {
+        self.reflective_texture = Some(texture);
+        self
+    }
+
+    pub fn brdf(mut self, brdf: BrdfModel) -> Self {
+        self.brdf = brdf;
+        self
+    }
+
+    pub fn clearcoat(mut self, clearcoat: f32) -> Self {
+        self.clearcoat = clearcoat;
+        self
+    }
+
+    pub fn clearcoat_roughness(mut self, clearcoat_roughness: f32) -> Self {
+        self.clearcoat_roughness = clearcoat_roughness;
+        self
+    }
+
+    /// Animates the given property with the keyframes, replacing any curve
+    /// it already had.
+    pub fn animate(mut self, property: Property, keyframes: Keyframes) -> Self {
+        self.animation.retain(|&(p, _)| p != property);
+        self.animation.push((property, keyframes));
+        self
+    }
+
+    /// Returns the material as it appears at the given time, with its
+    /// animation curves evaluated and its texture moved by its velocity.
+    pub fn at_time(mut self, time: f32) -> Self {
+        self.texture = self.texture.at_time(time);
+
+        for &(property, keyframes) in &self.animation {
+            let value = keyframes.value_at(time);
+            match property {
+                Property::Ambient => self.ambient = value,
+                Property::Diffuse => self.diffuse = value,
+                Property::Specular => self.specular = value,
+                Property::Reflective => self.reflective = value,
+                Property::Transparency => self.transparency = value,
+            }
+        }
+
+        self
+    }
+
+    /// Whether the material disperses light into its component colors.
+    pub fn is_dispersive(&self) -> bool {
+        self.abbe > 0.
+    }
+
+    /// Returns the refractive indexes for the red, green, and blue channels.
+    ///
+    /// These are derived from the refractive index and Abbe number using
+    /// Cauchy's equation, with the channels at the C, d, and F lines. If
+    /// dispersion is disabled all three are equal to the refractive index.
+    pub fn channel_refractive_indexes(&self) -> (f32, f32, f32) {
+        let n = self.refractive_index;
+
+        if !self.is_dispersive() {
+            return (n, n, n);
+        }
+
+        // Cauchy's equation: n(λ) = A + B / λ², where the Abbe number is
+        // V = (n_d - 1) / (n_F - n_C).
+        let inv_c2 = 1. / (WAVELENGTH_C * WAVELENGTH_C);
+        let inv_d2 = 1. / (WAVELENGTH_D * WAVELENGTH_D);
+        let inv_f2 = 1. / (WAVELENGTH_F * WAVELENGTH_F);
+        let b = (n - 1.) / (self.abbe * (inv_f2 - inv_c2));
+
+        (
+            b.mul_add(inv_c2 - inv_d2, n),
+            n,
+            b.mul_add(inv_f2 - inv_d2, n),
+        )
+    }
+
+    /// Returns the fraction of the light the clear coat reflects toward the
+    /// eye, leaving the rest of the surface's light to pass through it.
+    pub fn clearcoat_reflectance(&self, eyev: Tuple4, normalv: Tuple4) -> f32 {
+        ClearCoat::<LambertPhong>::reflectance(self.clearcoat, eyev.dot(normalv))
+    }
+
+    /// Returns the reflectivity at the given point in world space.
+    pub fn reflective_at<R: Rng>(&self, rng: &mut R, transform: Transform, point: Tuple4) -> f32 {
+        self.reflective * mask(&self.reflective_texture, rng, transform, point)
+    }
+
+    /// Computes the color of the surface at the given point, with the diffuse
+    /// and specular contributions scaled by the fraction of the light which
+    /// reaches it (0 when fully in shadow).
     pub fn lighting<R: Rng>(
-        self,
+        &self,
         rng: &mut R,
         transform: Transform,
-        light: Light,
+        light: &Light,
         point: Tuple4,
         eyev: Tuple4,
         normalv: Tuple4,
-        in_shadow: bool,
+        light_intensity: f32,
     ) -> Color {
         let base_color = self.texture.evaluate(rng, transform, point);
 
         // Combine the surface color with the light's color/intensity.
-        let effective_color = base_color * light.intensity;
+        let effective_color = base_color * light.intensity * light.falloff(point);
 
         // Compute and add the ambient contribution.
         let mut result = effective_color * self.ambient;
 
         // Skip the diffuse and specular components if the point is in shadow.
-        if in_shadow {
+        if light_intensity <= 0. {
             return result;
         }
 
+        let diffuse = self.diffuse * mask(&self.diffuse_texture, rng, transform, point);
+        let specular = self.specular * mask(&self.specular_texture, rng, transform, point);
+
         // Find the direction to the light source.
         let lightv = (light.position - point).normalize();
 
-        // light_dot_normal represents the cosine of the angle between the light
-        // vector and the normal vector. A negative number means the light is on
-        // the other side of the surface.
-        let light_dot_normal = lightv.dot(normalv);
-        if light_dot_normal >= 0. {
-            // Compute and add the diffuse contribution.
-            result = result + effective_color * self.diffuse * light_dot_normal;
-
-            // reflect_dot_eye represents the cosine of the angle between the
-            // reflection vector and the eye vector. A negative number means the
-            // light reflects away from the eye.
-            let reflectv = (-lightv).reflect(normalv);
-            let reflect_dot_eye = reflectv.dot(eyev);
-            if reflect_dot_eye >= 0. {
-                // Compute and add the specular contribution.
-                let factor = reflect_dot_eye.powi(self.shininess);
-                result = result + light.intensity * self.specular * factor;
-            }
+        // Compute and add the diffuse and specular contributions.
+        let reflectance = self.reflectance(base_color, diffuse, specular, 0.);
+        let mut reflected = reflectance(lightv, eyev, normalv);
+
+        // An area light's highlight is that of its point nearest the
+        // reflection ray, widened by its size. The BRDFs are linear in the
+        // diffuse weight, so this swaps out the highlight seen from the
+        // center without changing the diffuse light.
+        let reflection = ray(point, (-eyev).reflect(normalv));
+        if let Some((highlight_point, angle)) = light.representative_point(reflection) {
+            let highlightv = (highlight_point - point).normalize();
+            let highlight = self.reflectance(base_color, 0., specular, angle);
+            let center_highlight = self.reflectance(base_color, 0., specular, 0.);
+            reflected = reflected - center_highlight(lightv, eyev, normalv)
+                + highlight(highlightv, eyev, normalv);
         }
 
+        let intensity = light.intensity_toward(point);
+        result = result + intensity * reflected * light_intensity;
+
         result
     }
+
+    /// Returns the material's BRDF (evaluated) for a surface of the given
+    /// color, with the given diffuse and specular weights, and its highlight
+    /// widened by the given angle for a light of that angular radius.
+    fn reflectance(
+        &self,
+        color: Color,
+        diffuse: f32,
+        specular: f32,
+        spread: f32,
+    ) -> impl Fn(Tuple4, Tuple4, Tuple4) -> Color {
+        let shininess = self.shininess;
+        let brdf = self.brdf;
+        let coat = (self.clearcoat, self.clearcoat_roughness);
+        move |lightv, eyev, normalv| match brdf {
+            BrdfModel::LambertPhong => LambertPhong {
+                diffuse: color * diffuse,
+                specular,
+                shininess,
+            }
+            .widened(spread)
+            .evaluate_coated(coat, lightv, eyev, normalv),
+            BrdfModel::BlinnPhong => BlinnPhong {
+                diffuse: color * diffuse,
+                specular,
+                shininess,
+            }
+            .widened(spread)
+            .evaluate_coated(coat, lightv, eyev, normalv),
+            BrdfModel::CookTorrance {
+                roughness,
+                metallic,
+            } => CookTorrance {
+                color,
+                diffuse,
+                specular,
+                roughness,
+                metallic,
+            }
+            .widened(spread)
+            .evaluate_coated(coat, lightv, eyev, normalv),
+        }
+    }
+}
+
+/// Returns the brightness (mean of the color channels) of the mask texture at
+/// the given point, or 1.0 if there is no texture.
+fn mask<R: Rng>(
+    texture: &Option<Texture>,
+    rng: &mut R,
+    transform: Transform,
+    point: Tuple4,
+) -> f32 {
+    texture.as_ref().map_or(1., |texture| {
+        let color = texture.evaluate(rng, transform, point);
+        (color.r + color.g + color.b) / 3.
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::angle::*;
     use assert_approx_eq::assert_approx_eq;
-    use rand::rngs::SmallRng;
-    use rand::SeedableRng;
     use test::Bencher;
 
     #[test]
@@ -146,6 +461,163 @@ mod tests {
         assert_eq!(m.reflective, 0.0);
         assert_eq!(m.transparency, 0.0);
         assert_eq!(m.refractive_index, 1.0);
+        assert_eq!(m.abbe, 0.0);
+        assert!(m.animation.is_empty());
+        assert_eq!(m.diffuse_texture, None);
+        assert_eq!(m.specular_texture, None);
+        assert_eq!(m.reflective_texture, None);
+        assert_eq!(m.brdf, BrdfModel::LambertPhong);
+        assert_eq!(m.clearcoat, 0.0);
+        assert_eq!(m.clearcoat_roughness, 0.1);
+    }
+
+    #[test]
+    fn lighting_without_a_clear_coat_is_unchanged() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let eyev = vector3(0., 0.6, -0.8);
+        let normalv = vector3(0., 0., -1.);
+        let light = Light::new(point3(3., 5., -10.), Color::new(1., 1., 1.));
+        let p = point3(0., 0., 0.);
+        for brdf in [
+            BrdfModel::LambertPhong,
+            BrdfModel::BlinnPhong,
+            BrdfModel::CookTorrance {
+                roughness: 0.4,
+                metallic: 0.,
+            },
+        ] {
+            let m = Material::new().brdf(brdf);
+            let coated = m.clone().clearcoat(0.).clearcoat_roughness(0.5);
+            assert_eq!(
+                coated.lighting(&mut rng, Transform::new(), &light, p, eyev, normalv, 1.),
+                m.lighting(&mut rng, Transform::new(), &light, p, eyev, normalv, 1.)
+            );
+            assert_eq!(coated.clearcoat_reflectance(eyev, normalv), 0.);
+        }
+    }
+
+    #[test]
+    fn lighting_with_diffuse_and_specular_textures() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let m = Material::new()
+            .ambient(0.)
+            .diffuse_texture(Texture::stripe(Color::WHITE, Color::BLACK))
+            .specular_texture(Texture::stripe(Color::BLACK, Color::WHITE));
+        let eyev = vector3(0., 0., -1.);
+        let normalv = vector3(0., 0., -1.);
+        let light = Light::new(point3(0., 0., -10.), Color::new(1., 1., 1.));
+        let lighting = |rng: &mut SmallRng, m: Material, point| {
+            m.lighting(rng, Transform::new(), &light, point, eyev, normalv, 1.)
+        };
+
+        // Diffuse only in white stripes, specular only in black ones.
+        let diffuse_only = Material::new().ambient(0.).specular(0.);
+        let specular_only = Material::new().ambient(0.).diffuse(0.);
+        let p1 = point3(0.5, 0., 0.);
+        let p2 = point3(1.5, 0., 0.);
+        assert_eq!(
+            lighting(&mut rng, m.clone(), p1),
+            lighting(&mut rng, diffuse_only, p1)
+        );
+        assert_eq!(
+            lighting(&mut rng, m, p2),
+            lighting(&mut rng, specular_only, p2)
+        );
+    }
+
+    #[test]
+    fn the_reflectivity_with_a_reflective_texture() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let m = Material::new()
+            .reflective(0.8)
+            .reflective_texture(Texture::checkerboard_2d(Color::WHITE, Color::BLACK));
+        let t = Transform::new();
+        assert_eq!(m.reflective_at(&mut rng, t, point3(0.5, 0., 0.5)), 0.8);
+        assert_eq!(m.reflective_at(&mut rng, t, point3(1.5, 0., 0.5)), 0.);
+        assert_eq!(
+            Material::new()
+                .reflective(0.8)
+                .reflective_at(&mut rng, t, point3(1.5, 0., 0.5)),
+            0.8
+        );
+    }
+
+    #[test]
+    fn keyframes_are_interpolated_linearly() {
+        let k = Keyframes::new(&[(0., 0.), (1., 1.), (3., 0.)]).unwrap();
+        assert_eq!(k.value_at(-1.), 0.);
+        assert_eq!(k.value_at(0.), 0.);
+        assert_eq!(k.value_at(0.25), 0.25);
+        assert_eq!(k.value_at(1.), 1.);
+        assert_eq!(k.value_at(2.), 0.5);
+        assert_eq!(k.value_at(3.), 0.);
+        assert_eq!(k.value_at(10.), 0.);
+    }
+
+    #[test]
+    fn an_animated_material_at_a_given_time() {
+        let keyframes = Keyframes::new(&[(0., 0.1), (1., 0.9)]).unwrap();
+        let m = Material::new().animate(Property::Ambient, keyframes);
+        assert_approx_eq!(m.clone().at_time(0.).ambient, 0.1);
+        assert_approx_eq!(m.clone().at_time(0.5).ambient, 0.5);
+        assert_approx_eq!(m.clone().at_time(1.).ambient, 0.9);
+        assert_eq!(m.clone().at_time(0.5).diffuse, m.diffuse);
+    }
+
+    #[test]
+    fn invalid_keyframes_are_errors() {
+        let too_many = [(0., 0.); MAX_KEYFRAMES + 1];
+        assert!(Keyframes::new(&[]).is_err());
+        assert!(Keyframes::new(&too_many).is_err());
+        assert!(Keyframes::new(&too_many[..MAX_KEYFRAMES]).is_ok());
+        assert!(Keyframes::new(&[(1., 0.), (0., 1.)]).is_err());
+        assert!(Keyframes::new(&[(0., 0.), (f32::NAN, 1.)]).is_err());
+    }
+
+    #[test]
+    fn a_material_can_animate_several_properties() {
+        let rising = Keyframes::new(&[(0., 0.), (1., 1.)]).unwrap();
+        let falling = Keyframes::new(&[(0., 1.), (1., 0.)]).unwrap();
+        let m = Material::new()
+            .animate(Property::Ambient, rising)
+            .animate(Property::Diffuse, falling)
+            .animate(Property::Specular, rising)
+            .animate(Property::Specular, falling);
+
+        let m = m.at_time(0.25);
+        assert_approx_eq!(m.ambient, 0.25);
+        assert_approx_eq!(m.diffuse, 0.75);
+        assert_approx_eq!(m.specular, 0.75);
+    }
+
+    #[test]
+    fn a_material_without_animation_is_constant_over_time() {
+        let m = Material::new().ambient(0.3);
+        assert_eq!(m.clone().at_time(0.), m);
+        assert_eq!(m.clone().at_time(5.), m);
+    }
+
+    #[test]
+    fn a_lower_abbe_number_spreads_the_channel_refractive_indexes() {
+        let crown = Material::new().glass(Glass::BK7);
+        let flint = Material::new()
+            .refractive_index(1.5168)
+            .abbe(Glass::SF10.abbe());
+        let (crown_r, crown_g, crown_b) = crown.channel_refractive_indexes();
+        let (flint_r, flint_g, flint_b) = flint.channel_refractive_indexes();
+
+        assert_eq!(crown_g, 1.5168);
+        assert_eq!(flint_g, 1.5168);
+        assert!(crown_r < crown_g && crown_g < crown_b);
+        assert!(flint_b - flint_r > crown_b - crown_r);
+        assert_approx_eq!(crown_b - crown_r, 0.5168 / 64.17, 1e-5);
+    }
+
+    #[test]
+    fn an_abbe_number_of_zero_disables_dispersion() {
+        let m = Material::new().refractive_index(1.5).abbe(0.);
+        assert!(!m.is_dispersive());
+        assert_eq!(m.channel_refractive_indexes(), (1.5, 1.5, 1.5));
     }
 
     #[test]
@@ -159,11 +631,11 @@ mod tests {
         let result = m.lighting(
             &mut rng,
             Transform::new(),
-            light,
+            &light,
             position,
             eyev,
             normalv,
-            false,
+            1.,
         );
         assert_eq!(result, Color::new(1.9, 1.9, 1.9));
     }
@@ -183,11 +655,11 @@ mod tests {
         let result = m.lighting(
             &mut rng,
             Transform::new(),
-            light,
+            &light,
             position,
             eyev,
             normalv,
-            false,
+            1.,
         );
         assert_eq!(result, Color::new(1.0, 1.0, 1.0));
     }
@@ -203,11 +675,11 @@ mod tests {
         let result = m.lighting(
             &mut rng,
             Transform::new(),
-            light,
+            &light,
             position,
             eyev,
             normalv,
-            false,
+            1.,
         );
         assert_approx_eq!(result.r, 0.7364, 1e-5);
         assert_approx_eq!(result.g, 0.7364, 1e-5);
@@ -229,11 +701,11 @@ mod tests {
         let result = m.lighting(
             &mut rng,
             Transform::new(),
-            light,
+            &light,
             position,
             eyev,
             normalv,
-            false,
+            1.,
         );
         assert_approx_eq!(result.r, 1.6364, 1e-4);
         assert_approx_eq!(result.g, 1.6364, 1e-4);
@@ -251,17 +723,49 @@ mod tests {
         let result = m.lighting(
             &mut rng,
             Transform::new(),
-            light,
+            &light,
             position,
             eyev,
             normalv,
-            false,
+            1.,
         );
         assert_approx_eq!(result.r, 0.1, 1e-5);
         assert_approx_eq!(result.g, 0.1, 1e-5);
         assert_approx_eq!(result.b, 0.1, 1e-5);
     }
 
+    #[test]
+    fn lighting_with_an_angular_profile() {
+        // The light points straight down; the profile fades out by 45°.
+        let mut rng = SmallRng::seed_from_u64(0);
+        let m = Material::new().ambient(0.).specular(0.);
+        let eyev = vector3(0., 1., 0.);
+        let normalv = vector3(0., 1., 0.);
+        let plain = Light::new(point3(0., 10., 0.), Color::WHITE);
+        let profile = AngularProfile::new(&[(Angle::degrees(0.), 1.), (Angle::degrees(45.), 0.)]);
+        let profiled = plain.clone().profile(vector3(0., -1., 0.), profile);
+
+        let offset = 10. * Angle::degrees(22.5).to_radians().tan();
+        let examples = vec![(0., 1.), (offset, 0.5), (10., 0.)];
+        for (x, expected) in examples {
+            let position = point3(x, 0., 0.);
+            let mut lighting = |light| {
+                m.lighting(
+                    &mut rng,
+                    Transform::new(),
+                    light,
+                    position,
+                    eyev,
+                    normalv,
+                    1.,
+                )
+            };
+            let full = lighting(&plain);
+            let result = lighting(&profiled);
+            assert_approx_eq!(result.g, full.g * expected, 1e-5);
+        }
+    }
+
     #[test]
     fn lighting_with_the_surface_in_shadow() {
         let mut rng = SmallRng::seed_from_u64(0);
@@ -273,17 +777,41 @@ mod tests {
         let result = m.lighting(
             &mut rng,
             Transform::new(),
-            light,
+            &light,
             position,
             eyev,
             normalv,
-            true,
+            0.,
         );
         assert_approx_eq!(result.r, 0.1, 1e-5);
         assert_approx_eq!(result.g, 0.1, 1e-5);
         assert_approx_eq!(result.b, 0.1, 1e-5);
     }
 
+    #[test]
+    fn lighting_uses_the_light_intensity_to_attenuate_color() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let m = Material::new();
+        let eyev = vector3(0., 0., -1.);
+        let normalv = vector3(0., 0., -1.);
+        let light = Light::new(point3(0., 0., -10.), Color::new(1., 1., 1.));
+        let examples = vec![(1., 1.9), (0.5, 1.0), (0., 0.1)];
+        for (intensity, expected) in examples {
+            let result = m.lighting(
+                &mut rng,
+                Transform::new(),
+                &light,
+                point3(0., 0., 0.),
+                eyev,
+                normalv,
+                intensity,
+            );
+            assert_approx_eq!(result.r, expected, 1e-5);
+            assert_approx_eq!(result.g, expected, 1e-5);
+            assert_approx_eq!(result.b, expected, 1e-5);
+        }
+    }
+
     #[test]
     fn lighting_with_a_texture_applied() {
         let mut rng = SmallRng::seed_from_u64(0);
@@ -298,20 +826,20 @@ mod tests {
         let c1 = m.lighting(
             &mut rng,
             Transform::new(),
-            light,
+            &light,
             point3(0.9, 0., 0.),
             eyev,
             normalv,
-            false,
+            1.,
         );
         let c2 = m.lighting(
             &mut rng,
             Transform::new(),
-            light,
+            &light,
             point3(1.1, 0., 0.),
             eyev,
             normalv,
-            false,
+            1.,
         );
         assert_eq!(c1, Color::WHITE);
         assert_eq!(c2, Color::BLACK);
@@ -327,7 +855,7 @@ mod tests {
         let light = Light::new(point3(0., 0., -10.), Color::new(1., 1., 1.));
         let transform = Transform::new();
 
-        bencher.iter(|| m.lighting(&mut rng, transform, light, position, eyev, normalv, true));
+        bencher.iter(|| m.lighting(&mut rng, transform, &light, position, eyev, normalv, 0.));
     }
 
     #[bench]
@@ -340,6 +868,6 @@ mod tests {
         let light = Light::new(point3(0., 10., -10.), Color::new(1., 1., 1.));
         let transform = Transform::new();
 
-        bencher.iter(|| m.lighting(&mut rng, transform, light, position, eyev, normalv, false));
+        bencher.iter(|| m.lighting(&mut rng, transform, &light, position, eyev, normalv, 1.));
     }
 }
@@ -281,6 +281,7 @@ pub const I3: Matrix3 = Matrix3 {
 /// | z0 | z1 | z2 | z3 |
 /// | w0 | w1 | w2 | w3 |
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Matrix4 {
     pub x0: f32,
     pub y0: f32,
@@ -731,6 +732,17 @@ impl Matrix4 {
         self.determinant() != 0.
     }
 
+    /// Returns the inverse, or None if the matrix isn't invertible (or its
+    /// determinant isn't finite).
+    pub fn try_inverse(&self) -> Option<Matrix4> {
+        let det = self.determinant();
+        if det != 0. && det.is_finite() {
+            Some(self.inverse())
+        } else {
+            None
+        }
+    }
+
     pub fn inverse(&self) -> Matrix4 {
         debug_assert!(
             self.is_invertible(),
@@ -1073,6 +1085,7 @@ mod tests {
         );
         assert_eq!(a.determinant(), 0.);
         assert!(!a.is_invertible());
+        assert_eq!(a.try_inverse(), None);
     }
 
     #[test]
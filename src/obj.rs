@@ -0,0 +1,766 @@
+use crate::angle::*;
+use crate::constants::*;
+use crate::geometry::mesh::Mesh;
+use crate::geometry::*;
+use crate::material::*;
+use crate::object::*;
+use crate::transform::*;
+use crate::tuple::*;
+use std::collections::HashMap;
+
+/// A named group of triangles from an OBJ file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjGroup {
+    pub name: String,
+    pub triangles: Vec<Geometry>,
+}
+
+/// The geometry parsed from an OBJ file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjModel {
+    pub vertices: Vec<Tuple4>,
+    pub normals: Vec<Tuple4>,
+    /// The groups of faces, starting with the default group (named "") for
+    /// faces which precede any `g` line.
+    pub groups: Vec<ObjGroup>,
+    /// The number of lines which were not understood, and skipped.
+    pub ignored: usize,
+    /// The number of triangles dropped for having (next to) no area.
+    pub degenerate: usize,
+}
+
+impl ObjModel {
+    /// Returns the group with the given name, if any.
+    pub fn group(&self, name: &str) -> Option<&ObjGroup> {
+        self.groups.iter().find(|group| group.name == name)
+    }
+
+    /// Reverses every triangle, so that it faces the other way.
+    pub fn flip_normals(&mut self) {
+        for triangle in self.triangles_mut() {
+            *triangle = triangle.flipped();
+        }
+    }
+
+    /// Turns the triangles which face inward to face outward, returning how
+    /// many were flipped.
+    ///
+    /// Each triangle faces outward if its normal points away from the
+    /// centroid of the model. Seen from a triangle's vertices that can
+    /// disagree for triangles which pass close to the centroid, so the
+    /// vertices vote, and the majority decides.
+    pub fn orient_outward(&mut self) -> usize {
+        let points: Vec<Tuple4> = self
+            .groups
+            .iter()
+            .flat_map(|group| group.triangles.iter())
+            .flat_map(|triangle| {
+                let (p1, p2, p3) = vertices(triangle);
+                [p1, p2, p3]
+            })
+            .collect();
+        if points.is_empty() {
+            return 0;
+        }
+        let sum = points.iter().fold(vector3(0., 0., 0.), |sum, &p| {
+            sum + (p - point3(0., 0., 0.))
+        });
+        let centroid = point3(0., 0., 0.) + sum * (1. / points.len() as f32);
+
+        let mut flipped = 0;
+        for triangle in self.triangles_mut() {
+            let (p1, p2, p3) = vertices(triangle);
+            let normal = triangle.geometric_normal_at(p1, 0);
+            let outward = [p1, p2, p3]
+                .iter()
+                .filter(|&&p| (p - centroid).dot(normal) > 0.)
+                .count();
+            if outward < 2 {
+                *triangle = triangle.flipped();
+                flipped += 1;
+            }
+        }
+        flipped
+    }
+
+    /// Merges the triangles' vertices which lie within the (positive)
+    /// tolerance of one another into one position, the first of them found,
+    /// so that faces which should meet do. Triangles left without area are
+    /// dropped. Returns the number of vertices moved. The model's list of
+    /// vertices is left as parsed.
+    pub fn weld_vertices(&mut self, tolerance: f32) -> usize {
+        let cell = |p: Tuple4| {
+            let index = |x: f32| (x / tolerance).floor() as i64;
+            (index(p.x), index(p.y), index(p.z))
+        };
+        let mut cells: HashMap<(i64, i64, i64), Vec<Tuple4>> = HashMap::new();
+        let mut moved = 0;
+        let mut weld = |p: Tuple4| {
+            let (x, y, z) = cell(p);
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let nearby = cells.get(&(x + dx, y + dy, z + dz));
+                        let found = nearby.and_then(|points| {
+                            points.iter().find(|&&q| (q - p).magnitude() <= tolerance)
+                        });
+                        if let Some(&q) = found {
+                            if q != p {
+                                moved += 1;
+                            }
+                            return q;
+                        }
+                    }
+                }
+            }
+            cells.entry((x, y, z)).or_default().push(p);
+            p
+        };
+
+        for group in &mut self.groups {
+            let triangles = std::mem::take(&mut group.triangles);
+            group.triangles = triangles
+                .iter()
+                .map(|triangle| {
+                    let (p1, p2, p3) = vertices(triangle);
+                    with_vertices(triangle, weld(p1), weld(p2), weld(p3))
+                })
+                .collect();
+        }
+        self.drop_degenerate_triangles();
+        moved
+    }
+
+    /// Replaces the triangles' normals (if any) with ones averaged over the
+    /// faces around each vertex, weighted by their areas, making every
+    /// triangle smooth. Faces which meet at more than the given angle aren't
+    /// averaged together, so the edges between them stay sharp. Faces share
+    /// a vertex only if they share its exact position, so weld the vertices
+    /// first if they might not.
+    pub fn recompute_normals(&mut self, smooth_angle: impl Into<Angle>) {
+        let cos = smooth_angle.into().to_radians().cos();
+
+        // The normal of each face, scaled by twice its area.
+        let faces: Vec<Tuple4> = self
+            .groups
+            .iter()
+            .flat_map(|group| group.triangles.iter())
+            .map(|triangle| {
+                let (p1, p2, p3) = vertices(triangle);
+                (p3 - p1).cross(p2 - p1)
+            })
+            .collect();
+        let mut around: HashMap<_, Vec<usize>> = HashMap::new();
+        let triangles = self.groups.iter().flat_map(|group| group.triangles.iter());
+        for (i, triangle) in triangles.enumerate() {
+            let (p1, p2, p3) = vertices(triangle);
+            for &p in &[p1, p2, p3] {
+                around.entry(key(p)).or_default().push(i);
+            }
+        }
+
+        for (triangle, face) in self.triangles_mut().zip(&faces) {
+            let face = face.normalize();
+            let normal_at = |p: Tuple4| {
+                around[&key(p)]
+                    .iter()
+                    .map(|&j| faces[j])
+                    .filter(|&other| other.normalize().dot(face) >= cos)
+                    .fold(vector3(0., 0., 0.), |sum, other| sum + other)
+                    .normalize()
+            };
+            let (p1, p2, p3) = vertices(triangle);
+            *triangle = Geometry::smooth_triangle(
+                (p1, normal_at(p1)),
+                (p2, normal_at(p2)),
+                (p3, normal_at(p3)),
+            );
+        }
+    }
+
+    /// Returns every triangle in the model as one mesh, whose vertices are
+    /// shared by the triangles which meet at them. If any of the triangles
+    /// are smooth the mesh is too, and the flat ones keep their face normals
+    /// at their corners.
+    pub fn to_mesh(&self) -> Mesh {
+        let triangles = || self.groups.iter().flat_map(|group| group.triangles.iter());
+        let smooth =
+            triangles().any(|triangle| matches!(triangle, Geometry::SmoothTriangle { .. }));
+
+        let mut positions = vec![];
+        let mut normals = vec![];
+        let mut indices = vec![];
+        let mut shared = HashMap::new();
+        for triangle in triangles() {
+            let (p1, p2, p3) = vertices(triangle);
+            let (n1, n2, n3) = match *triangle {
+                Geometry::SmoothTriangle { n1, n2, n3, .. } => (n1, n2, n3),
+                _ => {
+                    let normal = triangle.geometric_normal_at(p1, 0);
+                    (normal, normal, normal)
+                }
+            };
+            let mut index = |p: Tuple4, n: Tuple4| {
+                let n = if smooth { n } else { vector3(0., 0., 0.) };
+                *shared.entry((key(p), key(n))).or_insert_with(|| {
+                    positions.push(p);
+                    normals.push(n);
+                    positions.len() as u32 - 1
+                })
+            };
+            indices.push([index(p1, n1), index(p2, n2), index(p3, n3)]);
+        }
+
+        let mesh = Mesh::new(positions, indices).unwrap();
+        if smooth {
+            mesh.normals(normals).unwrap()
+        } else {
+            mesh
+        }
+    }
+
+    /// Adds the triangle to the last group, unless it has no area.
+    fn push_triangle(&mut self, triangle: Geometry) {
+        if area(&triangle) < DEGENERATE_AREA_EPSILON {
+            self.degenerate += 1;
+        } else {
+            self.groups.last_mut().unwrap().triangles.push(triangle);
+        }
+    }
+
+    fn drop_degenerate_triangles(&mut self) {
+        for group in &mut self.groups {
+            let before = group.triangles.len();
+            group
+                .triangles
+                .retain(|triangle| area(triangle) >= DEGENERATE_AREA_EPSILON);
+            self.degenerate += before - group.triangles.len();
+        }
+    }
+
+    fn triangles_mut(&mut self) -> impl Iterator<Item = &mut Geometry> {
+        self.groups
+            .iter_mut()
+            .flat_map(|group| group.triangles.iter_mut())
+    }
+
+    /// Returns every triangle in the model as an object with the given
+    /// material.
+    pub fn to_objects(&self, material: Material) -> Vec<Object> {
+        self.groups
+            .iter()
+            .flat_map(|group| group.triangles.iter())
+            .map(|triangle| {
+                Object::new()
+                    .geometry(triangle.clone())
+                    .material(material.clone())
+            })
+            .collect()
+    }
+}
+
+/// Returns the vertices of a triangle.
+fn vertices(triangle: &Geometry) -> (Tuple4, Tuple4, Tuple4) {
+    match *triangle {
+        Geometry::Triangle { p1, p2, p3, .. } | Geometry::SmoothTriangle { p1, p2, p3, .. } => {
+            (p1, p2, p3)
+        }
+        _ => unreachable!("OBJ models contain only triangles"),
+    }
+}
+
+/// Returns the bits of the tuple's x, y and z, to tell which are the same.
+fn key(p: Tuple4) -> (u32, u32, u32) {
+    // Adding 0 turns -0 into 0, which would otherwise be a different key.
+    (
+        (p.x + 0.).to_bits(),
+        (p.y + 0.).to_bits(),
+        (p.z + 0.).to_bits(),
+    )
+}
+
+/// Returns the triangle with its vertices moved, keeping its normals.
+fn with_vertices(triangle: &Geometry, p1: Tuple4, p2: Tuple4, p3: Tuple4) -> Geometry {
+    match *triangle {
+        Geometry::SmoothTriangle { n1, n2, n3, .. } => {
+            Geometry::smooth_triangle((p1, n1), (p2, n2), (p3, n3))
+        }
+        _ => Geometry::triangle(p1, p2, p3),
+    }
+}
+
+/// Returns the area of a triangle.
+fn area(triangle: &Geometry) -> f32 {
+    let (p1, p2, p3) = vertices(triangle);
+    (p2 - p1).cross(p3 - p1).magnitude() / 2.
+}
+
+/// Parses the vertices, vertex normals, faces, and groups of an OBJ file.
+///
+/// Polygons with more than three vertices are split into a fan of triangles.
+/// Faces which specify vertex normals become smooth triangles, and those
+/// which don't become flat ones (see `ObjModel::recompute_normals` to smooth
+/// them). Triangles with no area are counted and dropped. Unrecognized lines
+/// are counted and skipped, but malformed vertex, normal, and face lines are
+/// errors.
+pub fn parse_obj(source: &str) -> Result<ObjModel, String> {
+    parse_obj_in_basis(source, Transform::new())
+}
+
+/// Parses an OBJ file like `parse_obj`, changing its vertices and normals to
+/// this crate's coordinates with the given basis, such as
+/// `Transform::new().z_up_to_y_up()` for models authored with +z up.
+/// Normals change by the inverse transpose, and faces are rewound if the
+/// basis mirrors them, so both still point outward.
+pub fn parse_obj_in_basis(source: &str, basis: Transform) -> Result<ObjModel, String> {
+    let mirrored = basis.local_to_world.determinant() < 0.;
+    let mut model = ObjModel {
+        vertices: vec![],
+        normals: vec![],
+        groups: vec![ObjGroup {
+            name: String::new(),
+            triangles: vec![],
+        }],
+        ignored: 0,
+        degenerate: 0,
+    };
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let mut tokens = line.split_whitespace();
+        let result = match tokens.next() {
+            Some("v") => parse_xyz(tokens)
+                .map(|(x, y, z)| model.vertices.push(basis.local_to_world * point3(x, y, z))),
+            Some("vn") => parse_xyz(tokens).map(|(x, y, z)| {
+                let mut normal = basis.world_to_local.transpose() * vector3(x, y, z);
+                normal.w = 0.;
+                model.normals.push(normal)
+            }),
+            Some("f") => parse_face(&model, tokens, mirrored).map(|triangles| {
+                for triangle in triangles {
+                    model.push_triangle(triangle);
+                }
+            }),
+            Some("g") => {
+                model.groups.push(ObjGroup {
+                    name: tokens.collect::<Vec<_>>().join(" "),
+                    triangles: vec![],
+                });
+                Ok(())
+            }
+            None => Ok(()),
+            Some(_) => {
+                model.ignored += 1;
+                Ok(())
+            }
+        };
+        result.map_err(|err| format!("line {}: {}", line_number, err))?;
+    }
+
+    Ok(model)
+}
+
+fn parse_xyz<'a, I: Iterator<Item = &'a str>>(mut tokens: I) -> Result<(f32, f32, f32), String> {
+    let mut next = || -> Result<f32, String> {
+        let token = tokens.next().ok_or("expected three coordinates")?;
+        token
+            .parse()
+            .map_err(|_| format!("invalid coordinate: {}", token))
+    };
+    Ok((next()?, next()?, next()?))
+}
+
+/// Parses a face into triangles, reversing its winding if `mirrored`.
+fn parse_face<'a, I: Iterator<Item = &'a str>>(
+    model: &ObjModel,
+    tokens: I,
+    mirrored: bool,
+) -> Result<Vec<Geometry>, String> {
+    let mut corners = tokens
+        .map(|token| parse_corner(model, token))
+        .collect::<Result<Vec<_>, _>>()?;
+    if corners.len() < 3 {
+        return Err("a face needs at least three vertices".to_string());
+    }
+    if mirrored {
+        corners.reverse();
+    }
+
+    let smooth = corners.iter().all(|(_, normal)| normal.is_some());
+    let triangles = (1..corners.len() - 1).map(|i| {
+        let (a, b, c) = (corners[0], corners[i], corners[i + 1]);
+        if smooth {
+            Geometry::smooth_triangle(
+                (a.0, a.1.unwrap()),
+                (b.0, b.1.unwrap()),
+                (c.0, c.1.unwrap()),
+            )
+        } else {
+            Geometry::triangle(a.0, b.0, c.0)
+        }
+    });
+
+    Ok(triangles.collect())
+}
+
+/// Parses a face corner in the form `v`, `v/vt`, `v//vn`, or `v/vt/vn`,
+/// returning the vertex and its normal (if any). Texture coordinates are
+/// ignored.
+fn parse_corner(model: &ObjModel, token: &str) -> Result<(Tuple4, Option<Tuple4>), String> {
+    let mut indexes = token.split('/');
+    let vertex = lookup(&model.vertices, indexes.next().unwrap_or(""), "vertex")?;
+    let normal = match indexes.nth(1) {
+        Some(index) if !index.is_empty() => Some(lookup(&model.normals, index, "normal")?),
+        _ => None,
+    };
+    Ok((vertex, normal))
+}
+
+/// Returns the element at the given 1-based index, where negative indexes
+/// count back from the last element.
+fn lookup(elements: &[Tuple4], index: &str, name: &str) -> Result<Tuple4, String> {
+    let parsed: isize = index
+        .parse()
+        .map_err(|_| format!("invalid {} index: {}", name, index))?;
+    let i = if parsed < 0 {
+        elements.len() as isize + parsed
+    } else {
+        parsed - 1
+    };
+    if i < 0 || i as usize >= elements.len() {
+        return Err(format!("{} index out of range: {}", name, index));
+    }
+    Ok(elements[i as usize])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn ignoring_unrecognized_lines() {
+        let gibberish = "There was a young lady named Bright
+who traveled much faster than light.
+She set out one day
+in a relative way,
+and came back the previous night.";
+        let model = parse_obj(gibberish).unwrap();
+        assert_eq!(model.ignored, 5);
+        assert!(model.vertices.is_empty());
+    }
+
+    #[test]
+    fn vertex_records() {
+        let model = parse_obj("v -1 1 0\nv -1.0000 0.5000 0.0000\nv 1 0 0\nv 1 1 0\n").unwrap();
+        assert_eq!(
+            model.vertices,
+            vec![
+                point3(-1., 1., 0.),
+                point3(-1., 0.5, 0.),
+                point3(1., 0., 0.),
+                point3(1., 1., 0.),
+            ]
+        );
+    }
+
+    #[test]
+    fn parsing_triangle_faces() {
+        let source = "v -1 1 0\nv -1 0 0\nv 1 0 0\nv 1 1 0\n\nf 1 2 3\nf 1 3 4\n";
+        let model = parse_obj(source).unwrap();
+        let triangles = &model.groups[0].triangles;
+        let v = &model.vertices;
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(vertices(&triangles[0]), (v[0], v[1], v[2]));
+        assert_eq!(vertices(&triangles[1]), (v[0], v[2], v[3]));
+        assert!(matches!(triangles[0], Geometry::Triangle { .. }));
+    }
+
+    #[test]
+    fn triangulating_polygons() {
+        let source = "v -1 1 0\nv -1 0 0\nv 1 0 0\nv 1 1 0\nv 0 2 0\n\nf 1 2 3 4 5\n";
+        let model = parse_obj(source).unwrap();
+        let triangles = &model.groups[0].triangles;
+        let v = &model.vertices;
+        assert_eq!(triangles.len(), 3);
+        assert_eq!(vertices(&triangles[0]), (v[0], v[1], v[2]));
+        assert_eq!(vertices(&triangles[1]), (v[0], v[2], v[3]));
+        assert_eq!(vertices(&triangles[2]), (v[0], v[3], v[4]));
+    }
+
+    #[test]
+    fn triangles_in_groups() {
+        let source = "v -1 1 0\nv -1 0 0\nv 1 0 0\nv 1 1 0\n\
+                      g FirstGroup\nf 1 2 3\ng SecondGroup\nf 1 3 4\n";
+        let model = parse_obj(source).unwrap();
+        let v = &model.vertices;
+        let first = model.group("FirstGroup").unwrap();
+        let second = model.group("SecondGroup").unwrap();
+        assert!(model.group("").unwrap().triangles.is_empty());
+        assert_eq!(vertices(&first.triangles[0]), (v[0], v[1], v[2]));
+        assert_eq!(vertices(&second.triangles[0]), (v[0], v[2], v[3]));
+        assert_eq!(model.to_objects(Material::new()).len(), 2);
+    }
+
+    #[test]
+    fn vertex_normal_records() {
+        let model = parse_obj("vn 0 0 1\nvn 0.707 0 -0.707\nvn 1 2 3\n").unwrap();
+        assert_eq!(
+            model.normals,
+            vec![
+                vector3(0., 0., 1.),
+                vector3(0.707, 0., -0.707),
+                vector3(1., 2., 3.),
+            ]
+        );
+    }
+
+    #[test]
+    fn faces_with_normals() {
+        let source = "v 0 1 0\nv -1 0 0\nv 1 0 0\n\
+                      vn -1 0 0\nvn 1 0 0\nvn 0 1 0\n\
+                      f 1//3 2//1 3//2\nf 1/0/3 2/102/1 3/14/2\n";
+        let model = parse_obj(source).unwrap();
+        let triangles = &model.groups[0].triangles;
+        let expected = Geometry::smooth_triangle(
+            (model.vertices[0], model.normals[2]),
+            (model.vertices[1], model.normals[0]),
+            (model.vertices[2], model.normals[1]),
+        );
+        assert_eq!(triangles[0], expected);
+        assert_eq!(triangles[1], expected);
+    }
+
+    #[test]
+    fn negative_indexes_count_from_the_end() {
+        let model = parse_obj("v -1 1 0\nv -1 0 0\nv 1 0 0\nf -3 -2 -1\n").unwrap();
+        let v = &model.vertices;
+        assert_eq!(vertices(&model.groups[0].triangles[0]), (v[0], v[1], v[2]));
+    }
+
+    #[test]
+    fn malformed_records_are_errors() {
+        assert!(parse_obj("v 1 two 3\n").is_err());
+        assert!(parse_obj("v 1 2\n").is_err());
+        assert!(parse_obj("v 0 0 0\nv 1 0 0\nf 1 2\n").is_err());
+        assert_eq!(
+            parse_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n"),
+            Err("line 4: vertex index out of range: 4".to_string())
+        );
+    }
+
+    #[test]
+    fn orienting_a_model_outward() {
+        // A tetrahedron, with one of its faces wound inward.
+        let source = "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 0 0 1
+f 1 2 3
+f 1 4 2
+f 1 3 4
+f 2 3 4
+";
+        let mut model = parse_obj(source).unwrap();
+        assert_eq!(model.orient_outward(), 1);
+        assert_eq!(model.orient_outward(), 0);
+
+        model.flip_normals();
+        assert_eq!(model.orient_outward(), 4);
+    }
+
+    #[test]
+    fn z_up_models_in_y_up_basis() {
+        let z_up = "v 1 2 3\nv 0 0 1\nv 2 0 0\nvn 0 0 1\nvn 0 1 0\nf 1//1 2//2 3//1\n";
+        let y_up = "v 1 3 -2\nv 0 1 0\nv 2 0 0\nvn 0 1 0\nvn 0 0 -1\nf 1//1 2//2 3//1\n";
+        let converted = parse_obj_in_basis(z_up, Transform::new().z_up_to_y_up()).unwrap();
+        let expected = parse_obj(y_up).unwrap();
+        for (a, b) in converted.vertices.iter().zip(&expected.vertices) {
+            assert_approx_eq!(a.x, b.x, 1e-5);
+            assert_approx_eq!(a.y, b.y, 1e-5);
+            assert_approx_eq!(a.z, b.z, 1e-5);
+        }
+        for (a, b) in converted.normals.iter().zip(&expected.normals) {
+            assert_approx_eq!(a.x, b.x, 1e-5);
+            assert_approx_eq!(a.y, b.y, 1e-5);
+            assert_approx_eq!(a.z, b.z, 1e-5);
+            assert_eq!(a.w, 0.);
+        }
+    }
+
+    #[test]
+    fn mirroring_basis_keeps_normals_outward() {
+        // An octahedron wound outward, with a vertex normal at each corner.
+        let source = "\
+v 1 0 0
+v -1 0 0
+v 0 1 0
+v 0 -1 0
+v 0 0 1
+v 0 0 -1
+vn 1 0 0
+vn -1 0 0
+vn 0 1 0
+vn 0 -1 0
+vn 0 0 1
+vn 0 0 -1
+f 1 5 3
+f 3 5 2
+f 2 5 4
+f 4 5 1
+f 1 3 6
+f 3 2 6
+f 2 4 6
+f 4 1 6
+";
+        assert_eq!(parse_obj(source).unwrap().orient_outward(), 0);
+        let basis = Transform::new().convert_handedness().z_up_to_y_up();
+        let mut model = parse_obj_in_basis(source, basis).unwrap();
+        for (vertex, normal) in model.vertices.iter().zip(&model.normals) {
+            assert!(normal.dot(*vertex - point3(0., 0., 0.)) > 0.);
+        }
+        assert_eq!(model.orient_outward(), 0);
+    }
+
+    /// Returns the vertex normals of a smooth triangle.
+    fn normals(triangle: &Geometry) -> (Tuple4, Tuple4, Tuple4) {
+        match *triangle {
+            Geometry::SmoothTriangle { n1, n2, n3, .. } => (n1, n2, n3),
+            _ => panic!("{:?} isn't smooth", triangle),
+        }
+    }
+
+    fn assert_same_direction(a: Tuple4, b: Tuple4) {
+        assert!(a.dot(b) > 0.9999, "{:?} vs {:?}", a, b);
+    }
+
+    #[test]
+    fn zero_area_faces_are_dropped() {
+        // The second face's corners lie on a line, and the third's normals
+        // would have been NaN.
+        let source = "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 2 0 0
+vn 0 0 1
+f 1 2 3
+f 1 2 4
+f 1//1 4//1 4//1
+";
+        let mut model = parse_obj(source).unwrap();
+        assert_eq!(model.degenerate, 2);
+        assert_eq!(model.groups[0].triangles.len(), 1);
+
+        model.recompute_normals(Angle::degrees(30.));
+        let (n1, n2, n3) = normals(&model.groups[0].triangles[0]);
+        for n in [n1, n2, n3].iter() {
+            assert!(n.x.is_finite() && n.y.is_finite() && n.z.is_finite());
+        }
+    }
+
+    #[test]
+    fn welding_nearby_vertices() {
+        let source = "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 1.000001 0 0
+v 1 1 0
+v 0.5 0 0
+v 0.5000001 0 0
+f 1 2 3
+f 4 5 3
+f 6 7 3
+";
+        let mut model = parse_obj(source).unwrap();
+        assert_eq!(model.weld_vertices(1e-4), 2);
+        // The last face collapses.
+        assert_eq!(model.degenerate, 1);
+        let triangles = &model.groups[0].triangles;
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(vertices(&triangles[1]).0, point3(1., 0., 0.));
+    }
+
+    #[test]
+    fn recomputed_normals_keep_a_cubes_faces_flat() {
+        let source = "\
+v -1 -1 -1
+v 1 -1 -1
+v 1 1 -1
+v -1 1 -1
+v -1 -1 1
+v 1 -1 1
+v 1 1 1
+v -1 1 1
+f 1 4 3 2
+f 5 6 7 8
+f 1 2 6 5
+f 4 8 7 3
+f 1 5 8 4
+f 2 3 7 6
+";
+        let mut model = parse_obj(source).unwrap();
+        model.orient_outward();
+        model.recompute_normals(Angle::degrees(30.));
+
+        let triangles = &model.groups[0].triangles;
+        assert_eq!(triangles.len(), 12);
+        for triangle in triangles {
+            let (p1, _, _) = vertices(triangle);
+            let face = triangle.geometric_normal_at(p1, 0);
+            let (n1, n2, n3) = normals(triangle);
+            for &n in &[n1, n2, n3] {
+                assert_same_direction(n, face);
+            }
+        }
+    }
+
+    /// An OBJ sphere of radius 1 without normals, divided into the given
+    /// number of bands of latitude and of longitude.
+    fn uv_sphere_obj(bands: usize) -> String {
+        use std::f32::consts::PI;
+        let mut obj = String::new();
+        for i in 0..=bands {
+            let theta = PI * i as f32 / bands as f32;
+            for j in 0..bands {
+                let phi = 2. * PI * j as f32 / bands as f32;
+                let (x, y, z) = (
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                obj += &format!("v {} {} {}\n", x, y, z);
+            }
+        }
+        let index = |i: usize, j: usize| i * bands + j % bands + 1;
+        for i in 0..bands {
+            for j in 0..bands {
+                let (a, b) = (index(i, j), index(i, j + 1));
+                let (c, d) = (index(i + 1, j), index(i + 1, j + 1));
+                obj += &format!("f {} {} {} {}\n", a, b, d, c);
+            }
+        }
+        obj
+    }
+
+    #[test]
+    fn recomputed_normals_smooth_a_sphere() {
+        let mut model = parse_obj(&uv_sphere_obj(16)).unwrap();
+        model.weld_vertices(1e-5);
+        model.orient_outward();
+        model.recompute_normals(Angle::degrees(30.));
+
+        // The normals point away from the center, as on a true sphere, so
+        // they differ across each face.
+        for triangle in &model.groups[0].triangles {
+            let (p1, p2, p3) = vertices(triangle);
+            let (n1, n2, n3) = normals(triangle);
+            for &(p, n) in &[(p1, n1), (p2, n2), (p3, n3)] {
+                assert!(n.dot((p - point3(0., 0., 0.)).normalize()) > 0.98);
+            }
+            assert!(n1 != n2 || n2 != n3);
+        }
+    }
+}
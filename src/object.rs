@@ -1,14 +1,24 @@
+use crate::bounds::*;
 use crate::geometry::*;
 use crate::material::*;
 use crate::transform::*;
 
 pub type ObjectId = usize;
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct Object {
     pub transform: Transform,
     pub geometry: Geometry,
     pub material: Material,
     pub parent: Option<ObjectId>,
+    /// How present the object is, from 0 (absent) to 1 (fully present).
+    /// Partially visible objects are skipped by a matching fraction of rays,
+    /// so they dissolve smoothly when many samples are averaged.
+    pub visibility: f32,
+    /// Whether the object blocks light from reaching other objects.
+    pub casts_shadow: bool,
+    /// The render layer the object belongs to (see `RenderLayer`).
+    pub layer: u8,
 }
 
 impl Object {
@@ -18,6 +28,9 @@ impl Object {
             geometry: Geometry::sphere(),
             material: Material::new(),
             parent: None,
+            visibility: 1.,
+            casts_shadow: true,
+            layer: 0,
         }
     }
 
@@ -40,11 +53,34 @@ impl Object {
         self.parent = Some(parent);
         self
     }
+
+    pub fn visibility(mut self, visibility: f32) -> Self {
+        self.visibility = visibility;
+        self
+    }
+
+    pub fn casts_shadow(mut self, casts_shadow: bool) -> Self {
+        self.casts_shadow = casts_shadow;
+        self
+    }
+
+    pub fn layer(mut self, layer: u8) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    /// Returns the box enclosing the object in world space.
+    pub fn bounds(&self) -> Bounds3 {
+        self.geometry
+            .bounds()
+            .transform(self.transform.local_to_world)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tuple::*;
 
     #[test]
     fn an_objects_default_transformation() {
@@ -67,7 +103,30 @@ mod tests {
     #[test]
     fn an_object_may_be_assigned_a_material() {
         let m = Material::new().ambient(1.);
-        let s = Object::new().material(m);
+        let s = Object::new().material(m.clone());
         assert_eq!(s.material, m);
     }
+
+    #[test]
+    fn an_object_is_fully_visible_by_default() {
+        assert_eq!(Object::new().visibility, 1.);
+        assert_eq!(Object::new().visibility(0.25).visibility, 0.25);
+    }
+
+    #[test]
+    fn an_object_is_in_layer_0_by_default() {
+        assert_eq!(Object::new().layer, 0);
+        assert_eq!(Object::new().layer(3).layer, 3);
+    }
+
+    #[test]
+    fn the_world_bounds_of_an_object() {
+        let o = Object::new()
+            .geometry(Geometry::cube())
+            .transform(Transform::new().translate(1., 2., 3.).scale(1., 2., 3.));
+        assert_eq!(
+            o.bounds(),
+            Bounds3::new(point3(0., 0., 0.), point3(2., 4., 6.))
+        );
+    }
 }
@@ -1,50 +1,220 @@
 use crate::canvas::*;
 
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+/// The longest line allowed in a PPM file.
+const MAX_LINE_LENGTH: usize = 70;
+
+/// Notes on how an image was made, such as the settings it was rendered
+/// with. They're stored as `# key: value` comments after the magic number,
+/// which PPM readers skip.
+pub type Metadata = BTreeMap<String, String>;
+
 pub fn canvas_to_ppm(canvas: Canvas) -> String {
-    let mut result = String::new();
-    result.push_str(&format!(
-        "P3
-{} {}
-255
-",
-        canvas.width, canvas.height
-    ));
+    let mut result = vec![];
+    write_ppm(&canvas, &mut result).unwrap();
+    String::from_utf8(result).unwrap()
+}
+
+/// Writes a canvas as a plain (P3) PPM image, a row at a time, without
+/// building the whole file in memory.
+pub fn write_ppm<W: Write>(canvas: &Canvas, writer: &mut W) -> io::Result<()> {
+    write_ppm_with_metadata(canvas, &Metadata::new(), writer)
+}
 
+/// Like `write_ppm`, recording the metadata in the header.
+pub fn write_ppm_with_metadata<W: Write>(
+    canvas: &Canvas,
+    metadata: &Metadata,
+    writer: &mut W,
+) -> io::Result<()> {
+    write_header(writer, "P3", canvas, metadata)?;
+
+    let mut line = Vec::with_capacity(MAX_LINE_LENGTH + 1);
+    let mut row = Vec::with_capacity(4 * 3 * canvas.width);
     for y in 0..canvas.height {
-        let row: Vec<_> = canvas.data[3 * (y * canvas.width)..3 * ((y + 1) * canvas.width)]
-            .iter()
-            .map(|c| c.to_string())
-            .collect();
-
-        // PPM files need to be wrapped to 70 chars.
-        let mut chars_written = 0;
-        for c in row {
-            let len = 1 + c.len();
-            if len + chars_written > 70 {
-                result.push_str("\n");
-                chars_written = 0;
-            }
+        let samples = &canvas.data[3 * (y * canvas.width)..3 * ((y + 1) * canvas.width)];
+        row.clear();
+        line.clear();
+        for &sample in samples {
+            let mut digits = [0; 3];
+            let digits = format_sample(canvas.quantize(sample), &mut digits);
 
-            if chars_written == 0 {
-                result.push_str(&c);
-                chars_written += len - 1;
-            } else {
-                result.push_str(" ");
-                result.push_str(&c);
-                chars_written += len;
+            // PPM files need to be wrapped to 70 chars.
+            if !line.is_empty() && line.len() + 1 + digits.len() > MAX_LINE_LENGTH {
+                row.extend_from_slice(&line);
+                row.push(b'\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(b' ');
             }
+            line.extend_from_slice(digits);
         }
-
-        result.push_str("\n");
+        row.extend_from_slice(&line);
+        row.push(b'\n');
+        writer.write_all(&row)?;
     }
 
+    Ok(())
+}
+
+pub fn canvas_to_ppm_p6(canvas: Canvas) -> Vec<u8> {
+    let mut result = Vec::with_capacity(canvas.data.len() + 32);
+    write_ppm_p6(&canvas, &mut result).unwrap();
     result
 }
 
+/// Writes a canvas as a binary (P6) PPM image: the header followed by the
+/// quantized RGB samples, a row at a time.
+pub fn write_ppm_p6<W: Write>(canvas: &Canvas, writer: &mut W) -> io::Result<()> {
+    write_ppm_p6_with_metadata(canvas, &Metadata::new(), writer)
+}
+
+/// Like `write_ppm_p6`, recording the metadata in the header.
+pub fn write_ppm_p6_with_metadata<W: Write>(
+    canvas: &Canvas,
+    metadata: &Metadata,
+    writer: &mut W,
+) -> io::Result<()> {
+    write_header(writer, "P6", canvas, metadata)?;
+
+    let mut row = Vec::with_capacity(3 * canvas.width);
+    for samples in canvas.data.chunks(3 * canvas.width.max(1)) {
+        row.clear();
+        row.extend(samples.iter().map(|&sample| canvas.quantize(sample)));
+        writer.write_all(&row)?;
+    }
+    Ok(())
+}
+
+/// Writes the magic number, the metadata, and the image's size and maximum
+/// value. Keys can't be empty or hold colons or line breaks, and values
+/// can't hold line breaks.
+fn write_header<W: Write>(
+    writer: &mut W,
+    magic: &str,
+    canvas: &Canvas,
+    metadata: &Metadata,
+) -> io::Result<()> {
+    writeln!(writer, "{}", magic)?;
+    for (key, value) in metadata {
+        if key.is_empty() || key.contains([':', '\n', '\r']) || value.contains(['\n', '\r']) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("can't store metadata as a comment: {:?}", key),
+            ));
+        }
+        writeln!(writer, "# {}: {}", key, value)?;
+    }
+    write!(writer, "{} {}\n255\n", canvas.width, canvas.height)
+}
+
+/// Reads the metadata from the header of a PPM image (plain or binary),
+/// leaving the pixels unread.
+pub fn read_metadata(path: impl AsRef<Path>) -> io::Result<Metadata> {
+    parse_metadata(BufReader::new(File::open(path)?))
+}
+
+/// Like `read_metadata`, reading the image from a reader.
+pub fn parse_metadata<R: BufRead>(mut reader: R) -> io::Result<Metadata> {
+    let mut metadata = Metadata::new();
+    // The header ends with its fourth field (the magic number, width,
+    // height, and maximum value), after which binary pixels may follow.
+    let mut fields = 0;
+    let mut line = vec![];
+    while fields < 4 {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        let line = String::from_utf8_lossy(&line);
+        let (content, comment) = match line.split_once('#') {
+            Some((content, comment)) => (content, Some(comment)),
+            None => (&*line, None),
+        };
+        fields += content.split_whitespace().count();
+        if let Some((key, value)) = comment.and_then(|comment| comment.split_once(':')) {
+            metadata.insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    Ok(metadata)
+}
+
+/// Writes the decimal digits of a sample into the buffer, returning them.
+fn format_sample(sample: u8, buffer: &mut [u8; 3]) -> &[u8] {
+    let mut start = buffer.len();
+    let mut value = sample;
+    loop {
+        start -= 1;
+        buffer[start] = b'0' + value % 10;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+    &buffer[start..]
+}
+
+/// The most pixels a PPM file may declare (8192 × 8192), which bounds how
+/// much memory reading one can allocate.
+const MAX_PPM_PIXELS: usize = 1 << 26;
+
+/// Parses a plain (P3) PPM image into a canvas.
+///
+/// Comments are ignored, and samples are rescaled from the file's maximum
+/// value to the canvas's 0-1 range. Images with no pixels, or more than
+/// 8192 × 8192 of them, are errors.
+pub fn canvas_from_ppm(ppm: &str) -> Result<Canvas, String> {
+    let mut tokens = ppm
+        .lines()
+        .map(|line| line.split('#').next().unwrap())
+        .flat_map(|line| line.split_whitespace());
+
+    match tokens.next() {
+        Some("P3") => {}
+        Some(magic) => return Err(format!("unsupported PPM format: {}", magic)),
+        None => return Err("empty PPM file".to_string()),
+    }
+
+    let mut next_number = |name: &str| -> Result<usize, String> {
+        let token = tokens
+            .next()
+            .ok_or_else(|| format!("PPM file ended while reading {}", name))?;
+        token
+            .parse()
+            .map_err(|_| format!("invalid {} in PPM file: {}", name, token))
+    };
+
+    let width = next_number("width")?;
+    let height = next_number("height")?;
+    match width.checked_mul(height) {
+        Some(0) => return Err(format!("empty PPM image: {}x{}", width, height)),
+        Some(pixels) if pixels <= MAX_PPM_PIXELS => {}
+        _ => return Err(format!("PPM image is too large: {}x{}", width, height)),
+    }
+    let max_value = next_number("maximum value")?;
+    if max_value == 0 || max_value > 255 {
+        return Err(format!("unsupported PPM maximum value: {}", max_value));
+    }
+
+    let mut canvas = Canvas::new(width, height);
+    for i in 0..canvas.data.len() {
+        let value = next_number("pixel data")?.min(max_value);
+        canvas.data[i] = value as f32 / max_value as f32;
+    }
+
+    Ok(canvas)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::color::*;
+    use test::Bencher;
 
     #[test]
     fn constructing_the_ppm_header() {
@@ -99,10 +269,213 @@ mod tests {
         ))
     }
 
+    #[test]
+    fn reading_a_ppm_file_back_into_a_canvas() {
+        let mut c = Canvas::new(10, 2);
+        c.set_color(0, 0, Color::new(1.0, 0.8, 0.6));
+        c.set_color(9, 1, Color::new(0.2, 0.4, 0.0));
+        let expected = c.to_rgb8();
+
+        let canvas = canvas_from_ppm(&canvas_to_ppm(c)).unwrap();
+        assert_eq!(canvas.width, 10);
+        assert_eq!(canvas.height, 2);
+        assert_eq!(canvas.to_rgb8(), expected);
+    }
+
+    #[test]
+    fn reading_a_ppm_file_with_comments_and_a_different_scale() {
+        let ppm = "P3
+# a comment
+2 1
+100 # the maximum value
+100 50 0   0 0 100
+";
+        let canvas = canvas_from_ppm(ppm).unwrap();
+        assert_eq!(canvas.data, vec![1., 0.5, 0., 0., 0., 1.]);
+    }
+
+    #[test]
+    fn reading_an_invalid_ppm_file() {
+        assert!(canvas_from_ppm("").is_err());
+        assert!(canvas_from_ppm("P6\n1 1\n255\n").is_err());
+        assert!(canvas_from_ppm("P3\n2 1\n255\n0 0 0\n").is_err());
+        assert!(canvas_from_ppm("P3\n1 x\n255\n0 0 0\n").is_err());
+        assert!(canvas_from_ppm("P3\n0 0\n255\n").is_err());
+        assert!(canvas_from_ppm("P3\n4 0\n255\n").is_err());
+        assert!(canvas_from_ppm("P3\n100000 100000\n255\n0 0 0\n").is_err());
+        let huge = format!("P3\n{} 2\n255\n0 0 0\n", usize::MAX);
+        assert!(canvas_from_ppm(&huge).is_err());
+    }
+
+    fn sample_metadata() -> Metadata {
+        [
+            ("scene", "room"),
+            ("resolution", "10x2"),
+            ("seed", "42"),
+            ("render-time", "1.500s"),
+        ]
+        .iter()
+        .map(|&(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn writing_and_reading_back_metadata() {
+        let mut canvas = Canvas::new(10, 2);
+        canvas.set_color(3, 1, Color::new(1.0, 0.8, 0.6));
+        let metadata = sample_metadata();
+
+        let mut p3 = vec![];
+        write_ppm_with_metadata(&canvas, &metadata, &mut p3).unwrap();
+        assert!(p3.starts_with(b"P3\n# render-time: 1.500s\n# resolution: 10x2\n"));
+        assert_eq!(parse_metadata(&p3[..]).unwrap(), metadata);
+
+        // Readers which skip comments still load the pixels.
+        let read = canvas_from_ppm(std::str::from_utf8(&p3).unwrap()).unwrap();
+        assert_eq!(read.to_rgb8(), canvas.to_rgb8());
+
+        // Binary pixels, even those which look like text, stay unread.
+        canvas.set_color(0, 0, Color::new(35. / 255., 10. / 255., 0.));
+        let path = std::env::temp_dir().join(format!("metadata-{}.ppm", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        write_ppm_p6_with_metadata(&canvas, &metadata, &mut file).unwrap();
+        drop(file);
+        let read = read_metadata(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.unwrap(), metadata);
+
+        assert_eq!(
+            parse_metadata(&canvas_to_ppm_p6(canvas)[..]).unwrap(),
+            Metadata::new()
+        );
+    }
+
+    #[test]
+    fn metadata_which_cant_be_a_comment_is_an_error() {
+        let canvas = Canvas::new(1, 1);
+        for (key, value) in [("a:b", "c"), ("", "c"), ("a", "b\nc")] {
+            let mut metadata = Metadata::new();
+            metadata.insert(key.to_string(), value.to_string());
+            let result = write_ppm_with_metadata(&canvas, &metadata, &mut vec![]);
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
     #[test]
     fn ppm_files_are_terminated_by_a_newline() {
         let c = Canvas::new(5, 3);
         let ppm = canvas_to_ppm(c);
         assert!(ppm.ends_with('\n'));
     }
+
+    /// The original implementation, built up in one String, which the
+    /// streaming writer must match byte for byte.
+    fn reference_ppm(canvas: &Canvas) -> String {
+        let mut result = String::new();
+        result.push_str(&format!("P3\n{} {}\n255\n", canvas.width, canvas.height));
+
+        let samples = canvas.to_rgb8();
+        for y in 0..canvas.height {
+            let row: Vec<_> = samples[3 * (y * canvas.width)..3 * ((y + 1) * canvas.width)]
+                .iter()
+                .map(|c| c.to_string())
+                .collect();
+
+            let mut chars_written = 0;
+            for c in row {
+                let len = 1 + c.len();
+                if len + chars_written > 70 {
+                    result.push('\n');
+                    chars_written = 0;
+                }
+
+                if chars_written == 0 {
+                    result.push_str(&c);
+                    chars_written += len - 1;
+                } else {
+                    result.push(' ');
+                    result.push_str(&c);
+                    chars_written += len;
+                }
+            }
+
+            result.push('\n');
+        }
+
+        result
+    }
+
+    fn large_canvas() -> Canvas {
+        let mut c = Canvas::new(1000, 1000);
+        for (i, sample) in c.data.iter_mut().enumerate() {
+            *sample = (i * 7 % 256) as f32 / 255.;
+        }
+        c
+    }
+
+    #[test]
+    fn streaming_matches_building_the_whole_file() {
+        let mut varied = Canvas::new(37, 5);
+        for (i, sample) in varied.data.iter_mut().enumerate() {
+            *sample = (i * 37 % 256) as f32 / 255.;
+        }
+        let mut pale = Canvas::new(10, 2);
+        for x in 0..pale.width {
+            for y in 0..pale.height {
+                pale.set_color(x, y, Color::new(1.0, 0.8, 0.6));
+            }
+        }
+        let canvases = vec![Canvas::new(5, 3), pale, varied, Canvas::new(0, 2)];
+
+        for canvas in canvases {
+            let mut streamed = vec![];
+            write_ppm(&canvas, &mut streamed).unwrap();
+            assert_eq!(String::from_utf8(streamed).unwrap(), reference_ppm(&canvas));
+            assert_eq!(canvas_to_ppm(canvas.clone()), reference_ppm(&canvas));
+        }
+    }
+
+    #[bench]
+    fn bench_write_ppm_1000x1000(b: &mut Bencher) {
+        let canvas = large_canvas();
+        b.iter(|| write_ppm(&canvas, &mut io::sink()).unwrap());
+    }
+
+    #[bench]
+    fn bench_reference_ppm_1000x1000(b: &mut Bencher) {
+        let canvas = large_canvas();
+        b.iter(|| reference_ppm(&canvas));
+    }
+
+    #[test]
+    fn constructing_a_binary_ppm() {
+        let mut c = Canvas::new(5, 3);
+        c.set_color(2, 1, Color::new(1.0, 0.5, 0.0));
+        let ppm = canvas_to_ppm_p6(c);
+
+        let header = b"P6\n5 3\n255\n";
+        assert!(ppm.starts_with(header));
+        let payload = &ppm[header.len()..];
+        assert_eq!(payload.len(), 3 * 5 * 3);
+        let offset = 3 * (5 + 2);
+        assert_eq!(&payload[offset..offset + 3], &[255, 128, 0]);
+        assert_eq!(payload.iter().filter(|&&sample| sample != 0).count(), 2);
+    }
+
+    #[test]
+    fn an_empty_binary_ppm_has_only_a_header() {
+        let mut ppm = vec![];
+        write_ppm_p6(&Canvas::new(0, 0), &mut ppm).unwrap();
+        assert_eq!(ppm, b"P6\n0 0\n255\n");
+    }
+
+    #[bench]
+    fn bench_write_ppm_p6_1000x1000(b: &mut Bencher) {
+        let canvas = large_canvas();
+        b.iter(|| {
+            let mut ppm = vec![];
+            write_ppm_p6(&canvas, &mut ppm).unwrap();
+            ppm
+        });
+    }
 }
@@ -0,0 +1,159 @@
+use crate::geometry::*;
+use crate::object::*;
+use crate::transform::*;
+use std::f32::consts::PI;
+
+/// Generates a sphere flake: a unit sphere with nine spheres a third of its
+/// size resting on its surface, each of which recursively has nine children
+/// of its own, to the given depth.
+///
+/// Produces (9^(depth + 1) - 1) / 8 spheres.
+pub fn sphere_flake(depth: usize) -> Vec<Object> {
+    let mut objects = Vec::new();
+    add_sphere_flake(&mut objects, Transform::new(), depth);
+    objects
+}
+
+fn add_sphere_flake(objects: &mut Vec<Object>, transform: Transform, depth: usize) {
+    objects.push(
+        Object::new()
+            .geometry(Geometry::sphere())
+            .transform(transform),
+    );
+
+    if depth == 0 {
+        return;
+    }
+
+    // Six children around the equator, and three more around the upper
+    // hemisphere, offset to sit between them. The parent's pole is left
+    // clear.
+    let scale = 1. / 3.;
+    let equator = (0..6).map(|i| (PI / 2., i as f32 * PI / 3.));
+    let upper = (0..3).map(|i| (PI / 4., PI / 6. + i as f32 * 2. * PI / 3.));
+    for (polar, azimuth) in equator.chain(upper) {
+        // Orient each child so that its own pole points away from its parent.
+        let child = transform
+            .clone()
+            .rotate_y(azimuth)
+            .rotate_z(polar)
+            .translate(0., 1. + scale, 0.)
+            .scale(scale, scale, scale);
+        add_sphere_flake(objects, child, depth - 1);
+    }
+}
+
+/// Generates a Menger sponge from cubes, filling the unit cube (from -1 to 1
+/// on each axis) at the given depth.
+///
+/// Produces 20^depth cubes.
+pub fn menger_sponge(depth: usize) -> Vec<Object> {
+    let mut objects = Vec::new();
+    add_menger_sponge(&mut objects, Transform::new(), depth);
+    objects
+}
+
+fn add_menger_sponge(objects: &mut Vec<Object>, transform: Transform, depth: usize) {
+    if depth == 0 {
+        objects.push(
+            Object::new()
+                .geometry(Geometry::cube())
+                .transform(transform),
+        );
+        return;
+    }
+
+    // Split into 27 subcubes, removing the center and the center of each
+    // face.
+    let scale = 1. / 3.;
+    for x in -1..=1_i32 {
+        for y in -1..=1_i32 {
+            for z in -1..=1_i32 {
+                if x.abs() + y.abs() + z.abs() <= 1 {
+                    continue;
+                }
+
+                let child = transform
+                    .clone()
+                    .translate(
+                        x as f32 * 2. * scale,
+                        y as f32 * 2. * scale,
+                        z as f32 * 2. * scale,
+                    )
+                    .scale(scale, scale, scale);
+                add_menger_sponge(objects, child, depth - 1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::*;
+    use crate::scene::*;
+    use crate::tuple::*;
+    use assert_approx_eq::assert_approx_eq;
+    use test::Bencher;
+
+    fn scene_from(objects: Vec<Object>) -> Scene {
+        let mut scene = Scene::new();
+        for object in objects {
+            scene.add_object(object);
+        }
+        scene
+    }
+
+    #[test]
+    fn the_number_of_spheres_in_a_sphere_flake() {
+        for depth in 0..4 {
+            let expected = (9_usize.pow(depth as u32 + 1) - 1) / 8;
+            assert_eq!(sphere_flake(depth).len(), expected);
+        }
+    }
+
+    #[test]
+    fn the_number_of_cubes_in_a_menger_sponge() {
+        for depth in 0..4 {
+            assert_eq!(menger_sponge(depth).len(), 20_usize.pow(depth as u32));
+        }
+    }
+
+    #[test]
+    fn sphere_flake_children_rest_on_their_parent() {
+        let objects = sphere_flake(1);
+        for child in &objects[1..] {
+            let center = child.transform.local_to_world * point3(0., 0., 0.);
+            let surface = child.transform.local_to_world * point3(0., 1., 0.);
+            assert_approx_eq!((center - point3(0., 0., 0.)).magnitude(), 4. / 3.);
+            assert_approx_eq!((surface - center).magnitude(), 1. / 3.);
+        }
+    }
+
+    #[test]
+    fn a_ray_down_the_axis_hits_the_central_sphere_first() {
+        let scene = scene_from(sphere_flake(2));
+        let r = ray(point3(0., 10., 0.), vector3(0., -1., 0.));
+        let hit = scene.nearest_intersection(r).unwrap();
+        assert_eq!(hit.object_id, 0);
+        assert_approx_eq!(hit.t, 9.);
+    }
+
+    #[test]
+    fn a_ray_through_a_menger_sponge_tunnel_misses() {
+        let scene = scene_from(menger_sponge(2));
+        let r = ray(point3(0., 10., 0.), vector3(0., -1., 0.));
+        assert_eq!(scene.nearest_intersection(r), None);
+
+        let r = ray(point3(0.9, 10., 0.9), vector3(0., -1., 0.));
+        assert_approx_eq!(scene.nearest_intersection(r).unwrap().t, 9.);
+    }
+
+    #[bench]
+    fn bench_sphere_flake_nearest_intersection(bencher: &mut Bencher) {
+        let scene = scene_from(sphere_flake(2));
+        let r = ray(point3(0., 10., 0.), vector3(0., -1., 0.));
+
+        bencher.iter(|| scene.nearest_intersection(r));
+    }
+}
@@ -0,0 +1,147 @@
+use crate::bounds::*;
+use crate::color::*;
+use crate::rng::*;
+use crate::tuple::*;
+use std::f32::consts::PI;
+
+/// A grid of irradiance probes, each recording the light arriving at its
+/// point from every direction, averaged. Between the probes it is
+/// interpolated, to approximate the light bounced off nearby surfaces.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProbeGrid {
+    bounds: Bounds3,
+    /// The number of probes along each axis.
+    resolution: usize,
+    /// The light at each probe, with x varying fastest, then y, then z.
+    irradiance: Vec<Color>,
+}
+
+impl ProbeGrid {
+    /// Constructs a grid of `resolution` probes along each axis, spanning
+    /// the (finite) bounds from corner to corner, with the given irradiance
+    /// in the order of `points`.
+    pub fn new(bounds: Bounds3, resolution: usize, irradiance: Vec<Color>) -> Self {
+        assert!(
+            resolution >= 2,
+            "a probe grid needs at least 2 probes along each axis"
+        );
+        assert!(bounds.is_finite(), "a probe grid must have finite bounds");
+        assert_eq!(irradiance.len(), resolution.pow(3));
+        ProbeGrid {
+            bounds,
+            resolution,
+            irradiance,
+        }
+    }
+
+    /// Returns the positions of the probes within the bounds, with x varying
+    /// fastest, then y, then z.
+    pub fn points(bounds: Bounds3, resolution: usize) -> impl Iterator<Item = Tuple4> {
+        let step = (bounds.max - bounds.min) * (1. / (resolution - 1) as f32);
+        (0..resolution.pow(3)).map(move |i| {
+            let (x, y, z) = (
+                i % resolution,
+                i / resolution % resolution,
+                i / (resolution * resolution),
+            );
+            point3(
+                bounds.min.x + step.x * x as f32,
+                bounds.min.y + step.y * y as f32,
+                bounds.min.z + step.z * z as f32,
+            )
+        })
+    }
+
+    /// Returns the irradiance at the point, interpolated trilinearly between
+    /// the eight probes around it. Points outside the grid take the value
+    /// at the nearest point on its boundary.
+    pub fn sample(&self, point: Tuple4) -> Color {
+        let n = self.resolution;
+        let cell = |value: f32, min: f32, max: f32| {
+            let f = if max > min {
+                ((value - min) / (max - min)).clamp(0., 1.) * (n - 1) as f32
+            } else {
+                0.
+            };
+            let i = (f as usize).min(n - 2);
+            (i, f - i as f32)
+        };
+        let (x, tx) = cell(point.x, self.bounds.min.x, self.bounds.max.x);
+        let (y, ty) = cell(point.y, self.bounds.min.y, self.bounds.max.y);
+        let (z, tz) = cell(point.z, self.bounds.min.z, self.bounds.max.z);
+
+        let probe = |dx, dy, dz| self.irradiance[x + dx + n * (y + dy + n * (z + dz))];
+        let lerp = |a: Color, b: Color, t: f32| a * (1. - t) + b * t;
+        let plane = |dz| {
+            lerp(
+                lerp(probe(0, 0, dz), probe(1, 0, dz), tx),
+                lerp(probe(0, 1, dz), probe(1, 1, dz), tx),
+                ty,
+            )
+        };
+        lerp(plane(0), plane(1), tz)
+    }
+}
+
+/// Returns a random direction, chosen uniformly over the sphere.
+pub fn uniform_sphere_direction<R: Rng>(rng: &mut R) -> Tuple4 {
+    let z = 1. - 2. * rng.gen::<f32>();
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * PI * rng.gen::<f32>();
+    vector3(r * phi.cos(), r * phi.sin(), z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn unit_bounds() -> Bounds3 {
+        Bounds3::new(point3(0., 0., 0.), point3(1., 1., 1.))
+    }
+
+    #[test]
+    fn the_probes_span_the_bounds() {
+        let bounds = Bounds3::new(point3(-1., 0., 2.), point3(1., 4., 3.));
+        let points: Vec<Tuple4> = ProbeGrid::points(bounds, 3).collect();
+        assert_eq!(points.len(), 27);
+        assert_eq!(points[0], point3(-1., 0., 2.));
+        assert_eq!(points[1], point3(0., 0., 2.));
+        assert_eq!(points[3], point3(-1., 2., 2.));
+        assert_eq!(points[9], point3(-1., 0., 2.5));
+        assert_eq!(points[26], point3(1., 4., 3.));
+    }
+
+    #[test]
+    fn interpolation_between_two_probes_is_linear() {
+        // Black probes at x = 0, and white probes at x = 1.
+        let irradiance = ProbeGrid::points(unit_bounds(), 2)
+            .map(|point| Color::WHITE * point.x)
+            .collect();
+        let grid = ProbeGrid::new(unit_bounds(), 2, irradiance);
+
+        for &x in &[0., 0.25, 0.5, 0.9, 1.] {
+            let c = grid.sample(point3(x, 0.3, 0.7));
+            assert_approx_eq!(c.r, x);
+            assert_approx_eq!(c.g, x);
+            assert_approx_eq!(c.b, x);
+        }
+
+        // Beyond the grid, the nearest boundary value holds.
+        assert_eq!(grid.sample(point3(-1., 0.5, 0.5)), Color::BLACK);
+        assert_eq!(grid.sample(point3(2., 0.5, 0.5)), Color::WHITE);
+    }
+
+    #[test]
+    fn sphere_directions_are_unit_vectors_covering_both_hemispheres() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let directions: Vec<Tuple4> = (0..1000)
+            .map(|_| uniform_sphere_direction(&mut rng))
+            .collect();
+        for d in &directions {
+            assert_approx_eq!(d.magnitude(), 1.);
+        }
+        let mean_z = directions.iter().map(|d| d.z).sum::<f32>() / 1000.;
+        assert!(mean_z.abs() < 0.1);
+    }
+}
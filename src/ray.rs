@@ -5,13 +5,21 @@ use crate::tuple::*;
 pub struct Ray {
     pub origin: Tuple4,
     pub direction: Tuple4,
+    /// How quickly the ray's footprint widens: its width after travelling a
+    /// distance d is `spread * d`. Zero (the default) for an infinitely thin
+    /// ray.
+    pub spread: f32,
 }
 
 /// Constructs a Ray with the given origin and direction.
 pub fn ray(origin: Tuple4, direction: Tuple4) -> Ray {
     debug_assert!(origin.is_point());
     debug_assert!(direction.is_vector());
-    Ray { origin, direction }
+    Ray {
+        origin,
+        direction,
+        spread: 0.,
+    }
 }
 
 impl Ray {
@@ -25,10 +33,25 @@ impl Ray {
         }
     }
 
+    /// Returns a copy of the ray with the given spread.
+    pub fn spread(mut self, spread: f32) -> Self {
+        self.spread = spread;
+        self
+    }
+
+    /// Returns the width of the ray's footprint at the given distance t along
+    /// it.
+    pub fn width_at(&self, t: f32) -> f32 {
+        self.spread * t * self.direction.magnitude()
+    }
+
     /// Returns a new ray with the given transformation matrix applied to origin
     /// and direction.
+    ///
+    /// The spread is kept, so the footprint's width scales along with the
+    /// direction (and is measured in the new space).
     pub fn transform(&self, matrix: Matrix4) -> Ray {
-        ray(matrix * self.origin, matrix * self.direction)
+        ray(matrix * self.origin, matrix * self.direction).spread(self.spread)
     }
 }
 
@@ -36,6 +59,7 @@ impl Ray {
 mod tests {
     use super::*;
     use crate::transform::*;
+    use assert_approx_eq::assert_approx_eq;
     use test::Bencher;
 
     #[test]
@@ -74,6 +98,17 @@ mod tests {
         assert_eq!(r2.direction, vector3(0., 3., 0.,));
     }
 
+    #[test]
+    fn the_footprint_of_a_spreading_ray() {
+        let r = ray(point3(0., 0., 0.), vector3(0., 0., 1.)).spread(0.01);
+        assert_eq!(r.width_at(0.), 0.);
+        assert_approx_eq!(r.width_at(10.), 0.1);
+
+        // Scaling the ray scales the footprint along with it.
+        let m = Transform::new().scale(1., 1., 2.).local_to_world;
+        assert_approx_eq!(r.transform(m).width_at(10.), 0.2);
+    }
+
     #[bench]
     fn bench_position_on_a_ray(bencher: &mut Bencher) {
         let r = ray(point3(1., 2., 3.), vector3(1., 1., 1.).normalize());
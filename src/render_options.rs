@@ -0,0 +1,371 @@
+use crate::canvas::*;
+use crate::color::*;
+use crate::color_grade::*;
+use crate::constants::*;
+use crate::filter::*;
+use crate::material::*;
+use crate::tuple::*;
+
+/// How thoroughly a scene is shaded.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RenderQuality {
+    /// Skips shadows, reflection, and refraction, for fast previews.
+    Preview,
+    /// Computes everything.
+    Full,
+}
+
+/// Which part of the scene a render shows, for splitting it into layers to
+/// be composited (see `Camera::render_layers`).
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RenderLayer {
+    /// Everything.
+    All,
+    /// Only what rays which miss every object see.
+    Background,
+    /// Only the objects tagged with the layer are seen by primary rays,
+    /// which see black elsewhere. The other objects are seen by secondary
+    /// rays (and so appear in reflections and cast shadows) if
+    /// `others_visible`, and are ignored entirely if not.
+    Objects { layer: u8, others_visible: bool },
+    /// White where primary rays hit the objects of the `Objects` layer, and
+    /// black elsewhere: averaged over each pixel's samples, the layer's
+    /// coverage (alpha). Hits are shaded all the same, so the matte takes
+    /// the same samples as the layer itself.
+    Matte { layer: u8, others_visible: bool },
+}
+
+/// The settings used to render a scene.
+///
+/// Everything that affects the rendered image apart from the scene and camera
+/// themselves lives here, so that a render can be reproduced (or logged) from
+/// a single value.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RenderOptions {
+    /// How thoroughly the scene is shaded.
+    pub quality: RenderQuality,
+    /// The maximum number of bounces for reflected and refracted rays.
+    pub max_depth: usize,
+    /// The time at which the scene is rendered, for animated materials and
+    /// textures.
+    pub time: f32,
+    /// Whether to scale each pixel by cos³ of the angle between its ray and
+    /// the view axis, compensating for the larger solid angle covered by
+    /// pixels near the edges of wide field-of-view cameras.
+    pub exposure_compensation: bool,
+    /// The least distance hit points are pushed off the surface before
+    /// spawning secondary rays. Far from the origin the offset grows (see
+    /// `surface_offset_at`), but raising this can still help for scenes
+    /// with very large coordinates.
+    pub surface_offset: f32,
+    /// The number of threads used to render tiles.
+    pub threads: usize,
+    /// The seed from which every tile's random number generator is derived.
+    /// Renders with the same seed are identical, whatever the thread count.
+    pub seed: u64,
+    /// The white balance and color grade applied to each pixel before it is
+    /// written to the canvas.
+    pub grade: ColorGrade,
+    /// If set, scales each image so that its overall brightness maps to a
+    /// target gray, before the color grade is applied.
+    pub auto_exposure: Option<AutoExposure>,
+    /// If set, scales each image by the exposure of a physical camera, for
+    /// scenes lit by photometric lights. Automatic exposure overrides it.
+    pub physical_exposure: Option<PhysicalExposure>,
+    /// The fewest samples taken for each pixel.
+    pub min_samples: usize,
+    /// The most samples taken for each pixel.
+    pub max_samples: usize,
+    /// Sampling a pixel stops (after at least `min_samples`) once the
+    /// standard error of its mean luminance falls below this.
+    pub error_target: f32,
+    /// How samples are combined into pixels. The default box filter averages
+    /// the samples through each pixel's center; wider filters take samples
+    /// across each pixel and blend them into the pixels around it, which
+    /// smooths edges.
+    pub filter: Filter,
+    /// The samples added, spread across the pixel, to each pixel on the
+    /// silhouette of an object (where the object seen through its center
+    /// differs from that of a pixel beside it), with the box filter. This
+    /// smooths edges at a fraction of the cost of sampling every pixel, and
+    /// catches edges between objects of similar colors, which adaptive
+    /// sampling misses.
+    pub edge_samples: usize,
+    /// If set, every object is shaded with this material in place of its
+    /// own (and its own textures), leaving the scene untouched.
+    pub material_override: Option<Material>,
+    /// How the rendered canvas stores colors. Linear by default; use sRGB
+    /// for images meant to be looked at.
+    pub encoding: Encoding,
+    /// Whether surfaces are lit by the scene's irradiance probes (see
+    /// `Scene::bake_probes`), if it has any, as well as directly.
+    pub irradiance_probes: bool,
+    /// Which part of the scene is rendered.
+    pub layer: RenderLayer,
+}
+
+impl RenderOptions {
+    pub fn new() -> Self {
+        RenderOptions {
+            quality: RenderQuality::Full,
+            max_depth: 5,
+            time: 0.,
+            exposure_compensation: false,
+            surface_offset: SURFACE_OFFSET,
+            threads: 1,
+            seed: 0,
+            grade: ColorGrade::new(),
+            auto_exposure: None,
+            physical_exposure: None,
+            min_samples: 1,
+            max_samples: 1,
+            error_target: 0.01,
+            filter: Filter::Box,
+            edge_samples: 0,
+            material_override: None,
+            encoding: Encoding::Linear,
+            irradiance_probes: false,
+            layer: RenderLayer::All,
+        }
+    }
+
+    pub fn quality(mut self, quality: RenderQuality) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn time(mut self, time: f32) -> Self {
+        self.time = time;
+        self
+    }
+
+    pub fn exposure_compensation(mut self, exposure_compensation: bool) -> Self {
+        self.exposure_compensation = exposure_compensation;
+        self
+    }
+
+    pub fn surface_offset(mut self, surface_offset: f32) -> Self {
+        self.surface_offset = surface_offset;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn grade(mut self, grade: ColorGrade) -> Self {
+        self.grade = grade;
+        self
+    }
+
+    pub fn auto_exposure(mut self, auto_exposure: AutoExposure) -> Self {
+        self.auto_exposure = Some(auto_exposure);
+        self
+    }
+
+    pub fn physical_exposure(mut self, physical_exposure: PhysicalExposure) -> Self {
+        self.physical_exposure = Some(physical_exposure);
+        self
+    }
+
+    pub fn min_samples(mut self, min_samples: usize) -> Self {
+        self.min_samples = min_samples;
+        self
+    }
+
+    pub fn max_samples(mut self, max_samples: usize) -> Self {
+        self.max_samples = max_samples;
+        self
+    }
+
+    pub fn error_target(mut self, error_target: f32) -> Self {
+        self.error_target = error_target;
+        self
+    }
+
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn edge_samples(mut self, edge_samples: usize) -> Self {
+        self.edge_samples = edge_samples;
+        self
+    }
+
+    pub fn material_override(mut self, material: Material) -> Self {
+        self.material_override = Some(material);
+        self
+    }
+
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn irradiance_probes(mut self, irradiance_probes: bool) -> Self {
+        self.irradiance_probes = irradiance_probes;
+        self
+    }
+
+    pub fn layer(mut self, layer: RenderLayer) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    /// Shades every object with the same matte gray material (a "clay
+    /// render"), to check the lighting and shapes of a scene on their own.
+    pub fn clay(self) -> Self {
+        self.material_override(
+            Material::new()
+                .color(Color::new(0.8, 0.8, 0.8))
+                .specular(0.),
+        )
+    }
+
+    /// The maximum depth after accounting for the render quality.
+    pub fn effective_max_depth(&self) -> usize {
+        match self.quality {
+            RenderQuality::Preview => 1,
+            RenderQuality::Full => self.max_depth,
+        }
+    }
+
+    /// Whether shadows are computed.
+    pub fn shadows(&self) -> bool {
+        self.quality == RenderQuality::Full
+    }
+
+    /// Returns how far to push a hit point, the given distance along its
+    /// ray, off the surface: enough to clear the rounding error in its
+    /// position, which grows with its distance from the origin and from the
+    /// ray's origin.
+    pub fn surface_offset_at(&self, point: Tuple4, t: f32) -> f32 {
+        let distance = (point - point3(0., 0., 0.)).magnitude().max(t.abs());
+        self.surface_offset.max(distance * RELATIVE_SURFACE_OFFSET)
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn the_default_render_options() {
+        let options = RenderOptions::default();
+        assert_eq!(options.quality, RenderQuality::Full);
+        assert_eq!(options.max_depth, 5);
+        assert_eq!(options.time, 0.);
+        assert!(!options.exposure_compensation);
+        assert_eq!(options.surface_offset, SURFACE_OFFSET);
+        assert_eq!(options.threads, 1);
+        assert_eq!(options.seed, 0);
+        assert!(options.grade.is_neutral());
+        assert_eq!(options.auto_exposure, None);
+        assert_eq!(options.material_override, None);
+        assert_eq!(options, RenderOptions::new());
+    }
+
+    #[test]
+    fn the_surface_offset_grows_far_from_the_origin() {
+        let options = RenderOptions::new();
+        assert_eq!(
+            options.surface_offset_at(point3(1., 2., 3.), 4.),
+            SURFACE_OFFSET
+        );
+        assert_approx_eq!(options.surface_offset_at(point3(0., 3e3, 4e3), 1.), 0.05);
+        assert_approx_eq!(options.surface_offset_at(point3(1., 0., 0.), 1e4), 0.1);
+
+        let options = options.surface_offset(1.);
+        assert_eq!(options.surface_offset_at(point3(0., 3e3, 4e3), 1.), 1.);
+    }
+
+    #[test]
+    fn preview_quality_limits_depth_and_disables_shadows() {
+        let options = RenderOptions::new()
+            .max_depth(10)
+            .quality(RenderQuality::Preview);
+        assert_eq!(options.effective_max_depth(), 1);
+        assert!(!options.shadows());
+
+        let options = options.quality(RenderQuality::Full);
+        assert_eq!(options.effective_max_depth(), 10);
+        assert!(options.shadows());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn render_options_round_trip_through_serde() {
+        use crate::texture::*;
+        use crate::transform::*;
+
+        let face = |c: f32| {
+            let color = Color::new(c, 1. - c, 0.5);
+            AlignCheck::new(color, Color::WHITE, Color::BLACK, color, color)
+        };
+        let mut texture = Texture::cube_map([
+            face(0.),
+            face(0.2),
+            face(0.4),
+            face(0.6),
+            face(0.8),
+            face(1.),
+        ])
+        .perturb(3, 0.25);
+        texture.transform = Transform::new().scale(2., 1., 0.5);
+        let material = Material::new().texture(texture).reflective(0.3).animate(
+            Property::Ambient,
+            Keyframes::new(&[(0., 0.1), (1., 0.9)]).unwrap(),
+        );
+        let mut grade = ColorGrade::new().white_balance(5000., 0.1);
+        grade.gamma = Color::new(1.1, 1., 0.9);
+        let options = RenderOptions::new()
+            .quality(RenderQuality::Preview)
+            .max_depth(3)
+            .time(0.5)
+            .seed(42)
+            .grade(grade)
+            .auto_exposure(
+                AutoExposure::new()
+                    .metering(Metering::Percentile(0.9))
+                    .target(0.2),
+            )
+            .physical_exposure(PhysicalExposure::new())
+            .filter(Filter::Mitchell {
+                radius: 2.,
+                b: 1. / 3.,
+                c: 1. / 3.,
+            })
+            .material_override(material)
+            .encoding(Encoding::Srgb)
+            .layer(RenderLayer::Matte {
+                layer: 2,
+                others_visible: true,
+            });
+        let json = serde_json::to_string(&options).unwrap();
+        let loaded: RenderOptions = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, options);
+        assert_eq!(serde_json::to_string(&loaded).unwrap(), json);
+    }
+}
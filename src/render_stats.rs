@@ -0,0 +1,152 @@
+use crate::canvas::*;
+use crate::color::*;
+
+use std::fmt;
+
+/// What it cost to render a single pixel.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PixelCost {
+    /// The number of samples taken.
+    pub samples: usize,
+    /// The number of rays traced over every sample, including secondary and
+    /// shadow rays.
+    pub rays: usize,
+}
+
+/// Maps t (from 0 to 1) through a blue, green, yellow, red gradient.
+pub fn false_color(t: f32) -> Color {
+    let stops = [
+        Color::new(0., 0., 1.),
+        Color::new(0., 1., 0.),
+        Color::new(1., 1., 0.),
+        Color::new(1., 0., 0.),
+    ];
+    let x = t.clamp(0., 1.) * (stops.len() - 1) as f32;
+    let i = (x.floor() as usize).min(stops.len() - 2);
+    let f = x - i as f32;
+    stops[i] * (1. - f) + stops[i + 1] * f
+}
+
+/// Returns a false color image of the number of rays traced for each pixel
+/// (in row-major order), scaled so that the most expensive pixel is red.
+pub fn cost_heatmap(costs: &[PixelCost], width: usize, height: usize) -> Canvas {
+    let max = costs.iter().map(|cost| cost.rays).max().unwrap_or(0).max(1);
+    let mut image = Canvas::new(width, height);
+    for (i, cost) in costs.iter().enumerate() {
+        let color = false_color(cost.rays as f32 / max as f32);
+        image.set_color(i % width, i / width, color);
+    }
+    image
+}
+
+/// A histogram of the number of rays traced per pixel, in buckets doubling
+/// in size so that a few very expensive pixels still stand out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CostHistogram {
+    /// The (inclusive) lowest and highest cost in each bucket, and the number
+    /// of pixels whose cost falls in it.
+    pub buckets: Vec<(usize, usize, usize)>,
+}
+
+impl CostHistogram {
+    pub fn new(costs: &[PixelCost]) -> Self {
+        let max = costs.iter().map(|cost| cost.rays).max().unwrap_or(0);
+        let mut buckets = vec![(0, 0, 0)];
+        let mut low = 1;
+        while low <= max {
+            buckets.push((low, 2 * low - 1, 0));
+            low *= 2;
+        }
+
+        for cost in costs {
+            let i = match cost.rays {
+                0 => 0,
+                rays => (usize::BITS - rays.leading_zeros()) as usize,
+            };
+            buckets[i].2 += 1;
+        }
+
+        CostHistogram { buckets }
+    }
+
+    /// Returns the total number of pixels counted.
+    pub fn total(&self) -> usize {
+        self.buckets.iter().map(|&(_, _, count)| count).sum()
+    }
+}
+
+impl fmt::Display for CostHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const BAR_WIDTH: usize = 40;
+        let most = self
+            .buckets
+            .iter()
+            .map(|&(_, _, count)| count)
+            .max()
+            .unwrap_or(0);
+        writeln!(f, "rays per pixel:")?;
+        for &(low, high, count) in &self.buckets {
+            let bar = if most > 0 {
+                (count * BAR_WIDTH).div_ceil(most)
+            } else {
+                0
+            };
+            writeln!(
+                f,
+                "{:>8}..={:<8} {:>9} {}",
+                low,
+                high,
+                count,
+                "#".repeat(bar)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn costs(rays: &[usize]) -> Vec<PixelCost> {
+        rays.iter()
+            .map(|&rays| PixelCost { samples: 1, rays })
+            .collect()
+    }
+
+    #[test]
+    fn the_false_color_gradient() {
+        assert_eq!(false_color(0.), Color::new(0., 0., 1.));
+        assert_eq!(false_color(0.5), Color::new(0.5, 1., 0.));
+        assert_eq!(false_color(1.), Color::new(1., 0., 0.));
+        assert_eq!(false_color(2.), Color::new(1., 0., 0.));
+    }
+
+    #[test]
+    fn the_heatmap_is_scaled_to_the_most_expensive_pixel() {
+        let image = cost_heatmap(&costs(&[0, 10, 5, 10]), 2, 2);
+        assert_eq!(image.get_color(0, 0), Color::new(0., 0., 1.));
+        assert_eq!(image.get_color(1, 0), Color::new(1., 0., 0.));
+        assert_eq!(image.get_color(1, 1), Color::new(1., 0., 0.));
+    }
+
+    #[test]
+    fn the_histogram_buckets_double_in_size() {
+        let histogram = CostHistogram::new(&costs(&[0, 1, 2, 3, 4, 7, 8, 100]));
+        assert_eq!(
+            histogram.buckets,
+            vec![
+                (0, 0, 1),
+                (1, 1, 1),
+                (2, 3, 2),
+                (4, 7, 2),
+                (8, 15, 1),
+                (16, 31, 0),
+                (32, 63, 0),
+                (64, 127, 1),
+            ]
+        );
+        assert_eq!(histogram.total(), 8);
+        assert_eq!(histogram.to_string().lines().count(), 9);
+    }
+}
@@ -1,20 +1,166 @@
+use crate::bounds::*;
+use crate::bvh::*;
+use crate::camera::*;
 use crate::color::*;
+use crate::constants::*;
+use crate::geometry::csg::*;
 use crate::geometry::*;
 use crate::intersection::*;
 use crate::light::*;
 use crate::material::*;
 use crate::object::*;
+use crate::probes::*;
 use crate::ray::*;
+use crate::render_options::*;
+use crate::rng::*;
+use crate::sky::*;
+use crate::texture::*;
 use crate::transform::*;
 use crate::tuple::*;
-use rand::Rng;
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+/// The ID given to the next scene created, so that render contexts can tell
+/// scenes apart.
+static NEXT_SCENE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The pixels added around the projection of an object's bounds by
+/// `Scene::affected_region`, to take in the antialiased pixels along its
+/// silhouette.
+pub const REGION_PADDING: f32 = 2.;
 
 pub struct Scene {
     lights: Vec<Light>,
     transforms: Vec<Transform>,
     materials: Vec<Material>,
     geometrys: Vec<Geometry>,
-    max_depth: usize,
+    /// Whether each object's transform is invertible. Objects whose
+    /// transforms aren't (such as those scaled to zero) are skipped.
+    invertible: Vec<bool>,
+    /// How present each object is, from 0 (absent) to 1 (fully present).
+    visibilitys: Vec<f32>,
+    /// Whether each object blocks shadow rays.
+    casts_shadows: Vec<bool>,
+    /// The render layer each object belongs to.
+    layers: Vec<u8>,
+    /// The CSG object each object is a child of, if any. Children are only
+    /// intersected as part of their parents.
+    parents: Vec<Option<ObjectId>>,
+    /// What rays see if they miss every object.
+    background: Background,
+    /// The hierarchy used to find the objects each ray might hit, if built
+    /// (by `build_bvh` or `prepare_render`).
+    bvh: OnceLock<Bvh>,
+    /// Distinguishes this scene from every other, for render contexts.
+    id: u64,
+    /// Incremented whenever the scene changes, to detect stale render
+    /// contexts.
+    generation: u64,
+    /// The history of edits for undo and redo, if journaling is enabled.
+    journal: Option<Journal>,
+    /// Irradiance probes for approximate indirect light, if baked.
+    probes: Option<ProbeGrid>,
+}
+
+/// An edit to a scene, kept in its journal to undo or redo a change.
+/// Applying an edit returns the edit which reverses it.
+#[derive(Clone, Debug)]
+enum Edit {
+    RemoveObject(ObjectId),
+    /// Puts a removed object back under its old ID, with the lights it
+    /// emitted, at their old indices.
+    InsertObject(ObjectId, Box<Object>, Vec<(usize, Light)>),
+    SetTransform(ObjectId, Transform),
+    SetMaterial(ObjectId, Material),
+    SetGeometry(ObjectId, Geometry),
+    RemoveLight(usize),
+    InsertLight(usize, Light),
+    SetLight(usize, Light),
+}
+
+/// The edits which undo the latest changes to a scene (oldest first), and
+/// those which redo the changes undone since.
+#[derive(Debug)]
+struct Journal {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    capacity: usize,
+}
+
+#[cfg(test)]
+thread_local! {
+    /// The number of times an object's geometry has been intersected on this
+    /// thread, for testing how many intersections shading takes.
+    static INTERSECTION_TESTS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Which side of a surface a ray hits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Facing {
+    /// The side the normal points out of.
+    Front,
+    Back,
+}
+
+/// What shading needs to know about where a ray hits an object, computed
+/// once per hit by `Scene::prepare_computations`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HitComputations {
+    pub t: f32,
+    pub object_id: ObjectId,
+    pub point: Tuple4,
+    /// The direction back toward the ray's origin.
+    pub eyev: Tuple4,
+    /// The surface normal, flipped to face the eye.
+    pub normalv: Tuple4,
+    /// Whether the ray hits the surface from inside the object.
+    pub inside: bool,
+    /// Points just above and below the surface, for rays leaving it without
+    /// hitting it again.
+    pub over_point: Tuple4,
+    pub under_point: Tuple4,
+    /// Where shadow rays start: the over point, except on the outside of a
+    /// smooth triangle, where it's above the curved surface the vertex
+    /// normals describe rather than the flat face, so that low-poly meshes
+    /// don't shadow themselves facet by facet near the terminator.
+    pub shadow_point: Tuple4,
+    pub reflectv: Tuple4,
+    /// The objects the ray leaves and enters at the surface (None is empty
+    /// space), and their indexes of refraction.
+    pub exited: Option<ObjectId>,
+    pub entered: Option<ObjectId>,
+    pub n1: f32,
+    pub n2: f32,
+}
+
+impl HitComputations {
+    /// Returns the fraction of light reflected at the surface, by the
+    /// Schlick approximation.
+    pub fn schlick(&self) -> f32 {
+        schlick(self.eyev, self.normalv, self.n1, self.n2)
+    }
+}
+
+/// A token showing that a scene's derived data (its bounding volume
+/// hierarchy) has been built by `Scene::prepare_render`, so that renders
+/// sharing it don't build it again.
+///
+/// A context is only valid for the scene it was prepared from, until that
+/// scene is next modified; rendering with any other context panics.
+#[derive(Debug)]
+pub struct RenderContext {
+    scene_id: u64,
+    generation: u64,
+}
+
+impl RenderContext {
+    /// Whether the context was prepared from this scene, and is still valid
+    /// for it.
+    pub fn is_valid_for(&self, scene: &Scene) -> bool {
+        self.scene_id == scene.id && self.generation == scene.generation
+    }
 }
 
 impl Scene {
@@ -24,67 +170,285 @@ impl Scene {
             transforms: vec![],
             materials: vec![],
             geometrys: vec![],
-            max_depth: 5,
+            invertible: vec![],
+            visibilitys: vec![],
+            casts_shadows: vec![],
+            layers: vec![],
+            parents: vec![],
+            background: Background::default(),
+            bvh: OnceLock::new(),
+            id: NEXT_SCENE_ID.fetch_add(1, Ordering::Relaxed),
+            generation: 0,
+            journal: None,
+            probes: None,
+        }
+    }
+
+    /// Starts recording the changes made to the objects and lights, so the
+    /// latest of them (up to the given number) can be undone. Changes to the
+    /// background aren't recorded.
+    ///
+    /// Undoing the removal of an object puts it back under its old ID, and
+    /// renumbers the objects after it as they were, so IDs from before the
+    /// removal are valid again.
+    pub fn enable_journal(&mut self, capacity: usize) {
+        self.journal = Some(Journal {
+            undo: VecDeque::new(),
+            redo: vec![],
+            capacity,
+        });
+    }
+
+    /// Reverses the latest recorded change which hasn't been undone,
+    /// returning whether there was one.
+    pub fn undo(&mut self) -> bool {
+        let edit = match self
+            .journal
+            .as_mut()
+            .and_then(|journal| journal.undo.pop_back())
+        {
+            Some(edit) => edit,
+            None => return false,
+        };
+        let inverse = self.apply(edit);
+        if let Some(journal) = &mut self.journal {
+            journal.redo.push(inverse);
+        }
+        true
+    }
+
+    /// Makes the latest undone change again, returning whether there was
+    /// one. Any new change discards the changes which could be redone.
+    pub fn redo(&mut self) -> bool {
+        let edit = match self.journal.as_mut().and_then(|journal| journal.redo.pop()) {
+            Some(edit) => edit,
+            None => return false,
+        };
+        let inverse = self.apply(edit);
+        if let Some(journal) = &mut self.journal {
+            journal.undo.push_back(inverse);
+        }
+        true
+    }
+
+    /// Records the edit which reverses a change, if journaling is enabled.
+    fn record(&mut self, inverse: Edit) {
+        if let Some(journal) = &mut self.journal {
+            journal.redo.clear();
+            journal.undo.push_back(inverse);
+            if journal.undo.len() > journal.capacity {
+                journal.undo.pop_front();
+            }
+        }
+    }
+
+    /// Makes the edit, without recording it, returning the edit which
+    /// reverses it.
+    fn apply(&mut self, edit: Edit) -> Edit {
+        match edit {
+            Edit::RemoveObject(object_id) => {
+                let (object, lights) = self.take_object(object_id);
+                Edit::InsertObject(object_id, Box::new(object), lights)
+            }
+            Edit::InsertObject(object_id, object, lights) => {
+                self.insert_object(object_id, *object, lights);
+                Edit::RemoveObject(object_id)
+            }
+            Edit::SetTransform(object_id, transform) => {
+                let old = self.transforms[object_id];
+                *self.transform_mut_unrecorded(object_id) = transform;
+                Edit::SetTransform(object_id, old)
+            }
+            Edit::SetMaterial(object_id, material) => {
+                let old = std::mem::replace(&mut self.materials[object_id], material);
+                self.generation += 1;
+                Edit::SetMaterial(object_id, old)
+            }
+            Edit::SetGeometry(object_id, geometry) => {
+                let old = self.geometrys[object_id].clone();
+                *self.geometry_mut_unrecorded(object_id) = geometry;
+                Edit::SetGeometry(object_id, old)
+            }
+            Edit::RemoveLight(index) => {
+                let light = self.lights.remove(index);
+                self.generation += 1;
+                Edit::InsertLight(index, light)
+            }
+            Edit::InsertLight(index, light) => {
+                self.lights.insert(index, light);
+                self.generation += 1;
+                Edit::RemoveLight(index)
+            }
+            Edit::SetLight(index, light) => {
+                let old = std::mem::replace(&mut self.lights[index], light);
+                self.generation += 1;
+                Edit::SetLight(index, old)
+            }
+        }
+    }
+
+    /// Builds the data needed to render the scene (its bounding volume
+    /// hierarchy, unless it's already built), which can then be reused for
+    /// any number of renders until the scene is modified.
+    pub fn prepare_render(&self) -> RenderContext {
+        self.bvh.get_or_init(|| self.new_bvh());
+        RenderContext {
+            scene_id: self.id,
+            generation: self.generation,
         }
     }
 
     /// Intersects the ray with the world and returns the color at the resulting
     /// intersection.
     pub fn color_at<R: Rng>(&self, rng: &mut R, world_ray: Ray) -> Color {
-        self.color_at_remaining(rng, world_ray, self.max_depth)
-            .clamp()
+        self.color_at_with_options(rng, world_ray, &RenderOptions::default())
+    }
+
+    /// Intersects the ray with the world and returns the color at the resulting
+    /// intersection, rendered with the given options.
+    pub fn color_at_with_options<R: Rng>(
+        &self,
+        rng: &mut R,
+        world_ray: Ray,
+        options: &RenderOptions,
+    ) -> Color {
+        self.radiance_at(rng, world_ray, options).clamp()
+    }
+
+    /// Like `color_at_with_options`, but without clamping the result to the
+    /// displayable range.
+    pub fn radiance_at<R: Rng>(
+        &self,
+        rng: &mut R,
+        world_ray: Ray,
+        options: &RenderOptions,
+    ) -> Color {
+        self.radiance_and_cost_at(rng, world_ray, options).0
+    }
+
+    /// Like `radiance_at`, but also returns the number of rays (including
+    /// shadow rays) which were traced to find the radiance, as a measure of
+    /// how expensive it was.
+    pub fn radiance_and_cost_at<R: Rng>(
+        &self,
+        rng: &mut R,
+        world_ray: Ray,
+        options: &RenderOptions,
+    ) -> (Color, usize) {
+        let mut rays = 0;
+        let color = self.color_at_remaining(
+            rng,
+            world_ray,
+            options.effective_max_depth(),
+            options,
+            &mut rays,
+        );
+        (color, rays)
     }
 
     /// Intersects the ray with the world and returns the color at the resulting
-    /// intersection (with specified remaining depth).
-    fn color_at_remaining<R: Rng>(&self, rng: &mut R, world_ray: Ray, remaining: usize) -> Color {
+    /// intersection (with specified remaining depth), adding the number of
+    /// rays traced to `rays`.
+    fn color_at_remaining<R: Rng>(
+        &self,
+        rng: &mut R,
+        world_ray: Ray,
+        remaining: usize,
+        options: &RenderOptions,
+        rays: &mut usize,
+    ) -> Color {
         if remaining == 0 {
             return Color::BLACK;
         }
+        *rays += 1;
 
-        if let Some(intersection) = self.nearest_intersection(world_ray) {
-            let transform = self.transforms[intersection.object_id];
-            let material = self.materials[intersection.object_id];
-            let geometry = self.geometrys[intersection.object_id];
+        // Every secondary ray is spawned with less depth remaining.
+        let primary = remaining == options.effective_max_depth();
+        let (nearest, intersections) =
+            self.nearest_visible_intersection_on_line(rng, world_ray, |object_id| {
+                self.in_layer(object_id, options, primary)
+            });
+        // A matte is shaded like its layer, to draw the same random numbers.
+        let matte = primary && matches!(options.layer, RenderLayer::Matte { .. });
+        if let (RenderLayer::Objects { .. } | RenderLayer::Matte { .. }, None, true) =
+            (options.layer, nearest, primary)
+        {
+            return Color::BLACK;
+        }
+        if let Some(intersection) = nearest {
+            let transform = self.world_transform(intersection.object_id);
+            let mut material = options.material_override.clone().unwrap_or_else(|| {
+                self.materials[intersection.object_id]
+                    .clone()
+                    .at_time(options.time)
+            });
+            let geometry = &self.geometrys[intersection.object_id];
 
-            // Compute the surface normal.
-            let world_point = world_ray.position(intersection.t);
-            let eye_vector = -world_ray.direction;
-            let world_normal = world_normal_at(transform, geometry, world_point, eye_vector);
+            // A checker floor colors itself, filtered over the ray's footprint.
+            if let (&Geometry::CheckerFloor { a, b, size }, None) =
+                (geometry, &options.material_override)
+            {
+                let local_ray = world_ray.transform(transform.world_to_local);
+                let color = checker_floor::color_at(local_ray, intersection.t, a, b, size);
+                material.texture = Texture::constant(color);
+            }
 
-            // Compute surface color.
-            let over_point = world_point + world_normal * 1e-3;
-            let under_point = world_point - world_normal * 1e-3;
-            let surface_color = self.lights.iter().fold(Color::BLACK, |acc, &light| {
-                let in_shadow = self.is_shadowed(over_point, light);
-                acc + material.lighting(
+            // The objects on either side of the surface are only needed for
+            // refraction, so skip finding them otherwise.
+            let intersections = if material.transparency > 0. {
+                intersections.unwrap_or_else(|| self.sorted_intersections(world_ray))
+            } else {
+                vec![]
+            };
+            let comps = self.prepare_computations(world_ray, intersection, &intersections, options);
+
+            // Textures which can be filtered are filtered over the footprint
+            // of rays which have one (camera rays), so that distant patterns
+            // don't alias, as on a checker floor.
+            if world_ray.spread > 0. && material.texture.is_filtered() {
+                material.texture = Texture::constant(material.texture.evaluate_footprint(
                     rng,
                     transform,
-                    light,
-                    world_point,
-                    eye_vector,
-                    world_normal,
-                    in_shadow,
-                )
-            });
+                    world_ray,
+                    intersection.t,
+                    comps.normalv,
+                ));
+            }
+            let HitComputations {
+                point: world_point,
+                eyev: eye_vector,
+                normalv: world_normal,
+                over_point,
+                under_point,
+                reflectv,
+                exited,
+                entered,
+                n1,
+                n2,
+                ..
+            } = comps;
+
+            // Compute surface color.
+            let mut surface_color =
+                self.surface_lighting(rng, &material, transform, &comps, options, rays);
+            if let (Some(probes), true) = (&self.probes, options.irradiance_probes) {
+                // Approximate the light bounced off other surfaces.
+                let albedo = material.texture.evaluate(rng, transform, world_point);
+                surface_color =
+                    surface_color + probes.sample(over_point) * albedo * material.diffuse;
+            }
 
             // Compute reflect color.
-            let reflect_color = if material.reflective > 0. && remaining > 0 {
-                let reflect_vector = world_ray.direction.reflect(world_normal);
-                let reflect_ray = ray(over_point, reflect_vector);
-                self.color_at_remaining(rng, reflect_ray, remaining - 1) * material.reflective
+            let reflective = material.reflective_at(rng, transform, world_point);
+            let reflect_color = if reflective > 0. && remaining > 0 {
+                let reflect_ray = ray(over_point, reflectv);
+                self.color_at_remaining(rng, reflect_ray, remaining - 1, options, rays) * reflective
             } else {
                 Color::BLACK
             };
 
             // Compute refract color.
-            let (n1, n2) = if material.transparency > 0. {
-                self.refractive_indexes(world_ray, intersection)
-            } else {
-                // Skip computation if the values aren't needed.
-                (1.0, 1.0)
-            };
-            let refract_color = if material.transparency > 0. && remaining > 0 {
+            let mut refract = |n1: f32, n2: f32| {
                 let n_ratio = n1 / n2;
                 let cos_i = eye_vector.dot(world_normal);
                 let sin2_t = n_ratio * n_ratio * (1. - cos_i * cos_i);
@@ -96,132 +460,1269 @@ impl Scene {
                     let cos_t = (1. - sin2_t).sqrt();
                     let direction = world_normal * (n_ratio * cos_i - cos_t) - eye_vector * n_ratio;
                     let refract_ray = ray(under_point, direction);
-                    let refract_color = self.color_at_remaining(rng, refract_ray, remaining - 1);
+                    let refract_color =
+                        self.color_at_remaining(rng, refract_ray, remaining - 1, options, rays);
                     refract_color * material.transparency
                 }
+            };
+            let refract_color = if material.transparency > 0. && remaining > 0 {
+                let dispersive = exited
+                    .into_iter()
+                    .chain(entered)
+                    .any(|id| self.materials[id].is_dispersive());
+
+                if dispersive {
+                    // Refract each channel separately.
+                    let indexes = |object_id: Option<ObjectId>| {
+                        object_id.map_or((1.0, 1.0, 1.0), |id| {
+                            self.materials[id].channel_refractive_indexes()
+                        })
+                    };
+                    let (r1, g1, b1) = indexes(exited);
+                    let (r2, g2, b2) = indexes(entered);
+                    Color::new(refract(r1, r2).r, refract(g1, g2).g, refract(b1, b2).b)
+                } else {
+                    refract(n1, n2)
+                }
             } else {
                 Color::BLACK
             };
 
-            if material.reflective > 0. && material.transparency > 0. {
+            let beneath = if reflective > 0. && material.transparency > 0. {
                 // Apply Fresnel effect.
-                let reflectance = schlick(eye_vector, world_normal, n1, n2);
-                surface_color + reflect_color * reflectance + refract_color * (1. - reflectance)
+                let reflectance = comps.schlick();
+                reflect_color * reflectance + refract_color * (1. - reflectance)
+            } else {
+                reflect_color + refract_color
+            };
+
+            // A clear coat mirrors the scene over what lies beneath it. (Its
+            // highlights, and the dimming of the direct light beneath it, are
+            // part of the surface color.)
+            let coat = material.clearcoat_reflectance(eye_vector, world_normal);
+            let color = if coat > 0. {
+                let reflect_ray = ray(over_point, reflectv);
+                let coat_color =
+                    self.color_at_remaining(rng, reflect_ray, remaining - 1, options, rays);
+                surface_color + beneath * (1. - coat) + coat_color * coat
             } else {
-                surface_color + reflect_color + refract_color
+                surface_color + beneath
+            };
+            if matte {
+                Color::WHITE
+            } else {
+                color
             }
         } else {
-            Color::BLACK
+            self.background_color(rng, world_ray)
+        }
+    }
+
+    /// Returns what the ray sees if it misses every object.
+    fn background_color<R: Rng>(&self, rng: &mut R, world_ray: Ray) -> Color {
+        self.background.sample(rng, world_ray.direction)
+    }
+
+    /// Returns the light from every light source reflected by the surface at
+    /// the hit toward the eye, adding the number of shadow rays traced to
+    /// `rays`.
+    fn surface_lighting<R: Rng>(
+        &self,
+        rng: &mut R,
+        material: &Material,
+        transform: Transform,
+        comps: &HitComputations,
+        options: &RenderOptions,
+        rays: &mut usize,
+    ) -> Color {
+        self.lights.iter().fold(Color::BLACK, |acc, light| {
+            let (light_intensity, color) = if options.shadows() {
+                let in_layer = |object_id| self.in_layer(object_id, options, false);
+                self.light_at_where(rng, comps.shadow_point, light, true, in_layer, rays)
+            } else if light.emitter.is_some() {
+                self.light_at(rng, comps.shadow_point, light, false)
+            } else {
+                (1., Color::WHITE)
+            };
+            let light = Light {
+                intensity: light.intensity * color,
+                ..light.clone()
+            };
+            acc + material.lighting(
+                rng,
+                transform,
+                &light,
+                comps.point,
+                comps.eyev,
+                comps.normalv,
+                light_intensity,
+            )
+        })
+    }
+
+    /// Bakes a grid of irradiance probes over the bounds, with the given
+    /// number along each axis (at least 2), replacing any baked before. Each
+    /// probe averages, over `samples` rays in random directions, the light
+    /// directly from the light sources reflected toward it by the surfaces
+    /// the rays hit (without ambient light, or the background).
+    ///
+    /// When `RenderOptions::irradiance_probes` is set, each surface is lit
+    /// by the light interpolated from the probes too, scaled by its diffuse
+    /// color, as a single bounce of indirect light. Bake the probes again
+    /// after changing the scene.
+    pub fn bake_probes(&mut self, bounds: Bounds3, resolution: usize, samples: usize) {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let options = RenderOptions::default();
+        let samples = samples.max(1);
+        let irradiance = ProbeGrid::points(bounds, resolution)
+            .map(|point| {
+                let total = (0..samples).fold(Color::BLACK, |acc, _| {
+                    let probe_ray = ray(point, uniform_sphere_direction(&mut rng));
+                    acc + self.direct_radiance(&mut rng, probe_ray, &options)
+                });
+                total * (1. / samples as f32)
+            })
+            .collect();
+        self.probes = Some(ProbeGrid::new(bounds, resolution, irradiance));
+        self.generation += 1;
+    }
+
+    /// Returns the light from the light sources reflected back along the ray
+    /// by the surface it hits, if any, without ambient light.
+    fn direct_radiance<R: Rng>(
+        &self,
+        rng: &mut R,
+        world_ray: Ray,
+        options: &RenderOptions,
+    ) -> Color {
+        match self.nearest_visible_intersection(rng, world_ray, false, |_| true) {
+            Some(intersection) => {
+                let comps = self.prepare_computations(world_ray, intersection, &[], options);
+                let material = self.materials[intersection.object_id].clone().ambient(0.);
+                let transform = self.world_transform(intersection.object_id);
+                self.surface_lighting(rng, &material, transform, &comps, options, &mut 0)
+            }
+            None => Color::BLACK,
         }
     }
 
-    /// Returns an iterator of all intersections between the ray and the scene.
+    /// Returns an iterator of all intersections between the ray and the scene,
+    /// found by testing every object (whether or not a BVH has been built).
+    ///
+    /// Partially visible objects are always included.
     pub fn intersections(&self, world_ray: Ray) -> impl Iterator<Item = Intersection> + '_ {
-        let local_rays = self
-            .transforms
-            .iter()
-            .map(move |transform| world_ray.transform(transform.world_to_local));
-        local_rays.zip(self.geometrys.iter()).enumerate().flat_map(
-            |(object_id, (local_ray, geometry))| {
-                geometry
-                    .intersect(local_ray)
-                    .map(move |t| Intersection { t, object_id })
+        (0..self.geometrys.len())
+            .filter(move |&object_id| {
+                self.parents[object_id].is_none() && self.visibilitys[object_id] > 0.
+            })
+            .flat_map(move |object_id| {
+                let mut intersections = vec![];
+                self.intersect_object(object_id, world_ray, &mut |intersection| {
+                    intersections.push(intersection)
+                });
+                intersections
+            })
+    }
+
+    /// Calls `visit` with each intersection (in front of the ray or behind
+    /// it) between the ray and the object, where the ray is in the space
+    /// containing the object. The intersections with a CSG object are
+    /// reported on the children whose surfaces were hit.
+    fn intersect_object(&self, object_id: ObjectId, ray: Ray, visit: &mut dyn FnMut(Intersection)) {
+        if !self.invertible[object_id] {
+            return;
+        }
+
+        let local_ray = ray.transform(self.transforms[object_id].world_to_local);
+        match self.geometrys[object_id] {
+            Geometry::Csg {
+                operation,
+                left,
+                right,
+            } => {
+                let mut hits = vec![];
+                self.intersect_object(left, local_ray, &mut |i| hits.push((i, true)));
+                self.intersect_object(right, local_ray, &mut |i| hits.push((i, false)));
+                for intersection in csg::filter_intersections(operation, hits) {
+                    visit(intersection);
+                }
+            }
+            ref geometry => {
+                #[cfg(test)]
+                INTERSECTION_TESTS.with(|tests| tests.set(tests.get() + 1));
+                for (t, uv, face) in geometry.intersect(local_ray).with_uv_and_face() {
+                    visit(Intersection {
+                        t,
+                        object_id,
+                        uv,
+                        face,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Returns the transform from the object's space to world space,
+    /// including the transforms of any CSG objects containing it.
+    fn world_transform(&self, object_id: ObjectId) -> Transform {
+        let mut transform = self.transforms[object_id];
+        let mut parent = self.parents[object_id];
+        while let Some(parent_id) = parent {
+            let outer = self.transforms[parent_id];
+            transform = Transform {
+                local_to_world: outer.local_to_world * transform.local_to_world,
+                world_to_local: transform.world_to_local * outer.world_to_local,
+            };
+            parent = self.parents[parent_id];
+        }
+        transform
+    }
+
+    /// Returns the box enclosing every visible object in world space, leaving
+    /// out those that extend forever (like planes), or None if there are no
+    /// such objects.
+    pub fn bounds(&self) -> Option<Bounds3> {
+        (0..self.geometrys.len())
+            .filter(|&object_id| {
+                self.parents[object_id].is_none() && self.visibilitys[object_id] > 0.
+            })
+            .map(|object_id| self.object_bounds(object_id))
+            .filter(|bounds| bounds.is_finite())
+            .reduce(|a, b| a.union(b))
+    }
+
+    /// Returns the box enclosing the object in the space containing it. The
+    /// intersection or difference of two objects lies within the left one.
+    fn object_bounds(&self, object_id: ObjectId) -> Bounds3 {
+        let bounds = match self.geometrys[object_id] {
+            Geometry::Csg {
+                operation,
+                left,
+                right,
+            } => match operation {
+                CsgOperation::Union => self.object_bounds(left).union(self.object_bounds(right)),
+                CsgOperation::Intersection | CsgOperation::Difference => self.object_bounds(left),
             },
-        )
+            ref geometry => geometry.bounds(),
+        };
+        bounds.transform(self.transforms[object_id].local_to_world)
+    }
+
+    /// Returns the rectangle of the camera's frame that the object covers,
+    /// from (x0, y0) up to (but not including) (x1, y1), for re-rendering
+    /// with `Camera::render_region` after editing it. It's found by
+    /// projecting the corners of the object's world bounds onto the frame,
+    /// padded by REGION_PADDING pixels for antialiasing, and is None if the
+    /// object can't be seen. Objects which extend forever, or whose bounds
+    /// reach behind the camera, cover the whole frame.
+    ///
+    /// This only covers the object itself. Its reflection or refraction in
+    /// other objects, the light it lets through onto them, and its defocus
+    /// blur beyond the padding aren't, and need a full render to update.
+    pub fn affected_region(
+        &self,
+        camera: &Camera,
+        object_id: ObjectId,
+    ) -> Option<(usize, usize, usize, usize)> {
+        let whole_frame = Some((0, 0, camera.hsize, camera.vsize));
+        let mut bounds = self.object_bounds(object_id);
+        if let Some(parent) = self.parents[object_id] {
+            bounds = bounds.transform(self.world_transform(parent).local_to_world);
+        }
+        if !bounds.is_finite() {
+            return whole_frame;
+        }
+
+        let (min, max) = (bounds.min, bounds.max);
+        let corners = (0..8).map(|i| {
+            let corner = |bit: usize, min: f32, max: f32| if i & bit == 0 { min } else { max };
+            point3(
+                corner(1, min.x, max.x),
+                corner(2, min.y, max.y),
+                corner(4, min.z, max.z),
+            )
+        });
+        let projected: Vec<Option<(f32, f32)>> =
+            corners.map(|corner| camera.project(corner)).collect();
+        if projected.iter().all(Option::is_none) {
+            return None;
+        }
+        if projected.iter().any(Option::is_none) {
+            return whole_frame;
+        }
+
+        let inf = f32::INFINITY;
+        let (x0, y0, x1, y1) = projected
+            .iter()
+            .flatten()
+            .fold((inf, inf, -inf, -inf), |(x0, y0, x1, y1), &(x, y)| {
+                (x0.min(x), y0.min(y), x1.max(x), y1.max(y))
+            });
+        let clamp = |value: f32, size: usize| value.clamp(0., size as f32) as usize;
+        let (x0, x1) = (
+            clamp((x0 - REGION_PADDING).floor(), camera.hsize),
+            clamp((x1 + REGION_PADDING).ceil(), camera.hsize),
+        );
+        let (y0, y1) = (
+            clamp((y0 - REGION_PADDING).floor(), camera.vsize),
+            clamp((y1 + REGION_PADDING).ceil(), camera.vsize),
+        );
+        if x0 == x1 || y0 == y1 {
+            return None;
+        }
+        Some((x0, y0, x1, y1))
+    }
+
+    /// Calls `visit` with each intersection in front of the ray (t >= 0) with
+    /// the objects for which `keep` returns true. `keep` is called at most
+    /// once per object, and the intersections with each object are visited
+    /// together.
+    ///
+    /// `visit` returns the distance beyond which intersections are no longer
+    /// wanted, which lets the BVH (if built) skip the objects beyond it, and
+    /// a negative distance stops the search.
+    fn visit_intersections(
+        &self,
+        world_ray: Ray,
+        mut keep: impl FnMut(ObjectId) -> bool,
+        mut visit: impl FnMut(Intersection) -> f32,
+    ) {
+        let mut limit = f32::INFINITY;
+        let mut test = |object_id: ObjectId| {
+            if self.parents[object_id].is_none()
+                && self.visibilitys[object_id] > 0.
+                && keep(object_id)
+            {
+                self.intersect_object(object_id, world_ray, &mut |intersection| {
+                    if intersection.t >= 0. && limit >= 0. {
+                        limit = visit(intersection);
+                    }
+                });
+            }
+            limit
+        };
+
+        match self.bvh.get() {
+            Some(bvh) => bvh.traverse(world_ray, test),
+            None => {
+                for object_id in 0..self.geometrys.len() {
+                    if test(object_id) < 0. {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the nearest of the intersections found by
+    /// `visit_intersections`.
+    fn nearest_where(
+        &self,
+        world_ray: Ray,
+        keep: impl FnMut(ObjectId) -> bool,
+    ) -> Option<Intersection> {
+        let mut nearest: Option<Intersection> = None;
+        self.visit_intersections(world_ray, keep, |intersection| match nearest {
+            Some(n) if n.t <= intersection.t => n.t,
+            _ => {
+                nearest = Some(intersection);
+                intersection.t
+            }
+        });
+        nearest
     }
 
     /// Returns the nearest intersection (if any).
     pub fn nearest_intersection(&self, world_ray: Ray) -> Option<Intersection> {
-        self.intersections(world_ray)
-            .filter(|intersection| intersection.t >= 0.)
-            .min_by(|a, b| a.t.partial_cmp(&b.t).unwrap())
+        self.nearest_where(world_ray, |_| true)
     }
 
-    /// Whether the given point is considered to be in shadow.
-    pub fn is_shadowed(&self, point: Tuple4, light: Light) -> bool {
-        let v = light.position - point;
-        let distance = v.magnitude();
-        let direction = v.normalize();
+    /// Returns the nearest intersection (if any), skipping each partially
+    /// visible object at random in proportion to how much it has dissolved,
+    /// (for shadow rays) objects which don't cast shadows, and objects for
+    /// which `seen` returns false.
+    fn nearest_visible_intersection<R: Rng>(
+        &self,
+        rng: &mut R,
+        world_ray: Ray,
+        shadow_ray: bool,
+        seen: impl Fn(ObjectId) -> bool,
+    ) -> Option<Intersection> {
+        self.nearest_where(world_ray, |object_id| {
+            let visibility = self.visibilitys[object_id];
+            (!shadow_ray || self.casts_shadows[object_id])
+                && seen(object_id)
+                && (visibility >= 1. || rng.gen::<f32>() < visibility)
+        })
+    }
 
-        if let Some(intersection) = self.nearest_intersection(ray(point, direction)) {
-            intersection.t < distance
-        } else {
-            false
+    /// Returns the nearest visible intersection, as
+    /// `nearest_visible_intersection`, along with every intersection along
+    /// the ray's line (as `sorted_intersections`) if they were found on the
+    /// way: they are when there's no BVH, since every object is intersected
+    /// anyway, but with one only the objects near the ray are.
+    fn nearest_visible_intersection_on_line<R: Rng>(
+        &self,
+        rng: &mut R,
+        world_ray: Ray,
+        seen: impl Fn(ObjectId) -> bool,
+    ) -> (Option<Intersection>, Option<Vec<Intersection>>) {
+        if self.bvh.get().is_some() {
+            return (
+                self.nearest_visible_intersection(rng, world_ray, false, seen),
+                None,
+            );
+        }
+
+        let mut nearest: Option<Intersection> = None;
+        let mut intersections = vec![];
+        for object_id in 0..self.geometrys.len() {
+            if self.parents[object_id].is_some() || self.visibilitys[object_id] <= 0. {
+                continue;
+            }
+
+            let visibility = self.visibilitys[object_id];
+            let visible = seen(object_id) && (visibility >= 1. || rng.gen::<f32>() < visibility);
+            self.intersect_object(object_id, world_ray, &mut |intersection| {
+                intersections.push(intersection);
+                if visible && intersection.t >= 0. && nearest.is_none_or(|n| intersection.t < n.t) {
+                    nearest = Some(intersection);
+                }
+            });
         }
+
+        intersections.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+        (nearest, Some(intersections))
     }
 
-    /// Returns the indexes of refraction of the materials on either side of a
-    /// ray-object intersection, with n1 belonging to the material being
-    /// exited, and n2 belonging to the material being entered.
-    pub fn refractive_indexes(&self, world_ray: Ray, intersection: Intersection) -> (f32, f32) {
-        let mut n1 = 1.0;
-        let mut n2 = 1.0;
+    /// Returns the object the ray hits first, and which side of its surface
+    /// the ray hits, judged by the geometric normal (ignoring interpolated
+    /// normals) against the ray's direction. Back faces seen from outside a
+    /// closed mesh show where its triangles are wound the wrong way.
+    pub fn facing(&self, world_ray: Ray) -> Option<(ObjectId, Facing)> {
+        let intersection = self.nearest_intersection(world_ray)?;
+        let transform = self.world_transform(intersection.object_id);
+        let local_point = transform.world_to_local * world_ray.position(intersection.t);
+        let local_normal = self.geometrys[intersection.object_id]
+            .geometric_normal_at(local_point, intersection.face);
+        let mut world_normal = transform.world_to_local.transpose() * local_normal;
+        world_normal.w = 0.;
+
+        let facing = if world_normal.dot(world_ray.direction) <= 0. {
+            Facing::Front
+        } else {
+            Facing::Back
+        };
+        Some((intersection.object_id, facing))
+    }
+
+    /// Returns every object under the camera's (x, y) pixel, with the
+    /// distance along the pixel's ray to its nearest hit, nearest first: the
+    /// candidates for an editor to cycle through when picking among stacked
+    /// transparent objects. Hidden objects (of visibility 0) are skipped,
+    /// partially visible ones aren't, and CSG objects are reported as the
+    /// children whose surfaces were hit.
+    pub fn pick_all(&self, camera: &Camera, x: usize, y: usize) -> Vec<(ObjectId, f32)> {
+        let mut picks: Vec<(ObjectId, f32)> = vec![];
+        for intersection in self.intersections(camera.ray(x, y)) {
+            if intersection.t < 0. {
+                continue;
+            }
+            match picks
+                .iter_mut()
+                .find(|(id, _)| *id == intersection.object_id)
+            {
+                Some((_, t)) => *t = t.min(intersection.t),
+                None => picks.push((intersection.object_id, intersection.t)),
+            }
+        }
+        picks.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        picks
+    }
 
-        let mut containers: Vec<ObjectId> = vec![];
-        let mut all_intersections: Vec<Intersection> = self.intersections(world_ray).collect();
-        all_intersections.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+    /// Whether any object lies between the two points (strictly before `to`),
+    /// e.g. whether a target can be seen from an observer. Every object that
+    /// can be seen counts, whether or not it casts shadows or is transparent.
+    pub fn is_occluded(&self, from: Tuple4, to: Tuple4) -> bool {
+        let v = to - from;
+        let distance = v.magnitude();
+        if distance == 0. {
+            return false;
+        }
 
-        for i in all_intersections {
-            if i == intersection {
-                if containers.is_empty() {
-                    n1 = 1.0;
+        let mut occluded = false;
+        self.visit_intersections(
+            ray(from, v / distance),
+            |_| true,
+            |intersection| {
+                if intersection.t < distance {
+                    occluded = true;
+                    -1.
                 } else {
-                    n1 = self.materials[*containers.last().unwrap()].refractive_index;
+                    distance
                 }
-            }
+            },
+        );
+        occluded
+    }
+
+    /// Runs `is_occluded` for each (from, to) pair, spread across the
+    /// available threads. Build the BVH first when there are many objects.
+    pub fn batch_occlusion(&self, pairs: &[(Tuple4, Tuple4)]) -> Vec<bool> {
+        self.batch(pairs, |&(from, to)| self.is_occluded(from, to))
+    }
+
+    /// Runs `nearest_intersection` for each ray, spread across the available
+    /// threads. Build the BVH first when there are many objects.
+    pub fn batch_raycast(&self, rays: &[Ray]) -> Vec<Option<Intersection>> {
+        self.batch(rays, |&ray| self.nearest_intersection(ray))
+    }
+
+    /// Answers each query, writing the results in place. Small batches are
+    /// answered on the calling thread, since spawning threads would cost more
+    /// than the queries; larger ones are split into one contiguous chunk per
+    /// thread.
+    fn batch<Q: Sync, T: Copy + Default + Send>(
+        &self,
+        queries: &[Q],
+        query: impl Fn(&Q) -> T + Sync,
+    ) -> Vec<T> {
+        const MIN_CHUNK: usize = 1024;
+
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk = queries.len().div_ceil(threads).max(MIN_CHUNK);
+        batch_in_chunks(queries, chunk, query)
+    }
+
+    /// Whether any of the light is blocked on its way to the given point.
+    /// Fully transparent objects let all of the light through, so they don't
+    /// count.
+    pub fn is_shadowed(&self, point: Tuple4, light: &Light) -> bool {
+        self.transmittance(point, light.position, |object_id| {
+            self.casts_shadows[object_id]
+        }) < 1.
+    }
 
-            if containers.contains(&i.object_id) {
-                containers.retain(|o| o != &i.object_id);
+    /// Returns the fraction of the light reaching the given point, found by
+    /// tracing a shadow ray toward each of the light's sample points. Each
+    /// object along the way lets through only the transparent part of the
+    /// light, so a point light behind an opaque object gives 0.
+    pub fn intensity_at<R: Rng>(&self, rng: &mut R, point: Tuple4, light: &Light) -> f32 {
+        self.light_at(rng, point, light, true).0
+    }
+
+    /// Returns the fraction of the light reaching the given point (all of
+    /// it, without `shadows`), and the average color of the light which
+    /// does. The color is white unless the light comes from an emitter, in
+    /// which case each sample point on its surface gives off its own color
+    /// and counts for the area it stands for.
+    pub fn light_at<R: Rng>(
+        &self,
+        rng: &mut R,
+        point: Tuple4,
+        light: &Light,
+        shadows: bool,
+    ) -> (f32, Color) {
+        self.light_at_where(rng, point, light, shadows, |_| true, &mut 0)
+    }
+
+    /// Like `light_at`, but only objects for which `casts` returns true
+    /// cast shadows, adding the number of shadow rays traced to `rays`.
+    fn light_at_where<R: Rng>(
+        &self,
+        rng: &mut R,
+        point: Tuple4,
+        light: &Light,
+        shadows: bool,
+        casts: impl Fn(ObjectId) -> bool,
+        rays: &mut usize,
+    ) -> (f32, Color) {
+        let emitter_id = light.emitter.as_ref().map(|emitter| emitter.object_id);
+        let samples = light.samples();
+        // With adaptive shadows, a coarse grid of cells, spread evenly from
+        // corner to corner, is sampled first.
+        let (coarse_usteps, coarse_vsteps) = match light.adaptive_shadow_steps {
+            Some(steps) if shadows => (steps.min(light.usteps), steps.min(light.vsteps)),
+            _ => (light.usteps, light.vsteps),
+        };
+        let coarse = |i: usize, coarse_steps: usize, steps: usize| {
+            if coarse_steps == 1 {
+                steps / 2
             } else {
-                containers.push(i.object_id);
+                i * (steps - 1) / (coarse_steps - 1)
             }
+        };
+        let is_coarse = |steps: usize, coarse_steps: usize, cell: usize| {
+            (0..coarse_steps).any(|i| coarse(i, coarse_steps, steps) == cell)
+        };
+        let initial = coarse_usteps * coarse_vsteps;
+        let cells = (0..initial)
+            .map(|i| {
+                let u = coarse(i % coarse_usteps, coarse_usteps, light.usteps);
+                let v = coarse(i / coarse_usteps, coarse_vsteps, light.vsteps);
+                (u, v)
+            })
+            .chain(
+                (0..samples)
+                    .map(|i| (i % light.usteps, i / light.usteps))
+                    .filter(|&(u, v)| {
+                        initial < samples
+                            && !(is_coarse(light.usteps, coarse_usteps, u)
+                                && is_coarse(light.vsteps, coarse_vsteps, v))
+                    }),
+            );
 
-            if i == intersection {
-                if containers.is_empty() {
-                    n2 = 1.0;
-                } else {
-                    n2 = self.materials[*containers.last().unwrap()].refractive_index;
-                }
+        let mut total = 0.;
+        let mut visible = 0.;
+        let mut visible_color = Color::BLACK;
+        let mut color = Color::BLACK;
+        let (mut least, mut most) = (f32::INFINITY, f32::NEG_INFINITY);
+        for (i, (u, v)) in cells.enumerate() {
+            // The coarse grid agrees the point is fully lit or fully shadowed.
+            if i == initial && least == most {
                 break;
             }
+
+            let light_point = light.point_on(rng, u, v);
+            let (emission, weight) = match &light.emitter {
+                Some(emitter) => emitter.emission_at(rng, light_point),
+                None => (Color::WHITE, 1.),
+            };
+            let transmittance = if shadows {
+                *rays += 1;
+                self.transmittance(point, light_point, |object_id| {
+                    let visibility = self.visibilitys[object_id];
+                    Some(object_id) != emitter_id
+                        && self.casts_shadows[object_id]
+                        && casts(object_id)
+                        && (visibility >= 1. || rng.gen::<f32>() < visibility)
+                })
+            } else {
+                1.
+            };
+            if i < initial {
+                least = least.min(transmittance);
+                most = most.max(transmittance);
+            }
+
+            total += weight;
+            visible += weight * transmittance;
+            visible_color = visible_color + emission * (weight * transmittance);
+            color = color + emission * weight;
+        }
+
+        let color = if light.emitter.is_none() {
+            Color::WHITE
+        } else if visible > 0. {
+            visible_color * (1. / visible)
+        } else {
+            color * (1. / total)
+        };
+        (visible / total, color)
+    }
+
+    /// Returns the fraction of the light passing from the light position to
+    /// the point, multiplying in the transparency of each object (for which
+    /// `keep` returns true) that lies between them. An object is counted
+    /// once, however many of its surfaces the shadow ray crosses.
+    fn transmittance(
+        &self,
+        point: Tuple4,
+        light_position: Tuple4,
+        keep: impl FnMut(ObjectId) -> bool,
+    ) -> f32 {
+        let v = light_position - point;
+        let distance = v.magnitude();
+        let direction = v.normalize();
+
+        let mut previous = None;
+        let mut transmittance = 1.;
+        self.visit_intersections(ray(point, direction), keep, |intersection| {
+            if intersection.t < distance && previous != Some(intersection.object_id) {
+                previous = Some(intersection.object_id);
+                transmittance *= self.materials[intersection.object_id].transparency;
+            }
+            if transmittance > 0. {
+                distance
+            } else {
+                -1.
+            }
+        });
+        transmittance
+    }
+
+    /// Returns the indexes of refraction of the materials on either side of a
+    /// ray-object intersection, with n1 belonging to the material being
+    /// exited, and n2 belonging to the material being entered.
+    pub fn refractive_indexes(&self, world_ray: Ray, intersection: Intersection) -> (f32, f32) {
+        let intersections = self.sorted_intersections(world_ray);
+        let (exited, entered, _) = refractive_containers(intersection, &intersections);
+        (
+            self.refractive_index(exited),
+            self.refractive_index(entered),
+        )
+    }
+
+    /// Returns the index of refraction inside the object, or of empty space.
+    fn refractive_index(&self, object_id: Option<ObjectId>) -> f32 {
+        object_id.map_or(1.0, |id| self.materials[id].refractive_index)
+    }
+
+    /// Returns every intersection between the ray's line and the scene (as
+    /// `intersections`), sorted by distance.
+    fn sorted_intersections(&self, world_ray: Ray) -> Vec<Intersection> {
+        let mut intersections: Vec<Intersection> = self.intersections(world_ray).collect();
+        intersections.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+        intersections
+    }
+
+    /// Computes what shading needs to know about the ray's intersection.
+    ///
+    /// The objects on either side of the surface (and so n1 and n2) are found
+    /// from `intersections`, every intersection along the ray's line sorted
+    /// by distance, as returned by `intersections`. Leave it empty to skip
+    /// them where there's no refraction; the hit is then taken to be
+    /// surrounded by empty space.
+    pub fn prepare_computations(
+        &self,
+        world_ray: Ray,
+        intersection: Intersection,
+        intersections: &[Intersection],
+        options: &RenderOptions,
+    ) -> HitComputations {
+        let object_id = intersection.object_id;
+        let point = world_ray.position(intersection.t);
+        let eyev = -world_ray.direction;
+        let transform = self.world_transform(object_id);
+        let geometry = &self.geometrys[object_id];
+        let mut normalv = outward_world_normal_at(
+            transform,
+            geometry,
+            point,
+            intersection.uv,
+            intersection.face,
+        );
+        let inside = normalv.dot(eyev) < 0.;
+        if inside {
+            normalv = -normalv;
+        }
+
+        let (exited, entered, next_t) = refractive_containers(intersection, intersections);
+        let offset = options.surface_offset_at(point, intersection.t);
+
+        // When another surface is in contact (or nearly so), keep the refracted
+        // ray from starting beyond it.
+        let under_offset =
+            next_t.map_or(offset, |next_t| offset.min((next_t - intersection.t) / 2.));
+
+        let over_point = point + normalv * offset;
+        let shading_point = geometry.shading_point(
+            transform.world_to_local * point,
+            intersection.uv,
+            intersection.face,
+        );
+        let shadow_point = match shading_point {
+            Some(local_point) if !inside => {
+                transform.local_to_world * local_point + normalv * offset
+            }
+            _ => over_point,
+        };
+
+        HitComputations {
+            t: intersection.t,
+            object_id,
+            point,
+            eyev,
+            normalv,
+            inside,
+            over_point,
+            under_point: point - normalv * under_offset,
+            shadow_point,
+            reflectv: world_ray.direction.reflect(normalv),
+            exited,
+            entered,
+            n1: self.refractive_index(exited),
+            n2: self.refractive_index(entered),
         }
+    }
+
+    /// Sets a procedural sky as the background, and adds a light standing in
+    /// for the sun.
+    pub fn set_sky(&mut self, sun_direction: Tuple4, haze: f32, sun_intensity: f32) {
+        let sky = Sky::new(sun_direction, haze, sun_intensity);
+        self.set_background(sky);
+        self.add_light(sky.sun_light());
+    }
+
+    /// Sets what rays that miss every object see (a color, gradient, sky,
+    /// texture, or cube map), replacing the previous background. It is black
+    /// by default.
+    pub fn set_background(&mut self, background: impl Into<Background>) {
+        self.background = background.into();
+        self.generation += 1;
+    }
 
-        (n1, n2)
+    /// Returns what rays that miss every object see.
+    pub fn background(&self) -> &Background {
+        &self.background
     }
 
     /// Adds the light to the scene.
     pub fn add_light(&mut self, light: Light) {
         self.lights.push(light);
+        self.generation += 1;
+        self.record(Edit::RemoveLight(self.lights.len() - 1));
+    }
+
+    /// Replaces the light at the given index (in the order they were added).
+    pub fn set_light(&mut self, index: usize, light: Light) {
+        let inverse = self.apply(Edit::SetLight(index, light));
+        self.record(inverse);
+    }
+
+    /// Removes the light at the given index, returning it. The indices of
+    /// the lights after it shift down by one.
+    pub fn remove_light(&mut self, index: usize) -> Light {
+        let light = self.lights[index].clone();
+        let inverse = self.apply(Edit::RemoveLight(index));
+        self.record(inverse);
+        light
+    }
+
+    /// Adds a light given off by the surface of the object, sampled in a
+    /// grid of usteps by vsteps cells. Each point gives off the intensity
+    /// times the color of the object's texture there, so a textured panel
+    /// casts patterned light. The object doesn't shadow its own light, and
+    /// the light stays where the object was when it was added.
+    pub fn add_emitter(&mut self, id: ObjectId, usteps: usize, vsteps: usize, intensity: Color) {
+        let emitter = Emitter {
+            object_id: id,
+            geometry: self.geometrys[id].clone(),
+            transform: self.world_transform(id),
+            texture: self.materials[id].texture.clone(),
+        };
+        self.add_light(Light::emitter(emitter, usteps, vsteps, intensity));
     }
 
     /// Adds the object to the scene, returning its ID.
+    ///
+    /// A CSG object takes ownership of its children, which must already be
+    /// in the scene (and not belong to another CSG object). From then on
+    /// they're only seen as part of it, placed relative to it.
     pub fn add_object(&mut self, object: Object) -> ObjectId {
         let object_id = self.transforms.len();
 
         self.transforms.push(object.transform);
         self.materials.push(object.material);
         self.geometrys.push(object.geometry);
+        self.invertible.push(object.transform.is_invertible());
+        self.visibilitys.push(object.visibility);
+        self.casts_shadows.push(object.casts_shadow);
+        self.layers.push(object.layer);
+        self.parents.push(None);
+        self.adopt_children(object_id);
+        self.bvh = OnceLock::new();
+        self.generation += 1;
 
         debug_assert!(
             (self.transforms.len() == self.materials.len())
                 == (self.geometrys.len() == object_id + 1)
         );
 
+        self.record(Edit::RemoveObject(object_id));
         object_id
     }
-}
-
-/// Computes the Schlick approximation for the given intersection.
-pub fn schlick(eyev: Tuple4, normalv: Tuple4, n1: f32, n2: f32) -> f32 {
-    let mut cos = eyev.dot(normalv);
 
-    if n1 > n2 {
-        let n = n1 / n2;
-        let sin2_t = n * n * (1. - cos * cos);
+    /// Makes the object (if it's a CSG object) the parent of its children.
+    fn adopt_children(&mut self, object_id: ObjectId) {
+        if let Geometry::Csg { left, right, .. } = self.geometrys[object_id] {
+            for child in [left, right] {
+                assert!(
+                    child < object_id && self.parents[child].is_none() && left != right,
+                    "CSG children must be distinct objects added before their parent, without another parent"
+                );
+                self.parents[child] = Some(object_id);
+            }
+        }
+    }
 
-        if sin2_t > 1.0 {
-            // Total internal reflection.
-            return 1.0;
+    /// Releases the object's children (if it's a CSG object), which become
+    /// standalone objects again.
+    fn release_children(&mut self, object_id: ObjectId) {
+        if let Geometry::Csg { left, right, .. } = self.geometrys[object_id] {
+            self.parents[left] = None;
+            self.parents[right] = None;
+        }
+    }
+
+    /// Returns the number of objects in the scene, including the children of
+    /// CSG objects.
+    pub fn object_count(&self) -> usize {
+        self.geometrys.len()
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    /// Returns the render layer the object belongs to.
+    pub fn layer(&self, object_id: ObjectId) -> u8 {
+        self.layers[object_id]
+    }
+
+    /// Returns the render layers of the objects (apart from the children of
+    /// CSG objects, which belong to their parents' layers), in order.
+    pub fn layers(&self) -> Vec<u8> {
+        let mut layers: Vec<u8> = (0..self.layers.len())
+            .filter(|&object_id| self.parents[object_id].is_none())
+            .map(|object_id| self.layers[object_id])
+            .collect();
+        layers.sort_unstable();
+        layers.dedup();
+        layers
+    }
+
+    /// Whether rays see the object when rendering `options.layer`: primary
+    /// rays (from the camera) only see the objects in the layer, and other
+    /// rays see the rest too unless the layer hides them.
+    fn in_layer(&self, object_id: ObjectId, options: &RenderOptions, primary: bool) -> bool {
+        match options.layer {
+            RenderLayer::All => true,
+            RenderLayer::Background => !primary,
+            RenderLayer::Objects {
+                layer,
+                others_visible,
+            }
+            | RenderLayer::Matte {
+                layer,
+                others_visible,
+            } => self.layers[object_id] == layer || (others_visible && !primary),
+        }
+    }
+
+    pub fn material(&self, object_id: ObjectId) -> &Material {
+        &self.materials[object_id]
+    }
+
+    /// Returns the object's material for modification.
+    pub fn material_mut(&mut self, object_id: ObjectId) -> &mut Material {
+        self.record(Edit::SetMaterial(
+            object_id,
+            self.materials[object_id].clone(),
+        ));
+        self.generation += 1;
+        &mut self.materials[object_id]
+    }
+
+    pub fn transform(&self, object_id: ObjectId) -> &Transform {
+        &self.transforms[object_id]
+    }
+
+    /// Returns the object's transform for modification. When the returned
+    /// guard is dropped, the inverse is recomputed from `local_to_world`
+    /// (so only it needs to be changed), and the scene is updated to match.
+    pub fn transform_mut(&mut self, object_id: ObjectId) -> TransformMut<'_> {
+        self.record(Edit::SetTransform(object_id, self.transforms[object_id]));
+        self.transform_mut_unrecorded(object_id)
+    }
+
+    /// Replaces the object's transform.
+    pub fn set_transform(&mut self, object_id: ObjectId, transform: Transform) {
+        *self.transform_mut(object_id) = transform;
+    }
+
+    fn transform_mut_unrecorded(&mut self, object_id: ObjectId) -> TransformMut<'_> {
+        self.bvh = OnceLock::new();
+        self.generation += 1;
+        TransformMut {
+            scene: self,
+            object_id,
+        }
+    }
+
+    pub fn geometry(&self, object_id: ObjectId) -> &Geometry {
+        &self.geometrys[object_id]
+    }
+
+    /// Returns the object's geometry for modification. When the returned
+    /// guard is dropped the scene is updated to match, so a CSG object may
+    /// be given new children (following the rules of `add_object`).
+    pub fn geometry_mut(&mut self, object_id: ObjectId) -> GeometryMut<'_> {
+        self.record(Edit::SetGeometry(
+            object_id,
+            self.geometrys[object_id].clone(),
+        ));
+        self.geometry_mut_unrecorded(object_id)
+    }
+
+    fn geometry_mut_unrecorded(&mut self, object_id: ObjectId) -> GeometryMut<'_> {
+        self.release_children(object_id);
+        self.bvh = OnceLock::new();
+        self.generation += 1;
+        GeometryMut {
+            scene: self,
+            object_id,
+        }
+    }
+
+    /// Removes the object from the scene, returning it. The IDs of the
+    /// objects added after it shift down by one.
+    ///
+    /// Removing a CSG object leaves its children in the scene as standalone
+    /// objects; the children themselves can't be removed while they belong
+    /// to it. Removing an emitter's object removes its light too.
+    pub fn remove_object(&mut self, object_id: ObjectId) -> Object {
+        let (object, lights) = self.take_object(object_id);
+        self.record(Edit::InsertObject(
+            object_id,
+            Box::new(object.clone()),
+            lights,
+        ));
+        object
+    }
+
+    /// Removes the object, returning it with the lights it emitted and their
+    /// indices.
+    fn take_object(&mut self, object_id: ObjectId) -> (Object, Vec<(usize, Light)>) {
+        assert!(
+            self.parents[object_id].is_none(),
+            "object {} belongs to a CSG object and can't be removed",
+            object_id
+        );
+        self.release_children(object_id);
+
+        let object = Object {
+            transform: self.transforms.remove(object_id),
+            geometry: self.geometrys.remove(object_id),
+            material: self.materials.remove(object_id),
+            parent: None,
+            visibility: self.visibilitys.remove(object_id),
+            casts_shadow: self.casts_shadows.remove(object_id),
+            layer: self.layers.remove(object_id),
+        };
+        self.invertible.remove(object_id);
+        self.parents.remove(object_id);
+
+        // Renumber the references to the objects which moved.
+        let renumber = |id: &mut ObjectId| {
+            if *id > object_id {
+                *id -= 1;
+            }
+        };
+        for parent in self.parents.iter_mut().flatten() {
+            renumber(parent);
+        }
+        for geometry in &mut self.geometrys {
+            if let Geometry::Csg { left, right, .. } = geometry {
+                renumber(left);
+                renumber(right);
+            }
+        }
+        let mut emitted = vec![];
+        for (index, light) in std::mem::take(&mut self.lights).into_iter().enumerate() {
+            if light.emitter.as_ref().map(|emitter| emitter.object_id) == Some(object_id) {
+                emitted.push((index, light));
+            } else {
+                self.lights.push(light);
+            }
+        }
+        for emitter in self
+            .lights
+            .iter_mut()
+            .filter_map(|light| light.emitter.as_mut())
+        {
+            renumber(&mut emitter.object_id);
+        }
+
+        self.bvh = OnceLock::new();
+        self.generation += 1;
+        (object, emitted)
+    }
+
+    /// Puts a removed object back under its old ID, shifting the IDs of
+    /// those after it up by one, and its lights back at their old indices.
+    fn insert_object(&mut self, object_id: ObjectId, object: Object, lights: Vec<(usize, Light)>) {
+        let renumber = |id: &mut ObjectId| {
+            if *id >= object_id {
+                *id += 1;
+            }
+        };
+        for parent in self.parents.iter_mut().flatten() {
+            renumber(parent);
+        }
+        for geometry in &mut self.geometrys {
+            if let Geometry::Csg { left, right, .. } = geometry {
+                renumber(left);
+                renumber(right);
+            }
+        }
+        for emitter in self
+            .lights
+            .iter_mut()
+            .filter_map(|light| light.emitter.as_mut())
+        {
+            renumber(&mut emitter.object_id);
+        }
+
+        self.transforms.insert(object_id, object.transform);
+        self.materials.insert(object_id, object.material);
+        self.geometrys.insert(object_id, object.geometry);
+        self.invertible
+            .insert(object_id, object.transform.is_invertible());
+        self.visibilitys.insert(object_id, object.visibility);
+        self.casts_shadows.insert(object_id, object.casts_shadow);
+        self.layers.insert(object_id, object.layer);
+        self.parents.insert(object_id, None);
+        self.adopt_children(object_id);
+        for (index, light) in lights {
+            self.lights.insert(index, light);
+        }
+
+        self.bvh = OnceLock::new();
+        self.generation += 1;
+    }
+
+    /// Builds a bounding volume hierarchy over the objects, so that each ray
+    /// is only tested against the objects near it. Changing the objects
+    /// discards the hierarchy; `prepare_render` (and so every render)
+    /// rebuilds it if need be, so this is only needed to use one outside of
+    /// renders.
+    ///
+    /// Objects which extend forever (such as planes) are tested against
+    /// every ray, as before. CSG objects are placed in the hierarchy whole.
+    pub fn build_bvh(&mut self) {
+        self.bvh = OnceLock::from(self.new_bvh());
+    }
+
+    fn new_bvh(&self) -> Bvh {
+        let objects = (0..self.geometrys.len())
+            .filter(|&object_id| self.invertible[object_id] && self.parents[object_id].is_none())
+            .map(|object_id| (object_id, self.object_bounds(object_id)));
+        Bvh::new(objects)
+    }
+
+    /// Returns warnings about problems with the scene which don't prevent it
+    /// from rendering, such as objects that are skipped because their
+    /// transforms can't be inverted.
+    pub fn validate(&self) -> Vec<String> {
+        self.invertible
+            .iter()
+            .enumerate()
+            .filter(|(_, &invertible)| !invertible)
+            .map(|(object_id, _)| {
+                format!(
+                    "object {} has a non-invertible transform and will not be rendered",
+                    object_id
+                )
+            })
+            .collect()
+    }
+}
+
+/// A transform in a scene being modified, which brings the scene up to date
+/// with the change when dropped.
+pub struct TransformMut<'a> {
+    scene: &'a mut Scene,
+    object_id: ObjectId,
+}
+
+impl Deref for TransformMut<'_> {
+    type Target = Transform;
+
+    fn deref(&self) -> &Transform {
+        &self.scene.transforms[self.object_id]
+    }
+}
+
+impl DerefMut for TransformMut<'_> {
+    fn deref_mut(&mut self) -> &mut Transform {
+        &mut self.scene.transforms[self.object_id]
+    }
+}
+
+impl Drop for TransformMut<'_> {
+    fn drop(&mut self) {
+        let transform =
+            Transform::from_matrix(self.scene.transforms[self.object_id].local_to_world);
+        self.scene.transforms[self.object_id] = transform;
+        self.scene.invertible[self.object_id] = transform.is_invertible();
+    }
+}
+
+/// A geometry in a scene being modified, which brings the scene up to date
+/// with the change when dropped.
+pub struct GeometryMut<'a> {
+    scene: &'a mut Scene,
+    object_id: ObjectId,
+}
+
+impl Deref for GeometryMut<'_> {
+    type Target = Geometry;
+
+    fn deref(&self) -> &Geometry {
+        &self.scene.geometrys[self.object_id]
+    }
+}
+
+impl DerefMut for GeometryMut<'_> {
+    fn deref_mut(&mut self) -> &mut Geometry {
+        &mut self.scene.geometrys[self.object_id]
+    }
+}
+
+impl Drop for GeometryMut<'_> {
+    fn drop(&mut self) {
+        self.scene.adopt_children(self.object_id);
+    }
+}
+
+/// Returns the objects on either side of a ray-object intersection, with
+/// the first being exited and the second being entered (None means empty
+/// space), and the distance along the ray to the next boundary (if any),
+/// given every intersection along the ray's line sorted by distance.
+///
+/// Boundaries within CONTACT_EPSILON of the intersection (such as a sphere
+/// resting on a slab) are treated as a single interface between the
+/// object exited and the one ultimately entered.
+fn refractive_containers(
+    intersection: Intersection,
+    intersections: &[Intersection],
+) -> (Option<ObjectId>, Option<ObjectId>, Option<f32>) {
+    let mut exited = None;
+
+    let mut containers: Vec<ObjectId> = vec![];
+    let mut found = false;
+    for &i in intersections {
+        if i == intersection || (i.t - intersection.t).abs() < CONTACT_EPSILON {
+            if !found {
+                exited = containers.last().copied();
+                found = true;
+            }
+        } else if found {
+            return (exited, containers.last().copied(), Some(i.t));
+        }
+
+        if containers.contains(&i.object_id) {
+            containers.retain(|o| o != &i.object_id);
+        } else {
+            containers.push(i.object_id);
+        }
+    }
+
+    (exited, containers.last().copied().filter(|_| found), None)
+}
+
+/// Computes the Schlick approximation for the given intersection.
+pub fn schlick(eyev: Tuple4, normalv: Tuple4, n1: f32, n2: f32) -> f32 {
+    let mut cos = eyev.dot(normalv);
+
+    if n1 > n2 {
+        let n = n1 / n2;
+        let sin2_t = n * n * (1. - cos * cos);
+
+        if sin2_t > 1.0 {
+            // Total internal reflection.
+            return 1.0;
         }
 
         let cos_t = (1. - sin2_t).sqrt();
@@ -234,18 +1735,46 @@ pub fn schlick(eyev: Tuple4, normalv: Tuple4, n1: f32, n2: f32) -> f32 {
     (1. - r0).mul_add((1. - cos).powi(5), r0)
 }
 
-/// Computes the world normal vector at the given point.
+/// Answers each query, on the calling thread if they fit in one chunk of the
+/// given size, or else on a thread per chunk.
+fn batch_in_chunks<Q: Sync, T: Copy + Default + Send>(
+    queries: &[Q],
+    chunk: usize,
+    query: impl Fn(&Q) -> T + Sync,
+) -> Vec<T> {
+    let mut results = vec![T::default(); queries.len()];
+    if queries.len() <= chunk {
+        for (result, q) in results.iter_mut().zip(queries) {
+            *result = query(q);
+        }
+        return results;
+    }
+
+    std::thread::scope(|s| {
+        for (results, queries) in results.chunks_mut(chunk).zip(queries.chunks(chunk)) {
+            let query = &query;
+            s.spawn(move || {
+                for (result, q) in results.iter_mut().zip(queries) {
+                    *result = query(q);
+                }
+            });
+        }
+    });
+    results
+}
+
+/// Computes the world normal vector at the given point, interpolating across
+/// the surface using the intersection's (u, v) coordinates where available,
+/// on the triangle hit for a mesh.
 pub fn world_normal_at(
     transform: Transform,
-    geometry: Geometry,
+    geometry: &Geometry,
     world_point: Tuple4,
     eye_vector: Tuple4,
+    uv: Option<(f32, f32)>,
+    face: usize,
 ) -> Tuple4 {
-    let local_point = transform.world_to_local * world_point;
-    let local_normal = geometry.normal_at(local_point);
-    let mut world_normal = transform.world_to_local.transpose() * local_normal;
-    world_normal.w = 0.;
-    world_normal = world_normal.normalize();
+    let mut world_normal = outward_world_normal_at(transform, geometry, world_point, uv, face);
 
     if world_normal.dot(eye_vector) < 0. {
         // The ray originates inside the object.
@@ -255,13 +1784,28 @@ pub fn world_normal_at(
     world_normal
 }
 
+/// Returns the surface normal at the point, pointing out of the object.
+fn outward_world_normal_at(
+    transform: Transform,
+    geometry: &Geometry,
+    world_point: Tuple4,
+    uv: Option<(f32, f32)>,
+    face: usize,
+) -> Tuple4 {
+    let local_point = transform.world_to_local * world_point;
+    let local_normal = geometry.normal_at_uv(local_point, uv, face);
+    let mut world_normal = transform.world_to_local.transpose() * local_normal;
+    world_normal.w = 0.;
+    world_normal.normalize()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::texture::*;
+    use crate::brdf::*;
+    use crate::canvas::*;
+    use crate::cube_map::*;
     use assert_approx_eq::assert_approx_eq;
-    use rand::rngs::SmallRng;
-    use rand::SeedableRng;
     use test::Bencher;
 
     fn default_scene() -> Scene {
@@ -323,159 +1867,1749 @@ mod tests {
     fn there_is_no_shadow_when_nothing_is_collinear_with_point_and_light() {
         let scene = default_scene();
         let p = point3(0., 10., 0.);
-        assert_eq!(scene.is_shadowed(p, scene.lights[0]), false);
+        assert_eq!(scene.is_shadowed(p, &scene.lights[0]), false);
     }
 
     #[test]
     fn the_shadow_when_an_object_is_between_the_point_and_the_light() {
         let scene = default_scene();
         let p = point3(10., -10., 10.);
-        assert_eq!(scene.is_shadowed(p, scene.lights[0]), true);
+        assert_eq!(scene.is_shadowed(p, &scene.lights[0]), true);
     }
 
     #[test]
     fn there_is_no_shadow_when_an_object_is_behind_the_light() {
         let scene = default_scene();
         let p = point3(-20., 20., -20.);
-        assert_eq!(scene.is_shadowed(p, scene.lights[0]), false);
+        assert_eq!(scene.is_shadowed(p, &scene.lights[0]), false);
     }
 
     #[test]
     fn there_is_no_shadow_when_an_object_is_behind_the_point() {
         let scene = default_scene();
         let p = point3(-2., 2., -2.);
-        assert_eq!(scene.is_shadowed(p, scene.lights[0]), false);
+        assert_eq!(scene.is_shadowed(p, &scene.lights[0]), false);
+    }
+
+    #[test]
+    fn shading_an_intersection() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let scene = default_scene();
+        let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
+        let c = scene.color_at(&mut rng, r);
+
+        assert_approx_eq!(c.r, 0.38066, 1e-5);
+        assert_approx_eq!(c.g, 0.47583, 1e-5);
+        assert_approx_eq!(c.b, 0.2855, 1e-5);
+    }
+
+    #[test]
+    fn shading_an_intersection_from_the_inside() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = default_scene();
+        scene.lights = vec![Light::new(point3(0., 0.25, 0.), Color::new(1., 1., 1.))];
+        let r = ray(point3(0., 0., 0.), vector3(0., 0., 1.));
+        let i = scene.nearest_intersection(r).unwrap();
+        let c = scene.color_at(&mut rng, r);
+
+        assert_eq!(i.t, 0.5);
+        assert_eq!(i.object_id, 1);
+        assert_approx_eq!(c.r, 0.90498, 1e-5);
+        assert_approx_eq!(c.g, 0.90498, 1e-5);
+        assert_approx_eq!(c.b, 0.90498, 1e-5);
+    }
+
+    #[test]
+    fn the_color_when_a_ray_misses() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let scene = default_scene();
+        let r = ray(point3(0., 0., -5.), vector3(0., 1., 0.));
+        let c = scene.color_at(&mut rng, r);
+
+        assert_approx_eq!(c.r, 0.0, 1e-5);
+        assert_approx_eq!(c.g, 0.0, 1e-5);
+        assert_approx_eq!(c.b, 0.0, 1e-5);
+    }
+
+    #[test]
+    fn the_color_when_a_ray_misses_with_an_environment() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = default_scene();
+        let face = |color: Color| {
+            let mut canvas = Canvas::new(1, 1);
+            canvas.set_color(0, 0, color);
+            canvas
+        };
+        let sky = Color::new(0., 0., 1.);
+        scene.set_background(
+            CubeMap::new([
+                face(Color::BLACK),
+                face(Color::BLACK),
+                face(sky),
+                face(Color::BLACK),
+                face(Color::BLACK),
+                face(Color::BLACK),
+            ])
+            .unwrap(),
+        );
+
+        let r = ray(point3(0., 0., -5.), vector3(0., 1., 0.));
+        assert_eq!(scene.color_at(&mut rng, r), sky);
+    }
+
+    #[test]
+    fn the_color_when_a_ray_hits() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let scene = default_scene();
+        let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
+        let c = scene.color_at(&mut rng, r);
+
+        assert_approx_eq!(c.r, 0.38066, 1e-5);
+        assert_approx_eq!(c.g, 0.47583, 1e-5);
+        assert_approx_eq!(c.b, 0.2855, 1e-5);
+    }
+
+    #[test]
+    fn the_color_with_an_intersection_behind_the_ray() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(-10., 10., -10.), Color::new(1., 1., 1.)));
+        scene.add_object(
+            Object::new().geometry(Geometry::sphere()).material(
+                Material::new()
+                    .color(Color::new(0.8, 1.0, 0.6))
+                    .ambient(1.0)
+                    .diffuse(0.7)
+                    .specular(0.2),
+            ),
+        );
+        let expected_color = Color::new(0.3, 0.5, 0.1);
+        scene.add_object(
+            Object::new()
+                .material(Material::new().ambient(1.0).color(expected_color))
+                .geometry(Geometry::sphere())
+                .transform(Transform::new().scale(0.5, 0.5, 0.5)),
+        );
+
+        let r = ray(point3(0., 0., 0.75), vector3(0., 0., -1.));
+        let c = scene.color_at(&mut rng, r);
+
+        assert_eq!(c, expected_color);
+    }
+
+    #[test]
+    fn point_lights_evaluate_the_light_intensity_at_a_point() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let scene = default_scene();
+        let light = scene.lights[0].clone();
+        let examples = vec![
+            (point3(0., 1.0001, 0.), 1.),
+            (point3(-1.0001, 0., 0.), 1.),
+            (point3(0., 0., -1.0001), 1.),
+            (point3(0., 0., 1.0001), 0.),
+            (point3(1.0001, 0., 0.), 0.),
+            (point3(0., -1.0001, 0.), 0.),
+            (point3(0., 0., 0.), 0.),
+        ];
+        for (point, expected) in examples {
+            assert_eq!(scene.intensity_at(&mut rng, point, &light), expected);
+        }
+    }
+
+    #[test]
+    fn area_lights_give_partial_intensity_in_the_penumbra() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let scene = default_scene();
+        let light = Light::area(
+            point3(-0.5, -0.5, -5.),
+            vector3(1., 0., 0.),
+            4,
+            vector3(0., 1., 0.),
+            4,
+            Color::WHITE,
+        );
+
+        // Fully hidden behind the sphere, and in full view of the light.
+        assert_eq!(scene.intensity_at(&mut rng, point3(0., 0., 2.), &light), 0.);
+        assert_eq!(
+            scene.intensity_at(&mut rng, point3(0., 0., -2.), &light),
+            1.
+        );
+
+        // Midway behind the edge of the sphere.
+        for &point in &[point3(1.5, 0., 2.), point3(0., -1.5, 2.)] {
+            let intensity = scene.intensity_at(&mut rng, point, &light);
+            assert!(intensity > 0. && intensity < 1., "{:?}", point);
+        }
+    }
+
+    #[test]
+    fn an_area_light_casts_a_penumbra() {
+        // A strip across the shadow of a small sphere hanging between the
+        // light and the camera.
+        let mut scene = Scene::new();
+        scene.add_light(Light::area(
+            point3(-1., 5., -1.),
+            vector3(2., 0., 0.),
+            4,
+            vector3(0., 0., 2.),
+            4,
+            Color::WHITE,
+        ));
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::plane())
+                .transform(Transform::new().translate(0., -1., 0.))
+                .material(Material::new().specular(0.)),
+        );
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::sphere())
+                .transform(Transform::new().translate(0., 2., 0.).scale(0.5, 0.5, 0.5)),
+        );
+
+        let mut camera = Camera::new(40, 1, 2.);
+        camera.set_transform(Transform::look_at(
+            point3(0., 0.5, 0.),
+            point3(0., -1., 0.),
+            vector3(0., 0., 1.),
+        ));
+        let image = camera.render_with_options(&scene, &RenderOptions::new());
+        let brightness: Vec<f32> = (0..40).map(|x| image.get_color(x, 0).r).collect();
+
+        // The shadow darkens gradually toward the middle of the strip, rather
+        // than jumping straight from lit to ambient.
+        let ambient = 0.1;
+        let edge = brightness[0].min(brightness[39]);
+        let middle = brightness[20];
+        assert!(middle < 0.5 * edge);
+        let partial = brightness
+            .iter()
+            .filter(|&&b| b > middle + 0.05 && b < edge - 0.05)
+            .count();
+        assert!(partial > 5);
+        assert!(brightness.iter().all(|&b| b >= ambient - 0.01));
+    }
+
+    /// A floor lit from 5 units above by a 2 by 2 panel of the given number
+    /// of cells on a side, with a small sphere hanging beneath it.
+    fn hanging_sphere_scene(cells: usize, adaptive_shadow_steps: Option<usize>) -> Scene {
+        let mut light = Light::area(
+            point3(-1., 5., -1.),
+            vector3(2., 0., 0.),
+            cells,
+            vector3(0., 0., 2.),
+            cells,
+            Color::WHITE,
+        );
+        light.adaptive_shadow_steps = adaptive_shadow_steps;
+
+        let mut scene = Scene::new();
+        scene.add_light(light);
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::plane())
+                .transform(Transform::new().translate(0., -1., 0.))
+                .material(Material::new().specular(0.)),
+        );
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::sphere())
+                .transform(Transform::new().translate(0., 2., 0.).scale(0.5, 0.5, 0.5)),
+        );
+        scene
+    }
+
+    #[test]
+    fn adaptive_shadows_take_only_the_coarse_grid_at_a_fully_lit_point() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let down = ray(point3(4., 0.5, 0.), vector3(0., -1., 0.));
+        let options = RenderOptions::new();
+        let (adaptive, adaptive_rays) =
+            hanging_sphere_scene(4, Some(2)).radiance_and_cost_at(&mut rng, down, &options);
+        let (full, full_rays) =
+            hanging_sphere_scene(4, None).radiance_and_cost_at(&mut rng, down, &options);
+
+        // One camera ray, then the shadow rays.
+        assert_eq!(adaptive_rays, 1 + 4);
+        assert_eq!(full_rays, 1 + 16);
+        assert_eq!(adaptive, full);
+    }
+
+    #[test]
+    fn adaptive_shadows_take_every_sample_in_a_penumbra() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        // The sphere hides the panel's first corner from the point, but not
+        // the opposite one.
+        let down = ray(point3(0.7, 0.5, 0.7), vector3(0., -1., 0.));
+        let scene = hanging_sphere_scene(4, Some(2));
+        let (_, rays) = scene.radiance_and_cost_at(&mut rng, down, &RenderOptions::new());
+        assert_eq!(rays, 1 + 16);
+
+        let point = point3(0.7, -0.99, 0.7);
+        let intensity = scene.intensity_at(&mut rng, point, &scene.lights[0]);
+        assert!(intensity > 0. && intensity < 1.);
+    }
+
+    #[test]
+    fn adaptive_shadows_match_sampling_every_cell() {
+        let mut camera = Camera::new(40, 40, 2.);
+        camera.set_transform(Transform::look_at(
+            point3(0., 0.5, 0.),
+            point3(0., -1., 0.),
+            vector3(0., 0., 1.),
+        ));
+        let options = RenderOptions::new();
+        let (adaptive, adaptive_costs) =
+            camera.render_with_costs(&hanging_sphere_scene(8, Some(3)), &options);
+        let (full, full_costs) = camera.render_with_costs(&hanging_sphere_scene(8, None), &options);
+        // The two differ by no more than noise: about as much as two renders
+        // of either with different seeds do.
+        let mut total_difference = 0.;
+        for y in 0..40 {
+            for x in 0..40 {
+                let difference = (adaptive.get_color(x, y).r - full.get_color(x, y).r).abs();
+                assert!(difference < 0.1, "{} at ({}, {})", difference, x, y);
+                total_difference += difference;
+            }
+        }
+        assert!(total_difference / 1600. < 0.015);
+
+        let rays = |costs: &[crate::render_stats::PixelCost]| {
+            costs.iter().map(|cost| cost.rays).sum::<usize>() as f32
+        };
+        assert!(rays(&adaptive_costs) < 0.8 * rays(&full_costs));
+    }
+
+    /// Renders a dark, glossy sphere lit by the light, returning how many
+    /// pixels its highlight brightens past the given brightness.
+    fn highlight_pixels(light: Light, threshold: f32) -> (Canvas, usize) {
+        let mut scene = Scene::new();
+        scene.add_light(light);
+        scene.add_object(
+            Object::new().geometry(Geometry::sphere()).material(
+                Material::new()
+                    .color(Color::new(0.2, 0.2, 0.2))
+                    .specular(0.9)
+                    .shininess(200),
+            ),
+        );
+        let mut camera = Camera::new(60, 60, 0.6);
+        camera.set_transform(Transform::look_at(
+            point3(0., 0., -5.),
+            point3(0., 0., 0.),
+            vector3(0., 1., 0.),
+        ));
+        let image = camera.render(scene);
+        let bright = (0..60)
+            .flat_map(|y| (0..60).map(move |x| (x, y)))
+            .filter(|&(x, y)| image.get_color(x, y).r > threshold)
+            .count();
+        (image, bright)
+    }
+
+    #[test]
+    fn a_large_panel_casts_a_broad_highlight() {
+        let panel = Light::area(
+            point3(-2., 3., -5.),
+            vector3(4., 0., 0.),
+            4,
+            vector3(0., 2., 0.),
+            4,
+            Color::WHITE,
+        );
+        let point = Light::new(panel.position, Color::WHITE);
+
+        // Without a highlight the sphere is no brighter than 0.16.
+        let (_, broad) = highlight_pixels(panel, 0.2);
+        let (_, narrow) = highlight_pixels(point, 0.2);
+        assert!(narrow > 0);
+        assert!(broad > 2 * narrow, "{} vs {}", broad, narrow);
+    }
+
+    #[test]
+    fn a_panel_with_no_area_casts_a_point_lights_highlight() {
+        let position = point3(-2., 3., -5.);
+        let zero = vector3(0., 0., 0.);
+        let panel = Light::area(position, zero, 1, zero, 1, Color::WHITE);
+        let (panel_image, _) = highlight_pixels(panel, 0.3);
+        let (point_image, _) = highlight_pixels(Light::new(position, Color::WHITE), 0.3);
+        assert_eq!(panel_image.data, point_image.data);
+    }
+
+    /// A matte floor lit from 5 units above by a 2 by 2 panel: a standard
+    /// area light or, given a texture, a thin emissive box. (Only area
+    /// lights widen highlights by their size.)
+    fn panel_scene(texture: Option<Texture>) -> Scene {
+        let mut scene = Scene::new();
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::plane())
+                .material(Material::new().specular(0.)),
+        );
+        match texture {
+            Some(texture) => {
+                let panel = scene.add_object(
+                    Object::new()
+                        .geometry(Geometry::cube())
+                        .transform(Transform::new().translate(0., 5., 0.).scale(1., 0.001, 1.))
+                        .material(Material::new().texture(texture)),
+                );
+                scene.add_emitter(panel, 12, 8, Color::WHITE);
+            }
+            None => scene.add_light(Light::area(
+                point3(-1., 5., -1.),
+                vector3(2., 0., 0.),
+                8,
+                vector3(0., 0., 2.),
+                8,
+                Color::WHITE,
+            )),
+        }
+        scene
+    }
+
+    #[test]
+    fn a_white_emitter_lights_like_an_area_light_of_the_same_size() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let white = Texture::constant(Color::WHITE);
+        let down = ray(point3(0.3, 1., 0.2), vector3(0., -1., 0.));
+        let options = RenderOptions::default();
+        let expected = panel_scene(None).radiance_at(&mut rng, down, &options);
+        let actual = panel_scene(Some(white.clone())).radiance_at(&mut rng, down, &options);
+        assert!(expected.r > 0.9);
+        assert_approx_eq!(actual.r, expected.r, 1e-5);
+        assert_approx_eq!(actual.b, expected.b, 1e-5);
+
+        // A ball partly shading the floor blocks about as much of either.
+        let ball = Transform::new()
+            .translate(0.5, 2.5, 0.)
+            .scale(0.5, 0.5, 0.5);
+        let point = point3(0., 0.01, 0.);
+        let mut fractions = vec![];
+        for texture in [None, Some(white)] {
+            let mut scene = panel_scene(texture);
+            scene.add_object(Object::new().geometry(Geometry::sphere()).transform(ball));
+            let light = scene.lights[0].clone();
+            fractions.push(scene.intensity_at(&mut rng, point, &light));
+        }
+        assert!(fractions[0] > 0.2 && fractions[0] < 0.9, "{:?}", fractions);
+        assert_approx_eq!(fractions[0], fractions[1], 0.1);
+    }
+
+    #[test]
+    fn a_half_black_emitter_gives_half_the_light() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let down = ray(point3(0.3, 1., 0.2), vector3(0., -1., 0.));
+        let options = RenderOptions::default();
+        let white_panel = panel_scene(Some(Texture::constant(Color::WHITE)));
+        let white = white_panel.radiance_at(&mut rng, down, &options);
+
+        // Black stripes over half of the panel.
+        let striped = panel_scene(Some(Texture::stripe(Color::WHITE, Color::BLACK)));
+        let (fraction, color) =
+            striped.light_at(&mut rng, point3(0.3, 0.01, 0.2), &striped.lights[0], true);
+        assert_approx_eq!(fraction, 1.);
+        assert_approx_eq!(color.g, 0.5, 0.05);
+        let half = striped.radiance_at(&mut rng, down, &options);
+        assert_approx_eq!(half.g, white.g / 2., 0.03);
+    }
+
+    #[test]
+    fn objects_which_dont_cast_shadows_are_ignored_by_shadow_rays() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let p = point3(10., -10., 10.);
+        for &casts_shadow in &[true, false] {
+            let mut scene = Scene::new();
+            scene.add_light(Light::new(point3(-10., 10., -10.), Color::WHITE));
+            scene.add_object(
+                Object::new()
+                    .geometry(Geometry::sphere())
+                    .casts_shadow(casts_shadow),
+            );
+            let light = scene.lights[0].clone();
+            assert_eq!(scene.is_shadowed(p, &light), casts_shadow);
+            let expected = if casts_shadow { 0. } else { 1. };
+            assert_eq!(scene.intensity_at(&mut rng, p, &light), expected);
+
+            // The object is still seen by other rays.
+            let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
+            assert!(scene.nearest_intersection(r).is_some());
+        }
+    }
+
+    #[test]
+    fn transparent_objects_let_light_through_their_shadows() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let p = point3(10., -10., 10.);
+        let examples = vec![(0., 0., true), (0.5, 0.5, true), (1., 1., false)];
+        for (transparency, expected, shadowed) in examples {
+            let mut scene = Scene::new();
+            scene.add_light(Light::new(point3(-10., 10., -10.), Color::WHITE));
+            scene.add_object(
+                Object::new()
+                    .geometry(Geometry::sphere())
+                    .material(Material::new().transparency(transparency)),
+            );
+            let light = scene.lights[0].clone();
+            assert_eq!(scene.intensity_at(&mut rng, p, &light), expected);
+            assert_eq!(scene.is_shadowed(p, &light), shadowed);
+        }
+    }
+
+    #[test]
+    fn each_transparent_occluder_attenuates_the_light() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(0., 0., -10.), Color::WHITE));
+        for &z in &[-4., 0., 4.] {
+            scene.add_object(
+                Object::new()
+                    .geometry(Geometry::sphere())
+                    .transform(Transform::new().translate(0., 0., z))
+                    .material(Material::new().transparency(0.5)),
+            );
+        }
+        let light = scene.lights[0].clone();
+        assert_eq!(
+            scene.intensity_at(&mut rng, point3(0., 0., 10.), &light),
+            0.125
+        );
+        // From between the last two spheres, only two lie in the way.
+        assert_eq!(
+            scene.intensity_at(&mut rng, point3(0., 0., 2.), &light),
+            0.25
+        );
+    }
+
+    #[test]
+    fn shade_is_given_an_intersection_in_shadow() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(0., 0., -10.), Color::new(1., 1., 1.)));
+        scene.add_object(Object::new().geometry(Geometry::sphere()));
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::sphere())
+                .transform(Transform::new().translate(0., 0., 10.)),
+        );
+        let r = ray(point3(0., 0., 5.), vector3(0., 0., 1.));
+
+        let c = scene.color_at(&mut rng, r);
+
+        assert_approx_eq!(c.r, 0.1, 1e-5);
+        assert_approx_eq!(c.g, 0.1, 1e-5);
+        assert_approx_eq!(c.b, 0.1, 1e-5);
+    }
+
+    #[test]
+    fn preview_quality_skips_shadows() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(0., 0., -10.), Color::new(1., 1., 1.)));
+        scene.add_object(Object::new().geometry(Geometry::sphere()));
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::sphere())
+                .transform(Transform::new().translate(0., 0., 10.)),
+        );
+        let r = ray(point3(0., 0., 5.), vector3(0., 0., 1.));
+
+        let full = scene.color_at_with_options(&mut rng, r, &RenderOptions::default());
+        let preview = scene.color_at_with_options(
+            &mut rng,
+            r,
+            &RenderOptions::new().quality(RenderQuality::Preview),
+        );
+
+        assert_eq!(full, scene.color_at(&mut rng, r));
+        assert!(preview.r > full.r);
+        assert!(preview.g > full.g);
+        assert!(preview.b > full.b);
+    }
+
+    #[test]
+    fn preview_quality_skips_reflections() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = default_scene();
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::plane())
+                .material(
+                    Material::new()
+                        .reflective(0.5)
+                        .color(Color::BLACK)
+                        .diffuse(0.)
+                        .specular(0.),
+                )
+                .transform(Transform::new().translate(0., -1., 0.)),
+        );
+        let r = ray(
+            point3(0., 0., -3.),
+            vector3(
+                0.,
+                -std::f32::consts::SQRT_2 * 0.5,
+                std::f32::consts::SQRT_2 * 0.5,
+            ),
+        );
+
+        let c = scene.color_at_with_options(
+            &mut rng,
+            r,
+            &RenderOptions::new().quality(RenderQuality::Preview),
+        );
+        assert_eq!(c, Color::BLACK);
+    }
+
+    #[test]
+    fn the_render_time_animates_materials() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(-10., 10., -10.), Color::new(1., 1., 1.)));
+        scene.add_object(
+            Object::new().material(Material::new().diffuse(0.).specular(0.).animate(
+                Property::Ambient,
+                Keyframes::new(&[(0., 0.), (1., 1.)]).unwrap(),
+            )),
+        );
+        let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
+
+        assert_eq!(scene.color_at(&mut rng, r), Color::BLACK);
+        let options = RenderOptions::new().time(0.5);
+        assert_eq!(
+            scene.color_at_with_options(&mut rng, r, &options),
+            Color::new(0.5, 0.5, 0.5)
+        );
+    }
+
+    #[test]
+    fn the_reflected_color_for_a_reflective_material() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = default_scene();
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::plane())
+                .material(
+                    Material::new()
+                        .reflective(0.5)
+                        .color(Color::BLACK)
+                        .diffuse(0.)
+                        .specular(0.),
+                )
+                .transform(Transform::new().translate(0., -1., 0.)),
+        );
+        let r = ray(
+            point3(0., 0., -3.),
+            vector3(
+                0.,
+                -std::f32::consts::SQRT_2 * 0.5,
+                std::f32::consts::SQRT_2 * 0.5,
+            ),
+        );
+
+        let c = scene.color_at(&mut rng, r);
+        assert_approx_eq!(c.r, 0.19032, 1e-2);
+        assert_approx_eq!(c.g, 0.2379, 1e-2);
+        assert_approx_eq!(c.b, 0.14274, 1e-2);
+    }
+
+    #[test]
+    fn the_reflected_color_with_a_reflectivity_mask() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = Scene::new();
+        let sky = || {
+            let mut canvas = Canvas::new(1, 1);
+            canvas.set_color(0, 0, Color::WHITE);
+            canvas
+        };
+        scene.set_background(CubeMap::new([sky(), sky(), sky(), sky(), sky(), sky()]).unwrap());
+        scene.add_object(
+            Object::new().geometry(Geometry::plane()).material(
+                Material::new()
+                    .color(Color::BLACK)
+                    .reflective(1.)
+                    .reflective_texture(Texture::checkerboard_2d(Color::WHITE, Color::BLACK)),
+            ),
+        );
+
+        let white_cell = ray(point3(0.5, 1., 0.5), vector3(0., -1., 0.));
+        let black_cell = ray(point3(1.5, 1., 0.5), vector3(0., -1., 0.));
+        assert_eq!(scene.color_at(&mut rng, white_cell), Color::WHITE);
+        assert_eq!(scene.color_at(&mut rng, black_cell), Color::BLACK);
+    }
+
+    #[test]
+    fn shadow_rays_start_at_the_surface_offset() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(0., 10., 0.), Color::WHITE));
+        scene.add_object(Object::new().geometry(Geometry::plane()));
+
+        // A thin occluder hovering between the surface and the default offset
+        // point, out of the way of the (grazing) camera ray.
+        scene.add_object(
+            Object::new().geometry(Geometry::cube()).transform(
+                Transform::new()
+                    .translate(0., 0.4 * SURFACE_OFFSET, 0.)
+                    .scale(1e-3, 1e-4, 1e-3),
+            ),
+        );
+
+        let r = ray(point3(-10., 1., 0.), vector3(1., -0.1, 0.).normalize());
+        let lit = scene.color_at_with_options(&mut rng, r, &RenderOptions::default());
+        let shadowed = scene.color_at_with_options(
+            &mut rng,
+            r,
+            &RenderOptions::default().surface_offset(0.1 * SURFACE_OFFSET),
+        );
+        assert_eq!(shadowed, Color::new(0.1, 0.1, 0.1));
+        assert!(lit.r > shadowed.r);
+    }
+
+    #[test]
+    fn large_scenes_have_no_shadow_acne() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(0., 0., 0.), Color::WHITE));
+        scene.add_object(
+            Object::new().transform(Transform::new().translate(0., 0., 1e4).scale(1e3, 1e3, 1e3)),
+        );
+
+        // The light is at the eye, so every point the eye sees is lit. With
+        // a fixed offset of 1e-3 over half of them shadow themselves.
+        let shadowed = Color::new(0.1, 0.1, 0.1);
+        for i in 0..40 {
+            for j in 0..40 {
+                let (x, y) = (-60. + 3. * i as f32, -60. + 3. * j as f32);
+                let r = ray(point3(0., 0., 0.), vector3(x, y, 900.).normalize());
+                assert_ne!(scene.color_at(&mut rng, r), shadowed, "{:?}", r);
+            }
+        }
+    }
+
+    fn glass_sphere_on_glass_slab() -> Scene {
+        let glass = Material::new()
+            .color(Color::BLACK)
+            .diffuse(0.1)
+            .reflective(0.9)
+            .transparency(0.9)
+            .refractive_index(1.5);
+
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(-10., 10., -10.), Color::WHITE));
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::plane())
+                .transform(
+                    Transform::new()
+                        .translate(0., 0., 10.)
+                        .rotate_x(std::f32::consts::FRAC_PI_2),
+                )
+                .material(Material::new().color(Color::new(0.5, 0.5, 0.5))),
+        );
+        scene.add_object(
+            Object::new()
+                .transform(Transform::new().translate(0., 1., 0.))
+                .material(glass.clone()),
+        );
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::cube())
+                .transform(Transform::new().translate(0., -0.5, 0.).scale(3., 0.5, 3.))
+                .material(glass.refractive_index(1.3)),
+        );
+        scene
+    }
+
+    #[test]
+    fn refracting_from_one_object_into_another_in_contact() {
+        let scene = glass_sphere_on_glass_slab();
+
+        // Straight down through the contact point, the sphere is exited at
+        // the same point the slab is entered.
+        let r = ray(point3(0., 1., 0.), vector3(0., -1., 0.));
+        let contact: Vec<Intersection> = scene
+            .intersections(r)
+            .filter(|i| (i.t - 1.).abs() < CONTACT_EPSILON)
+            .collect();
+        assert_eq!(contact.len(), 2);
+        for i in contact {
+            assert_eq!(scene.refractive_indexes(r, i), (1.5, 1.3));
+        }
+    }
+
+    #[test]
+    fn the_contact_between_glass_objects_has_no_bright_ring() {
+        let scene = glass_sphere_on_glass_slab();
+        let mut camera = Camera::new(41, 41, 0.1);
+        camera.set_transform(Transform::look_at(
+            point3(0., 0.5, -6.),
+            point3(0., 0., 0.),
+            vector3(0., 1., 0.),
+        ));
+        let image = camera.render_with_options(&scene, &RenderOptions::default());
+
+        // The contact point is at the center of the image.
+        let brightness = |x, y| {
+            let c = image.get_color(x, y);
+            (c.r + c.g + c.b) / 3.
+        };
+        for x in 15..=25 {
+            let neighbors = (brightness(x, 19) + brightness(x, 21)) / 2.;
+            assert!(brightness(x, 20) < neighbors + 0.05, "{}", x);
+        }
+    }
+
+    /// A jumble of randomly placed objects of every kind, including ones
+    /// which extend forever and one which can't be rendered.
+    fn scene_of_many_objects(rng: &mut SmallRng) -> Scene {
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(-10., 20., -10.), Color::WHITE));
+        let truncated = Geometry::cylinder().min(-1.).unwrap().max(1.).unwrap();
+        let geometrys = [
+            Geometry::sphere(),
+            Geometry::cube(),
+            truncated,
+            Geometry::triangle(point3(0., 1., 0.), point3(-1., 0., 0.), point3(1., 0., 0.)),
+        ];
+        for i in 0..200 {
+            let mut coordinate = || rng.gen::<f32>() * 20. - 10.;
+            let transform = Transform::new()
+                .translate(coordinate(), coordinate(), coordinate())
+                .rotate_y(coordinate())
+                .rotate_x(coordinate())
+                .scale(0.5, 1., 0.75);
+            scene.add_object(
+                Object::new()
+                    .geometry(geometrys[i % geometrys.len()].clone())
+                    .transform(transform),
+            );
+        }
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::plane())
+                .transform(Transform::new().translate(0., -10., 0.)),
+        );
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::cylinder())
+                .transform(Transform::new().translate(12., 0., 0.)),
+        );
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::sphere())
+                .transform(Transform::new().scale(0., 1., 1.)),
+        );
+        scene
+    }
+
+    #[test]
+    fn the_bvh_finds_the_same_intersections_as_testing_every_object() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = scene_of_many_objects(&mut rng);
+        let mut random_point = || {
+            let mut coordinate = || rng.gen::<f32>() * 30. - 15.;
+            point3(coordinate(), coordinate(), coordinate())
+        };
+        let rays: Vec<Ray> = (0..500)
+            .map(|_| {
+                let origin = random_point();
+                ray(origin, (random_point() - origin).normalize())
+            })
+            .collect();
+        let points: Vec<Tuple4> = (0..500).map(|_| random_point()).collect();
+
+        let light = scene.lights[0].clone();
+        let nearest: Vec<_> = rays
+            .iter()
+            .map(|&r| scene.nearest_intersection(r))
+            .collect();
+        let shadowed: Vec<_> = points
+            .iter()
+            .map(|&p| scene.is_shadowed(p, &light.clone()))
+            .collect();
+        assert!(nearest.iter().filter(|hit| hit.is_some()).count() > 100);
+        assert!(shadowed.iter().filter(|&&shadowed| shadowed).count() > 10);
+
+        scene.build_bvh();
+        for (&r, &expected) in rays.iter().zip(nearest.iter()) {
+            assert_eq!(scene.nearest_intersection(r), expected, "{:?}", r);
+        }
+        for (&p, &expected) in points.iter().zip(shadowed.iter()) {
+            assert_eq!(scene.is_shadowed(p, &light.clone()), expected, "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn points_are_occluded_by_any_object_between_them() {
+        let mut scene = default_scene();
+        let (from, to) = (point3(0., 0., -5.), point3(0., 0., 5.));
+        assert!(scene.is_occluded(from, to));
+        assert!(scene.is_occluded(to, from));
+        assert!(!scene.is_occluded(from, point3(0., 0., -2.)));
+        assert!(!scene.is_occluded(from, point3(0., 5., 5.)));
+        assert!(!scene.is_occluded(from, from));
+
+        // Unlike shadow rays, occlusion ignores casts_shadows.
+        scene.materials[0].transparency = 1.;
+        scene.casts_shadows[0] = false;
+        scene.casts_shadows[1] = false;
+        assert!(scene.is_occluded(from, to));
+    }
+
+    #[test]
+    fn batch_queries_match_single_queries() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = default_scene();
+        let mut random_point = || {
+            let mut coordinate = || rng.gen::<f32>() * 6. - 3.;
+            point3(coordinate(), coordinate(), coordinate())
+        };
+        let pairs: Vec<(Tuple4, Tuple4)> = (0..1000)
+            .map(|_| (random_point(), random_point()))
+            .collect();
+        let rays: Vec<Ray> = pairs
+            .iter()
+            .map(|&(from, to)| ray(from, (to - from).normalize()))
+            .collect();
+
+        for _ in 0..2 {
+            let hits = scene.batch_raycast(&rays);
+            let occluded = scene.batch_occlusion(&pairs);
+            assert_eq!(hits.len(), rays.len());
+            assert_eq!(occluded.len(), pairs.len());
+            assert!(hits.iter().filter(|hit| hit.is_some()).count() > 100);
+            assert!(occluded.iter().filter(|&&occluded| occluded).count() > 100);
+
+            for (&r, &hit) in rays.iter().zip(hits.iter()) {
+                assert_eq!(hit, scene.nearest_intersection(r), "{:?}", r);
+            }
+            for (&(from, to), &occluded) in pairs.iter().zip(occluded.iter()) {
+                assert_eq!(occluded, scene.is_occluded(from, to), "{:?}", (from, to));
+            }
+            scene.build_bvh();
+        }
+        assert_eq!(scene.batch_raycast(&[]), vec![]);
+
+        // Smaller chunks than batches use, so the queries are split across
+        // threads however many the machine has.
+        for &chunk in &[7, 250, 999] {
+            let hits = batch_in_chunks(&rays, chunk, |&r| scene.nearest_intersection(r));
+            let occluded =
+                batch_in_chunks(&pairs, chunk, |&(from, to)| scene.is_occluded(from, to));
+            for (&r, &hit) in rays.iter().zip(hits.iter()) {
+                assert_eq!(hit, scene.nearest_intersection(r), "{:?}", r);
+            }
+            for (&(from, to), &occluded) in pairs.iter().zip(occluded.iter()) {
+                assert_eq!(occluded, scene.is_occluded(from, to), "{:?}", (from, to));
+            }
+        }
+    }
+
+    /// Returns the ambient occlusion at a point on a surface by a sphere
+    /// wholly above its horizon: the fraction of the cosine-weighted
+    /// hemisphere the sphere covers, which is its form factor cos θ (r/d)².
+    fn analytic_sphere_occlusion(
+        point: Tuple4,
+        normalv: Tuple4,
+        center: Tuple4,
+        radius: f32,
+    ) -> f32 {
+        let to_center = center - point;
+        let distance = to_center.magnitude();
+        let cos = to_center.dot(normalv) / distance;
+        let horizon = cos.acos() + (radius / distance).asin();
+        assert!(
+            horizon <= std::f32::consts::FRAC_PI_2,
+            "the sphere dips below the horizon"
+        );
+        cos * (radius / distance).powi(2)
+    }
+
+    /// Estimates the ambient occlusion at a point on a surface by casting
+    /// cosine-weighted rays into the scene, returning the fraction blocked.
+    fn sampled_occlusion<R: Rng>(
+        scene: &Scene,
+        rng: &mut R,
+        point: Tuple4,
+        normalv: Tuple4,
+        samples: usize,
+    ) -> f32 {
+        let from = point + normalv * SURFACE_OFFSET;
+        let occluded = (0..samples)
+            .filter(|_| {
+                let direction = cosine_weighted_direction(rng, normalv);
+                scene.is_occluded(from, from + direction * 1000.)
+            })
+            .count();
+        occluded as f32 / samples as f32
+    }
+
+    #[test]
+    fn sampled_occlusion_under_a_sphere_matches_the_analytic_value() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = Scene::new();
+        scene.add_object(Object::new().geometry(Geometry::plane()));
+        let (center, radius) = (point3(0., 1.5, 0.), 1.);
+        scene.add_object(
+            Object::new().transform(Transform::new().translate(center.x, center.y, center.z)),
+        );
+
+        let samples = 4096;
+        let normalv = vector3(0., 1., 0.);
+        for &x in &[0., 0.5, 1., 2., 3.] {
+            let point = point3(x, 0., 0.3 * x);
+            let expected = analytic_sphere_occlusion(point, normalv, center, radius);
+            let sampled = sampled_occlusion(&scene, &mut rng, point, normalv, samples);
+
+            // Each sample is a coin flip, so allow four standard errors.
+            let tolerance = 4. * (expected * (1. - expected) / samples as f32).sqrt();
+            assert!(
+                (sampled - expected).abs() <= tolerance,
+                "at {:?}: sampled {}, expected {} ± {}",
+                point,
+                sampled,
+                expected,
+                tolerance
+            );
+        }
+    }
+
+    #[test]
+    fn adding_an_object_discards_the_bvh() {
+        let mut scene = default_scene();
+        scene.build_bvh();
+        let r = ray(point3(5., 0., -5.), vector3(0., 0., 1.));
+        assert_eq!(scene.nearest_intersection(r), None);
+
+        let id = scene.add_object(
+            Object::new()
+                .geometry(Geometry::sphere())
+                .transform(Transform::new().translate(5., 0., 0.)),
+        );
+        assert_eq!(scene.nearest_intersection(r).unwrap().object_id, id);
+    }
+
+    fn sphere_at(z: f32) -> Object {
+        Object::new()
+            .geometry(Geometry::sphere())
+            .transform(Transform::new().translate(0., 0., z))
+    }
+
+    /// Returns the (t, object ID) of each intersection, in order.
+    fn hits(scene: &Scene, r: Ray) -> Vec<(f32, ObjectId)> {
+        let mut hits: Vec<_> = scene.intersections(r).map(|i| (i.t, i.object_id)).collect();
+        hits.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        hits
+    }
+
+    #[test]
+    fn filtering_a_ray_through_two_overlapping_spheres() {
+        let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
+        let examples = vec![
+            (CsgOperation::Union, vec![(4., 0), (6.5, 1)]),
+            (CsgOperation::Intersection, vec![(4.5, 1), (6., 0)]),
+            (CsgOperation::Difference, vec![(4., 0), (4.5, 1)]),
+        ];
+        for (operation, expected) in examples {
+            let mut scene = Scene::new();
+            let left = scene.add_object(sphere_at(0.));
+            let right = scene.add_object(sphere_at(0.5));
+            scene.add_object(Object::new().geometry(Geometry::csg(operation, left, right)));
+
+            assert_eq!(hits(&scene, r), expected, "{:?}", operation);
+            let nearest = scene.nearest_intersection(r).unwrap();
+            assert_eq!((nearest.t, nearest.object_id), expected[0]);
+            scene.build_bvh();
+            let nearest = scene.nearest_intersection(r).unwrap();
+            assert_eq!((nearest.t, nearest.object_id), expected[0]);
+        }
+    }
+
+    #[test]
+    fn a_ray_misses_a_csg_object() {
+        let mut scene = Scene::new();
+        let left = scene.add_object(sphere_at(0.));
+        let right = scene.add_object(Object::new().geometry(Geometry::cube()));
+        scene.add_object(Object::new().geometry(Geometry::csg(CsgOperation::Union, left, right)));
+        let r = ray(point3(0., 2., -5.), vector3(0., 0., 1.));
+        assert!(hits(&scene, r).is_empty());
+        assert_eq!(scene.nearest_intersection(r), None);
+    }
+
+    #[test]
+    fn the_front_of_a_subtracted_object_is_not_seen() {
+        let mut scene = Scene::new();
+        let left = scene.add_object(sphere_at(0.));
+        let right = scene.add_object(sphere_at(-0.5));
+        scene.add_object(Object::new().geometry(Geometry::csg(
+            CsgOperation::Difference,
+            left,
+            right,
+        )));
+        let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
+        assert_eq!(hits(&scene, r), vec![(5.5, right), (6., left)]);
+
+        // The dimple's normal faces back out of the remaining solid.
+        let point = r.position(5.5);
+        let normal = world_normal_at(
+            scene.world_transform(right),
+            &Geometry::sphere(),
+            point,
+            -r.direction,
+            None,
+            0,
+        );
+        assert_eq!(normal, vector3(0., 0., -1.));
+    }
+
+    #[test]
+    fn nested_csg_objects_are_placed_relative_to_their_parents() {
+        let mut scene = Scene::new();
+        let a = scene.add_object(sphere_at(0.));
+        let b = scene.add_object(sphere_at(1.5));
+        let c = scene.add_object(sphere_at(3.));
+        let union =
+            scene.add_object(Object::new().geometry(Geometry::csg(CsgOperation::Union, a, b)));
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::csg(CsgOperation::Difference, union, c))
+                .transform(Transform::new().translate(0., 0., -1.)),
+        );
+
+        let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
+        assert_eq!(hits(&scene, r), vec![(3., a), (6., c)]);
+        assert_eq!(
+            scene.world_transform(c).local_to_world * point3(0., 0., 0.),
+            point3(0., 0., 2.)
+        );
+        assert_eq!(
+            scene.object_bounds(4),
+            Bounds3::new(point3(-1., -1., -2.), point3(1., 1., 1.5))
+        );
+    }
+
+    #[test]
+    fn the_bounds_of_a_scene_leave_out_unbounded_and_hidden_objects() {
+        let mut scene = Scene::new();
+        assert_eq!(scene.bounds(), None);
+        scene.add_object(Object::new().geometry(Geometry::plane()));
+        assert_eq!(scene.bounds(), None);
+
+        let a = scene.add_object(sphere_at(0.));
+        let b = scene.add_object(sphere_at(3.));
+        scene.add_object(Object::new().geometry(Geometry::csg(CsgOperation::Union, a, b)));
+        scene.add_object(sphere_at(10.).visibility(0.));
+        assert_eq!(
+            scene.bounds(),
+            Some(Bounds3::new(point3(-1., -1., -1.), point3(1., 1., 4.)))
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn an_object_can_only_belong_to_one_csg_object() {
+        let mut scene = Scene::new();
+        let left = scene.add_object(sphere_at(0.));
+        let right = scene.add_object(sphere_at(0.5));
+        scene.add_object(Object::new().geometry(Geometry::csg(CsgOperation::Union, left, right)));
+        scene.add_object(Object::new().geometry(Geometry::csg(CsgOperation::Union, left, right)));
+    }
+
+    #[test]
+    fn changing_a_material_after_adding_its_object() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = default_scene();
+        let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
+        let before = scene.color_at(&mut rng, r);
+
+        let context = scene.prepare_render();
+        *scene.material_mut(0) = scene.material(0).clone().color(Color::new(1., 0., 0.));
+        assert!(!context.is_valid_for(&scene));
+        let after = scene.color_at(&mut rng, r);
+        assert_ne!(before, after);
+        assert_eq!(after.g, 0.);
+        assert_eq!(after.b, 0.);
+    }
+
+    #[test]
+    fn moving_an_object_after_adding_it() {
+        let mut scene = default_scene();
+        scene.build_bvh();
+        let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
+        assert_eq!(scene.nearest_intersection(r).unwrap().t, 4.);
+
+        scene.transform_mut(0).translate(0., 0., 2.);
+        assert_eq!(*scene.transform(0), Transform::new().translate(0., 0., 2.));
+        assert_approx_eq!(scene.nearest_intersection(r).unwrap().t, 4.5);
+
+        // Setting only the local to world matrix keeps the inverse in step.
+        scene.transform_mut(0).local_to_world =
+            Transform::new().translate(0., 0., 3.).local_to_world;
+        assert_eq!(
+            scene.transform(0).world_to_local,
+            Transform::new().translate(0., 0., -3.).local_to_world
+        );
+        let hit = scene.nearest_intersection(r).unwrap();
+        assert_eq!((hit.t, hit.object_id), (4.5, 1));
+
+        // An object scaled to nothing is skipped, as when it's added.
+        scene.transform_mut(1).scale(0., 1., 1.);
+        assert_eq!(scene.validate().len(), 1);
+        assert_approx_eq!(scene.nearest_intersection(r).unwrap().t, 7.);
+    }
+
+    #[test]
+    fn changing_the_geometry_of_an_object() {
+        let mut scene = default_scene();
+        let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
+        *scene.geometry_mut(1) = Geometry::cube();
+        assert_eq!(*scene.geometry(1), Geometry::cube());
+
+        // Turning an object into a CSG object adopts its children.
+        let a = scene.add_object(sphere_at(0.));
+        let b = scene.add_object(sphere_at(0.5));
+        let csg = scene.add_object(Object::new());
+        *scene.geometry_mut(csg) = Geometry::csg(CsgOperation::Intersection, a, b);
+        scene.remove_object(0);
+        scene.remove_object(0);
+        assert_eq!(hits(&scene, r), vec![(4.5, 1), (6., 0)]);
+    }
+
+    #[test]
+    fn removing_objects() {
+        let mut scene = Scene::new();
+        let first = scene.add_object(sphere_at(-10.));
+        let left = scene.add_object(sphere_at(0.));
+        let right = scene.add_object(sphere_at(0.5));
+        let csg = scene.add_object(Object::new().geometry(Geometry::csg(
+            CsgOperation::Difference,
+            left,
+            right,
+        )));
+        assert_eq!(scene.object_count(), 4);
+
+        // Later IDs shift down, including the children of CSG objects.
+        let removed = scene.remove_object(first);
+        assert_eq!(removed.transform, Transform::new().translate(0., 0., -10.));
+        assert_eq!(scene.object_count(), 3);
+        assert_eq!(
+            *scene.geometry(csg - 1),
+            Geometry::csg(CsgOperation::Difference, 0, 1)
+        );
+        let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
+        assert_eq!(hits(&scene, r), vec![(4., 0), (4.5, 1)]);
+
+        // Removing a CSG object frees its children.
+        scene.remove_object(2);
+        assert_eq!(hits(&scene, r), vec![(4., 0), (4.5, 1), (6., 0), (6.5, 1)]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn the_children_of_a_csg_object_cannot_be_removed() {
+        let mut scene = Scene::new();
+        let left = scene.add_object(sphere_at(0.));
+        let right = scene.add_object(sphere_at(0.5));
+        scene.add_object(Object::new().geometry(Geometry::csg(CsgOperation::Union, left, right)));
+        scene.remove_object(left);
+    }
+
+    #[test]
+    fn irradiance_probes_light_the_shadowed_side_of_a_box() {
+        // A closed white box lit from near its ceiling, with a slab beneath
+        // the light shadowing the middle of the floor.
+        let white = Material::new().ambient(0.).specular(0.);
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(0., 4.5, 0.), Color::WHITE));
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::cube())
+                .transform(Transform::new().scale(5., 5., 5.))
+                .material(white.clone()),
+        );
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::cube())
+                .transform(Transform::new().translate(0., 2., 0.).scale(2., 0.1, 2.))
+                .material(white),
+        );
+        let bounds = Bounds3::new(point3(-4.5, -4.5, -4.5), point3(4.5, 4.5, 4.5));
+        scene.bake_probes(bounds, 3, 64);
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        let r = ray(point3(4., -4.5, 0.), vector3(-4., -0.5, 0.).normalize());
+        let without = scene.color_at_with_options(&mut rng, r, &RenderOptions::default());
+        let with = scene.color_at_with_options(
+            &mut rng,
+            r,
+            &RenderOptions::default().irradiance_probes(true),
+        );
+        assert_eq!(without, Color::BLACK);
+        assert!(with.r > 0.01);
+        assert_eq!((with.r, with.g), (with.g, with.b));
+    }
+
+    #[test]
+    fn undoing_and_redoing_changes() {
+        let mut scene = Scene::new();
+        scene.enable_journal(10);
+        let moved = Transform::new().translate(0., 0., 2.);
+
+        let id = scene.add_object(Object::new());
+        scene.set_transform(id, moved);
+        let context = scene.prepare_render();
+
+        assert!(scene.undo());
+        assert!(!context.is_valid_for(&scene));
+        assert_eq!(*scene.transform(id), Transform::new());
+        assert!(scene.undo());
+        assert_eq!(scene.object_count(), 0);
+        assert!(!scene.undo());
+
+        assert!(scene.redo());
+        assert!(scene.redo());
+        assert_eq!(scene.object_count(), 1);
+        assert_eq!(*scene.transform(id), moved);
+        assert!(!scene.redo());
+
+        // A new change can't be followed by a redo.
+        scene.undo();
+        scene.material_mut(id).reflective = 0.5;
+        assert!(!scene.redo());
+        scene.undo();
+        assert_eq!(*scene.material(id), Material::new());
+    }
+
+    #[test]
+    fn undoing_a_removal_restores_the_ids() {
+        let mut scene = Scene::new();
+        let first = scene.add_object(sphere_at(-10.));
+        let left = scene.add_object(sphere_at(0.));
+        let right = scene.add_object(sphere_at(0.5));
+        let csg = scene.add_object(Object::new().geometry(Geometry::csg(
+            CsgOperation::Difference,
+            left,
+            right,
+        )));
+        scene.add_light(Light::new(point3(0., 10., 0.), Color::WHITE));
+        scene.add_emitter(first, 1, 1, Color::WHITE);
+        scene.enable_journal(10);
+        let r = ray(point3(0., 0., -15.), vector3(0., 0., 1.));
+        let before = hits(&scene, r);
+        let lights = scene.lights().to_vec();
+
+        scene.remove_object(first);
+        scene.remove_object(csg - 1);
+        assert_eq!(scene.lights().len(), 1);
+        scene.undo();
+        scene.undo();
+        assert_eq!(hits(&scene, r), before);
+        assert_eq!(scene.lights(), &lights[..]);
+        assert_eq!(
+            *scene.geometry(csg),
+            Geometry::csg(CsgOperation::Difference, left, right)
+        );
+    }
+
+    #[test]
+    fn a_scenes_layers_are_those_of_its_top_level_objects() {
+        let mut scene = Scene::new();
+        let first = scene.add_object(sphere_at(-10.).layer(2));
+        let left = scene.add_object(sphere_at(0.).layer(7));
+        let right = scene.add_object(sphere_at(0.5).layer(7));
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::csg(CsgOperation::Union, left, right))
+                .layer(1),
+        );
+        scene.add_object(sphere_at(10.).layer(2));
+        assert_eq!(scene.layers(), vec![1, 2]);
+
+        scene.enable_journal(1);
+        scene.remove_object(first);
+        assert_eq!(scene.layer(first), 7);
+        scene.undo();
+        assert_eq!(scene.layer(first), 2);
+    }
+
+    #[test]
+    fn primary_rays_only_see_the_objects_in_the_layer() {
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(0., 0., -10.), Color::WHITE));
+        scene.add_object(sphere_at(0.).layer(1));
+        scene.add_object(sphere_at(5.));
+        let rng = SmallRng::seed_from_u64(0);
+        let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
+        let radiance =
+            |layer| scene.radiance_at(&mut rng.clone(), r, &RenderOptions::new().layer(layer));
+
+        let front = radiance(RenderLayer::All);
+        let layer = |layer, others_visible| RenderLayer::Objects {
+            layer,
+            others_visible,
+        };
+        assert_eq!(radiance(layer(1, true)), front);
+        let matte = |layer| RenderLayer::Matte {
+            layer,
+            others_visible: true,
+        };
+        assert_eq!(radiance(matte(1)), Color::WHITE);
+        assert_eq!(radiance(matte(2)), Color::BLACK);
+        assert_eq!(radiance(layer(2, true)), Color::BLACK);
+        assert_eq!(radiance(RenderLayer::Background), Color::BLACK);
+
+        // Seen through the front sphere, the back one is in its shadow,
+        // unless the front sphere's layer is hidden.
+        let back = radiance(layer(0, true));
+        assert!(back.r > 0.);
+        assert!(radiance(layer(0, false)).r > back.r);
+    }
+
+    #[test]
+    fn the_journal_keeps_only_the_latest_changes() {
+        let mut scene = Scene::new();
+        scene.enable_journal(2);
+        for _ in 0..3 {
+            scene.add_object(Object::new());
+        }
+
+        assert!(scene.undo());
+        assert!(scene.undo());
+        assert!(!scene.undo());
+        assert_eq!(scene.object_count(), 1);
+    }
+
+    #[test]
+    fn changes_are_not_recorded_without_a_journal() {
+        let mut scene = Scene::new();
+        scene.add_object(Object::new());
+        assert!(!scene.undo());
+        assert_eq!(scene.object_count(), 1);
+    }
+
+    #[test]
+    fn objects_with_non_invertible_transforms_are_skipped() {
+        let mut scene = default_scene();
+        let expected = scene.color_at(
+            &mut SmallRng::seed_from_u64(0),
+            ray(point3(0., 0., -5.), vector3(0., 0., 1.)),
+        );
+        assert!(scene.validate().is_empty());
+
+        let flat = scene.add_object(
+            Object::new()
+                .geometry(Geometry::sphere())
+                .transform(Transform::new().translate(0., 0., -3.).scale(0., 1., 1.)),
+        );
+        assert_eq!(
+            scene.validate(),
+            vec![format!(
+                "object {} has a non-invertible transform and will not be rendered",
+                flat
+            )]
+        );
+
+        let c = scene.color_at(
+            &mut SmallRng::seed_from_u64(0),
+            ray(point3(0., 0., -5.), vector3(0., 0., 1.)),
+        );
+        assert_eq!(c, expected);
+
+        let mut camera = Camera::new(11, 11, std::f32::consts::FRAC_PI_2);
+        camera.set_transform(Transform::look_at(
+            point3(0., 0., -5.),
+            point3(0., 0., 0.),
+            vector3(0., 1., 0.),
+        ));
+        let image = camera.render_with_options(&scene, &RenderOptions::new());
+        assert_ne!(image.get_color(5, 5), Color::BLACK);
+    }
+
+    fn scene_with_visibility(visibility: f32) -> Scene {
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(-10., 10., -10.), Color::WHITE));
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::sphere())
+                .material(Material::new().color(Color::new(1., 0., 0.)))
+                .visibility(visibility),
+        );
+        scene
+    }
+
+    #[test]
+    fn fully_visible_and_invisible_objects() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
+        assert_ne!(
+            scene_with_visibility(1.).color_at(&mut rng, r),
+            Color::BLACK
+        );
+        assert_eq!(
+            scene_with_visibility(0.).color_at(&mut rng, r),
+            Color::BLACK
+        );
+
+        // Invisible objects cast no shadows either.
+        let p = point3(10., -10., 10.);
+        let light = Light::new(point3(-10., 10., -10.), Color::WHITE);
+        assert_eq!(
+            scene_with_visibility(1.).intensity_at(&mut rng, p, &light),
+            0.
+        );
+        assert_eq!(
+            scene_with_visibility(0.).intensity_at(&mut rng, p, &light),
+            1.
+        );
+    }
+
+    #[test]
+    fn a_half_visible_object_is_hit_by_about_half_of_the_samples() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let scene = scene_with_visibility(0.5);
+        let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
+        let hits = (0..16)
+            .filter(|_| scene.color_at(&mut rng, r) != Color::BLACK)
+            .count();
+        assert!((4..=12).contains(&hits), "{} hits", hits);
+    }
+
+    #[test]
+    fn modifying_a_scene_invalidates_its_render_contexts() {
+        let mut scene = default_scene();
+        let context = scene.prepare_render();
+        assert!(context.is_valid_for(&scene));
+
+        scene.add_light(Light::new(point3(0., 10., 0.), Color::WHITE));
+        assert!(!context.is_valid_for(&scene));
+        assert!(scene.prepare_render().is_valid_for(&scene));
+    }
+
+    #[test]
+    fn render_contexts_belong_to_the_scene_they_were_prepared_from() {
+        // Two scenes built the same way have made the same number of edits.
+        let (a, b) = (default_scene(), default_scene());
+        assert!(a.prepare_render().is_valid_for(&a));
+        assert!(!a.prepare_render().is_valid_for(&b));
+    }
+
+    #[test]
+    fn preparing_a_render_builds_the_bvh() {
+        let mut scene = default_scene();
+        scene.build_bvh();
+        scene.add_object(Object::new().geometry(Geometry::sphere()));
+        assert!(scene.bvh.get().is_none());
+
+        scene.prepare_render();
+        assert!(scene.bvh.get().is_some());
+    }
+
+    #[test]
+    fn the_affected_region_of_a_small_sphere() {
+        let mut scene = Scene::new();
+        let sphere = scene.add_object(
+            Object::new().transform(Transform::new().translate(1., 0.5, 0.).scale(0.3, 0.3, 0.3)),
+        );
+        let floor = scene.add_object(
+            Object::new()
+                .geometry(Geometry::plane())
+                .transform(Transform::new().translate(0., -1., 0.)),
+        );
+        let behind =
+            scene.add_object(Object::new().transform(Transform::new().translate(0., 0., -9.)));
+        let mut camera = Camera::new(40, 30, std::f32::consts::FRAC_PI_2);
+        camera.set_transform(Transform::look_at(
+            point3(0., 0., -5.),
+            point3(0., 0., 0.),
+            vector3(0., 1., 0.),
+        ));
+
+        let (x0, y0, x1, y1) = scene.affected_region(&camera, sphere).unwrap();
+        let padding = REGION_PADDING as usize;
+        let ids = camera.render_object_ids(&scene);
+        let silhouette: Vec<(usize, usize)> = (0..ids.len())
+            .filter(|&i| ids[i] == Some(sphere))
+            .map(|i| (i % camera.hsize, i / camera.hsize))
+            .collect();
+        assert!(!silhouette.is_empty());
+        for &(x, y) in &silhouette {
+            assert!(
+                x >= x0 + padding && x + padding < x1,
+                "{} in {}..{}",
+                x,
+                x0,
+                x1
+            );
+            assert!(
+                y >= y0 + padding && y + padding < y1,
+                "{} in {}..{}",
+                y,
+                y0,
+                y1
+            );
+        }
+
+        // The corner of the frame farthest from the sphere is left out.
+        let (cx, cy) = silhouette[silhouette.len() / 2];
+        let far_x = if cx < camera.hsize / 2 {
+            camera.hsize - 1
+        } else {
+            0
+        };
+        let far_y = if cy < camera.vsize / 2 {
+            camera.vsize - 1
+        } else {
+            0
+        };
+        assert!(!(x0..x1).contains(&far_x) || !(y0..y1).contains(&far_y));
+        assert!((x1 - x0) * (y1 - y0) < camera.hsize * camera.vsize / 4);
+
+        // The floor extends forever, and the sphere behind the camera can't
+        // be seen.
+        assert_eq!(scene.affected_region(&camera, floor), Some((0, 0, 40, 30)));
+        assert_eq!(scene.affected_region(&camera, behind), None);
     }
 
     #[test]
-    fn shading_an_intersection() {
+    fn the_color_when_a_ray_misses_with_an_environment_texture() {
         let mut rng = SmallRng::seed_from_u64(0);
-        let scene = default_scene();
-        let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
-        let c = scene.color_at(&mut rng, r);
+        let mut scene = default_scene();
+        let sky = Color::new(0., 0., 1.);
+        let ground = Color::new(0.5, 0.3, 0.1);
+        let horizon = AlignCheck::solid(Color::WHITE);
+        let faces = [
+            horizon,
+            horizon,
+            AlignCheck::solid(sky),
+            AlignCheck::solid(ground),
+            horizon,
+            horizon,
+        ];
+        scene.set_background(Texture::cube_map(faces));
 
-        assert_approx_eq!(c.r, 0.38066, 1e-5);
-        assert_approx_eq!(c.g, 0.47583, 1e-5);
-        assert_approx_eq!(c.b, 0.2855, 1e-5);
+        let up = ray(point3(0., 0., -5.), vector3(0.2, 1., 0.1).normalize());
+        assert_eq!(scene.color_at(&mut rng, up), sky);
+        let down = ray(point3(0., 0., -5.), vector3(0.3, -1., 0.).normalize());
+        assert_eq!(scene.color_at(&mut rng, down), ground);
+        let across = ray(point3(0., 0., -5.), vector3(-1., 0.5, 0.));
+        assert_eq!(scene.color_at(&mut rng, across), Color::WHITE);
     }
 
     #[test]
-    fn shading_an_intersection_from_the_inside() {
+    fn the_color_when_a_ray_misses_with_a_background() {
         let mut rng = SmallRng::seed_from_u64(0);
         let mut scene = default_scene();
-        scene.lights = vec![Light::new(point3(0., 0.25, 0.), Color::new(1., 1., 1.))];
-        let r = ray(point3(0., 0., 0.), vector3(0., 0., 1.));
-        let i = scene.nearest_intersection(r).unwrap();
-        let c = scene.color_at(&mut rng, r);
+        let r = ray(point3(0., 0., -5.), vector3(0., 1., 0.));
+        assert_eq!(scene.color_at(&mut rng, r), Color::BLACK);
 
-        assert_eq!(i.t, 0.5);
-        assert_eq!(i.object_id, 1);
-        assert_approx_eq!(c.r, 0.90498, 1e-5);
-        assert_approx_eq!(c.g, 0.90498, 1e-5);
-        assert_approx_eq!(c.b, 0.90498, 1e-5);
+        let gray = Color::new(0.3, 0.3, 0.3);
+        scene.set_background(gray);
+        assert_eq!(scene.color_at(&mut rng, r), gray);
+
+        // Colors are clamped as usual, though not in the radiance.
+        scene.set_background(Color::new(2., 0.5, 0.));
+        assert_eq!(scene.color_at(&mut rng, r), Color::new(1., 0.5, 0.));
+        let radiance = scene.radiance_at(&mut rng, r, &RenderOptions::new());
+        assert_eq!(radiance, Color::new(2., 0.5, 0.));
     }
 
     #[test]
-    fn the_color_when_a_ray_misses() {
+    fn setting_a_background_replaces_the_previous_one() {
         let mut rng = SmallRng::seed_from_u64(0);
-        let scene = default_scene();
-        let r = ray(point3(0., 0., -5.), vector3(0., 1., 0.));
-        let c = scene.color_at(&mut rng, r);
+        let mut scene = Scene::new();
+        let r = ray(point3(0., 0., 0.), vector3(0.3, 1., 0.));
+        scene.set_sky(vector3(1., 1., 0.), 0.2, 3.);
+        assert!(matches!(scene.background(), Background::Sky(_)));
+        assert_ne!(scene.color_at(&mut rng, r), Color::BLACK);
 
-        assert_approx_eq!(c.r, 0.0, 1e-5);
-        assert_approx_eq!(c.g, 0.0, 1e-5);
-        assert_approx_eq!(c.b, 0.0, 1e-5);
+        let gray = Color::new(0.5, 0.5, 0.5);
+        scene.set_background(gray);
+        assert_eq!(scene.color_at(&mut rng, r), gray);
+        scene.set_background(Texture::constant(Color::WHITE));
+        assert_eq!(scene.color_at(&mut rng, r), Color::WHITE);
+        scene.set_background(Background::default());
+        assert_eq!(scene.color_at(&mut rng, r), Color::BLACK);
     }
 
     #[test]
-    fn the_color_when_a_ray_hits() {
+    fn a_vertical_gradient_background() {
+        let bottom = Color::new(0.2, 0.1, 0.);
+        let top = Color::new(0.4, 0.6, 1.);
+        let background = Background::VerticalGradient(bottom, top);
         let mut rng = SmallRng::seed_from_u64(0);
-        let scene = default_scene();
-        let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
-        let c = scene.color_at(&mut rng, r);
-
-        assert_approx_eq!(c.r, 0.38066, 1e-5);
-        assert_approx_eq!(c.g, 0.47583, 1e-5);
-        assert_approx_eq!(c.b, 0.2855, 1e-5);
+        assert_eq!(background.sample(&mut rng, vector3(0., -3., 0.)), bottom);
+        assert_eq!(background.sample(&mut rng, vector3(0., 2., 0.)), top);
+        let horizon = background.sample(&mut rng, vector3(1., 0., 1.));
+        assert_approx_eq!(horizon.g, 0.35);
     }
 
     #[test]
-    fn the_color_with_an_intersection_behind_the_ray() {
+    fn a_reflective_plane_reflects_the_background() {
         let mut rng = SmallRng::seed_from_u64(0);
         let mut scene = Scene::new();
-        scene.add_light(Light::new(point3(-10., 10., -10.), Color::new(1., 1., 1.)));
         scene.add_object(
-            Object::new().geometry(Geometry::sphere()).material(
+            Object::new().geometry(Geometry::plane()).material(
                 Material::new()
-                    .color(Color::new(0.8, 1.0, 0.6))
-                    .ambient(1.0)
-                    .diffuse(0.7)
-                    .specular(0.2),
+                    .color(Color::BLACK)
+                    .ambient(0.)
+                    .reflective(1.),
             ),
         );
-        let expected_color = Color::new(0.3, 0.5, 0.1);
-        scene.add_object(
-            Object::new()
-                .material(Material::new().ambient(1.0).color(expected_color))
-                .geometry(Geometry::sphere())
-                .transform(Transform::new().scale(0.5, 0.5, 0.5)),
-        );
+        let bottom = Color::new(0.2, 0.1, 0.);
+        let top = Color::new(0.4, 0.6, 1.);
+        scene.set_background(Background::VerticalGradient(bottom, top));
 
-        let r = ray(point3(0., 0., 0.75), vector3(0., 0., -1.));
+        // The ray is reflected up at 45 degrees.
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        let r = ray(point3(0., 1., -3.), vector3(0., -half, half));
         let c = scene.color_at(&mut rng, r);
-
-        assert_eq!(c, expected_color);
+        let expected =
+            Background::VerticalGradient(bottom, top).sample(&mut rng, vector3(0., half, half));
+        assert_approx_eq!(c.r, expected.r);
+        assert_approx_eq!(c.g, expected.g);
+        assert_approx_eq!(c.b, expected.b);
+        assert!(c.b > 0.8);
     }
 
     #[test]
-    fn shade_is_given_an_intersection_in_shadow() {
+    fn a_clear_coat_mirrors_the_background_at_grazing_angles() {
         let mut rng = SmallRng::seed_from_u64(0);
-        let mut scene = Scene::new();
-        scene.add_light(Light::new(point3(0., 0., -10.), Color::new(1., 1., 1.)));
-        scene.add_object(Object::new().geometry(Geometry::sphere()));
-        scene.add_object(
-            Object::new()
-                .geometry(Geometry::sphere())
-                .transform(Transform::new().translate(0., 0., 10.)),
-        );
-        let r = ray(point3(0., 0., 5.), vector3(0., 0., 1.));
+        let background = Color::new(0.2, 0.4, 0.8);
+        let base = Material::new().color(Color::BLACK).ambient(0.).specular(0.);
+        let color_of = |material: Material, rng: &mut SmallRng| {
+            let mut scene = Scene::new();
+            scene.add_object(Object::new().geometry(Geometry::plane()).material(material));
+            scene.set_background(background);
+            scene.color_at(rng, ray(point3(0., 1., -3.), vector3(0., -0.01, 1.)))
+        };
 
-        let c = scene.color_at(&mut rng, r);
+        let bare = color_of(base.clone(), &mut rng);
+        assert!(bare.b < 0.01);
 
-        assert_approx_eq!(c.r, 0.1, 1e-5);
-        assert_approx_eq!(c.g, 0.1, 1e-5);
-        assert_approx_eq!(c.b, 0.1, 1e-5);
+        let coated = color_of(base.clearcoat(1.), &mut rng);
+        assert_approx_eq!(coated.r, background.r, 0.02);
+        assert_approx_eq!(coated.g, background.g, 0.04);
+        assert_approx_eq!(coated.b, background.b, 0.08);
     }
 
     #[test]
-    fn the_reflected_color_for_a_reflective_material() {
+    fn the_color_when_a_ray_misses_under_a_sky() {
         let mut rng = SmallRng::seed_from_u64(0);
-        let mut scene = default_scene();
-        scene.add_object(
-            Object::new()
-                .geometry(Geometry::plane())
-                .material(
-                    Material::new()
-                        .reflective(0.5)
-                        .color(Color::BLACK)
-                        .diffuse(0.)
-                        .specular(0.),
-                )
-                .transform(Transform::new().translate(0., -1., 0.)),
-        );
-        let r = ray(
-            point3(0., 0., -3.),
-            vector3(
-                0.,
-                -std::f32::consts::SQRT_2 * 0.5,
-                std::f32::consts::SQRT_2 * 0.5,
-            ),
+        let mut scene = Scene::new();
+        let sun = vector3(0., 1., 1.).normalize();
+        scene.set_sky(sun, 0.2, 3.);
+
+        let sky = Sky::new(sun, 0.2, 3.);
+        let up = ray(point3(0., 0., 0.), vector3(0., 1., -1.));
+        assert_eq!(
+            scene.color_at(&mut rng, up),
+            sky.sample(up.direction).clamp()
         );
+        let at_sun = ray(point3(0., 0., 0.), sun);
+        assert_eq!(scene.color_at(&mut rng, at_sun), Color::WHITE);
 
-        let c = scene.color_at(&mut rng, r);
-        assert_approx_eq!(c.r, 0.19032, 1e-2);
-        assert_approx_eq!(c.g, 0.2379, 1e-2);
-        assert_approx_eq!(c.b, 0.14274, 1e-2);
+        assert_eq!(scene.lights, vec![sky.sun_light()]);
     }
 
     #[test]
@@ -544,19 +3678,59 @@ mod tests {
             ),
         );
 
-        let c = scene.color_at_remaining(&mut rng, r, 0);
+        let c = scene.color_at_remaining(&mut rng, r, 0, &RenderOptions::default(), &mut 0);
         assert_eq!(c, Color::new(0., 0., 0.));
     }
 
+    #[test]
+    fn the_max_depth_option_limits_reflections_of_reflections() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(0., 1., 0.), Color::WHITE));
+        let mirror = |color| {
+            Material::new()
+                .color(color)
+                .ambient(1.)
+                .diffuse(0.)
+                .specular(0.)
+                .reflective(0.5)
+        };
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::plane())
+                .material(mirror(Color::new(1., 0., 0.))),
+        );
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::plane())
+                .material(mirror(Color::new(0., 0., 1.)))
+                .transform(Transform::new().translate(0., 2., 0.)),
+        );
+
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        let r = ray(point3(0., 1., 0.), vector3(0., -half, half));
+        let radiance_at_depth = |rng: &mut SmallRng, max_depth| {
+            scene.radiance_at(rng, r, &RenderOptions::default().max_depth(max_depth))
+        };
+
+        // No rays at all, then the red floor alone, then the floor with its
+        // reflection of the blue ceiling, then with the ceiling's reflection
+        // of the floor too.
+        assert_eq!(radiance_at_depth(&mut rng, 0), Color::BLACK);
+        assert_eq!(radiance_at_depth(&mut rng, 1), Color::new(1., 0., 0.));
+        assert_eq!(radiance_at_depth(&mut rng, 2), Color::new(1., 0., 0.5));
+        assert_eq!(radiance_at_depth(&mut rng, 3), Color::new(1.25, 0., 0.5));
+    }
+
     #[test]
     fn the_refracted_color_at_the_maximum_recursive_depth() {
         let mut rng = SmallRng::seed_from_u64(0);
         let mut scene = default_scene();
-        let mut material = scene.materials.first_mut().unwrap();
+        let material = scene.material_mut(0);
         material.transparency = 1.0;
         material.refractive_index = 1.5;
         let r = ray(point3(0., 0., -5.), vector3(0., 0., 1.));
-        let c = scene.color_at_remaining(&mut rng, r, 0);
+        let c = scene.color_at_remaining(&mut rng, r, 0, &RenderOptions::default(), &mut 0);
         assert_eq!(c, Color::new(0., 0., 0.,));
     }
 
@@ -564,8 +3738,11 @@ mod tests {
     fn the_refracted_color_under_total_internal_reflection() {
         let mut rng = SmallRng::seed_from_u64(0);
         let mut scene = default_scene();
-        let mut material = scene.materials.first_mut().unwrap();
+        let material = scene.material_mut(0);
         material.texture = Texture::constant(Color::BLACK);
+        // The light now reaches the inside of the transparent sphere, so turn
+        // off its (vanishingly faint) highlight to leave only the refraction.
+        material.specular = 0.;
         material.transparency = 1.0;
         material.refractive_index = 1.5;
         let r = ray(
@@ -580,10 +3757,10 @@ mod tests {
     fn the_refracted_color_with_a_refracted_ray() {
         let mut rng = SmallRng::seed_from_u64(0);
         let mut scene = default_scene();
-        let a = scene.materials.first_mut().unwrap();
+        let a = scene.material_mut(0);
         a.ambient = 1.0;
         a.texture = Texture::test_pattern();
-        let b = scene.materials.last_mut().unwrap();
+        let b = scene.material_mut(1);
         b.ambient = 0.;
         b.transparency = 1.0;
         b.refractive_index = 1.5;
@@ -595,6 +3772,43 @@ mod tests {
         assert_approx_eq!(c.b, 0.04725, 1e-2);
     }
 
+    #[test]
+    fn a_ray_leaving_glass_bends_away_from_the_normal() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = Scene::new();
+        scene.add_object(
+            Object::new().material(
+                Material::new()
+                    .color(Color::BLACK)
+                    .ambient(0.)
+                    .transparency(1.)
+                    .refractive_index(1.5),
+            ),
+        );
+        let background = Background::VerticalGradient(Color::BLACK, Color::WHITE);
+        scene.set_background(background.clone());
+
+        // From inside, the ray meets the surface 30° from its normal, and
+        // leaves at asin(1.5 sin 30°) ≈ 48.6°, turned further from the normal
+        // (downward, here) rather than toward it.
+        let r = ray(point3(0., 0.5, 0.), vector3(0., 0., 1.));
+        let normal = vector3(0., 0.5, 0.75f32.sqrt());
+        let (sin_t, cos_t) = (0.75f32, 0.4375f32.sqrt());
+        let tangent = vector3(0., -normal.z, normal.y);
+        let expected = normal * cos_t + tangent * sin_t;
+        assert!(expected.y < 0.);
+        assert_approx_eq!(expected.dot(normal), cos_t);
+
+        // Nothing but the refracted ray contributes, and it mustn't start
+        // back inside the sphere.
+        let c = scene.color_at(&mut rng, r);
+        let expected = background.sample(&mut rng, expected);
+        assert_approx_eq!(c.r, expected.r, 1e-3);
+        assert_approx_eq!(c.g, expected.g, 1e-3);
+        assert_approx_eq!(c.b, expected.b, 1e-3);
+        assert!(c.r < background.sample(&mut rng, r.direction).r);
+    }
+
     #[test]
     fn shade_hit_with_a_transparent_material() {
         let mut rng = SmallRng::seed_from_u64(0);
@@ -622,11 +3836,66 @@ mod tests {
 
         let c = scene.color_at(&mut rng, r);
 
-        assert_approx_eq!(c.r, 0.93642, 1e-2);
+        // Unlike in the book, the ball is lit through the half transparent
+        // floor rather than sitting in its shadow.
+        assert_approx_eq!(c.r, 1.0, 1e-2);
         assert_approx_eq!(c.g, 0.68642, 1e-2);
         assert_approx_eq!(c.b, 0.68642, 1e-2);
     }
 
+    #[test]
+    fn a_clay_render_ignores_the_materials_of_the_scene() {
+        // A glass ball in front of a red and green checkered wall.
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(-10., 10., -10.), Color::WHITE));
+        let wall = scene.add_object(
+            Object::new()
+                .geometry(Geometry::checker_floor(
+                    Color::new(1., 0., 0.),
+                    Color::new(0., 1., 0.),
+                    0.5,
+                ))
+                .transform(
+                    Transform::new()
+                        .translate(0., 0., 5.)
+                        .rotate_x(std::f32::consts::FRAC_PI_2),
+                ),
+        );
+        let glass = Material::new()
+            .color(Color::BLACK)
+            .reflective(0.9)
+            .transparency(0.9)
+            .refractive_index(1.5);
+        let ball = scene.add_object(Object::new().material(glass.clone()));
+
+        let r = ray(point3(0.2, 0.1, -5.), vector3(0., 0., 1.));
+        let options = RenderOptions::default();
+        let c = scene.radiance_at(&mut rng, r, &options);
+        assert!((c.r - c.g).abs() > 0.1, "{:?}", c);
+
+        // The ball is now plain gray, and shaded like any matte surface.
+        let clay = options.clay();
+        let c = scene.radiance_at(&mut rng, r, &clay);
+        assert_eq!(c.r, c.g);
+        assert_eq!(c.g, c.b);
+        let material = clay.material_override.unwrap();
+        let point = point3(0.2, 0.1, -(1f32 - 0.05).sqrt());
+        let expected = material.lighting(
+            &mut rng,
+            Transform::new(),
+            &scene.lights[0],
+            point,
+            vector3(0., 0., -1.),
+            point - point3(0., 0., 0.),
+            1.,
+        );
+        assert_approx_eq!(c.r, expected.r, 1e-4);
+
+        assert_eq!(*scene.material(ball), glass);
+        assert_eq!(scene.material(wall).transparency, 0.);
+    }
+
     #[test]
     fn shade_hit_with_a_reflective_and_transparent_material() {
         let mut rng = SmallRng::seed_from_u64(0);
@@ -658,7 +3927,8 @@ mod tests {
         );
         let c = scene.color_at(&mut rng, r);
 
-        assert_approx_eq!(c.r, 0.93391, 1e-2);
+        // The ball is lit through the floor, as above.
+        assert_approx_eq!(c.r, 1.0, 1e-2);
         assert_approx_eq!(c.g, 0.69643, 1e-2);
         assert_approx_eq!(c.b, 0.69243, 1e-2);
     }
@@ -698,20 +3968,151 @@ mod tests {
         let root3over3 = 3_f32.sqrt() / 3.;
         let world_point = point3(root3over3, root3over3, root3over3);
         let eye_vector = world_point - point3(0., 0., 0.);
-        let n = world_normal_at(transform, geometry, world_point, eye_vector);
+        let n = world_normal_at(transform, &geometry, world_point, eye_vector, None, 0);
         let normalized = n.normalize();
         assert_approx_eq!(n.x, normalized.x);
         assert_approx_eq!(n.y, normalized.y);
         assert_approx_eq!(n.z, normalized.z);
     }
 
+    #[test]
+    fn the_normal_of_a_smooth_triangle_is_interpolated_from_the_hit() {
+        let geometry = Geometry::smooth_triangle(
+            (point3(0., 1., 0.), vector3(0., 1., 0.)),
+            (point3(-1., 0., 0.), vector3(-1., 0., 0.)),
+            (point3(1., 0., 0.), vector3(1., 0., 0.)),
+        );
+        let mut scene = Scene::new();
+        scene.add_object(Object::new().geometry(geometry.clone()));
+
+        let r = ray(point3(-0.2, 0.3, -2.), vector3(0., 0., 1.));
+        let intersection = scene.nearest_intersection(r).unwrap();
+        assert!(intersection.uv.is_some());
+
+        let world_point = r.position(intersection.t);
+        let n = world_normal_at(
+            Transform::new(),
+            &geometry,
+            world_point,
+            -r.direction,
+            intersection.uv,
+            intersection.face,
+        );
+        let expected = vector3(-0.2, 0.3, 0.).normalize();
+        assert_approx_eq!(n.x, expected.x);
+        assert_approx_eq!(n.y, expected.y);
+        assert_approx_eq!(n.z, expected.z);
+    }
+
+    /// An OBJ icosphere of radius 1: an icosahedron with each face split
+    /// into four, whose vertex normals point away from the center.
+    fn icosphere_obj() -> String {
+        let phi = (1. + 5_f32.sqrt()) / 2.;
+        let corners = [
+            vector3(-1., phi, 0.),
+            vector3(1., phi, 0.),
+            vector3(-1., -phi, 0.),
+            vector3(1., -phi, 0.),
+            vector3(0., -1., phi),
+            vector3(0., 1., phi),
+            vector3(0., -1., -phi),
+            vector3(0., 1., -phi),
+            vector3(phi, 0., -1.),
+            vector3(phi, 0., 1.),
+            vector3(-phi, 0., -1.),
+            vector3(-phi, 0., 1.),
+        ];
+        let faces = [
+            [0, 11, 5],
+            [0, 5, 1],
+            [0, 1, 7],
+            [0, 7, 10],
+            [0, 10, 11],
+            [1, 5, 9],
+            [5, 11, 4],
+            [11, 10, 2],
+            [10, 7, 6],
+            [7, 1, 8],
+            [3, 9, 4],
+            [3, 4, 2],
+            [3, 2, 6],
+            [3, 6, 8],
+            [3, 8, 9],
+            [4, 9, 5],
+            [2, 4, 11],
+            [6, 2, 10],
+            [8, 6, 7],
+            [9, 8, 1],
+        ];
+
+        let mut obj = String::new();
+        let mut vertices = 0;
+        for face in faces.iter() {
+            let [a, b, c] = [0, 1, 2].map(|i| corners[face[i]].normalize());
+            let [ab, bc, ca] = [(a + b), (b + c), (c + a)].map(|v| v.normalize());
+            for triangle in [[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]].iter() {
+                for v in triangle.iter() {
+                    obj += &format!("v {} {} {}\nvn {} {} {}\n", v.x, v.y, v.z, v.x, v.y, v.z);
+                }
+                obj += &format!(
+                    "f {0}//{0} {1}//{1} {2}//{2}\n",
+                    vertices + 1,
+                    vertices + 2,
+                    vertices + 3
+                );
+                vertices += 3;
+            }
+        }
+        obj
+    }
+
+    #[test]
+    fn smooth_meshes_are_shaded_smoothly_across_the_shadow_terminator() {
+        let model = crate::obj::parse_obj(&icosphere_obj()).unwrap();
+        let render = |casts_shadow: bool| {
+            let mut scene = Scene::new();
+            scene.add_light(Light::new(point3(10., 4., 3.), Color::WHITE));
+            for object in model.to_objects(Material::new().specular(0.)) {
+                scene.add_object(Object {
+                    casts_shadow,
+                    ..object
+                });
+            }
+            let mut camera = Camera::new(41, 41, 0.5);
+            camera.set_transform(Transform::look_at(
+                point3(0., 0., -5.),
+                point3(0., 0., 0.),
+                vector3(0., 1., 0.),
+            ));
+            camera.render(scene)
+        };
+        let (shadowed, unshadowed) = (render(true), render(false));
+
+        // The light falls obliquely across the facets. With no other objects
+        // around, the mesh should look as if it casts no shadows, rather than
+        // dropping to ambient facet by facet near the terminator. (Pixels
+        // near the silhouette are left out: there the interpolated normals
+        // face away from the eye.)
+        let inside = |x: usize, y: usize| (x as f32 - 20.).hypot(y as f32 - 20.) < 12.;
+        for y in 0..41 {
+            for x in (0..41).filter(|&x| inside(x, y)) {
+                let (a, b) = (shadowed.get_color(x, y), unshadowed.get_color(x, y));
+                assert_approx_eq!(a.r, b.r, 0.01);
+                if inside(x + 1, y) {
+                    let step = shadowed.get_color(x + 1, y).r - a.r;
+                    assert!(step.abs() < 0.08, "step of {} at ({}, {})", step, x, y);
+                }
+            }
+        }
+    }
+
     #[test]
     fn computing_the_normal_on_a_translated_sphere() {
         let transform = Transform::new().translate(0., 1., 0.);
         let geometry = Geometry::sphere();
         let world_point = point3(0., 1.70711, -0.70711);
         let eye_vector = world_point - point3(0., 0., 0.);
-        let n = world_normal_at(transform, geometry, world_point, eye_vector);
+        let n = world_normal_at(transform, &geometry, world_point, eye_vector, None, 0);
         assert_approx_eq!(n.x, 0., 1e-5);
         assert_approx_eq!(n.y, 0.70711, 1e-5);
         assert_approx_eq!(n.z, -0.70711, 1e-5);
@@ -729,7 +4130,7 @@ mod tests {
             -2. * std::f32::consts::FRAC_1_SQRT_2,
         );
         let eye_vector = world_point - point3(0., 0., 0.);
-        let n = world_normal_at(transform, geometry, world_point, eye_vector);
+        let n = world_normal_at(transform, &geometry, world_point, eye_vector, None, 0);
         assert_approx_eq!(n.x, 0., 1e-5);
         assert_approx_eq!(n.y, 0.97014, 1e-5);
         assert_approx_eq!(n.z, -0.24254, 1e-5);
@@ -763,26 +4164,38 @@ mod tests {
             Intersection {
                 t: 2.,
                 object_id: a,
+                uv: None,
+                face: 0,
             },
             Intersection {
                 t: 2.75,
                 object_id: b,
+                uv: None,
+                face: 0,
             },
             Intersection {
                 t: 3.25,
                 object_id: c,
+                uv: None,
+                face: 0,
             },
             Intersection {
                 t: 4.75,
                 object_id: b,
+                uv: None,
+                face: 0,
             },
             Intersection {
                 t: 5.25,
                 object_id: c,
+                uv: None,
+                face: 0,
             },
             Intersection {
                 t: 6.,
                 object_id: a,
+                uv: None,
+                face: 0,
             },
         ];
         let mut actual_intersections: Vec<Intersection> = scene.intersections(r).collect();
@@ -808,6 +4221,49 @@ mod tests {
         }
     }
 
+    #[test]
+    fn finding_n1_and_n2_through_an_object_with_four_intersections() {
+        // A ray up through both caps of a closed double cone, which it leaves
+        // and re-enters through the wall, inside a glass sphere.
+        let mut scene = Scene::new();
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::sphere())
+                .transform(Transform::new().scale(3., 3., 3.))
+                .material(Material::new().transparency(1.).refractive_index(1.5)),
+        );
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::capped_cone(-1., 1.))
+                .material(Material::new().transparency(1.).refractive_index(2.)),
+        );
+
+        let r = ray(point3(0.5, -5., 0.), vector3(0., 1., 0.));
+        let mut intersections: Vec<Intersection> = scene.intersections(r).collect();
+        intersections.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+        let ts: Vec<f32> = intersections.iter().map(|i| i.t).collect();
+        assert_eq!(ts.len(), 6);
+        for (&t, expected) in ts[1..5].iter().zip([4., 4.5, 5.5, 6.]) {
+            assert_approx_eq!(t, expected);
+        }
+
+        let indexes: Vec<(f32, f32)> = intersections
+            .iter()
+            .map(|&i| scene.refractive_indexes(r, i))
+            .collect();
+        assert_eq!(
+            indexes,
+            vec![
+                (1.0, 1.5),
+                (1.5, 2.0),
+                (2.0, 1.5),
+                (1.5, 2.0),
+                (2.0, 1.5),
+                (1.5, 1.0),
+            ]
+        );
+    }
+
     #[test]
     fn the_schlick_approximation_under_total_internal_reflection() {
         let mut scene = Scene::new();
@@ -815,7 +4271,7 @@ mod tests {
         let geometry = Geometry::sphere();
         scene.add_object(
             Object::new()
-                .geometry(geometry)
+                .geometry(geometry.clone())
                 .transform(transform)
                 .material(Material::new().transparency(1.).refractive_index(1.5)),
         );
@@ -827,7 +4283,7 @@ mod tests {
         assert_eq!(intersection.t, std::f32::consts::SQRT_2 * 0.5);
         let world_point = r.position(intersection.t);
         let eyev = -r.direction;
-        let normalv = world_normal_at(transform, geometry, world_point, eyev);
+        let normalv = world_normal_at(transform, &geometry, world_point, eyev, None, 0);
         assert_approx_eq!(normalv.x, 0.);
         assert_approx_eq!(normalv.y, -std::f32::consts::SQRT_2 * 0.5);
         assert_approx_eq!(normalv.z, -std::f32::consts::SQRT_2 * 0.5);
@@ -845,7 +4301,7 @@ mod tests {
         let geometry = Geometry::sphere();
         scene.add_object(
             Object::new()
-                .geometry(geometry)
+                .geometry(geometry.clone())
                 .transform(transform)
                 .material(Material::new().transparency(1.).refractive_index(1.5)),
         );
@@ -854,7 +4310,7 @@ mod tests {
         let (n1, n2) = scene.refractive_indexes(r, intersection);
         let world_point = r.position(intersection.t);
         let eyev = -r.direction;
-        let normalv = world_normal_at(transform, geometry, world_point, eyev);
+        let normalv = world_normal_at(transform, &geometry, world_point, eyev, None, 0);
         let reflectance = schlick(eyev, normalv, n1, n2);
         assert_approx_eq!(reflectance, 0.04);
     }
@@ -866,7 +4322,7 @@ mod tests {
         let geometry = Geometry::sphere();
         scene.add_object(
             Object::new()
-                .geometry(geometry)
+                .geometry(geometry.clone())
                 .transform(transform)
                 .material(Material::new().transparency(1.).refractive_index(1.5)),
         );
@@ -875,7 +4331,7 @@ mod tests {
         let (n1, n2) = scene.refractive_indexes(r, intersection);
         let world_point = r.position(intersection.t);
         let eyev = -r.direction;
-        let normalv = world_normal_at(transform, geometry, world_point, eyev);
+        let normalv = world_normal_at(transform, &geometry, world_point, eyev, None, 0);
         let reflectance = schlick(eyev, normalv, n1, n2);
         assert_approx_eq!(reflectance, 0.48873, 1e-3);
     }
@@ -925,4 +4381,201 @@ mod tests {
             let (_n1, _n2) = scene.refractive_indexes(r, intersection);
         });
     }
+
+    /// Returns 100 fixed rays through a 10x10 grid, looking at the origin
+    /// from slightly above.
+    fn bench_rays() -> Vec<Ray> {
+        let mut camera = Camera::new(10, 10, std::f32::consts::FRAC_PI_3);
+        camera.set_transform(Transform::look_at(
+            point3(0., 1.5, -5.),
+            point3(0., 0.5, 0.),
+            vector3(0., 1., 0.),
+        ));
+        (0..100).map(|i| camera.ray(i % 10, i / 10)).collect()
+    }
+
+    /// Shades each of the bench rays once per iteration, so ns/iter is 100
+    /// times the cost per ray. The scene's mean color over the rays is
+    /// checked first, so that a broken scene isn't benchmarked.
+    fn bench_shading_rays(bencher: &mut Bencher, scene: Scene, expected: Color) {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let rays = bench_rays();
+        let mut shade_all = || {
+            rays.iter()
+                .fold(Color::BLACK, |acc, &r| acc + scene.color_at(&mut rng, r))
+        };
+
+        let mean = shade_all() * 0.01;
+        assert_approx_eq!(mean.r, expected.r, 1e-3);
+        assert_approx_eq!(mean.g, expected.g, 1e-3);
+        assert_approx_eq!(mean.b, expected.b, 1e-3);
+
+        bencher.iter(shade_all);
+    }
+
+    fn bench_floor(material: Material) -> Object {
+        Object::new()
+            .geometry(Geometry::plane())
+            .transform(Transform::new().translate(0., -1., 0.))
+            .material(material)
+    }
+
+    #[bench]
+    fn bench_shading_rays_in_a_diffuse_scene(bencher: &mut Bencher) {
+        let mut scene = default_scene();
+        scene.add_object(bench_floor(Material::new()));
+        bench_shading_rays(bencher, scene, Color::new(0.3502, 0.3596, 0.3408));
+    }
+
+    #[bench]
+    fn bench_shading_rays_with_a_reflective_floor(bencher: &mut Bencher) {
+        let mut scene = default_scene();
+        scene.add_object(bench_floor(Material::new().reflective(0.5)));
+        bench_shading_rays(bencher, scene, Color::new(0.3572, 0.3684, 0.3460));
+    }
+
+    #[bench]
+    fn bench_shading_rays_through_a_glass_sphere(bencher: &mut Bencher) {
+        let mut scene = glass_sphere_scene();
+        scene.add_light(Light::new(point3(-10., 10., -10.), Color::new(1., 1., 1.)));
+        bench_shading_rays(bencher, scene, Color::new(0.1962, 0.1962, 0.1962));
+    }
+
+    #[test]
+    fn picking_through_a_glass_sphere_finds_the_wall_behind_it() {
+        let mut scene = Scene::new();
+        let wall = scene.add_object(
+            Object::new().geometry(Geometry::plane()).transform(
+                Transform::new()
+                    .translate(0., 0., 5.)
+                    .rotate_x(std::f32::consts::FRAC_PI_2),
+            ),
+        );
+        let sphere = scene.add_object(
+            Object::new()
+                .geometry(Geometry::sphere())
+                .material(Material::new().transparency(0.9).refractive_index(1.5)),
+        );
+        // A hidden helper between the sphere and the wall, and one behind
+        // the camera, are never picked.
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::cube())
+                .transform(Transform::new().translate(0., 0., 3.))
+                .visibility(0.),
+        );
+        scene.add_object(
+            Object::new()
+                .geometry(Geometry::sphere())
+                .transform(Transform::new().translate(0., 0., -10.)),
+        );
+        let mut camera = Camera::new(11, 11, std::f32::consts::FRAC_PI_3);
+        camera.set_transform(Transform::look_at(
+            point3(0., 0., -5.),
+            point3(0., 0., 0.),
+            vector3(0., 1., 0.),
+        ));
+
+        let picks = scene.pick_all(&camera, 5, 5);
+        let ids: Vec<ObjectId> = picks.iter().map(|&(id, _)| id).collect();
+        assert_eq!(ids, vec![sphere, wall]);
+        // The sphere is entered at t = 4 (and left at t = 6).
+        assert_approx_eq!(picks[0].1, 4.);
+        assert_approx_eq!(picks[1].1, 10.);
+
+        // Off to the side, only the wall is under the pixel.
+        let ids: Vec<ObjectId> = scene
+            .pick_all(&camera, 0, 0)
+            .iter()
+            .map(|&(id, _)| id)
+            .collect();
+        assert_eq!(ids, vec![wall]);
+    }
+
+    /// A glass sphere on a checkered floor, without lights.
+    fn glass_sphere_scene() -> Scene {
+        let mut scene = Scene::new();
+        scene.add_object(bench_floor(
+            Material::new().texture(Texture::checkerboard_2d(Color::WHITE, Color::BLACK)),
+        ));
+        scene.add_object(
+            Object::new().geometry(Geometry::sphere()).material(
+                Material::new()
+                    .color(Color::new(0.1, 0.1, 0.1))
+                    .transparency(0.9)
+                    .reflective(0.9)
+                    .refractive_index(1.5),
+            ),
+        );
+        scene
+    }
+
+    #[test]
+    fn shading_intersects_each_object_once_per_ray() {
+        // Without lights, no shadow rays are traced.
+        let scene = glass_sphere_scene();
+        let options = RenderOptions::default();
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut through_glass = 0;
+        for r in bench_rays() {
+            INTERSECTION_TESTS.with(|tests| tests.set(0));
+            let (_, rays) = scene.radiance_and_cost_at(&mut rng, r, &options);
+            if rays > 1 {
+                through_glass += 1;
+            }
+            // Finding n1 and n2 at the glass reuses the intersections found
+            // for the hit itself.
+            let tests = INTERSECTION_TESTS.with(|tests| tests.get());
+            assert_eq!(tests, rays * scene.object_count());
+        }
+        assert!(through_glass > 0);
+    }
+
+    #[bench]
+    fn bench_shading_rays_in_a_field_of_500_objects(bencher: &mut Bencher) {
+        let scene = field_of_500_objects();
+        bench_shading_rays(bencher, scene, Color::new(0.3358, 0.3570, 0.3146));
+    }
+
+    fn field_of_500_objects() -> Scene {
+        let mut scene = Scene::new();
+        scene.add_light(Light::new(point3(-10., 10., -10.), Color::new(1., 1., 1.)));
+        scene.add_object(bench_floor(Material::new()));
+        for i in 0..499 {
+            let (x, z) = ((i % 25) as f32 - 12., (i / 25) as f32);
+            scene.add_object(
+                Object::new()
+                    .geometry(Geometry::sphere())
+                    .transform(
+                        Transform::new()
+                            .translate(x, -0.75, z)
+                            .scale(0.25, 0.25, 0.25),
+                    )
+                    .material(Material::new().color(Color::new(0.8, 1.0, 0.6))),
+            );
+        }
+        scene
+    }
+
+    #[bench]
+    fn bench_batch_raycasting_a_field_of_500_objects(bencher: &mut Bencher) {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut scene = field_of_500_objects();
+        scene.build_bvh();
+        let rays: Vec<Ray> = (0..10_000)
+            .map(|_| {
+                let target = point3(rng.gen::<f32>() * 24. - 12., -0.75, rng.gen::<f32>() * 20.);
+                let origin = point3(0., 1.5, -5.);
+                ray(origin, (target - origin).normalize())
+            })
+            .collect();
+        bencher.iter(|| scene.batch_raycast(&rays));
+    }
+
+    #[bench]
+    fn bench_shading_rays_in_a_field_of_500_objects_with_a_bvh(bencher: &mut Bencher) {
+        let mut scene = field_of_500_objects();
+        scene.build_bvh();
+        bench_shading_rays(bencher, scene, Color::new(0.3358, 0.3570, 0.3146));
+    }
 }
@@ -0,0 +1,851 @@
+use crate::camera::*;
+use crate::color::*;
+use crate::geometry::*;
+use crate::light::*;
+use crate::material::*;
+use crate::object::*;
+use crate::scene::*;
+use crate::texture::*;
+use crate::transform::*;
+use crate::tuple::*;
+use std::collections::HashMap;
+
+/// Parses a scene file, in the YAML dialect of the book's bonus chapters,
+/// into the camera and the scene it looks at.
+///
+/// The file is a list of items. Each adds the camera, a light, or an object
+/// (a sphere, plane, cube, cylinder, cone, or triangle), or defines a named
+/// material or list of transforms which later items can refer to by name,
+/// and which later definitions can `extend`. Transforms apply in the order
+/// they are listed.
+/// ```text
+/// - add: camera
+///   width: 100
+///   height: 50
+///   field-of-view: 0.785
+///   from: [0, 1.5, -5]
+///   to: [0, 1, 0]
+///   up: [0, 1, 0]
+///
+/// - add: light
+///   at: [-10, 10, -10]
+///   intensity: [1, 1, 1]
+///
+/// - define: shiny
+///   value:
+///     color: [1, 0.2, 0.2]
+///     reflective: 0.5
+///
+/// - add: sphere
+///   material: shiny
+///   transform:
+///     - [scale, 0.5, 0.5, 0.5]
+///     - [translate, 0, 1, 0]
+/// ```
+/// Only the block and flow styles above are understood, not YAML in general.
+/// Anything unrecognized, including unknown keys, is an error which gives
+/// the line it's on.
+pub fn parse_scene(source: &str) -> Result<(Camera, Scene), String> {
+    let document = parse_yaml(source)?;
+    let mut loader = SceneLoader {
+        defines: HashMap::new(),
+        camera: None,
+        scene: Scene::new(),
+    };
+    for item in document.as_list()? {
+        loader.load(item)?;
+    }
+    let camera = loader.camera.ok_or("the scene has no camera")?;
+    Ok((camera, loader.scene))
+}
+
+struct SceneLoader {
+    defines: HashMap<String, Node>,
+    camera: Option<Camera>,
+    scene: Scene,
+}
+
+impl SceneLoader {
+    fn load(&mut self, item: &Node) -> Result<(), String> {
+        if let Some(kind) = item.get("add") {
+            self.add(item, kind)
+        } else if let Some(name) = item.get("define") {
+            self.define(item, name.as_str()?)
+        } else {
+            Err(item.error("expected an add or define item"))
+        }
+    }
+
+    fn add(&mut self, item: &Node, kind: &Node) -> Result<(), String> {
+        match kind.as_str()? {
+            "camera" => {
+                item.check_keys(
+                    &[
+                        "add",
+                        "width",
+                        "height",
+                        "field-of-view",
+                        "from",
+                        "to",
+                        "up",
+                    ],
+                    "a camera",
+                )?;
+                if self.camera.is_some() {
+                    return Err(item.error("the scene already has a camera"));
+                }
+                let mut camera = Camera::new(
+                    item.field("width")?.as_usize()?,
+                    item.field("height")?.as_usize()?,
+                    item.field("field-of-view")?.as_f32()?,
+                );
+                camera.set_transform(Transform::look_at(
+                    item.field("from")?.as_point()?,
+                    item.field("to")?.as_point()?,
+                    item.field("up")?.as_vector()?,
+                ));
+                self.camera = Some(camera);
+            }
+            "light" => {
+                item.check_keys(&["add", "at", "intensity"], "a light")?;
+                self.scene.add_light(Light::new(
+                    item.field("at")?.as_point()?,
+                    item.field("intensity")?.as_color()?,
+                ));
+            }
+            shape => {
+                let (geometry, keys): (Geometry, &[&str]) = match shape {
+                    "sphere" => (Geometry::sphere(), &[]),
+                    "plane" => (Geometry::plane(), &[]),
+                    "cube" => (Geometry::cube(), &[]),
+                    "cylinder" | "cone" => {
+                        let min = item.get("min").map_or(Ok(-f32::INFINITY), Node::as_f32)?;
+                        let max = item.get("max").map_or(Ok(f32::INFINITY), Node::as_f32)?;
+                        let closed = item.get("closed").map_or(Ok(false), Node::as_bool)?;
+                        let geometry = if shape == "cylinder" {
+                            Geometry::cylinder()
+                        } else {
+                            Geometry::cone()
+                        };
+                        let geometry = geometry
+                            .min(min)
+                            .and_then(|geometry| geometry.max(max))
+                            .and_then(|geometry| geometry.closed(closed))
+                            .map_err(|error| item.error(error))?;
+                        (geometry, &["min", "max", "closed"])
+                    }
+                    "triangle" => (
+                        Geometry::triangle(
+                            item.field("p1")?.as_point()?,
+                            item.field("p2")?.as_point()?,
+                            item.field("p3")?.as_point()?,
+                        ),
+                        &["p1", "p2", "p3"],
+                    ),
+                    _ => return Err(kind.error(format!("unknown item: {}", shape))),
+                };
+                let common = ["add", "material", "transform", "shadow"];
+                item.check_keys(&[&common[..], keys].concat(), &format!("a {}", shape))?;
+
+                let mut object = Object::new().geometry(geometry);
+                if let Some(material) = item.get("material") {
+                    object = object.material(self.material(material)?);
+                }
+                if let Some(transform) = item.get("transform") {
+                    object = object.transform(self.transform(transform)?);
+                }
+                if let Some(shadow) = item.get("shadow") {
+                    object = object.casts_shadow(shadow.as_bool()?);
+                }
+                self.scene.add_object(object);
+            }
+        }
+        Ok(())
+    }
+
+    /// Defines a name for the value, merged over the value it extends (if
+    /// any). Lists of transforms are expanded as they're defined, so that
+    /// definitions only ever refer to earlier ones.
+    fn define(&mut self, item: &Node, name: &str) -> Result<(), String> {
+        item.check_keys(&["define", "extend", "value"], "a definition")?;
+        let value = item.field("value")?;
+        let value = match (item.get("extend"), &value.value) {
+            (Some(base), Value::Map(entries)) => {
+                let mut merged: Vec<Entry> = self
+                    .resolve(base)?
+                    .as_map()?
+                    .iter()
+                    .filter(|base| entries.iter().all(|entry| entry.key != base.key))
+                    .cloned()
+                    .collect();
+                merged.extend(entries.iter().cloned());
+                Node {
+                    line: value.line,
+                    value: Value::Map(merged),
+                }
+            }
+            (Some(_), _) => return Err(value.error("only maps can extend a definition")),
+            (None, Value::List(_)) => {
+                let mut steps = vec![];
+                self.transform_steps(value, &mut steps)?;
+                Node {
+                    line: value.line,
+                    value: Value::List(steps.into_iter().cloned().collect()),
+                }
+            }
+            (None, _) => value.clone(),
+        };
+        self.defines.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    /// Returns the definition the node names, if it's a name, or else the
+    /// node itself.
+    fn resolve<'a>(&'a self, node: &'a Node) -> Result<&'a Node, String> {
+        match &node.value {
+            Value::Scalar(name) => self
+                .defines
+                .get(name)
+                .ok_or_else(|| node.error(format!("{} isn't defined", name))),
+            _ => Ok(node),
+        }
+    }
+
+    fn material(&self, node: &Node) -> Result<Material, String> {
+        let mut material = Material::new();
+        for entry in self.resolve(node)?.as_map()? {
+            let value = &entry.value;
+            material = match entry.key.as_str() {
+                "color" => material.color(value.as_color()?),
+                "pattern" => material.texture(self.texture(value)?),
+                "ambient" => material.ambient(value.as_f32()?),
+                "diffuse" => material.diffuse(value.as_f32()?),
+                "specular" => material.specular(value.as_f32()?),
+                "shininess" => material.shininess(value.as_f32()?.round() as i32),
+                "reflective" => material.reflective(value.as_f32()?),
+                "transparency" => material.transparency(value.as_f32()?),
+                "refractive-index" => material.refractive_index(value.as_f32()?),
+                key => {
+                    return Err(format!(
+                        "line {}: unknown key for a material: {}",
+                        entry.line, key
+                    ))
+                }
+            };
+        }
+        Ok(material)
+    }
+
+    fn texture(&self, node: &Node) -> Result<Texture, String> {
+        node.check_keys(&["type", "colors", "transform"], "a pattern")?;
+        let colors = node.field("colors")?;
+        let (a, b) = match colors.as_list()? {
+            [a, b] => (a.as_color()?, b.as_color()?),
+            _ => return Err(colors.error("a pattern needs two colors")),
+        };
+        let kind = node.field("type")?;
+        let mut texture = match kind.as_str()? {
+            "stripes" => Texture::stripe(a, b),
+            "gradient" => Texture::linear_gradient(a, b),
+            "radial-gradient" => Texture::radial_gradient(a, b),
+            "rings" => Texture::ring(a, b),
+            "checkers" => Texture::checkerboard_3d(a, b),
+            other => return Err(kind.error(format!("unknown pattern: {}", other))),
+        };
+        if let Some(transform) = node.get("transform") {
+            texture.transform = self.transform(transform)?;
+        }
+        Ok(texture)
+    }
+
+    fn transform(&self, node: &Node) -> Result<Transform, String> {
+        let mut steps = vec![];
+        self.transform_steps(node, &mut steps)?;
+
+        // Each step applies to the result of those before it, so the last
+        // is outermost.
+        let mut transform = Transform::new();
+        for step in steps.into_iter().rev() {
+            let items = step.as_list()?;
+            let (operation, args) = items
+                .split_first()
+                .ok_or_else(|| step.error("expected a transform"))?;
+            let operation = operation.as_str()?;
+            let args = args
+                .iter()
+                .map(Node::as_f32)
+                .collect::<Result<Vec<_>, _>>()?;
+            let count = match operation {
+                "translate" | "scale" => 3,
+                "rotate-x" | "rotate-y" | "rotate-z" => 1,
+                "shear" => 6,
+                _ => return Err(step.error(format!("unknown transform: {}", operation))),
+            };
+            if args.len() != count {
+                return Err(step.error(format!("{} takes {} numbers", operation, count)));
+            }
+            transform = match operation {
+                "translate" => transform.translate(args[0], args[1], args[2]),
+                "scale" => transform.scale(args[0], args[1], args[2]),
+                "rotate-x" => transform.rotate_x(args[0]),
+                "rotate-y" => transform.rotate_y(args[0]),
+                "rotate-z" => transform.rotate_z(args[0]),
+                _ => transform.shear(args[0], args[1], args[2], args[3], args[4], args[5]),
+            };
+        }
+        Ok(transform)
+    }
+
+    /// Collects the steps of a list of transforms, in which each item is
+    /// either a step or the name of a list to include. The list itself may
+    /// also be a name.
+    fn transform_steps<'a>(
+        &'a self,
+        node: &'a Node,
+        steps: &mut Vec<&'a Node>,
+    ) -> Result<(), String> {
+        for step in self.resolve(node)?.as_list()? {
+            match step.value {
+                Value::Scalar(_) => self.transform_steps(step, steps)?,
+                _ => steps.push(step),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A value in a scene file, and the line it begins on.
+#[derive(Clone, Debug, PartialEq)]
+struct Node {
+    line: usize,
+    value: Value,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Scalar(String),
+    List(Vec<Node>),
+    Map(Vec<Entry>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Entry {
+    line: usize,
+    key: String,
+    value: Node,
+}
+
+impl Node {
+    fn error(&self, message: impl std::fmt::Display) -> String {
+        format!("line {}: {}", self.line, message)
+    }
+
+    fn as_str(&self) -> Result<&str, String> {
+        match &self.value {
+            Value::Scalar(scalar) => Ok(scalar),
+            _ => Err(self.error("expected a single value")),
+        }
+    }
+
+    fn as_list(&self) -> Result<&[Node], String> {
+        match &self.value {
+            Value::List(items) => Ok(items),
+            _ => Err(self.error("expected a list")),
+        }
+    }
+
+    fn as_map(&self) -> Result<&[Entry], String> {
+        match &self.value {
+            Value::Map(entries) => Ok(entries),
+            _ => Err(self.error("expected keys and values")),
+        }
+    }
+
+    fn as_f32(&self) -> Result<f32, String> {
+        let scalar = self.as_str()?;
+        scalar
+            .parse()
+            .map_err(|_| self.error(format!("invalid number: {}", scalar)))
+    }
+
+    fn as_usize(&self) -> Result<usize, String> {
+        let scalar = self.as_str()?;
+        scalar
+            .parse()
+            .map_err(|_| self.error(format!("invalid size: {}", scalar)))
+    }
+
+    fn as_bool(&self) -> Result<bool, String> {
+        match self.as_str()? {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            other => Err(self.error(format!("expected true or false, not {}", other))),
+        }
+    }
+
+    fn as_xyz(&self) -> Result<(f32, f32, f32), String> {
+        match self.as_list()? {
+            [x, y, z] => Ok((x.as_f32()?, y.as_f32()?, z.as_f32()?)),
+            _ => Err(self.error("expected three numbers")),
+        }
+    }
+
+    fn as_point(&self) -> Result<Tuple4, String> {
+        self.as_xyz().map(|(x, y, z)| point3(x, y, z))
+    }
+
+    fn as_vector(&self) -> Result<Tuple4, String> {
+        self.as_xyz().map(|(x, y, z)| vector3(x, y, z))
+    }
+
+    fn as_color(&self) -> Result<Color, String> {
+        self.as_xyz().map(|(r, g, b)| Color::new(r, g, b))
+    }
+
+    /// Returns the value of the key, if the node is a map which has it.
+    fn get(&self, key: &str) -> Option<&Node> {
+        match &self.value {
+            Value::Map(entries) => entries
+                .iter()
+                .find(|entry| entry.key == key)
+                .map(|entry| &entry.value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a key the map must have.
+    fn field(&self, key: &str) -> Result<&Node, String> {
+        self.as_map()?;
+        self.get(key)
+            .ok_or_else(|| self.error(format!("missing {}", key)))
+    }
+
+    /// Checks that the node is a map with no keys but the given ones.
+    fn check_keys(&self, keys: &[&str], what: &str) -> Result<(), String> {
+        match self
+            .as_map()?
+            .iter()
+            .find(|entry| !keys.contains(&&*entry.key))
+        {
+            Some(entry) => Err(format!(
+                "line {}: unknown key for {}: {}",
+                entry.line, what, entry.key
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A line of a scene file, without its indentation or comment.
+#[derive(Copy, Clone, Debug)]
+struct Line<'a> {
+    number: usize,
+    indent: usize,
+    text: &'a str,
+}
+
+fn parse_yaml(source: &str) -> Result<Node, String> {
+    let mut lines = vec![];
+    for (i, line) in source.lines().enumerate() {
+        let number = i + 1;
+        let text = strip_comment(line).trim_end();
+        let content = text.trim_start_matches(' ');
+        if content.starts_with('\t') {
+            return Err(format!("line {}: indent with spaces, not tabs", number));
+        }
+        if !content.is_empty() {
+            lines.push(Line {
+                number,
+                indent: text.len() - content.len(),
+                text: content,
+            });
+        }
+    }
+
+    let indent = lines.first().ok_or("the file is empty")?.indent;
+    let mut next = 0;
+    let document = parse_block(&mut lines, &mut next, indent)?;
+    match lines.get(next) {
+        Some(line) => Err(format!("line {}: unexpected indentation", line.number)),
+        None => Ok(document),
+    }
+}
+
+/// Removes a comment, which begins with a # at the start of the line or
+/// after a space.
+fn strip_comment(line: &str) -> &str {
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        if c == '#' && previous.is_whitespace() {
+            return &line[..i];
+        }
+        previous = c;
+    }
+    line
+}
+
+/// Parses the block of lines at the given indentation, starting from the
+/// next line, and leaving next at the first line after the block.
+fn parse_block(lines: &mut [Line], next: &mut usize, indent: usize) -> Result<Node, String> {
+    let first = lines[*next];
+    if is_list_item(first.text) {
+        let mut items = vec![];
+        while let Some(&line) = lines.get(*next) {
+            if line.indent != indent || !is_list_item(line.text) {
+                break;
+            }
+            let rest = line.text[1..].trim_start();
+            if rest.is_empty() {
+                *next += 1;
+                match lines.get(*next) {
+                    Some(nested) if nested.indent > indent => {
+                        items.push(parse_block(lines, next, nested.indent)?)
+                    }
+                    _ => return Err(format!("line {}: the list item is empty", line.number)),
+                }
+            } else {
+                // The item begins on the same line, indented to where its
+                // text starts.
+                let indent = indent + line.text.len() - rest.len();
+                lines[*next] = Line {
+                    indent,
+                    text: rest,
+                    ..line
+                };
+                items.push(parse_block(lines, next, indent)?);
+            }
+        }
+        Ok(Node {
+            line: first.number,
+            value: Value::List(items),
+        })
+    } else if split_key(first.text).is_some() {
+        let mut entries: Vec<Entry> = vec![];
+        while let Some(&line) = lines.get(*next) {
+            if line.indent != indent {
+                break;
+            }
+            let (key, rest) = split_key(line.text)
+                .ok_or_else(|| format!("line {}: expected a key", line.number))?;
+            if entries.iter().any(|entry| entry.key == key) {
+                return Err(format!("line {}: {} is repeated", line.number, key));
+            }
+            *next += 1;
+            let value = if rest.is_empty() {
+                // A nested block, or a list, which may be indented as much
+                // as its key.
+                match lines.get(*next) {
+                    Some(nested)
+                        if nested.indent > indent
+                            || (nested.indent == indent && is_list_item(nested.text)) =>
+                    {
+                        parse_block(lines, next, nested.indent)?
+                    }
+                    _ => return Err(format!("line {}: {} has no value", line.number, key)),
+                }
+            } else {
+                parse_flow(rest, line.number)?
+            };
+            entries.push(Entry {
+                line: line.number,
+                key: key.to_string(),
+                value,
+            });
+        }
+        Ok(Node {
+            line: first.number,
+            value: Value::Map(entries),
+        })
+    } else {
+        *next += 1;
+        parse_flow(first.text, first.number)
+    }
+}
+
+fn is_list_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+/// Splits a `key: value` line into the key and the (possibly empty) value.
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    if is_list_item(text) || text.starts_with('[') {
+        return None;
+    }
+    let colon = text.find(':')?;
+    let (key, rest) = (text[..colon].trim_end(), &text[colon + 1..]);
+    if key.is_empty() || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    Some((key, rest.trim()))
+}
+
+/// Parses a value written on one line: a scalar, or a list such as
+/// `[translate, 1, 2, 3]`.
+fn parse_flow(text: &str, line: usize) -> Result<Node, String> {
+    let (node, rest) = parse_flow_value(text, line)?;
+    match rest.trim_start() {
+        "" => Ok(node),
+        rest => Err(format!("line {}: unexpected {}", line, rest)),
+    }
+}
+
+/// Parses the value at the start of the text, returning it and the text
+/// after it.
+fn parse_flow_value(text: &str, line: usize) -> Result<(Node, &str), String> {
+    let text = text.trim_start();
+    if let Some(mut rest) = text.strip_prefix('[') {
+        let mut items = vec![];
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                let list = Node {
+                    line,
+                    value: Value::List(items),
+                };
+                return Ok((list, after));
+            } else if rest.is_empty() {
+                return Err(format!("line {}: the list isn't closed", line));
+            } else if !items.is_empty() {
+                rest = rest
+                    .strip_prefix(',')
+                    .ok_or_else(|| format!("line {}: expected a comma", line))?;
+            }
+            let (item, after) = parse_flow_value(rest, line)?;
+            items.push(item);
+            rest = after;
+        }
+    }
+
+    let (scalar, rest) = if let Some(quote) = text.chars().next().filter(|&c| c == '"' || c == '\'')
+    {
+        let end = text[1..]
+            .find(quote)
+            .ok_or_else(|| format!("line {}: the string isn't closed", line))?;
+        (&text[1..end + 1], &text[end + 2..])
+    } else {
+        let end = text.find([',', ']']).unwrap_or(text.len());
+        let scalar = text[..end].trim_end();
+        if scalar.is_empty() {
+            return Err(format!("line {}: expected a value", line));
+        }
+        (scalar, &text[end..])
+    };
+    let scalar = Node {
+        line,
+        value: Value::Scalar(scalar.to_string()),
+    };
+    Ok((scalar, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    const SAMPLE: &str = "
+# A scene with one of everything.
+- add: camera
+  width: 40
+  height: 20
+  field-of-view: 1.0471975
+  from: [0, 1.5, -5]
+  to: [0, 1, 0]
+  up: [0, 1, 0]
+
+- add: light
+  at: [-10, 10, -10]
+  intensity: [1, 1, 1]
+
+- define: matte
+  value:
+    color: [1, 0.9, 0.9]
+    specular: 0
+    ambient: 0.2
+
+- define: red-matte
+  extend: matte
+  value:
+    color: [1, 0, 0]
+
+- define: small
+  value:
+    - [scale, 0.5, 0.5, 0.5]
+
+- define: small-and-raised
+  value:
+    - small
+    - [translate, 0, 1, 0]
+
+- add: plane
+  material:
+    pattern:
+      type: checkers
+      colors:
+        - [1, 1, 1]
+        - [0, 0, 0]
+      transform:
+        - [scale, 0.25, 0.25, 0.25]
+
+- add: sphere
+  material: red-matte
+  transform:
+  - small-and-raised
+  - [translate, -1, 0, 0]   # after the defined steps
+
+- add: cube
+  material: matte
+  transform:
+    - [rotate-y, 0.7853982]
+  shadow: false
+
+- add: cylinder
+  min: 0
+  max: 2
+  closed: true
+  material:
+    reflective: 0.5
+    transparency: 0.5
+    refractive-index: 1.5
+    shininess: 300
+
+- add: cone
+  min: -1
+  max: 0
+  transform:
+    - [shear, 1, 0, 0, 0, 0, 0]
+
+- add: triangle
+  p1: [0, 0, 0]
+  p2: [1, 0, 0]
+  p3: [0, 1, 0]
+";
+
+    #[test]
+    fn parsing_a_scene_file() {
+        let (camera, scene) = parse_scene(SAMPLE).unwrap();
+        assert_eq!((camera.hsize, camera.vsize), (40, 20));
+        assert_approx_eq!(camera.fov, std::f32::consts::FRAC_PI_3);
+        assert_eq!(scene.lights().len(), 1);
+        assert_eq!(scene.object_count(), 6);
+
+        let plane = scene.material(0);
+        assert_eq!(
+            plane.texture.spec,
+            TextureSpec::Checkerboard3D(Color::WHITE, Color::BLACK)
+        );
+        assert_eq!(
+            plane.texture.transform,
+            Transform::new().scale(0.25, 0.25, 0.25)
+        );
+
+        // The definition extended by red-matte keeps the properties it
+        // doesn't override.
+        let sphere = scene.material(1);
+        assert_eq!(sphere.texture, Texture::constant(Color::new(1., 0., 0.)));
+        assert_eq!((sphere.specular, sphere.ambient), (0., 0.2));
+        assert_eq!(
+            scene.material(2).texture,
+            Texture::constant(Color::new(1., 0.9, 0.9))
+        );
+
+        // Transforms apply in the order they're listed.
+        let expected = Transform::new()
+            .translate(-1., 0., 0.)
+            .translate(0., 1., 0.)
+            .scale(0.5, 0.5, 0.5);
+        assert_eq!(*scene.transform(1), expected);
+        let center = scene.transform(1).local_to_world * point3(0., 0., 0.);
+        assert_eq!(center, point3(-1., 1., 0.));
+
+        assert_eq!(*scene.geometry(0), Geometry::plane());
+        assert_eq!(*scene.geometry(1), Geometry::sphere());
+        assert_eq!(*scene.geometry(2), Geometry::cube());
+        assert_eq!(
+            *scene.geometry(3),
+            Geometry::Cylinder {
+                min: 0.,
+                max: 2.,
+                closed: true,
+                fillet: 0.
+            }
+        );
+        assert_eq!(
+            *scene.geometry(4),
+            Geometry::Cone {
+                min: -1.,
+                max: 0.,
+                closed: false,
+                fillet: 0.
+            }
+        );
+        assert_eq!(
+            *scene.geometry(5),
+            Geometry::triangle(point3(0., 0., 0.), point3(1., 0., 0.), point3(0., 1., 0.))
+        );
+
+        let glass = scene.material(3);
+        assert_eq!((glass.reflective, glass.transparency), (0.5, 0.5));
+        assert_eq!((glass.refractive_index, glass.shininess), (1.5, 300));
+    }
+
+    #[test]
+    fn a_parsed_scene_renders() {
+        let (camera, scene) = parse_scene(SAMPLE).unwrap();
+        let canvas = camera.render(scene);
+        assert_eq!((canvas.width, canvas.height), (40, 20));
+        assert!(canvas.data.iter().any(|&c| c > 0.));
+    }
+
+    #[test]
+    fn malformed_scene_files_are_errors() {
+        let cases = [
+            ("", "the file is empty"),
+            (
+                "- add: light\n  at: [0, 0, 0]\n",
+                "line 1: missing intensity",
+            ),
+            (
+                "- add: sphere\n  radius: 2\n",
+                "line 2: unknown key for a sphere: radius",
+            ),
+            ("- add: teapot\n", "line 1: unknown item: teapot"),
+            (
+                "- add: light\n  at: [0, 0, 0]\n  intensity: [1, 1, 1]\n\
+                 - add: cylinder\n  min: 2\n  max: 1\n",
+                "line 4: max 1 is below min 2",
+            ),
+            (
+                "- add: sphere\n  material:\n    colour: [1, 0, 0]\n",
+                "line 3: unknown key for a material: colour",
+            ),
+            (
+                "- add: sphere\n  transform:\n    - [scale, 2]\n",
+                "line 3: scale takes 3 numbers",
+            ),
+            (
+                "- add: sphere\n  material: shiny\n",
+                "line 2: shiny isn't defined",
+            ),
+            (
+                "- add: sphere\n  transform: [[scale, 1, 1, 1]\n",
+                "line 2: the list isn't closed",
+            ),
+            (
+                "- add: sphere\n    material: {}\n",
+                "line 2: unexpected indentation",
+            ),
+            (
+                "- add: light\n  at: [0, 0, 0]\n  intensity: [1, 1, 1]\n",
+                "the scene has no camera",
+            ),
+        ];
+        for (source, error) in cases {
+            assert_eq!(
+                parse_scene(source).err().as_deref(),
+                Some(error),
+                "{:?}",
+                source
+            );
+        }
+    }
+}
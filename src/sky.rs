@@ -0,0 +1,204 @@
+use crate::color::*;
+use crate::cube_map::*;
+use crate::light::*;
+use crate::rng::*;
+use crate::texture::*;
+use crate::transform::*;
+use crate::tuple::*;
+
+/// How far away the light standing in for the sun is placed, far enough that
+/// its rays are effectively parallel across the scene.
+pub const SUN_DISTANCE: f32 = 1e4;
+
+/// What rays that miss every object see.
+#[derive(Clone, Debug)]
+pub enum Background {
+    Solid(Color),
+    /// A gradient from the first color (looking straight down) to the second
+    /// (looking straight up), by the y component of the ray's direction.
+    VerticalGradient(Color, Color),
+    /// A procedural sky.
+    Sky(Sky),
+    /// A texture, looked up where the ray's direction meets the unit cube, so
+    /// the same cube map texture can also be put on a large cube around the
+    /// scene.
+    Texture(Texture),
+    /// Six images, one for each face of a cube around the scene.
+    CubeMap(CubeMap),
+}
+
+impl Background {
+    /// Returns the color seen looking along the given direction.
+    pub fn sample<R: Rng>(&self, rng: &mut R, direction: Tuple4) -> Color {
+        match self {
+            Background::Solid(color) => *color,
+            Background::VerticalGradient(bottom, top) => {
+                let t = (direction.normalize().y + 1.) / 2.;
+                *bottom * (1. - t) + *top * t
+            }
+            Background::Sky(sky) => sky.sample(direction),
+            Background::Texture(texture) => {
+                let d = direction;
+                let scale = 1. / d.x.abs().max(d.y.abs()).max(d.z.abs());
+                let point = point3(d.x * scale, d.y * scale, d.z * scale);
+                texture.evaluate(rng, Transform::new(), point)
+            }
+            Background::CubeMap(cube_map) => cube_map.sample(direction),
+        }
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Solid(Color::BLACK)
+    }
+}
+
+impl From<Color> for Background {
+    fn from(color: Color) -> Self {
+        Background::Solid(color)
+    }
+}
+
+impl From<Sky> for Background {
+    fn from(sky: Sky) -> Self {
+        Background::Sky(sky)
+    }
+}
+
+impl From<Texture> for Background {
+    fn from(texture: Texture) -> Self {
+        Background::Texture(texture)
+    }
+}
+
+impl From<CubeMap> for Background {
+    fn from(cube_map: CubeMap) -> Self {
+        Background::CubeMap(cube_map)
+    }
+}
+
+/// A procedural sky: a gradient from the horizon to the zenith, with a sun
+/// disc and a glow around it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sky {
+    /// The direction toward the sun.
+    pub sun_direction: Tuple4,
+    /// The angular radius (in radians) of the visible sun disc.
+    pub sun_radius: f32,
+    pub sun_color: Color,
+    pub sun_intensity: f32,
+    /// How hazy the sky is, from 0 (clear) to 1 (overcast). Haze washes the
+    /// zenith out toward the horizon color and widens the sun's glow.
+    pub haze: f32,
+    pub zenith: Color,
+    pub horizon: Color,
+}
+
+impl Sky {
+    pub fn new(sun_direction: Tuple4, haze: f32, sun_intensity: f32) -> Self {
+        Sky {
+            sun_direction: sun_direction.normalize(),
+            sun_radius: 0.02,
+            sun_color: Color::new(1., 0.95, 0.85),
+            sun_intensity,
+            haze: haze.clamp(0., 1.),
+            zenith: Color::new(0.2, 0.4, 0.9),
+            horizon: Color::new(0.85, 0.9, 1.),
+        }
+    }
+
+    pub fn sun_radius(mut self, sun_radius: f32) -> Self {
+        self.sun_radius = sun_radius;
+        self
+    }
+
+    pub fn sun_color(mut self, sun_color: Color) -> Self {
+        self.sun_color = sun_color;
+        self
+    }
+
+    pub fn zenith(mut self, zenith: Color) -> Self {
+        self.zenith = zenith;
+        self
+    }
+
+    pub fn horizon(mut self, horizon: Color) -> Self {
+        self.horizon = horizon;
+        self
+    }
+
+    /// Returns the color of the sky in the given direction, ignoring the sun.
+    pub fn gradient(&self, direction: Tuple4) -> Color {
+        let zenith = self.zenith * (1. - self.haze) + self.horizon * self.haze;
+        let t = direction.normalize().y.max(0.);
+        self.horizon * (1. - t) + zenith * t
+    }
+
+    /// Returns the color seen looking along the given direction.
+    pub fn sample(&self, direction: Tuple4) -> Color {
+        let cos = direction.normalize().dot(self.sun_direction);
+        if cos >= self.sun_radius.cos() {
+            return self.sun_color * self.sun_intensity;
+        }
+
+        // The glow falls off with angle from the sun, more slowly when hazy.
+        let glow = cos.max(0.).powf(64. * (1. - self.haze) + 4.) * (0.2 + self.haze);
+        self.gradient(direction) + self.sun_color * glow
+    }
+
+    /// Returns a light standing in for the sun, so that lighting agrees with
+    /// the sky.
+    pub fn sun_light(&self) -> Light {
+        Light::new(
+            point3(0., 0., 0.) + self.sun_direction * SUN_DISTANCE,
+            self.sun_color * self.sun_intensity,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn looking_at_the_sun() {
+        let sky = Sky::new(vector3(1., 1., 0.), 0.3, 5.);
+        assert_eq!(sky.sample(vector3(1., 1., 0.)), sky.sun_color * 5.);
+        assert_eq!(sky.sample(vector3(1., 1.01, 0.)), sky.sun_color * 5.);
+        assert_ne!(sky.sample(vector3(1., 1.2, 0.)), sky.sun_color * 5.);
+    }
+
+    #[test]
+    fn looking_away_from_the_sun() {
+        let sky = Sky::new(vector3(1., 0., 0.), 0., 5.);
+
+        // At 90° there is no glow, just the gradient.
+        assert_eq!(sky.sample(vector3(0., 1., 0.)), sky.zenith);
+        assert_eq!(sky.sample(vector3(0., 0., 1.)), sky.horizon);
+        let c = sky.sample(vector3(0., 1., 1.));
+        let t = std::f32::consts::FRAC_1_SQRT_2;
+        let expected = sky.horizon * (1. - t) + sky.zenith * t;
+        assert_approx_eq!(c.r, expected.r);
+        assert_approx_eq!(c.g, expected.g);
+        assert_approx_eq!(c.b, expected.b);
+    }
+
+    #[test]
+    fn haze_washes_out_the_zenith() {
+        let sky = Sky::new(vector3(1., 0., 0.), 1., 5.);
+        assert_eq!(sky.sample(vector3(0., 1., 0.)), sky.horizon);
+    }
+
+    #[test]
+    fn the_sun_light_is_in_the_direction_of_the_sun() {
+        let sky = Sky::new(vector3(0., 3., 4.), 0.3, 2.);
+        let light = sky.sun_light();
+        let direction = (light.position - point3(0., 0., 0.)).normalize();
+        assert_approx_eq!(direction.x, 0.);
+        assert_approx_eq!(direction.y, 0.6);
+        assert_approx_eq!(direction.z, 0.8);
+        assert_eq!(light.intensity, sky.sun_color * 2.);
+    }
+}
@@ -1,17 +1,27 @@
 use crate::color::*;
+use crate::constants::*;
+use crate::ray::*;
+use crate::rng::*;
 use crate::transform::*;
 use crate::tuple::*;
-use rand::Rng;
+use std::sync::Arc;
 
+pub mod brick;
+pub mod cache;
 pub mod checkerboard_2d;
 pub mod checkerboard_3d;
+pub mod cube_mapping;
 pub mod linear_gradient;
+pub mod perlin;
 pub mod radial_gradient;
 pub mod ring;
+pub mod spherical_mapping;
 pub mod stripe;
+pub mod tile;
 pub mod white_noise;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TextureSpec {
     Constant(Color),
     Stripe(Color, Color),
@@ -20,14 +30,125 @@ pub enum TextureSpec {
     Ring(Color, Color),
     Checkerboard2D(Color, Color),
     Checkerboard3D(Color, Color),
+    Brick {
+        brick: Color,
+        mortar: Color,
+        /// The width (along x) and depth (along z) of each brick.
+        size: (f32, f32),
+        /// The thickness of the joints between bricks.
+        mortar_width: f32,
+        /// The shift of each row relative to the previous, in brick widths.
+        row_offset: f32,
+    },
+    Tile {
+        tile: Color,
+        grout: Color,
+        /// The width of each (square) tile.
+        size: f32,
+        /// The width of the joints between tiles.
+        grout_width: f32,
+        /// The distance from the edge over which each tile darkens.
+        bevel: f32,
+    },
+    /// Perlin noise, mapped onto a gradient from the first color to the
+    /// second.
+    Perlin {
+        a: Color,
+        b: Color,
+        seed: u32,
+    },
+    /// A pattern for each face of a unit cube, in CubeFace order (+x, -x,
+    /// +y, -y, +z, -z), as seen from outside it. Points off the cube use
+    /// the face they lie beyond. The faces are shared by clones of the
+    /// texture, which keeps textures small.
+    CubeMap {
+        faces: Arc<[AlignCheck; 6]>,
+    },
     WhiteNoise,
     TestPattern,
+    /// The spherical (u, v) coordinates of the point as red and green, for
+    /// checking how a sphere is mapped.
+    UvDebug,
 }
 
+/// A square (u, v) pattern for checking how a texture is mapped: a main
+/// color with a different color in each corner. With every color the same,
+/// it is a solid color.
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AlignCheck {
+    pub main: Color,
+    pub upper_left: Color,
+    pub upper_right: Color,
+    pub bottom_left: Color,
+    pub bottom_right: Color,
+}
+
+impl AlignCheck {
+    pub fn new(
+        main: Color,
+        upper_left: Color,
+        upper_right: Color,
+        bottom_left: Color,
+        bottom_right: Color,
+    ) -> Self {
+        AlignCheck {
+            main,
+            upper_left,
+            upper_right,
+            bottom_left,
+            bottom_right,
+        }
+    }
+
+    pub fn solid(color: Color) -> Self {
+        AlignCheck::new(color, color, color, color, color)
+    }
+
+    /// Returns the color at (u, v) in [0, 1], where the corners take up the
+    /// outer fifth of each side.
+    pub fn evaluate(&self, u: f32, v: f32) -> Color {
+        if v > 0.8 {
+            if u < 0.2 {
+                return self.upper_left;
+            } else if u > 0.8 {
+                return self.upper_right;
+            }
+        } else if v < 0.2 {
+            if u < 0.2 {
+                return self.bottom_left;
+            } else if u > 0.8 {
+                return self.bottom_right;
+            }
+        }
+        self.main
+    }
+}
+
+/// Jitters the point at which a texture is looked up by a vector of Perlin
+/// noise, so that straight edges in the texture become wavy.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Perturbation {
+    pub seed: u32,
+    /// The largest distance, in texture space, that a point moves along
+    /// each axis.
+    pub scale: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Texture {
     pub spec: TextureSpec,
     pub transform: Transform,
+    /// The distance the texture moves per unit of time, in object space.
+    pub velocity: Tuple4,
+    /// Perturbs the lookup point of the spec. Textures don't nest, so there
+    /// is one level of perturbation, applied in texture space.
+    pub perturbation: Option<Perturbation>,
+    /// If set, the texture's colors are cached under this ID (see
+    /// `Texture::cacheable`).
+    pub cache_id: Option<u32>,
 }
 
 impl Texture {
@@ -35,6 +156,9 @@ impl Texture {
         Texture {
             spec: TextureSpec::Constant(color),
             transform: Transform::new(),
+            velocity: vector3(0., 0., 0.),
+            perturbation: None,
+            cache_id: None,
         }
     }
 
@@ -42,6 +166,9 @@ impl Texture {
         Texture {
             spec: TextureSpec::Stripe(a, b),
             transform: Transform::new(),
+            velocity: vector3(0., 0., 0.),
+            perturbation: None,
+            cache_id: None,
         }
     }
 
@@ -49,6 +176,9 @@ impl Texture {
         Texture {
             spec: TextureSpec::LinearGradient(a, b),
             transform: Transform::new(),
+            velocity: vector3(0., 0., 0.),
+            perturbation: None,
+            cache_id: None,
         }
     }
 
@@ -56,6 +186,9 @@ impl Texture {
         Texture {
             spec: TextureSpec::RadialGradient(a, b),
             transform: Transform::new(),
+            velocity: vector3(0., 0., 0.),
+            perturbation: None,
+            cache_id: None,
         }
     }
 
@@ -63,6 +196,9 @@ impl Texture {
         Texture {
             spec: TextureSpec::Ring(a, b),
             transform: Transform::new(),
+            velocity: vector3(0., 0., 0.),
+            perturbation: None,
+            cache_id: None,
         }
     }
 
@@ -70,6 +206,9 @@ impl Texture {
         Texture {
             spec: TextureSpec::Checkerboard2D(a, b),
             transform: Transform::new(),
+            velocity: vector3(0., 0., 0.),
+            perturbation: None,
+            cache_id: None,
         }
     }
 
@@ -77,6 +216,71 @@ impl Texture {
         Texture {
             spec: TextureSpec::Checkerboard3D(a, b),
             transform: Transform::new(),
+            velocity: vector3(0., 0., 0.),
+            perturbation: None,
+            cache_id: None,
+        }
+    }
+
+    /// Creates a brick texture in a running bond, with bricks twice as wide
+    /// as they are deep and each row shifted by half a brick.
+    pub fn brick(brick: Color, mortar: Color) -> Self {
+        Texture {
+            spec: TextureSpec::Brick {
+                brick,
+                mortar,
+                size: (1., 0.5),
+                mortar_width: 0.05,
+                row_offset: 0.5,
+            },
+            transform: Transform::new(),
+            velocity: vector3(0., 0., 0.),
+            perturbation: None,
+            cache_id: None,
+        }
+    }
+
+    /// Creates a texture of unit tiles with narrow grout lines and beveled
+    /// edges.
+    pub fn tile(tile: Color, grout: Color) -> Self {
+        Texture {
+            spec: TextureSpec::Tile {
+                tile,
+                grout,
+                size: 1.,
+                grout_width: 0.05,
+                bevel: 0.05,
+            },
+            transform: Transform::new(),
+            velocity: vector3(0., 0., 0.),
+            perturbation: None,
+            cache_id: None,
+        }
+    }
+
+    /// Creates a Perlin noise texture, where the seed picks one of many
+    /// different noise fields.
+    pub fn perlin(a: Color, b: Color, seed: u32) -> Self {
+        Texture {
+            spec: TextureSpec::Perlin { a, b, seed },
+            transform: Transform::new(),
+            velocity: vector3(0., 0., 0.),
+            perturbation: None,
+            cache_id: None,
+        }
+    }
+
+    /// Creates a texture with a pattern on each face of the unit cube, in
+    /// CubeFace order (+x, -x, +y, -y, +z, -z).
+    pub fn cube_map(faces: [AlignCheck; 6]) -> Self {
+        Texture {
+            spec: TextureSpec::CubeMap {
+                faces: Arc::new(faces),
+            },
+            transform: Transform::new(),
+            velocity: vector3(0., 0., 0.),
+            perturbation: None,
+            cache_id: None,
         }
     }
 
@@ -84,6 +288,9 @@ impl Texture {
         Texture {
             spec: TextureSpec::WhiteNoise,
             transform: Transform::new(),
+            velocity: vector3(0., 0., 0.),
+            perturbation: None,
+            cache_id: None,
         }
     }
 
@@ -91,9 +298,61 @@ impl Texture {
         Texture {
             spec: TextureSpec::TestPattern,
             transform: Transform::new(),
+            velocity: vector3(0., 0., 0.),
+            perturbation: None,
+            cache_id: None,
         }
     }
 
+    /// Returns a texture which shows the spherical (u, v) mapping of each
+    /// point as its red and green.
+    pub fn uv_debug() -> Self {
+        Texture {
+            spec: TextureSpec::UvDebug,
+            transform: Transform::new(),
+            velocity: vector3(0., 0., 0.),
+            perturbation: None,
+            cache_id: None,
+        }
+    }
+
+    /// Perturbs the texture's lookup point by a vector of noise with the given
+    /// seed and scale.
+    pub fn perturb(mut self, seed: u32, scale: f32) -> Self {
+        self.perturbation = Some(Perturbation { seed, scale });
+        self
+    }
+
+    /// Caches the texture's colors, for textures expensive enough to be worth
+    /// it, such as Perlin noise. Lookups snap to the centers of cells of side
+    /// TEXTURE_CACHE_STEP, so the colors don't depend on which point of a
+    /// cell happened to be evaluated first. White noise is never cached.
+    ///
+    /// Cached colors are only used for the spec and perturbation they were
+    /// evaluated from, so the texture may still be changed afterwards (its
+    /// transform and velocity don't matter, as cells are in texture space).
+    pub fn cacheable(mut self) -> Self {
+        self.cache_id = Some(cache::next_id());
+        self
+    }
+
+    /// Returns the texture as it appears at the given time, offset by its
+    /// velocity.
+    pub fn at_time(mut self, time: f32) -> Self {
+        let offset = self.velocity * time;
+
+        if offset == vector3(0., 0., 0.) {
+            return self;
+        }
+
+        let translation = Transform::new().translate(offset.x, offset.y, offset.z);
+        self.transform = Transform {
+            local_to_world: translation.local_to_world * self.transform.local_to_world,
+            world_to_local: self.transform.world_to_local * translation.world_to_local,
+        };
+        self
+    }
+
     /// Returns the color at the given point in world space.
     pub fn evaluate<R: Rng>(
         &self,
@@ -113,6 +372,21 @@ impl Texture {
 
     /// Returns the color at the given point in texture space.
     pub fn evaluate_local<R: Rng>(&self, rng: &mut R, texture_point: Tuple4) -> Color {
+        match self.cache_id {
+            Some(id) if self.spec != TextureSpec::WhiteNoise => {
+                let (cell, center) = cache::cell(texture_point);
+                cache::lookup((id, cell), (self.spec.clone(), self.perturbation), || {
+                    self.evaluate_uncached(rng, center)
+                })
+            }
+            _ => self.evaluate_uncached(rng, texture_point),
+        }
+    }
+
+    /// Returns the color at the given point in texture space, bypassing the
+    /// cache.
+    fn evaluate_uncached<R: Rng>(&self, rng: &mut R, texture_point: Tuple4) -> Color {
+        let texture_point = self.perturbed(texture_point);
         match self.spec {
             TextureSpec::Constant(color) => color,
             TextureSpec::Stripe(a, b) => stripe::evaluate(texture_point, a, b),
@@ -121,20 +395,171 @@ impl Texture {
             TextureSpec::Ring(a, b) => ring::evaluate(texture_point, a, b),
             TextureSpec::Checkerboard2D(a, b) => checkerboard_2d::evaluate(texture_point, a, b),
             TextureSpec::Checkerboard3D(a, b) => checkerboard_3d::evaluate(texture_point, a, b),
+            TextureSpec::Brick {
+                brick,
+                mortar,
+                size,
+                mortar_width,
+                row_offset,
+            } => brick::evaluate(texture_point, brick, mortar, size, mortar_width, row_offset),
+            TextureSpec::Tile {
+                tile,
+                grout,
+                size,
+                grout_width,
+                bevel,
+            } => tile::evaluate(texture_point, tile, grout, size, grout_width, bevel),
+            TextureSpec::Perlin { a, b, seed } => perlin::evaluate(texture_point, a, b, seed),
+            TextureSpec::CubeMap { ref faces } => cube_mapping::evaluate(texture_point, faces),
             TextureSpec::WhiteNoise => white_noise::evaluate(rng, Color::WHITE),
             TextureSpec::TestPattern => {
                 Color::new(texture_point.x, texture_point.y, texture_point.z)
             }
+            TextureSpec::UvDebug => spherical_mapping::evaluate_debug(texture_point),
         }
     }
+
+    /// Returns the color at the given point in texture space, averaged over
+    /// a box filter of the given width (also in texture space) to avoid
+    /// aliasing where the texture is seen from afar.
+    ///
+    /// Stripes, rings, and checkerboards are filtered analytically (see
+    /// `is_filtered`); other textures, and a width of zero, fall back to
+    /// point sampling.
+    pub fn evaluate_local_filtered<R: Rng>(
+        &self,
+        rng: &mut R,
+        texture_point: Tuple4,
+        filter_width: f32,
+    ) -> Color {
+        let width = vector3(filter_width, filter_width, filter_width);
+        self.evaluate_local_anisotropic(rng, texture_point, width)
+    }
+
+    /// Returns the color at the given point in texture space, averaged over
+    /// a box filter with the given width along each axis.
+    fn evaluate_local_anisotropic<R: Rng>(
+        &self,
+        rng: &mut R,
+        texture_point: Tuple4,
+        width: Tuple4,
+    ) -> Color {
+        if width.x <= 0. && width.y <= 0. && width.z <= 0. {
+            return self.evaluate_local(rng, texture_point);
+        }
+
+        let point = self.perturbed(texture_point);
+        match self.spec {
+            TextureSpec::Stripe(a, b) => stripe::evaluate_filtered(point, a, b, width.x),
+            TextureSpec::Ring(a, b) => {
+                // The width of the filter across the rings, i.e. radially.
+                let r = point.x.hypot(point.z);
+                let radial = if r > 0. {
+                    (point.x * width.x).hypot(point.z * width.z) / r
+                } else {
+                    width.x.max(width.z)
+                };
+                ring::evaluate_filtered(point, a, b, radial)
+            }
+            TextureSpec::Checkerboard2D(a, b) => {
+                checkerboard_2d::evaluate_filtered(point, a, b, width)
+            }
+            TextureSpec::Checkerboard3D(a, b) => {
+                checkerboard_3d::evaluate_filtered(point, a, b, width)
+            }
+            _ => self.evaluate_local(rng, texture_point),
+        }
+    }
+
+    /// Whether `evaluate_local_filtered` filters the texture, rather than
+    /// falling back to point sampling.
+    pub fn is_filtered(&self) -> bool {
+        matches!(
+            self.spec,
+            TextureSpec::Stripe(..)
+                | TextureSpec::Ring(..)
+                | TextureSpec::Checkerboard2D(..)
+                | TextureSpec::Checkerboard3D(..)
+        )
+    }
+
+    /// Returns the color where the ray, in world space, hits the surface of
+    /// an object with the given world normal at t, averaged over the ray's
+    /// footprint there (see `Ray::spread`).
+    ///
+    /// The footprint keeps its width across the ray, and is stretched along
+    /// it as the ray grazes the surface (as on a checker floor). Each of
+    /// these is carried into texture space, and the filter along each axis
+    /// spans both.
+    pub fn evaluate_footprint<R: Rng>(
+        &self,
+        rng: &mut R,
+        object_transform: Transform,
+        world_ray: Ray,
+        t: f32,
+        world_normal: Tuple4,
+    ) -> Color {
+        let width = world_ray.width_at(t);
+        let direction = world_ray.direction.normalize();
+        let cos = direction.dot(world_normal);
+        let along = direction - world_normal * cos;
+        let along = if along.magnitude() > PARALLEL_EPSILON {
+            along.normalize()
+        } else {
+            // Head on, any direction in the surface will do.
+            let other = if world_normal.x.abs() < 0.9 {
+                vector3(1., 0., 0.)
+            } else {
+                vector3(0., 1., 0.)
+            };
+            world_normal.cross(other).normalize()
+        };
+        let across = world_normal.cross(along) * width;
+        let along = along * (width / cos.abs().max(PARALLEL_EPSILON));
+
+        let to_texture = self.transform.world_to_local * object_transform.world_to_local;
+        let (along, across) = (to_texture * along, to_texture * across);
+        let texture_width = vector3(
+            along.x.hypot(across.x),
+            along.y.hypot(across.y),
+            along.z.hypot(across.z),
+        );
+        let texture_point = to_texture * world_ray.position(t);
+        self.evaluate_local_anisotropic(rng, texture_point, texture_width)
+    }
+
+    /// Returns the point moved by the texture's perturbation, if any.
+    fn perturbed(&self, texture_point: Tuple4) -> Tuple4 {
+        match self.perturbation {
+            Some(Perturbation { seed, scale }) => {
+                texture_point + perlin::noise_vector(seed, texture_point) * scale
+            }
+            None => texture_point,
+        }
+    }
+}
+
+/// Returns the fraction of the interval of the given width centered on x in
+/// which a square wave of period 2 is high, where the wave is low on [0, 1)
+/// and high on [1, 2). A width of zero samples the wave at x.
+pub fn filtered_square_wave(x: f32, width: f32) -> f32 {
+    if width <= 0. {
+        return if x.rem_euclid(2.) >= 1. { 1. } else { 0. };
+    }
+
+    // The integral of the wave from 0 to x.
+    let integral = |x: f32| {
+        let periods = (x / 2.).floor();
+        periods + (x - 2. * periods - 1.).max(0.)
+    };
+
+    (integral(x + width / 2.) - integral(x - width / 2.)) / width
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use assert_approx_eq::assert_approx_eq;
-    use rand::rngs::SmallRng;
-    use rand::SeedableRng;
     use test::Bencher;
 
     #[test]
@@ -223,6 +648,215 @@ mod tests {
         assert_approx_eq!(c.b, 0.25);
     }
 
+    #[test]
+    fn an_animated_texture_is_unchanged_at_time_zero() {
+        let mut texture = Texture::stripe(Color::WHITE, Color::BLACK);
+        texture.velocity = vector3(1., 0., 0.);
+        assert_eq!(texture.clone().at_time(0.), texture);
+    }
+
+    #[test]
+    fn an_animated_texture_is_offset_by_its_velocity() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut texture = Texture::stripe(Color::WHITE, Color::BLACK);
+        texture.velocity = vector3(1., 0., 0.);
+
+        for &x in &[-0.75, -0.25, 0.25, 0.75, 1.25, 1.75] {
+            let before =
+                texture
+                    .clone()
+                    .at_time(0.)
+                    .evaluate(&mut rng, Transform::new(), point3(x, 0., 0.));
+            let after = texture.clone().at_time(0.5).evaluate(
+                &mut rng,
+                Transform::new(),
+                point3(x + 0.5, 0., 0.),
+            );
+            assert_eq!(before, after);
+        }
+
+        let mut texture = Texture::test_pattern();
+        texture.velocity = vector3(1., 0., 0.);
+        let c = texture
+            .at_time(0.5)
+            .evaluate(&mut rng, Transform::new(), point3(2., 3., 4.));
+        assert_approx_eq!(c.r, 1.5);
+        assert_approx_eq!(c.g, 3.);
+        assert_approx_eq!(c.b, 4.);
+    }
+
+    #[test]
+    fn the_filtered_square_wave() {
+        // Narrow filters see only one side of the wave.
+        assert_eq!(filtered_square_wave(0.5, 0.5), 0.);
+        assert_eq!(filtered_square_wave(1.5, 0.5), 1.);
+        assert_eq!(filtered_square_wave(-0.5, 0.5), 1.);
+
+        // Straddling an edge, and covering whole periods.
+        assert_approx_eq!(filtered_square_wave(1., 0.5), 0.5);
+        assert_approx_eq!(filtered_square_wave(0.3, 2.), 0.5);
+        assert_approx_eq!(filtered_square_wave(0.3, 100.), 0.5);
+    }
+
+    #[test]
+    fn filtering_falls_back_to_point_sampling() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let checkers = Texture::brick(Color::WHITE, Color::BLACK);
+        let stripes = Texture::stripe(Color::WHITE, Color::BLACK);
+        for &x in &[-1.5, -0.25, 0.25, 0.75, 1.25] {
+            let p = point3(x, 0., 0.);
+            assert_eq!(
+                checkers.evaluate_local_filtered(&mut rng, p, 4.),
+                checkers.evaluate_local(&mut rng, p)
+            );
+            assert_eq!(
+                stripes.evaluate_local_filtered(&mut rng, p, 0.),
+                stripes.evaluate_local(&mut rng, p)
+            );
+        }
+    }
+
+    #[test]
+    fn a_perturbed_stripe_differs_from_a_plain_stripe() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let plain = Texture::stripe(Color::WHITE, Color::BLACK);
+        let perturbed = plain.clone().perturb(1, 0.5);
+        let points: Vec<_> = (0..100)
+            .map(|i| point3(i as f32 * 0.173, 0.37 * i as f32, -0.41 * i as f32))
+            .collect();
+
+        let differences = points
+            .iter()
+            .filter(|&&p| {
+                plain.evaluate_local(&mut rng, p) != perturbed.evaluate_local(&mut rng, p)
+            })
+            .count();
+        assert!(differences > 5 && differences < 95, "{}", differences);
+
+        // The same seed perturbs the same way.
+        for &p in &points {
+            assert_eq!(
+                perturbed.evaluate_local(&mut rng, p),
+                plain.clone().perturb(1, 0.5).evaluate_local(&mut rng, p)
+            );
+        }
+    }
+
+    #[test]
+    fn layout_of_the_align_check_pattern() {
+        let check = AlignCheck::new(
+            Color::WHITE,
+            Color::new(1., 0., 0.),
+            Color::new(1., 1., 0.),
+            Color::new(0., 1., 0.),
+            Color::new(0., 1., 1.),
+        );
+        assert_eq!(check.evaluate(0.5, 0.5), Color::WHITE);
+        assert_eq!(check.evaluate(0.1, 0.9), Color::new(1., 0., 0.));
+        assert_eq!(check.evaluate(0.9, 0.9), Color::new(1., 1., 0.));
+        assert_eq!(check.evaluate(0.1, 0.1), Color::new(0., 1., 0.));
+        assert_eq!(check.evaluate(0.9, 0.1), Color::new(0., 1., 1.));
+        assert_eq!(check.evaluate(0.5, 0.9), Color::WHITE);
+    }
+
+    #[test]
+    fn finding_the_colors_on_a_mapped_cube() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let red = Color::new(1., 0., 0.);
+        let yellow = Color::new(1., 1., 0.);
+        let brown = Color::new(1., 0.5, 0.);
+        let green = Color::new(0., 1., 0.);
+        let cyan = Color::new(0., 1., 1.);
+        let blue = Color::new(0., 0., 1.);
+        let purple = Color::new(1., 0., 1.);
+        let white = Color::WHITE;
+
+        let left = AlignCheck::new(yellow, cyan, red, blue, brown);
+        let front = AlignCheck::new(cyan, red, yellow, brown, green);
+        let right = AlignCheck::new(red, yellow, purple, green, white);
+        let back = AlignCheck::new(green, purple, cyan, white, blue);
+        let up = AlignCheck::new(brown, cyan, purple, red, yellow);
+        let down = AlignCheck::new(purple, brown, green, blue, white);
+        let texture = Texture::cube_map([right, left, up, down, front, back]);
+
+        let examples = [
+            (point3(-1., 0., 0.), yellow),
+            (point3(-1., 0.9, -0.9), cyan),
+            (point3(-1., 0.9, 0.9), red),
+            (point3(-1., -0.9, -0.9), blue),
+            (point3(-1., -0.9, 0.9), brown),
+            (point3(0., 0., 1.), cyan),
+            (point3(-0.9, 0.9, 1.), red),
+            (point3(0.9, 0.9, 1.), yellow),
+            (point3(-0.9, -0.9, 1.), brown),
+            (point3(0.9, -0.9, 1.), green),
+            (point3(1., 0., 0.), red),
+            (point3(1., 0.9, 0.9), yellow),
+            (point3(1., 0.9, -0.9), purple),
+            (point3(1., -0.9, 0.9), green),
+            (point3(1., -0.9, -0.9), white),
+            (point3(0., 0., -1.), green),
+            (point3(0.9, 0.9, -1.), purple),
+            (point3(-0.9, 0.9, -1.), cyan),
+            (point3(0.9, -0.9, -1.), white),
+            (point3(-0.9, -0.9, -1.), blue),
+            (point3(0., 1., 0.), brown),
+            (point3(-0.9, 1., -0.9), cyan),
+            (point3(0.9, 1., -0.9), purple),
+            (point3(-0.9, 1., 0.9), red),
+            (point3(0.9, 1., 0.9), yellow),
+            (point3(0., -1., 0.), purple),
+            (point3(-0.9, -1., 0.9), brown),
+            (point3(0.9, -1., 0.9), green),
+            (point3(-0.9, -1., -0.9), blue),
+            (point3(0.9, -1., -0.9), white),
+        ];
+        for (point, expected) in examples {
+            assert_eq!(
+                texture.evaluate(&mut rng, Transform::new(), point),
+                expected,
+                "{:?}",
+                point
+            );
+        }
+    }
+
+    #[test]
+    fn the_uv_debug_texture_colors_by_u_and_v() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let texture = Texture::uv_debug();
+        // The mapping follows the object, whatever its size.
+        let object = Transform::new().scale(3., 3., 3.);
+
+        let examples = [
+            (point3(3., 0., 0.), Color::new(0.25, 0.5, 0.)),
+            (point3(0., 0., 3.), Color::new(0.5, 0.5, 0.)),
+            (point3(-3., 0., 0.), Color::new(0.75, 0.5, 0.)),
+            (point3(0., 3., 0.), Color::new(0.5, 1., 0.)),
+            (point3(0., -3., 0.), Color::new(0.5, 0., 0.)),
+        ];
+        for (point, expected) in examples {
+            let c = texture.evaluate(&mut rng, object, point);
+            assert_approx_eq!(c.r, expected.r);
+            assert_approx_eq!(c.g, expected.g);
+            assert_approx_eq!(c.b, expected.b);
+        }
+    }
+
+    #[test]
+    fn creating_a_perlin_texture() {
+        let texture = Texture::perlin(Color::WHITE, Color::BLACK, 3);
+        assert_eq!(
+            texture.spec,
+            TextureSpec::Perlin {
+                a: Color::WHITE,
+                b: Color::BLACK,
+                seed: 3
+            }
+        );
+        assert_eq!(texture.perturbation, None);
+    }
+
     #[bench]
     fn bench_evaluate_constant_texture(bencher: &mut Bencher) {
         let mut rng = SmallRng::seed_from_u64(0);
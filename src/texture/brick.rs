@@ -0,0 +1,61 @@
+use crate::texture::*;
+
+/// Lays bricks of the given width and depth in rows along z, across the x-z
+/// plane. Each row is shifted along x by `row_offset` brick widths relative
+/// to the previous one, and the bricks are separated by joints of mortar
+/// `mortar_width` thick.
+pub fn evaluate<T>(
+    point: Tuple4,
+    brick: T,
+    mortar: T,
+    (width, depth): (f32, f32),
+    mortar_width: f32,
+    row_offset: f32,
+) -> T {
+    let row = (point.z / depth + PATTERN_EPSILON).floor();
+    let u = ((point.x / width + row * row_offset).rem_euclid(1.)) * width;
+    let v = point.z.rem_euclid(depth);
+    let half_joint = mortar_width / 2.;
+
+    if u < half_joint || u > width - half_joint || v < half_joint || v > depth - half_joint {
+        mortar
+    } else {
+        brick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn red() -> Color {
+        Color::new(0.6, 0.2, 0.1)
+    }
+
+    fn evaluate_at(x: f32, z: f32) -> Color {
+        evaluate(point3(x, 0., z), red(), Color::WHITE, (2., 1.), 0.1, 0.5)
+    }
+
+    #[test]
+    fn points_in_the_mortar_band_are_mortar() {
+        assert_eq!(evaluate_at(1., 0.5), red());
+        assert_eq!(evaluate_at(0.01, 0.5), Color::WHITE);
+        assert_eq!(evaluate_at(1.99, 0.5), Color::WHITE);
+        assert_eq!(evaluate_at(1., 0.01), Color::WHITE);
+        assert_eq!(evaluate_at(1., 0.99), Color::WHITE);
+        assert_eq!(evaluate_at(-0.5, -0.5), red());
+    }
+
+    #[test]
+    fn alternate_rows_are_shifted_by_half_a_brick() {
+        // In the first row the joint is at x = 2, in the second at x = 1.
+        assert_eq!(evaluate_at(2., 0.5), Color::WHITE);
+        assert_eq!(evaluate_at(1., 0.5), red());
+        assert_eq!(evaluate_at(2., 1.5), red());
+        assert_eq!(evaluate_at(1., 1.5), Color::WHITE);
+
+        // The third row lines up with the first.
+        assert_eq!(evaluate_at(2., 2.5), Color::WHITE);
+        assert_eq!(evaluate_at(1., 2.5), red());
+    }
+}
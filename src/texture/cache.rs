@@ -0,0 +1,251 @@
+use crate::texture::*;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// The most colors each thread's texture cache holds.
+pub const TEXTURE_CACHE_CAPACITY: usize = 4096;
+
+/// A cacheable texture's ID, and a cell of its texture space.
+pub type CacheKey = (u32, [i64; 3]);
+
+/// What a cached color was evaluated from. Textures have public fields, so a
+/// texture may change (or be cloned and changed) under the same ID; colors
+/// from anything else are treated as missing.
+pub type CacheSource = (TextureSpec, Option<Perturbation>);
+
+/// The numbers of lookups in a texture cache which found a color, and which
+/// had to evaluate one (including those which found a stale color).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// The fraction of lookups which found a color (0 if there were none).
+    pub fn hit_rate(&self) -> f32 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.
+        } else {
+            self.hits as f32 / lookups as f32
+        }
+    }
+}
+
+/// A cache of evaluated texture colors, which forgets the least recently
+/// used color when it is full.
+#[derive(Clone, Debug)]
+pub struct TextureCache {
+    capacity: usize,
+    /// The color for each key, what it was evaluated from, and when it was
+    /// last used.
+    colors: HashMap<CacheKey, (Color, CacheSource, u64)>,
+    /// The keys in order of when they were last used.
+    recency: BTreeMap<u64, CacheKey>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl TextureCache {
+    pub fn new(capacity: usize) -> Self {
+        TextureCache {
+            capacity,
+            colors: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Returns the color cached under the key, if it was evaluated from the
+    /// same source, or else the one evaluated by `color`, which is cached in
+    /// place of the stale color or the least recently used if the cache is
+    /// full.
+    pub fn get_or_insert_with(
+        &mut self,
+        key: CacheKey,
+        source: CacheSource,
+        color: impl FnOnce() -> Color,
+    ) -> Color {
+        self.clock += 1;
+        if let Some((cached, cached_source, used)) = self.colors.get_mut(&key) {
+            self.recency.remove(used);
+            *used = self.clock;
+            self.recency.insert(self.clock, key);
+            if *cached_source == source {
+                self.stats.hits += 1;
+                return *cached;
+            }
+
+            self.stats.misses += 1;
+            *cached = color();
+            *cached_source = source;
+            return *cached;
+        }
+
+        self.stats.misses += 1;
+        let color = color();
+        if self.capacity > 0 {
+            if self.colors.len() >= self.capacity {
+                if let Some((_, oldest)) = self.recency.pop_first() {
+                    self.colors.remove(&oldest);
+                }
+            }
+            self.colors.insert(key, (color, source, self.clock));
+            self.recency.insert(self.clock, key);
+        }
+        color
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+}
+
+thread_local! {
+    /// Each thread's cache, so that render threads never wait on each other.
+    static CACHE: RefCell<TextureCache> = RefCell::new(TextureCache::new(TEXTURE_CACHE_CAPACITY));
+}
+
+/// Returns an ID for a newly cacheable texture.
+pub fn next_id() -> u32 {
+    static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Returns the cell of side TEXTURE_CACHE_STEP containing the point, and the
+/// point at its center.
+pub fn cell(point: Tuple4) -> ([i64; 3], Tuple4) {
+    let index = |c: f32| (c / TEXTURE_CACHE_STEP).round() as i64;
+    let cell = [index(point.x), index(point.y), index(point.z)];
+    let center = |i: i64| i as f32 * TEXTURE_CACHE_STEP;
+    (
+        cell,
+        point3(center(cell[0]), center(cell[1]), center(cell[2])),
+    )
+}
+
+/// Returns the color cached in this thread under the key, if it was
+/// evaluated from the same source, or else the one evaluated by `color`
+/// (which mustn't look up cached colors itself).
+pub fn lookup(key: CacheKey, source: CacheSource, color: impl FnOnce() -> Color) -> Color {
+    CACHE.with(|cache| cache.borrow_mut().get_or_insert_with(key, source, color))
+}
+
+/// Returns the statistics of this thread's cache since it was last cleared.
+pub fn stats() -> CacheStats {
+    CACHE.with(|cache| cache.borrow().stats())
+}
+
+/// Empties this thread's cache, and resets its statistics.
+pub fn clear() {
+    CACHE.with(|cache| *cache.borrow_mut() = TextureCache::new(TEXTURE_CACHE_CAPACITY));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_evaluation_at_a_point_hits_the_cache() {
+        clear();
+        let mut rng = SmallRng::seed_from_u64(0);
+        let texture = Texture::perlin(Color::BLACK, Color::WHITE, 7).cacheable();
+        let point = point3(0.5, 0.25, 0.125);
+
+        let first = texture.evaluate_local(&mut rng, point);
+        assert_eq!(stats(), CacheStats { hits: 0, misses: 1 });
+        assert_eq!(texture.evaluate_local(&mut rng, point), first);
+        // Points in the same cell share its color.
+        let nearby = point + vector3(1e-6, -1e-6, 0.);
+        assert_eq!(texture.evaluate_local(&mut rng, nearby), first);
+        assert_eq!(stats(), CacheStats { hits: 2, misses: 1 });
+        assert_eq!(stats().hit_rate(), 2. / 3.);
+
+        // Other textures, and other cells, don't.
+        let other = Texture::perlin(Color::BLACK, Color::WHITE, 8).cacheable();
+        other.evaluate_local(&mut rng, point);
+        texture.evaluate_local(&mut rng, point3(0.5, 0.25, 0.2));
+        assert_eq!(stats().misses, 3);
+
+        // Textures which aren't cacheable don't use the cache at all.
+        Texture::perlin(Color::BLACK, Color::WHITE, 7).evaluate_local(&mut rng, point);
+        assert_eq!(stats(), CacheStats { hits: 2, misses: 3 });
+    }
+
+    #[test]
+    fn changing_a_cacheable_texture_evaluates_it_again() {
+        clear();
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut texture = Texture::perlin(Color::BLACK, Color::WHITE, 7).cacheable();
+        let point = point3(0.5, 0.25, 0.125);
+        let gray = texture.evaluate_local(&mut rng, point);
+
+        let red = Color::new(1., 0., 0.);
+        texture.spec = TextureSpec::Perlin {
+            a: red,
+            b: red,
+            seed: 1,
+        };
+        assert_eq!(texture.evaluate_local(&mut rng, point), red);
+        texture = texture.perturb(3, 0.5);
+        assert_eq!(texture.evaluate_local(&mut rng, point), red);
+        assert_eq!(stats(), CacheStats { hits: 0, misses: 3 });
+
+        texture.spec = TextureSpec::Perlin {
+            a: Color::BLACK,
+            b: Color::WHITE,
+            seed: 7,
+        };
+        texture.perturbation = None;
+        assert_eq!(texture.evaluate_local(&mut rng, point), gray);
+        assert_eq!(texture.evaluate_local(&mut rng, point), gray);
+        assert_eq!(stats(), CacheStats { hits: 1, misses: 4 });
+    }
+
+    fn source() -> CacheSource {
+        (TextureSpec::WhiteNoise, None)
+    }
+
+    #[test]
+    fn the_cache_forgets_the_least_recently_used_colors() {
+        let mut cache = TextureCache::new(8);
+        let key = |i: i64| (0, [i, 0, 0]);
+        for i in 0..20 {
+            cache.get_or_insert_with(key(i), source(), || Color::WHITE * i as f32);
+            // Keep the first color in use.
+            cache.get_or_insert_with(key(0), source(), || panic!("the first color was forgotten"));
+            assert!(cache.len() <= 8);
+        }
+        assert_eq!(cache.len(), 8);
+
+        let misses = cache.stats().misses;
+        assert_eq!(
+            cache.get_or_insert_with(key(19), source(), || Color::BLACK),
+            Color::WHITE * 19.
+        );
+        assert_eq!(
+            cache.get_or_insert_with(key(1), source(), || Color::BLACK),
+            Color::BLACK
+        );
+        assert_eq!(cache.stats().misses, misses + 1);
+    }
+
+    #[test]
+    fn a_cache_without_capacity_holds_nothing() {
+        let mut cache = TextureCache::new(0);
+        cache.get_or_insert_with((0, [0; 3]), source(), || Color::WHITE);
+        assert!(cache.is_empty());
+        assert_eq!(cache.stats().misses, 1);
+    }
+}
@@ -1,9 +1,9 @@
 use crate::texture::*;
 
 pub fn evaluate<T>(point: Tuple4, a: T, b: T) -> T {
-    let c = (point.x + 1e-5).floor().abs()
-        + (point.y + 1e-5).floor().abs()
-        + (point.z + 1e-5).floor().abs();
+    let c = (point.x + PATTERN_EPSILON).floor().abs()
+        + (point.y + PATTERN_EPSILON).floor().abs()
+        + (point.z + PATTERN_EPSILON).floor().abs();
     if c % 2. == 0. {
         a
     } else {
@@ -11,9 +11,21 @@ pub fn evaluate<T>(point: Tuple4, a: T, b: T) -> T {
     }
 }
 
+/// Returns the mix of the colors over a box filter of the given width along
+/// each axis,
+/// which blends toward an even mix as the cubes become narrower than the
+/// filter. The filter is separable, so the chance of landing in a `b` cube
+/// (an odd number of odd cells along the axes) follows from each axis's.
+pub fn evaluate_filtered(point: Tuple4, a: Color, b: Color, width: Tuple4) -> Color {
+    let sign = |c: f32, width: f32| 1. - 2. * filtered_square_wave(c + PATTERN_EPSILON, width);
+    let t = (1. - sign(point.x, width.x) * sign(point.y, width.y) * sign(point.z, width.z)) / 2.;
+    a * (1. - t) + b * t
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use assert_approx_eq::assert_approx_eq;
 
     #[test]
     fn checkerboard_3d_should_repeat_in_x() {
@@ -62,4 +74,56 @@ mod tests {
             Color::BLACK
         );
     }
+
+    #[test]
+    fn points_within_the_pattern_epsilon_of_a_boundary_snap_to_the_next_cell() {
+        assert_eq!(
+            evaluate(
+                point3(0., 1. - 0.5 * PATTERN_EPSILON, 0.),
+                Color::WHITE,
+                Color::BLACK
+            ),
+            Color::BLACK
+        );
+        assert_eq!(
+            evaluate(
+                point3(0., 1. - 2. * PATTERN_EPSILON, 0.),
+                Color::WHITE,
+                Color::BLACK
+            ),
+            Color::WHITE
+        );
+    }
+
+    fn width(w: f32) -> Tuple4 {
+        vector3(w, w, w)
+    }
+
+    #[test]
+    fn filtering_a_checkerboard_3d() {
+        let c = evaluate_filtered(
+            point3(0.3, 0.2, 0.4),
+            Color::WHITE,
+            Color::BLACK,
+            width(1000.),
+        );
+        assert_approx_eq!(c.r, 0.5, 1e-3);
+        let c = evaluate_filtered(
+            point3(0.3, 0.2, 0.4),
+            Color::WHITE,
+            Color::BLACK,
+            width(0.1),
+        );
+        assert_approx_eq!(c.r, 1.);
+        let c = evaluate_filtered(
+            point3(1.5, 0.2, 0.4),
+            Color::WHITE,
+            Color::BLACK,
+            width(0.1),
+        );
+        assert_approx_eq!(c.r, 0.);
+        // Straddling an edge, half of the filter lands in each color.
+        let c = evaluate_filtered(point3(1., 0.2, 0.4), Color::WHITE, Color::BLACK, width(0.2));
+        assert_approx_eq!(c.r, 0.5, 1e-3);
+    }
 }
@@ -0,0 +1,90 @@
+use crate::cube_map::CubeFace;
+use crate::texture::*;
+
+/// Returns the face of the unit cube that the point lies on (or, for points
+/// off the cube, the face it lies beyond).
+pub fn face(point: Tuple4) -> CubeFace {
+    let (ax, ay, az) = (point.x.abs(), point.y.abs(), point.z.abs());
+    let coord = ax.max(ay).max(az);
+
+    if coord == point.x {
+        CubeFace::PositiveX
+    } else if coord == -point.x {
+        CubeFace::NegativeX
+    } else if coord == point.y {
+        CubeFace::PositiveY
+    } else if coord == -point.y {
+        CubeFace::NegativeY
+    } else if coord == point.z {
+        CubeFace::PositiveZ
+    } else {
+        CubeFace::NegativeZ
+    }
+}
+
+/// Returns the (u, v) coordinates in [0, 1) of the point within the face,
+/// seen from outside the cube with v increasing upward (or, on the top and
+/// bottom faces, toward the front and back respectively).
+pub fn uv(face: CubeFace, point: Tuple4) -> (f32, f32) {
+    let wrap = |x: f32| x.rem_euclid(2.) / 2.;
+    match face {
+        CubeFace::PositiveZ => (wrap(point.x + 1.), wrap(point.y + 1.)),
+        CubeFace::NegativeZ => (wrap(1. - point.x), wrap(point.y + 1.)),
+        CubeFace::NegativeX => (wrap(point.z + 1.), wrap(point.y + 1.)),
+        CubeFace::PositiveX => (wrap(1. - point.z), wrap(point.y + 1.)),
+        CubeFace::PositiveY => (wrap(point.x + 1.), wrap(1. - point.z)),
+        CubeFace::NegativeY => (wrap(point.x + 1.), wrap(point.z + 1.)),
+    }
+}
+
+/// Returns the color at the point from the pattern of the face it lies on,
+/// where the faces are in CubeFace order.
+pub fn evaluate(point: Tuple4, faces: &[AlignCheck; 6]) -> Color {
+    let face = face(point);
+    let (u, v) = uv(face, point);
+    faces[face as usize].evaluate(u, v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn identifying_the_face_of_a_cube_from_a_point() {
+        let examples = [
+            (point3(-1., 0.5, -0.25), CubeFace::NegativeX),
+            (point3(1.1, -0.75, 0.8), CubeFace::PositiveX),
+            (point3(0.1, 0.6, 0.9), CubeFace::PositiveZ),
+            (point3(-0.7, 0., -2.), CubeFace::NegativeZ),
+            (point3(0.5, 1., 0.9), CubeFace::PositiveY),
+            (point3(-0.2, -1.3, 1.1), CubeFace::NegativeY),
+        ];
+        for (point, expected) in examples {
+            assert_eq!(face(point), expected, "{:?}", point);
+        }
+    }
+
+    #[test]
+    fn uv_mapping_each_face_of_a_cube() {
+        let examples = [
+            (CubeFace::PositiveZ, point3(-0.5, 0.5, 1.), (0.25, 0.75)),
+            (CubeFace::PositiveZ, point3(0.5, -0.5, 1.), (0.75, 0.25)),
+            (CubeFace::NegativeZ, point3(0.5, 0.5, -1.), (0.25, 0.75)),
+            (CubeFace::NegativeZ, point3(-0.5, -0.5, -1.), (0.75, 0.25)),
+            (CubeFace::NegativeX, point3(-1., 0.5, -0.5), (0.25, 0.75)),
+            (CubeFace::NegativeX, point3(-1., -0.5, 0.5), (0.75, 0.25)),
+            (CubeFace::PositiveX, point3(1., 0.5, 0.5), (0.25, 0.75)),
+            (CubeFace::PositiveX, point3(1., -0.5, -0.5), (0.75, 0.25)),
+            (CubeFace::PositiveY, point3(-0.5, 1., -0.5), (0.25, 0.75)),
+            (CubeFace::PositiveY, point3(0.5, 1., 0.5), (0.75, 0.25)),
+            (CubeFace::NegativeY, point3(-0.5, -1., 0.5), (0.25, 0.75)),
+            (CubeFace::NegativeY, point3(0.5, -1., -0.5), (0.75, 0.25)),
+        ];
+        for (face, point, (u, v)) in examples {
+            let (actual_u, actual_v) = uv(face, point);
+            assert_approx_eq!(actual_u, u);
+            assert_approx_eq!(actual_v, v);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::texture::*;
+
+/// Ken Perlin's reference permutation of 0..=255.
+const PERMUTATION: [u8; 256] = [
+    151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225, 140, 36, 103, 30, 69,
+    142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148, 247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219,
+    203, 117, 35, 11, 32, 57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+    74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122, 60, 211, 133, 230,
+    220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54, 65, 25, 63, 161, 1, 216, 80, 73, 209, 76,
+    132, 187, 208, 89, 18, 169, 200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173,
+    186, 3, 64, 52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212, 207, 206,
+    59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213, 119, 248, 152, 2, 44, 154, 163,
+    70, 221, 153, 101, 155, 167, 43, 172, 9, 129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232,
+    178, 185, 112, 104, 218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162,
+    241, 81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157, 184, 84, 204,
+    176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93, 222, 114, 67, 29, 24, 72, 243, 141,
+    128, 195, 78, 66, 215, 61, 156, 180,
+];
+
+/// Returns the seed's permutation of the lattice index i. Rotating the
+/// reference table's input and flipping bits of its output give another
+/// permutation, so each seed has its own table without storing one.
+fn permute(seed: u32, i: usize) -> usize {
+    let rotation = (seed & 0xff) as usize;
+    let flip = ((seed >> 8) & 0xff) as usize;
+    PERMUTATION[(i + rotation) & 0xff] as usize ^ flip
+}
+
+/// Returns the dot product of the offset from a lattice point with one of
+/// the twelve edge directions of a cube, picked by the hash.
+fn gradient(hash: usize, x: f32, y: f32, z: f32) -> f32 {
+    match hash & 15 {
+        0 | 12 => x + y,
+        1 | 14 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x + z,
+        5 => -x + z,
+        6 => x - z,
+        7 => -x - z,
+        8 => y + z,
+        9 | 13 => -y + z,
+        10 => y - z,
+        _ => -y - z,
+    }
+}
+
+/// Eases the fractional position between lattice points, so that the noise
+/// has continuous first and second derivatives.
+fn fade(t: f32) -> f32 {
+    t * t * t * t.mul_add(t.mul_add(6., -15.), 10.)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+    (b - a).mul_add(t, a)
+}
+
+/// Returns the (improved) Perlin gradient noise at the point, from -1 to 1.
+/// The noise is 0 at every lattice point and varies smoothly between them.
+pub fn noise(seed: u32, point: Tuple4) -> f32 {
+    let (xf, yf, zf) = (point.x.floor(), point.y.floor(), point.z.floor());
+    let (x, y, z) = (point.x - xf, point.y - yf, point.z - zf);
+    let (xi, yi, zi) = (
+        (xf as i32 & 0xff) as usize,
+        (yf as i32 & 0xff) as usize,
+        (zf as i32 & 0xff) as usize,
+    );
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+
+    let hash =
+        |i: usize, j: usize, k: usize| permute(seed, permute(seed, permute(seed, i) + j) + k);
+    let corner = |i: usize, j: usize, k: usize| {
+        gradient(
+            hash(xi + i, yi + j, zi + k),
+            x - i as f32,
+            y - j as f32,
+            z - k as f32,
+        )
+    };
+
+    let value = lerp(
+        w,
+        lerp(
+            v,
+            lerp(u, corner(0, 0, 0), corner(1, 0, 0)),
+            lerp(u, corner(0, 1, 0), corner(1, 1, 0)),
+        ),
+        lerp(
+            v,
+            lerp(u, corner(0, 0, 1), corner(1, 0, 1)),
+            lerp(u, corner(0, 1, 1), corner(1, 1, 1)),
+        ),
+    );
+    value.clamp(-1., 1.)
+}
+
+/// Maps the noise at the point onto a gradient from a (at -1) to b (at 1).
+pub fn evaluate(point: Tuple4, a: Color, b: Color, seed: u32) -> Color {
+    let t = (noise(seed, point) + 1.) / 2.;
+    a * (1. - t) + b * t
+}
+
+/// Returns a vector of three independent noise values at the point, for
+/// jittering it.
+pub fn noise_vector(seed: u32, point: Tuple4) -> Tuple4 {
+    // Distant, unaligned offsets decorrelate the three components.
+    vector3(
+        noise(seed, point),
+        noise(seed, point + vector3(31.416, 47.853, 12.793)),
+        noise(seed, point + vector3(-23.172, 5.271, 71.909)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_same_seed_gives_the_same_noise() {
+        let points = [point3(0.3, 1.7, -2.2), point3(10.5, 0.25, 3.75)];
+        for point in points {
+            assert_eq!(noise(7, point), noise(7, point));
+        }
+        assert_ne!(noise(7, points[0]), noise(8, points[0]));
+        assert_ne!(noise(7, points[0]), noise(7 << 8, points[0]));
+    }
+
+    #[test]
+    fn noise_is_between_minus_one_and_one() {
+        let mut lowest = f32::INFINITY;
+        let mut highest = -f32::INFINITY;
+        for i in 0..20_000 {
+            let t = i as f32 * 0.0137;
+            let n = noise(3, point3(t, (t * 1.7).sin() * 9., t * 0.31 - 20.));
+            lowest = lowest.min(n);
+            highest = highest.max(n);
+        }
+        assert!((-1. ..=1.).contains(&lowest) && (-1. ..=1.).contains(&highest));
+        assert!(lowest < -0.5 && highest > 0.5);
+    }
+
+    #[test]
+    fn noise_varies_smoothly_between_lattice_points() {
+        // Zero at the lattice points, and no jumps in between.
+        assert_eq!(noise(0, point3(2., -3., 5.)), 0.);
+        let step = 0.001;
+        let mut previous = noise(0, point3(0., 0.5, 0.5));
+        for i in 1..=3000 {
+            let n = noise(0, point3(i as f32 * step, 0.5, 0.5));
+            assert!((n - previous).abs() < 0.01, "{} {}", i, n - previous);
+            previous = n;
+        }
+    }
+
+    #[test]
+    fn noise_maps_onto_a_color_gradient() {
+        let a = Color::BLACK;
+        let b = Color::WHITE;
+        assert_eq!(
+            evaluate(point3(1., 2., 3.), a, b, 0),
+            Color::new(0.5, 0.5, 0.5)
+        );
+        let c = evaluate(point3(1.5, 2.25, 3.75), a, b, 0);
+        assert!((0. ..=1.).contains(&c.r));
+        assert_ne!(c.r, 0.5);
+    }
+}
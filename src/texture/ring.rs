@@ -8,9 +8,18 @@ pub fn evaluate<T>(point: Tuple4, a: T, b: T) -> T {
     }
 }
 
+/// Returns the mix of the colors over a box filter of the given width,
+/// which blends toward an even mix as the rings become narrower than the
+/// filter.
+pub fn evaluate_filtered(point: Tuple4, a: Color, b: Color, width: f32) -> Color {
+    let t = filtered_square_wave((point.x * point.x + point.z * point.z).sqrt(), width);
+    a * (1. - t) + b * t
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use assert_approx_eq::assert_approx_eq;
 
     #[test]
     fn a_ring_should_extend_in_both_x_and_z() {
@@ -31,4 +40,14 @@ mod tests {
             Color::BLACK
         );
     }
+
+    #[test]
+    fn filtering_rings() {
+        let c = evaluate_filtered(point3(0.3, 0., 0.4), Color::WHITE, Color::BLACK, 1000.);
+        assert_approx_eq!(c.r, 0.5, 1e-3);
+        let c = evaluate_filtered(point3(0.3, 0., 0.4), Color::WHITE, Color::BLACK, 0.1);
+        assert_approx_eq!(c.r, 1.);
+        let c = evaluate_filtered(point3(0.9, 0., 0.9), Color::WHITE, Color::BLACK, 0.1);
+        assert_approx_eq!(c.r, 0.);
+    }
 }
@@ -0,0 +1,53 @@
+use crate::texture::*;
+use std::f32::consts::PI;
+
+/// Returns the (u, v) coordinates in [0, 1] of the point's direction from
+/// the origin, as on a globe: u is the longitude, increasing eastward
+/// (counterclockwise seen from +y) from the seam at -z, and v is the
+/// latitude, from 0 at the south pole to 1 at the north.
+pub fn uv(point: Tuple4) -> (f32, f32) {
+    let theta = point.x.atan2(point.z);
+    let radius = vector3(point.x, point.y, point.z).magnitude();
+    let phi = (point.y / radius).clamp(-1., 1.).acos();
+
+    let u = 1. - (theta / (2. * PI) + 0.5);
+    let v = 1. - phi / PI;
+    (u, v)
+}
+
+/// Returns the point's (u, v) coordinates as the red and green of a color,
+/// to show how the sphere is mapped.
+pub fn evaluate_debug(point: Tuple4) -> Color {
+    let (u, v) = uv(point);
+    Color::new(u, v, 0.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn mapping_points_on_a_sphere_to_uv() {
+        let half = 2f32.sqrt() / 2.;
+        let cases = [
+            (point3(0., 0., -1.), (0.0, 0.5)),
+            (point3(1., 0., 0.), (0.25, 0.5)),
+            (point3(0., 0., 1.), (0.5, 0.5)),
+            (point3(-1., 0., 0.), (0.75, 0.5)),
+            (point3(0., 1., 0.), (0.5, 1.0)),
+            (point3(0., -1., 0.), (0.5, 0.0)),
+            (point3(half, half, 0.), (0.25, 0.75)),
+        ];
+        for (point, (u, v)) in cases {
+            let actual = uv(point);
+            assert_approx_eq!(actual.0, u);
+            assert_approx_eq!(actual.1, v);
+        }
+    }
+
+    #[test]
+    fn the_mapping_ignores_the_distance_from_the_origin() {
+        assert_eq!(uv(point3(0., 2., 2.)), uv(point3(0., 0.5, 0.5)));
+    }
+}
@@ -8,9 +8,18 @@ pub fn evaluate<T>(point: Tuple4, a: T, b: T) -> T {
     }
 }
 
+/// Returns the mix of the colors over a box filter of the given width,
+/// which blends toward an even mix as the stripes become narrower than the
+/// filter.
+pub fn evaluate_filtered(point: Tuple4, a: Color, b: Color, width: f32) -> Color {
+    let t = filtered_square_wave(point.x, width);
+    a * (1. - t) + b * t
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use assert_approx_eq::assert_approx_eq;
 
     #[test]
     fn a_stripe_pattern_is_constant_in_y() {
@@ -71,4 +80,38 @@ mod tests {
             Color::WHITE
         );
     }
+
+    #[test]
+    fn filtered_stripes_match_point_sampling_for_narrow_filters() {
+        for &x in &[-0.75, -0.25, 0.25, 0.5, 1.5] {
+            let p = point3(x, 0., 0.);
+            let filtered = evaluate_filtered(p, Color::WHITE, Color::BLACK, 0.1);
+            let expected = evaluate(p, Color::WHITE, Color::BLACK);
+            assert_approx_eq!(filtered.r, expected.r);
+            assert_approx_eq!(filtered.g, expected.g);
+            assert_approx_eq!(filtered.b, expected.b);
+        }
+    }
+
+    #[test]
+    fn filtered_stripes_average_out_under_wide_filters() {
+        let c = evaluate_filtered(point3(0.25, 0., 0.), Color::WHITE, Color::BLACK, 1000.);
+        assert_approx_eq!(c.r, 0.5, 1e-3);
+        assert_approx_eq!(c.g, 0.5, 1e-3);
+        assert_approx_eq!(c.b, 0.5, 1e-3);
+    }
+
+    #[test]
+    fn filtered_stripes_darken_monotonically_with_width() {
+        // Within a white stripe, widening the filter up to a whole period
+        // takes in more and more of the black stripes.
+        let p = point3(0.25, 0., 0.);
+        let mut previous = 1.;
+        for i in 1..=20 {
+            let c = evaluate_filtered(p, Color::WHITE, Color::BLACK, i as f32 * 0.1);
+            assert!(c.r <= previous);
+            previous = c.r;
+        }
+        assert_approx_eq!(previous, 0.5);
+    }
 }
@@ -0,0 +1,58 @@
+use crate::texture::*;
+
+/// Lays square tiles of the given size across the x-z plane, separated by
+/// grout `grout_width` wide. Each tile darkens toward its edges over the
+/// `bevel` distance, reaching half its color at the grout.
+pub fn evaluate(
+    point: Tuple4,
+    tile: Color,
+    grout: Color,
+    size: f32,
+    grout_width: f32,
+    bevel: f32,
+) -> Color {
+    let u = point.x.rem_euclid(size);
+    let v = point.z.rem_euclid(size);
+    let half_joint = grout_width / 2.;
+
+    // The distance from the point to the nearest edge of the tile.
+    let edge = u.min(size - u).min(v).min(size - v) - half_joint;
+    if edge < 0. {
+        grout
+    } else if edge < bevel {
+        tile * (0.5 + 0.5 * edge / bevel)
+    } else {
+        tile
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    fn evaluate_at(x: f32, z: f32) -> Color {
+        evaluate(point3(x, 0., z), Color::WHITE, Color::BLACK, 1., 0.1, 0.2)
+    }
+
+    #[test]
+    fn points_in_the_grout_are_grout() {
+        assert_eq!(evaluate_at(0.01, 0.5), Color::BLACK);
+        assert_eq!(evaluate_at(0.5, 0.99), Color::BLACK);
+        assert_eq!(evaluate_at(-0.01, 0.5), Color::BLACK);
+    }
+
+    #[test]
+    fn the_bevel_darkening_is_strongest_at_tile_edges() {
+        assert_eq!(evaluate_at(0.5, 0.5), Color::WHITE);
+
+        let at_edge = evaluate_at(0.051, 0.5);
+        let within_bevel = evaluate_at(0.15, 0.5);
+        assert_approx_eq!(at_edge.r, 0.5, 1e-2);
+        assert_approx_eq!(within_bevel.r, 0.75, 1e-2);
+        assert!(at_edge.r < within_bevel.r);
+
+        // Corners are darkened by their nearest edge.
+        assert_eq!(evaluate_at(0.15, 0.15), within_bevel);
+    }
+}
@@ -1,7 +1,9 @@
+use crate::angle::*;
 use crate::matrix::*;
 use crate::tuple::*;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Transform {
     pub local_to_world: Matrix4,
     pub world_to_local: Matrix4,
@@ -16,6 +18,15 @@ impl Transform {
         }
     }
 
+    /// Creates a transform from its local to world matrix, finding the
+    /// inverse.
+    pub fn from_matrix(local_to_world: Matrix4) -> Self {
+        Transform {
+            local_to_world,
+            world_to_local: invert(local_to_world),
+        }
+    }
+
     /// Creates a view transform given the point where the eye is looking
     /// from, the point where the eye is looking to, and a vector indicating
     /// which direction is up.
@@ -36,7 +47,7 @@ impl Transform {
 
         Transform {
             local_to_world: view_matrix,
-            world_to_local: view_matrix.inverse(),
+            world_to_local: invert(view_matrix),
         }
     }
 
@@ -44,12 +55,14 @@ impl Transform {
     pub fn translate(&mut self, x: f32, y: f32, z: f32) -> Self {
         let translate_matrix = matrix4(1., 0., 0., x, 0., 1., 0., y, 0., 0., 1., z, 0., 0., 0., 1.);
         self.local_to_world = self.local_to_world * translate_matrix;
-        self.world_to_local = self.local_to_world.inverse();
+        self.world_to_local = invert(self.local_to_world);
         *self
     }
 
-    /// Rotates around the x-axis by the angle in radians.
-    pub fn rotate_x(&mut self, radians: f32) -> Self {
+    /// Rotates around the x-axis by the angle (in radians, if given as a
+    /// bare number).
+    pub fn rotate_x(&mut self, angle: impl Into<Angle>) -> Self {
+        let radians = angle.into().to_radians();
         let rotation_matrix = matrix4(
             1.,
             0.,
@@ -69,12 +82,14 @@ impl Transform {
             1.,
         );
         self.local_to_world = self.local_to_world * rotation_matrix;
-        self.world_to_local = self.local_to_world.inverse();
+        self.world_to_local = invert(self.local_to_world);
         *self
     }
 
-    /// Rotates around the y-axis by the angle in radians.
-    pub fn rotate_y(&mut self, radians: f32) -> Self {
+    /// Rotates around the y-axis by the angle (in radians, if given as a
+    /// bare number).
+    pub fn rotate_y(&mut self, angle: impl Into<Angle>) -> Self {
+        let radians = angle.into().to_radians();
         let rotation_matrix = matrix4(
             radians.cos(),
             0.,
@@ -94,12 +109,14 @@ impl Transform {
             1.,
         );
         self.local_to_world = self.local_to_world * rotation_matrix;
-        self.world_to_local = self.local_to_world.inverse();
+        self.world_to_local = invert(self.local_to_world);
         *self
     }
 
-    /// Rotates around the z-axis by the angle in radians.
-    pub fn rotate_z(&mut self, radians: f32) -> Self {
+    /// Rotates around the z-axis by the angle (in radians, if given as a
+    /// bare number).
+    pub fn rotate_z(&mut self, angle: impl Into<Angle>) -> Self {
+        let radians = angle.into().to_radians();
         let rotation_matrix = matrix4(
             radians.cos(),
             -radians.sin(),
@@ -119,7 +136,7 @@ impl Transform {
             1.,
         );
         self.local_to_world = self.local_to_world * rotation_matrix;
-        self.world_to_local = self.local_to_world.inverse();
+        self.world_to_local = invert(self.local_to_world);
         *self
     }
 
@@ -127,7 +144,7 @@ impl Transform {
     pub fn scale(&mut self, x: f32, y: f32, z: f32) -> Self {
         let scale_matrix = matrix4(x, 0., 0., 0., 0., y, 0., 0., 0., 0., z, 0., 0., 0., 0., 1.);
         self.local_to_world = self.local_to_world * scale_matrix;
-        self.world_to_local = self.local_to_world.inverse();
+        self.world_to_local = invert(self.local_to_world);
         *self
     }
 
@@ -137,9 +154,44 @@ impl Transform {
             1., xy, xz, 0., yx, 1., yz, 0., zx, zy, 1., 0., 0., 0., 0., 1.,
         );
         self.local_to_world = self.local_to_world * shear_matrix;
-        self.world_to_local = self.local_to_world.inverse();
+        self.world_to_local = invert(self.local_to_world);
         *self
     }
+
+    /// Whether the transform can be inverted. Scaling any axis to zero
+    /// collapses space, leaving no way back to local coordinates.
+    pub fn is_invertible(&self) -> bool {
+        self.local_to_world.try_inverse().is_some()
+    }
+
+    /// Mirrors the z-axis, converting between left- and right-handed
+    /// coordinates.
+    pub fn convert_handedness(&mut self) -> Self {
+        self.scale(1., 1., -1.)
+    }
+
+    /// Converts from coordinates where +z is up (and +y forward) to this
+    /// crate's convention, where +y is up (and -z forward).
+    pub fn z_up_to_y_up(&mut self) -> Self {
+        self.rotate_x(-std::f32::consts::FRAC_PI_2)
+    }
+
+    /// Converts from this crate's y-up convention to coordinates where +z is
+    /// up.
+    pub fn y_up_to_z_up(&mut self) -> Self {
+        self.rotate_x(std::f32::consts::FRAC_PI_2)
+    }
+}
+
+/// Inverts the matrix, or returns a matrix of NaNs if it isn't invertible,
+/// rather than panicking partway through building a transform.
+fn invert(m: Matrix4) -> Matrix4 {
+    m.try_inverse().unwrap_or_else(|| {
+        let nan = f32::NAN;
+        matrix4(
+            nan, nan, nan, nan, nan, nan, nan, nan, nan, nan, nan, nan, nan, nan, nan, nan,
+        )
+    })
 }
 
 #[cfg(test)]
@@ -247,6 +299,23 @@ mod tests {
         assert_approx_eq!(full_rotation.z, 0.);
     }
 
+    #[test]
+    fn rotating_by_an_angle_in_degrees() {
+        let quarter = std::f32::consts::FRAC_PI_2;
+        assert_eq!(
+            Transform::new().rotate_x(Angle::degrees(90.)),
+            Transform::new().rotate_x(Angle::radians(quarter))
+        );
+        assert_eq!(
+            Transform::new().rotate_y(Angle::degrees(90.)),
+            Transform::new().rotate_y(quarter)
+        );
+        assert_eq!(
+            Transform::new().rotate_z(Angle::degrees(90.)),
+            Transform::new().rotate_z(quarter)
+        );
+    }
+
     #[test]
     fn shearing_transformation_moves_x_in_proportion_to_y() {
         let transform = Transform::new().shear(1., 0., 0., 0., 0., 0.);
@@ -394,4 +463,67 @@ mod tests {
         assert_approx_eq!(t.w2, 0.00000, 1e-5);
         assert_approx_eq!(t.w3, 1.00000, 1e-5);
     }
+
+    #[test]
+    fn scaling_an_axis_to_zero_is_not_invertible() {
+        assert!(Transform::new().scale(2., 1., 1.).is_invertible());
+        let t = Transform::new().scale(0., 1., 1.);
+        assert!(!t.is_invertible());
+        assert!(t.world_to_local.x0.is_nan());
+    }
+
+    #[test]
+    fn converting_from_z_up_to_y_up() {
+        let transform = Transform::new().z_up_to_y_up();
+        let up = transform.local_to_world * vector3(0., 0., 1.);
+        let forward = transform.local_to_world * vector3(0., 1., 0.);
+        let p = transform.local_to_world * point3(1., 2., 3.);
+        assert_approx_eq!(up.y, 1.);
+        assert_approx_eq!(forward.z, -1.);
+        assert_approx_eq!(p.x, 1.);
+        assert_approx_eq!(p.y, 3.);
+        assert_approx_eq!(p.z, -2.);
+
+        let round_trip = Transform::new()
+            .z_up_to_y_up()
+            .y_up_to_z_up()
+            .local_to_world
+            * point3(1., 2., 3.);
+        assert_approx_eq!(round_trip.x, 1.);
+        assert_approx_eq!(round_trip.y, 2.);
+        assert_approx_eq!(round_trip.z, 3.);
+    }
+
+    #[test]
+    fn converting_handedness_mirrors_z() {
+        let transform = Transform::new().convert_handedness();
+        assert_eq!(
+            transform.local_to_world * point3(1., 2., 3.),
+            point3(1., 2., -3.)
+        );
+    }
+
+    #[test]
+    fn normals_of_a_mirrored_object_still_point_outward() {
+        // Normals transform by the inverse transpose, which keeps them
+        // outward even when the transform flips handedness.
+        let transform = Transform::new()
+            .translate(1., 2., 3.)
+            .convert_handedness()
+            .z_up_to_y_up();
+        let center = transform.local_to_world * point3(0., 0., 0.);
+        let local_points = vec![
+            point3(1., 0., 0.),
+            point3(0., -1., 0.),
+            point3(0., 0., 1.),
+            point3(0.6, 0., -0.8),
+        ];
+        for local_point in local_points {
+            let world_point = transform.local_to_world * local_point;
+            let local_normal = local_point - point3(0., 0., 0.);
+            let mut world_normal = transform.world_to_local.transpose() * local_normal;
+            world_normal.w = 0.;
+            assert!(world_normal.dot(world_point - center) > 0.);
+        }
+    }
 }
@@ -2,6 +2,7 @@ use std::ops;
 
 /// A 4-element tuple, used for representing points and vectors.
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tuple4 {
     pub x: f32,
     pub y: f32,
@@ -0,0 +1,48 @@
+//! Renders each example at a small size, checking that it produces an image.
+
+// Each example's main is unused here.
+#[allow(dead_code)]
+#[path = "../examples/basic_sphere.rs"]
+mod basic_sphere;
+#[allow(dead_code)]
+#[path = "../examples/glass.rs"]
+mod glass;
+#[allow(dead_code)]
+#[path = "../examples/obj_mesh.rs"]
+mod obj_mesh;
+#[allow(dead_code)]
+#[path = "../examples/soft_shadows.rs"]
+mod soft_shadows;
+
+use rtchallenge::canvas::*;
+
+fn assert_rendered(canvas: Canvas) {
+    assert_eq!((canvas.width, canvas.height), (16, 16));
+    assert!(canvas.data.iter().any(|&c| c != 0.), "image is all black");
+    for y in 0..canvas.height {
+        for x in 0..canvas.width {
+            let color = canvas.get_color(x, y);
+            assert!(!color.r.is_nan() && !color.g.is_nan() && !color.b.is_nan());
+        }
+    }
+}
+
+#[test]
+fn the_basic_sphere_example_renders() {
+    assert_rendered(basic_sphere::render(16, 16));
+}
+
+#[test]
+fn the_glass_example_renders() {
+    assert_rendered(glass::render(16, 16));
+}
+
+#[test]
+fn the_soft_shadows_example_renders() {
+    assert_rendered(soft_shadows::render(16, 16));
+}
+
+#[test]
+fn the_obj_mesh_example_renders() {
+    assert_rendered(obj_mesh::render(16, 16));
+}